@@ -1,5 +1,8 @@
 //! Benchmark ZeroProto roundtrip performance
 
+// Sample values, not approximations of constants
+#![allow(clippy::approx_constant)]
+
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
 use zeroproto::{MessageBuilder, MessageReader};
 
@@ -3,13 +3,15 @@
 use clap::{ArgAction, Args, Parser, Subcommand};
 use color_eyre::eyre::{eyre, Context, Result};
 use globset::{Glob, GlobSet, GlobSetBuilder};
-use notify::{Config, Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use notify::{Config, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::sync::mpsc;
 use zeroproto_compiler::{
     self as compiler,
-    ast::{DefaultValue, Enum, Field, FieldType, Message, ScalarType, Schema, SchemaItem},
+    ast::{FieldType, Schema, SchemaItem},
+    compat::Compatibility,
+    loader::SchemaSet,
 };
 
 #[derive(Parser)]
@@ -108,6 +110,9 @@ enum Commands {
         /// Output directory for generated code
         #[arg(short, long, default_value = "src/generated")]
         output: PathBuf,
+        /// Additional directories to search for imported schemas
+        #[arg(short = 'I', long = "import-path", action = ArgAction::Append)]
+        import_paths: Vec<PathBuf>,
         /// Run in verbose mode
         #[arg(short, long)]
         verbose: bool,
@@ -123,6 +128,9 @@ enum Commands {
         /// Output directory for generated code
         #[arg(short, long, default_value = "src/generated")]
         output: PathBuf,
+        /// Additional directories to search for imported schemas
+        #[arg(short = 'I', long = "import-path", action = ArgAction::Append)]
+        import_paths: Vec<PathBuf>,
         /// Run in verbose mode
         #[arg(short, long)]
         verbose: bool,
@@ -142,6 +150,18 @@ enum Commands {
         #[command(flatten)]
         filters: SchemaFilterArgs,
     },
+    /// Rewrite schema files in the canonical style
+    Fmt {
+        /// Schema file or directory to format
+        #[arg(short, long)]
+        input: PathBuf,
+        /// Report files that aren't formatted instead of rewriting them, failing if any
+        #[arg(long)]
+        check: bool,
+        /// Schema filtering options
+        #[command(flatten)]
+        filters: SchemaFilterArgs,
+    },
     /// Inspect schema structure and statistics without generating code
     Inspect {
         /// Schema file or directory to inspect
@@ -154,6 +174,15 @@ enum Commands {
         #[command(flatten)]
         filters: SchemaFilterArgs,
     },
+    /// Compare two versions of a schema and classify every change
+    Compat {
+        /// The previous version of the schema
+        #[arg(long)]
+        old: PathBuf,
+        /// The new version of the schema
+        #[arg(long)]
+        new: PathBuf,
+    },
     /// Create a new ZeroProto project
     Init {
         /// Project name
@@ -174,20 +203,22 @@ fn main() -> Result<()> {
         Commands::Compile {
             input,
             output,
+            import_paths,
             verbose,
             filters,
         } => {
             let compiled = filters.build()?;
-            compile_schemas(&input, &output, verbose, &filters, &compiled)
+            compile_schemas(&input, &output, &import_paths, verbose, &filters, &compiled)
         }
         Commands::Watch {
             input,
             output,
+            import_paths,
             verbose,
             filters,
         } => {
             let compiled = filters.build()?;
-            watch_schemas(&input, &output, verbose, &filters, &compiled)
+            watch_schemas(&input, &output, &import_paths, verbose, &filters, &compiled)
         }
         Commands::Check {
             input,
@@ -197,6 +228,14 @@ fn main() -> Result<()> {
             let compiled = filters.build()?;
             check_schemas(&input, verbose, &filters, &compiled)
         }
+        Commands::Fmt {
+            input,
+            check,
+            filters,
+        } => {
+            let compiled = filters.build()?;
+            format_schemas(&input, check, &filters, &compiled)
+        }
         Commands::Inspect {
             input,
             verbose,
@@ -205,6 +244,7 @@ fn main() -> Result<()> {
             let compiled = filters.build()?;
             inspect_schemas(&input, verbose, &filters, &compiled)
         }
+        Commands::Compat { old, new } => compat_schemas(&old, &new),
         Commands::Init { name, current_dir } => init_project(&name, current_dir),
     }
 }
@@ -213,6 +253,7 @@ fn main() -> Result<()> {
 fn compile_schemas(
     input: &Path,
     output: &Path,
+    import_paths: &[PathBuf],
     verbose: bool,
     filters: &SchemaFilterArgs,
     compiled: &CompiledSchemaFilters,
@@ -226,7 +267,7 @@ fn compile_schemas(
         if verbose {
             println!("Compiling schema file: {}", input.display());
         }
-        compiler::compile(input, output)
+        compiler::compile_with_includes(input, output, import_paths)
             .with_context(|| format!("Failed to compile schema file: {}", input.display()))?;
 
         if verbose {
@@ -250,7 +291,7 @@ fn compile_schemas(
             filters.summary(&included_refs, &skipped_refs);
         }
 
-        compiler::compile_multiple(&schema_files, output.to_path_buf())
+        compiler::compile_multiple_with_includes(&schema_files, output, import_paths)
             .with_context(|| format!("Failed to compile schemas in: {}", input.display()))?;
 
         if verbose {
@@ -268,6 +309,7 @@ fn compile_schemas(
 fn watch_schemas(
     input: &Path,
     output: &Path,
+    import_paths: &[PathBuf],
     verbose: bool,
     filters: &SchemaFilterArgs,
     compiled: &CompiledSchemaFilters,
@@ -284,7 +326,7 @@ fn watch_schemas(
     println!("Press Ctrl+C to stop watching...");
 
     // Initial compilation
-    compile_schemas(input, output, verbose, filters, compiled)?;
+    compile_schemas(input, output, import_paths, verbose, filters, compiled)?;
 
     // Set up file watcher
     let (tx, rx) = mpsc::channel();
@@ -305,7 +347,14 @@ fn watch_schemas(
                     }
 
                     if touched {
-                        match compile_schemas(input, output, verbose, filters, compiled) {
+                        match compile_schemas(
+                            input,
+                            output,
+                            import_paths,
+                            verbose,
+                            filters,
+                            compiled,
+                        ) {
                             Ok(()) => println!("✅ Recompilation successful!"),
                             Err(e) => eprintln!("❌ Recompilation failed: {}", e),
                         }
@@ -345,6 +394,7 @@ fn check_schemas(
             .with_context(|| format!("Failed to read schema file: {}", input.display()))?;
 
         compiler::parse(&content)
+            .map_err(|err| err.in_file(input))
             .with_context(|| format!("Schema validation failed for: {}", input.display()))?;
 
         println!("✅ Schema file is valid: {}", input.display());
@@ -379,6 +429,7 @@ fn check_schemas(
                 .with_context(|| format!("Failed to read schema file: {}", file.display()))?;
 
             compiler::parse(&content)
+                .map_err(|err| err.in_file(file))
                 .with_context(|| format!("Schema validation failed for: {}", file.display()))?;
         }
 
@@ -390,6 +441,109 @@ fn check_schemas(
     Ok(())
 }
 
+/// Format schema files in place, or with `check` just report the ones that need it
+fn format_schemas(
+    input: &Path,
+    check: bool,
+    filters: &SchemaFilterArgs,
+    compiled: &CompiledSchemaFilters,
+) -> Result<()> {
+    let schema_files = if input.is_file() {
+        if !should_process_file(input, filters, compiled) {
+            println!("🚫 Skipping {} (filtered out)", input.display());
+            return Ok(());
+        }
+        vec![input.to_path_buf()]
+    } else if input.is_dir() {
+        collect_schema_files(input, filters, compiled)?.0
+    } else {
+        return Err(eyre!("Input path does not exist: {}", input.display()));
+    };
+
+    let mut changed = Vec::new();
+    for file in &schema_files {
+        let content = fs::read_to_string(file)
+            .with_context(|| format!("Failed to read schema file: {}", file.display()))?;
+
+        let formatted = compiler::formatter::try_format(&content)
+            .map_err(|err| err.in_file(file))
+            .with_context(|| format!("Failed to format: {}", file.display()))?;
+
+        if formatted != content {
+            if !check {
+                fs::write(file, &formatted)
+                    .with_context(|| format!("Failed to write: {}", file.display()))?;
+            }
+            changed.push(file);
+        }
+    }
+
+    if check {
+        if !changed.is_empty() {
+            println!("❌ {} schema files need formatting:", changed.len());
+            for file in &changed {
+                println!("  📝 {}", file.display());
+            }
+            return Err(eyre!("Run `zeroproto fmt` to format them"));
+        }
+        println!("✅ All {} schema files are formatted", schema_files.len());
+    } else {
+        for file in &changed {
+            println!("📝 Formatted {}", file.display());
+        }
+        println!(
+            "✅ Formatted {} of {} schema files",
+            changed.len(),
+            schema_files.len()
+        );
+    }
+
+    Ok(())
+}
+
+/// Compare two schema versions, failing if the changes break compatibility
+fn compat_schemas(old: &Path, new: &Path) -> Result<()> {
+    let old_schema = load_schema(old)?;
+    let new_schema = load_schema(new)?;
+    let report = compiler::compat::check(&old_schema, &new_schema);
+
+    if report.is_empty() {
+        println!("✅ No schema changes");
+        return Ok(());
+    }
+
+    println!("🔍 {} changes:", report.changes.len());
+    for change in &report.changes {
+        let icon = match change.compatibility {
+            Compatibility::Safe => "✅",
+            Compatibility::ForwardOnly | Compatibility::BackwardOnly => "⚠️",
+            Compatibility::Breaking => "❌",
+        };
+        println!("  {} {}", icon, change);
+    }
+
+    let overall = report.overall();
+    if overall == Compatibility::Breaking {
+        return Err(eyre!(
+            "Breaking schema changes from {} to {}",
+            old.display(),
+            new.display()
+        ));
+    }
+
+    println!("Overall: {}", overall);
+    Ok(())
+}
+
+/// Read, parse, and validate a single schema file along with its imports
+fn load_schema(path: &Path) -> Result<Schema> {
+    let mut set = SchemaSet::load(&[path], &[])
+        .with_context(|| format!("Failed to load schema file: {}", path.display()))?;
+    set.validate()
+        .with_context(|| format!("Schema validation failed for: {}", path.display()))?;
+    Ok(set.files.swap_remove(0).schema)
+}
+
 /// Inspect schema structure and print statistics
 fn inspect_schemas(
     input: &Path,
@@ -436,12 +590,15 @@ fn inspect_schemas(
         let content = fs::read_to_string(file)
             .with_context(|| format!("Failed to read schema file: {}", file.display()))?;
         let schema = compiler::parse(&content)
+            .map_err(|err| err.in_file(file))
             .with_context(|| format!("Failed to parse schema: {}", file.display()))?;
         let insight = analyze_schema(&schema);
 
         totals.files += 1;
         totals.messages += insight.messages.len();
         totals.enums += insight.enums.len();
+        totals.unions += insight.unions.len();
+        totals.structs += insight.structs.len();
         totals.fields += insight.total_fields;
         totals.optional_fields += insight.optional_fields;
         totals.defaulted_fields += insight.defaulted_fields;
@@ -449,9 +606,11 @@ fn inspect_schemas(
 
         println!("\n📄 {}", file.display());
         println!(
-            "   Messages: {} | Enums: {}",
+            "   Messages: {} | Enums: {} | Unions: {} | Structs: {}",
             insight.messages.len(),
-            insight.enums.len()
+            insight.enums.len(),
+            insight.unions.len(),
+            insight.structs.len()
         );
         println!(
             "   Fields: {} (optional {}, defaults {}, vectors {})",
@@ -475,6 +634,12 @@ fn inspect_schemas(
             for en in &insight.enums {
                 println!("     • enum {} — variants: {}", en.name, en.variant_count);
             }
+            for un in &insight.unions {
+                println!("     • union {} — variants: {}", un.name, un.variant_count);
+            }
+            for st in &insight.structs {
+                println!("     • struct {} — fields: {}", st.name, st.field_count);
+            }
         }
     }
 
@@ -482,6 +647,8 @@ fn inspect_schemas(
     println!("   Files: {}", totals.files);
     println!("   Messages: {}", totals.messages);
     println!("   Enums: {}", totals.enums);
+    println!("   Unions: {}", totals.unions);
+    println!("   Structs: {}", totals.structs);
     println!(
         "   Fields: {} (optional {}, defaults {}, vectors {})",
         totals.fields, totals.optional_fields, totals.defaulted_fields, totals.vector_fields
@@ -495,6 +662,8 @@ struct AggregateStats {
     files: usize,
     messages: usize,
     enums: usize,
+    unions: usize,
+    structs: usize,
     fields: usize,
     optional_fields: usize,
     defaulted_fields: usize,
@@ -504,6 +673,8 @@ struct AggregateStats {
 struct SchemaInsight {
     messages: Vec<MessageInsight>,
     enums: Vec<EnumInsight>,
+    unions: Vec<UnionInsight>,
+    structs: Vec<StructInsight>,
     total_fields: usize,
     optional_fields: usize,
     defaulted_fields: usize,
@@ -523,9 +694,21 @@ struct EnumInsight {
     variant_count: usize,
 }
 
+struct UnionInsight {
+    name: String,
+    variant_count: usize,
+}
+
+struct StructInsight {
+    name: String,
+    field_count: usize,
+}
+
 fn analyze_schema(schema: &Schema) -> SchemaInsight {
     let mut messages = Vec::new();
     let mut enums = Vec::new();
+    let mut unions = Vec::new();
+    let mut structs = Vec::new();
     let mut total_fields = 0;
     let mut optional_fields = 0;
     let mut defaulted_fields = 0;
@@ -571,12 +754,26 @@ fn analyze_schema(schema: &Schema) -> SchemaInsight {
                     variant_count: en.variants.len(),
                 });
             }
+            SchemaItem::Union(un) => {
+                unions.push(UnionInsight {
+                    name: un.name.clone(),
+                    variant_count: un.variants.len(),
+                });
+            }
+            SchemaItem::Struct(st) => {
+                structs.push(StructInsight {
+                    name: st.name.clone(),
+                    field_count: st.fields.len(),
+                });
+            }
         }
     }
 
     SchemaInsight {
         messages,
         enums,
+        unions,
+        structs,
         total_fields,
         optional_fields,
         defaulted_fields,
@@ -771,7 +968,7 @@ fn collect_schema_files_inner(
         };
         let path = entry.path();
 
-        if path.is_file() && path.extension().map_or(false, |ext| ext == "zp") {
+        if path.is_file() && path.extension().is_some_and(|ext| ext == "zp") {
             if should_process_path(&path, root, filters, compiled) {
                 included.push(path);
             } else {
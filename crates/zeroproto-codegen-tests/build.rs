@@ -0,0 +1,10 @@
+use std::path::PathBuf;
+
+fn main() {
+    let out_dir = PathBuf::from(std::env::var("OUT_DIR").unwrap());
+    for schema in ["schemas/profiles.zp", "schemas/orders.zp"] {
+        zeroproto_compiler::compile(PathBuf::from(schema), out_dir.clone())
+            .expect("Schema should compile");
+    }
+    println!("cargo:rerun-if-changed=schemas");
+}
@@ -0,0 +1,24 @@
+//! Code generated from `schemas/`, built with the workspace so that generated code
+//! which doesn't compile fails the build
+//!
+//! The round trips live in `tests/`.
+
+// Generated code isn't formatted, so blocks share lines
+#[allow(clippy::suspicious_else_formatting, clippy::possible_missing_else)]
+pub mod profiles {
+    include!(concat!(env!("OUT_DIR"), "/profiles.rs"));
+}
+
+/// Packages from `orders.zp` and the `common.zp` it imports, nested the way the
+/// generated `mod.rs` files declare them
+pub mod acme {
+    #[allow(clippy::suspicious_else_formatting, clippy::possible_missing_else)]
+    pub mod common {
+        include!(concat!(env!("OUT_DIR"), "/acme/common.rs"));
+    }
+
+    #[allow(clippy::suspicious_else_formatting, clippy::possible_missing_else)]
+    pub mod orders {
+        include!(concat!(env!("OUT_DIR"), "/acme/orders.rs"));
+    }
+}
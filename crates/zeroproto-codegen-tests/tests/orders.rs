@@ -0,0 +1,222 @@
+//! Round trips through `orders.zp`, which covers enums, unions, structs, `any` fields,
+//! well-known types, constants, aliases and `rust.*` attributes across an import
+
+use std::collections::HashSet;
+
+use zeroproto::any::{AnyMessage, TypeRegistry};
+use zeroproto::{Decimal, Duration, MissingFields, Timestamp, Uuid};
+use zeroproto_codegen_tests::acme::common::{self, *};
+use zeroproto_codegen_tests::acme::orders::{self, *};
+
+fn build_item() -> Vec<u8> {
+    let mut item = ItemBuilder::new();
+    item.set_sku("KB-101").set_comment("gift wrap");
+    item.finish().unwrap()
+}
+
+fn build_order() -> Vec<u8> {
+    let mut pickup = PickupBuilder::new();
+    pickup.set_location(Location {
+        lat: 45.5,
+        lon: -122.6,
+    });
+
+    let mut address = AddressBuilder::new();
+    address.set_street("1 Main St").set_city("Portland");
+
+    let mut order = PurchaseOrderBuilder::new();
+    order
+        .set_order_id(42)
+        .set_stage(Stage::Shipped)
+        .set_item(&build_item())
+        .set_placed_at(Timestamp::new(1_700_000_000, 500).unwrap())
+        .set_window(Duration::new(3600, 0).unwrap())
+        .set_tracking(Uuid::from_bytes([7; 16]))
+        .set_total(Decimal::new(1999, 2).unwrap())
+        .set_route(&[
+            Location { lat: 1.0, lon: 2.0 },
+            Location { lat: 3.0, lon: 4.0 },
+        ]);
+    order.set_destination_pickup(pickup).unwrap();
+    order.set_attachment(address).unwrap();
+    #[cfg(feature = "audit")]
+    order.set_audit("checked");
+    order.finish().unwrap()
+}
+
+#[test]
+fn test_reader_roundtrip() {
+    let data = build_order();
+    let order = PurchaseOrderReader::from_slice(&data).unwrap();
+
+    assert_eq!(order.order_id().unwrap(), 42);
+    assert_eq!(order.stage().unwrap(), Stage::Shipped);
+    assert_eq!(
+        order.placed_at().unwrap(),
+        Timestamp::new(1_700_000_000, 500).unwrap()
+    );
+    assert_eq!(
+        order.window().unwrap(),
+        Some(Duration::new(3600, 0).unwrap())
+    );
+    assert_eq!(order.tracking().unwrap(), Some(Uuid::from_bytes([7; 16])));
+    assert_eq!(order.total().unwrap(), Decimal::new(1999, 2).unwrap());
+    assert_eq!(order.item_limit().unwrap(), MAX_ITEMS);
+    #[cfg(feature = "audit")]
+    assert_eq!(order.audit().unwrap(), "checked");
+
+    // Imported aliases and `rust.name` renames
+    let item = order.item().unwrap();
+    let sku: Sku<'_> = item.sku().unwrap();
+    assert_eq!(sku, "KB-101");
+    let quantity: Quantity = item.quantity().unwrap();
+    assert_eq!(quantity, 1);
+    assert_eq!(item.comment().unwrap(), Some("gift wrap"));
+
+    let route = order.route().unwrap();
+    assert_eq!(route.len(), 2);
+    assert_eq!(
+        route.get(1).unwrap().to_value().unwrap(),
+        Location { lat: 3.0, lon: 4.0 }
+    );
+
+    match order.destination().unwrap() {
+        DestinationReader::Pickup(pickup) => {
+            let location = pickup.location().unwrap();
+            assert_eq!((location.lat(), location.lon()), (45.5, -122.6));
+        }
+        DestinationReader::Delivery(_) => panic!("expected a pickup"),
+    }
+
+    let attachment = order.attachment().unwrap().unwrap();
+    assert_eq!(attachment.type_name(), "acme.common.Address");
+    assert!(!attachment.is::<ItemReader>());
+    let address = attachment.downcast::<AddressReader>().unwrap();
+    assert_eq!(address.city().unwrap(), "Portland");
+
+    let mut registry = TypeRegistry::new();
+    orders::register_types(&mut registry);
+    common::register_types(&mut registry);
+    assert_eq!(
+        registry
+            .resolve(&attachment)
+            .map(|descriptor| descriptor.name),
+        Some("Address")
+    );
+}
+
+#[test]
+fn test_owned_roundtrip() {
+    let data = build_order();
+    let order =
+        PurchaseOrder::from_reader(&PurchaseOrderReader::from_slice(&data).unwrap()).unwrap();
+
+    assert_eq!(order.stage, Stage::Shipped);
+    assert_eq!(
+        order.item,
+        Item {
+            sku: "KB-101".to_string(),
+            quantity: 1,
+            comment: Some("gift wrap".to_string()),
+        }
+    );
+    assert!(
+        matches!(&order.destination, Destination::Pickup(pickup) if pickup.location.lat == 45.5)
+    );
+    let attachment = order.attachment.as_ref().unwrap();
+    assert_eq!(attachment.type_name, "acme.common.Address");
+    assert_eq!(
+        attachment
+            .reader()
+            .unwrap()
+            .downcast::<AddressReader>()
+            .unwrap()
+            .street()
+            .unwrap(),
+        "1 Main St"
+    );
+
+    let encoded = order.encode();
+    let decoded =
+        PurchaseOrder::from_reader(&PurchaseOrderReader::from_slice(&encoded).unwrap()).unwrap();
+    assert_eq!(decoded, order);
+}
+
+#[test]
+fn test_deprecated_fields_are_optional() {
+    // `legacy_ref` has neither `?` nor a default, but it's deprecated, so it can go unset
+    let data = build_order();
+    let order =
+        PurchaseOrder::from_reader(&PurchaseOrderReader::from_slice(&data).unwrap()).unwrap();
+    assert_eq!(order.legacy_ref, "");
+
+    let mut legacy = order.clone();
+    legacy.legacy_ref = "ORD-0042".to_string();
+    let decoded =
+        PurchaseOrder::from_reader(&PurchaseOrderReader::from_slice(&legacy.encode()).unwrap())
+            .unwrap();
+    assert_eq!(decoded.legacy_ref, "ORD-0042");
+}
+
+#[test]
+fn test_owned_default_roundtrip() {
+    let order = PurchaseOrder::default();
+    assert_eq!(order.stage, Stage::Pending);
+    assert_eq!(order.item_limit, MAX_ITEMS);
+    assert_eq!(order.item.quantity, 1);
+    assert_eq!(order.destination, Destination::Pickup(Pickup::default()));
+
+    let decoded =
+        PurchaseOrder::from_reader(&PurchaseOrderReader::from_slice(&order.encode()).unwrap())
+            .unwrap();
+    assert_eq!(decoded, order);
+
+    // A required `any` field defaults to an empty message that still reads back
+    let event = OrderEvent::default();
+    let data = event.encode();
+    let reader = OrderEventReader::from_slice(&data).unwrap();
+    assert_eq!(reader.payload().unwrap().type_name(), "");
+    assert_eq!(OrderEvent::from_reader(&reader).unwrap(), event);
+}
+
+#[test]
+fn test_nested_setters_check_required_fields() {
+    let mut order = PurchaseOrderBuilder::new();
+    let missing = order
+        .set_destination_delivery(DeliveryBuilder::new())
+        .unwrap_err();
+    assert_eq!(
+        missing,
+        MissingFields {
+            message: "Delivery",
+            fields: vec!["address", "fragile"],
+        }
+    );
+    let missing = order.set_attachment(ItemBuilder::new()).unwrap_err();
+    assert_eq!(missing.fields, ["sku"]);
+    assert!(AnyMessage::new(ItemBuilder::new()).is_err());
+
+    // Nothing was written by the failed setters
+    let data = order.finish_unchecked();
+    let order = PurchaseOrderReader::from_slice(&data).unwrap();
+    assert!(order.destination().is_err());
+    assert_eq!(order.attachment().unwrap().map(|any| any.type_name()), None);
+}
+
+#[test]
+fn test_rust_attributes() {
+    // `@rust.derive(Eq, Hash)` on the message and `@rust.derive(PartialOrd)` on the struct
+    let items: HashSet<Item> = [Item::default(), Item::default()].into_iter().collect();
+    assert_eq!(items.len(), 1);
+    assert!(Location { lat: 1.0, lon: 0.0 } > Location { lat: 0.0, lon: 5.0 });
+
+    let mut item = ItemBuilder::new();
+    item.set_sku("A").set_optional_comment(None);
+    let data = item.finish().unwrap();
+    assert_eq!(
+        ItemReader::from_slice(&data).unwrap().comment().unwrap(),
+        None
+    );
+    assert_eq!(ITEM_DESCRIPTOR.type_name, "acme.orders.Item");
+    assert_eq!(PURCHASE_ORDER_DESCRIPTOR.name, "Order");
+}
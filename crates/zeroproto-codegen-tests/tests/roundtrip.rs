@@ -0,0 +1,78 @@
+//! Build, read and convert messages through the generated code
+
+use zeroproto_codegen_tests::profiles::*;
+
+fn build_user() -> Vec<u8> {
+    let mut profile = ProfileBuilder::new();
+    profile.set_bio("Likes zero-copy formats");
+    let profile = profile.finish().unwrap();
+
+    let mut user = UserBuilder::new();
+    user.set_user_id(7)
+        .set_username("alice")
+        .set_profile(&profile)
+        .set_friends(&[2, 3, 5])
+        .set_optional_nickname(Some("ally"));
+    user.finish().unwrap()
+}
+
+#[test]
+fn test_reader_roundtrip() {
+    let data = build_user();
+    let user = UserReader::from_slice(&data).unwrap();
+
+    assert_eq!(user.user_id().unwrap(), 7);
+    assert_eq!(user.username().unwrap(), "alice");
+    assert_eq!(user.nickname().unwrap(), Some("ally"));
+    assert_eq!(user.role().unwrap(), "member");
+    assert_eq!(user.friends().unwrap().collect().unwrap(), [2, 3, 5]);
+
+    let profile = user.profile().unwrap();
+    assert_eq!(profile.bio().unwrap(), "Likes zero-copy formats");
+    assert_eq!(profile.avatar().unwrap(), None);
+}
+
+#[test]
+fn test_owned_roundtrip() {
+    let data = build_user();
+    let user = User::from_reader(&UserReader::from_slice(&data).unwrap()).unwrap();
+
+    let expected = User {
+        user_id: 7,
+        username: "alice".to_string(),
+        profile: Profile {
+            bio: "Likes zero-copy formats".to_string(),
+            avatar: None,
+        },
+        friends: vec![2, 3, 5],
+        nickname: Some("ally".to_string()),
+        role: "member".to_string(),
+    };
+    assert_eq!(user, expected);
+
+    let encoded = user.encode();
+    let decoded = User::from_reader(&UserReader::from_slice(&encoded).unwrap()).unwrap();
+    assert_eq!(decoded, expected);
+}
+
+#[test]
+fn test_owned_default() {
+    let user = User::default();
+    assert_eq!(user.role, "member");
+    assert_eq!(user.profile, Profile::default());
+
+    let decoded = User::from_reader(&UserReader::from_slice(&user.encode()).unwrap()).unwrap();
+    assert_eq!(decoded, user);
+}
+
+#[test]
+fn test_missing_required_fields() {
+    let mut user = UserBuilder::new();
+    user.set_username("alice")
+        .set_optional_nickname(Some("ally"));
+
+    let missing = user.finish().unwrap_err();
+    assert_eq!(missing.message, "User");
+    assert_eq!(missing.fields, ["user_id", "profile", "friends"]);
+    assert!(ProfileBuilder::new().finish().is_err());
+}
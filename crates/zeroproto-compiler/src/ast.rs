@@ -1,11 +1,31 @@
 //! Abstract Syntax Tree for ZeroProto schema language
 
+use crate::diagnostic::Span;
 use std::collections::HashMap;
+use std::fmt;
+
+/// Highest field number a message can use
+///
+/// The runtime reserves `u16::MAX` as a header marker and caps messages at `u16::MAX - 1`
+/// fields, so numbers run from 0 to `u16::MAX - 2`.
+pub const MAX_FIELD_NUMBER: u16 = u16::MAX - 2;
 
 /// A complete ZeroProto schema file
 #[derive(Debug, Clone)]
 pub struct Schema {
+    /// Dotted package name (from `package acme.orders;`)
+    pub package: Option<String>,
+    /// Location of the package name in the source
+    pub package_span: Span,
+    /// Paths of imported schema files, as written
+    pub imports: Vec<String>,
+    /// Location of each import's path in the source, in the order of `imports`
+    pub import_spans: Vec<Span>,
     pub items: Vec<SchemaItem>,
+    /// Named values declared with `const`
+    pub constants: Vec<Constant>,
+    /// Other names for types, declared with `type`
+    pub aliases: Vec<TypeAlias>,
 }
 
 /// Items that can appear in a schema
@@ -13,6 +33,36 @@ pub struct Schema {
 pub enum SchemaItem {
     Message(Message),
     Enum(Enum),
+    Union(Union),
+    Struct(Struct),
+}
+
+/// A named value (`const MAX_FRIENDS: u32 = 500;`)
+#[derive(Debug, Clone)]
+pub struct Constant {
+    pub name: String,
+    /// Declared type; a scalar, or an alias of one
+    pub value_type: FieldType,
+    pub value: DefaultValue,
+    /// Lines of the `///` doc comment written above the constant
+    pub docs: Vec<String>,
+    /// Location of the name in the source
+    pub span: Span,
+    /// Location of the type in the source
+    pub type_span: Span,
+}
+
+/// Another name for a type (`type UserId = u64;`)
+#[derive(Debug, Clone)]
+pub struct TypeAlias {
+    pub name: String,
+    pub target: FieldType,
+    /// Lines of the `///` doc comment written above the alias
+    pub docs: Vec<String>,
+    /// Location of the name in the source
+    pub span: Span,
+    /// Location of the target type in the source
+    pub type_span: Span,
 }
 
 /// A message definition
@@ -20,9 +70,36 @@ pub enum SchemaItem {
 pub struct Message {
     pub name: String,
     pub fields: Vec<Field>,
+    /// Whether the field table is encoded as a presence bitmap (marked with `@bitmap`)
+    pub presence_bitmap: bool,
+    /// Field numbers retired with `reserved`
+    pub reserved_numbers: Vec<u16>,
+    /// Field names retired with `reserved`
+    pub reserved_names: Vec<String>,
+    /// Namespaced attributes such as `@rust.derive(Hash)`
+    pub attributes: Vec<Attribute>,
+    /// Lines of the `///` doc comment written above the message
+    pub docs: Vec<String>,
+    /// Location of the name in the source
+    pub span: Span,
+}
+
+/// A fixed-layout struct of scalars, enums and other structs, stored inline
+#[derive(Debug, Clone)]
+pub struct Struct {
+    pub name: String,
+    /// Fields in layout order; they use the message field syntax, without `?`, defaults
+    /// or field annotations
+    pub fields: Vec<Field>,
+    /// Namespaced attributes such as `@rust.derive(Hash)`
+    pub attributes: Vec<Attribute>,
+    /// Lines of the `///` doc comment written above the struct
+    pub docs: Vec<String>,
+    /// Location of the name in the source
+    pub span: Span,
 }
 
-/// A field in a message
+/// A field in a message or struct
 #[derive(Debug, Clone)]
 pub struct Field {
     pub name: String,
@@ -31,19 +108,66 @@ pub struct Field {
     pub optional: bool,
     /// Default value for this field (if specified with `= value`)
     pub default_value: Option<DefaultValue>,
+    /// Whether a `[bool]` field is bit-packed (marked with `@packed`)
+    pub packed: bool,
+    /// Explicit wire index (written as `@N`)
+    pub number: Option<u16>,
+    /// Deprecation reason (from `@deprecated("reason")`), empty if none was given
+    pub deprecated: Option<String>,
+    /// Namespaced attributes such as `@rust.name("user_id")`
+    pub attributes: Vec<Attribute>,
+    /// Lines of the `///` doc comment written above the field
+    pub docs: Vec<String>,
+    /// Location of the name in the source
+    pub span: Span,
+    /// Location of the type in the source
+    pub type_span: Span,
+}
+
+/// A namespaced `@ns.name(args)` attribute
+///
+/// Attributes in the `rust` namespace are interpreted by the code generator; the rest are
+/// kept as written for plugins.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Attribute {
+    /// Dotted name, e.g. `rust.derive`
+    pub name: String,
+    pub args: Vec<AttributeArg>,
+}
+
+/// One argument of an attribute, optionally named (`feature = "serde"`)
+#[derive(Debug, Clone, PartialEq)]
+pub struct AttributeArg {
+    pub key: Option<String>,
+    pub value: AttributeValue,
+}
+
+/// Values an attribute argument can hold
+#[derive(Debug, Clone, PartialEq)]
+pub enum AttributeValue {
+    /// A bare name or `::`-separated path, e.g. `serde::Serialize`
+    Path(String),
+    String(String),
+    Integer(i64),
+    Float(f64),
+    Bool(bool),
 }
 
 /// Default values that can be assigned to fields
 #[derive(Debug, Clone, PartialEq)]
 pub enum DefaultValue {
     /// Integer default (for u8, u16, u32, u64, i8, i16, i32, i64)
-    Integer(i64),
+    ///
+    /// Wide enough for every value of `u64` and `i64`; the validator checks it fits the field.
+    Integer(i128),
     /// Float default (for f32, f64)
     Float(f64),
     /// Boolean default
     Bool(bool),
     /// String default
     String(String),
+    /// The value of the named constant
+    Constant(String),
 }
 
 /// The type of a field
@@ -70,6 +194,93 @@ pub enum ScalarType {
     Bool,
     String,
     Bytes,
+    Timestamp,
+    Duration,
+    Uuid,
+    Decimal,
+    Any,
+}
+
+impl ScalarType {
+    /// Every scalar type, in type ID order
+    pub const ALL: [ScalarType; 18] = [
+        ScalarType::U8,
+        ScalarType::U16,
+        ScalarType::U32,
+        ScalarType::U64,
+        ScalarType::I8,
+        ScalarType::I16,
+        ScalarType::I32,
+        ScalarType::I64,
+        ScalarType::F32,
+        ScalarType::F64,
+        ScalarType::Bool,
+        ScalarType::String,
+        ScalarType::Bytes,
+        ScalarType::Timestamp,
+        ScalarType::Duration,
+        ScalarType::Uuid,
+        ScalarType::Decimal,
+        ScalarType::Any,
+    ];
+
+    /// Look up a scalar type by its schema name
+    pub fn from_name(name: &str) -> Option<ScalarType> {
+        Self::ALL.into_iter().find(|scalar| scalar.name() == name)
+    }
+
+    /// Name of this type in schema syntax
+    pub fn name(&self) -> &'static str {
+        match self {
+            ScalarType::U8 => "u8",
+            ScalarType::U16 => "u16",
+            ScalarType::U32 => "u32",
+            ScalarType::U64 => "u64",
+            ScalarType::I8 => "i8",
+            ScalarType::I16 => "i16",
+            ScalarType::I32 => "i32",
+            ScalarType::I64 => "i64",
+            ScalarType::F32 => "f32",
+            ScalarType::F64 => "f64",
+            ScalarType::Bool => "bool",
+            ScalarType::String => "string",
+            ScalarType::Bytes => "bytes",
+            ScalarType::Timestamp => "timestamp",
+            ScalarType::Duration => "duration",
+            ScalarType::Uuid => "uuid",
+            ScalarType::Decimal => "decimal",
+            ScalarType::Any => "any",
+        }
+    }
+
+    /// Whether this is one of the well-known types backed by a runtime struct
+    pub fn is_well_known(&self) -> bool {
+        matches!(
+            self,
+            ScalarType::Timestamp | ScalarType::Duration | ScalarType::Uuid | ScalarType::Decimal
+        )
+    }
+}
+
+impl FieldType {
+    /// Name of the message, enum, union or struct this type refers to, looking inside vectors
+    pub fn user_type(&self) -> Option<&str> {
+        match self {
+            FieldType::Scalar(_) => None,
+            FieldType::UserDefined(name) => Some(name),
+            FieldType::Vector(inner) => inner.user_type(),
+        }
+    }
+}
+
+impl fmt::Display for FieldType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FieldType::Scalar(scalar) => f.write_str(scalar.name()),
+            FieldType::UserDefined(name) => f.write_str(name),
+            FieldType::Vector(inner) => write!(f, "[{}]", inner),
+        }
+    }
 }
 
 /// An enum definition
@@ -77,6 +288,10 @@ pub enum ScalarType {
 pub struct Enum {
     pub name: String,
     pub variants: Vec<EnumVariant>,
+    /// Lines of the `///` doc comment written above the enum
+    pub docs: Vec<String>,
+    /// Location of the name in the source
+    pub span: Span,
 }
 
 /// A variant in an enum
@@ -84,12 +299,90 @@ pub struct Enum {
 pub struct EnumVariant {
     pub name: String,
     pub value: Option<i64>,
+    /// Lines of the `///` doc comment written above the variant
+    pub docs: Vec<String>,
+    /// Location of the name in the source
+    pub span: Span,
+}
+
+/// A union definition (exactly one variant is present)
+#[derive(Debug, Clone)]
+pub struct Union {
+    pub name: String,
+    pub variants: Vec<UnionVariant>,
+    /// Lines of the `///` doc comment written above the union
+    pub docs: Vec<String>,
+    /// Location of the name in the source
+    pub span: Span,
+}
+
+/// A variant in a union
+#[derive(Debug, Clone)]
+pub struct UnionVariant {
+    pub name: String,
+    pub variant_type: FieldType,
+    /// Lines of the `///` doc comment written above the variant
+    pub docs: Vec<String>,
+    /// Location of the name in the source
+    pub span: Span,
+    /// Location of the type in the source
+    pub type_span: Span,
+}
+
+impl SchemaItem {
+    /// Name of the type this item defines
+    pub fn name(&self) -> &str {
+        match self {
+            SchemaItem::Message(msg) => &msg.name,
+            SchemaItem::Enum(en) => &en.name,
+            SchemaItem::Union(un) => &un.name,
+            SchemaItem::Struct(st) => &st.name,
+        }
+    }
+
+    /// The keyword that declares this kind of item
+    pub fn keyword(&self) -> &'static str {
+        match self {
+            SchemaItem::Message(_) => "message",
+            SchemaItem::Enum(_) => "enum",
+            SchemaItem::Union(_) => "union",
+            SchemaItem::Struct(_) => "struct",
+        }
+    }
+
+    /// Lines of the item's doc comment
+    pub fn docs(&self) -> &[String] {
+        match self {
+            SchemaItem::Message(msg) => &msg.docs,
+            SchemaItem::Enum(en) => &en.docs,
+            SchemaItem::Union(un) => &un.docs,
+            SchemaItem::Struct(st) => &st.docs,
+        }
+    }
+
+    /// Location of the item's name in the source
+    pub fn span(&self) -> Span {
+        match self {
+            SchemaItem::Message(msg) => msg.span,
+            SchemaItem::Enum(en) => en.span,
+            SchemaItem::Union(un) => un.span,
+            SchemaItem::Struct(st) => st.span,
+        }
+    }
 }
 
 impl Schema {
     /// Create a new empty schema
     pub fn new() -> Self {
-        Self { items: Vec::new() }
+        Self {
+            package: None,
+            package_span: Span::default(),
+            imports: Vec::new(),
+            import_spans: Vec::new(),
+            items: Vec::new(),
+            constants: Vec::new(),
+            aliases: Vec::new(),
+        }
     }
 
     /// Add an item to the schema
@@ -97,6 +390,68 @@ impl Schema {
         self.items.push(item);
     }
 
+    /// Name of every type the schema defines, including aliases
+    pub fn type_names(&self) -> impl Iterator<Item = &str> {
+        self.items
+            .iter()
+            .map(SchemaItem::name)
+            .chain(self.aliases.iter().map(|alias| alias.name.as_str()))
+    }
+
+    /// Location of the name of the type or alias called `name`
+    pub fn type_span(&self, name: &str) -> Option<Span> {
+        self.find_item(name).map(SchemaItem::span).or_else(|| {
+            self.aliases
+                .iter()
+                .find(|alias| alias.name == name)
+                .map(|alias| alias.span)
+        })
+    }
+
+    /// Find the message, enum, union or struct named `name`
+    pub fn find_item(&self, name: &str) -> Option<&SchemaItem> {
+        self.items.iter().find(|item| item.name() == name)
+    }
+
+    /// Find a constant by name
+    pub fn find_constant(&self, name: &str) -> Option<&Constant> {
+        self.constants.iter().find(|constant| constant.name == name)
+    }
+
+    /// Find a type alias by name
+    pub fn find_alias(&self, name: &str) -> Option<&TypeAlias> {
+        self.aliases.iter().find(|alias| alias.name == name)
+    }
+
+    /// Follow this schema's type aliases, inside vectors too, to the types they stand for
+    ///
+    /// Names the schema doesn't alias are kept, and so is an alias that refers to itself.
+    pub fn resolve_alias(&self, field_type: &FieldType) -> FieldType {
+        self.resolve_alias_via(field_type, &mut Vec::new())
+    }
+
+    fn resolve_alias_via<'a>(
+        &'a self,
+        field_type: &FieldType,
+        path: &mut Vec<&'a str>,
+    ) -> FieldType {
+        match field_type {
+            FieldType::UserDefined(name) => match self.find_alias(name) {
+                Some(alias) if !path.contains(&alias.name.as_str()) => {
+                    path.push(&alias.name);
+                    let resolved = self.resolve_alias_via(&alias.target, path);
+                    path.pop();
+                    resolved
+                }
+                _ => field_type.clone(),
+            },
+            FieldType::Vector(inner) => {
+                FieldType::Vector(Box::new(self.resolve_alias_via(inner, path)))
+            }
+            FieldType::Scalar(_) => field_type.clone(),
+        }
+    }
+
     /// Get all message definitions
     pub fn messages(&self) -> impl Iterator<Item = &Message> {
         self.items.iter().filter_map(|item| match item {
@@ -113,6 +468,22 @@ impl Schema {
         })
     }
 
+    /// Get all union definitions
+    pub fn unions(&self) -> impl Iterator<Item = &Union> {
+        self.items.iter().filter_map(|item| match item {
+            SchemaItem::Union(un) => Some(un),
+            _ => None,
+        })
+    }
+
+    /// Get all struct definitions
+    pub fn structs(&self) -> impl Iterator<Item = &Struct> {
+        self.items.iter().filter_map(|item| match item {
+            SchemaItem::Struct(st) => Some(st),
+            _ => None,
+        })
+    }
+
     /// Find a message by name
     pub fn find_message(&self, name: &str) -> Option<&Message> {
         self.messages().find(|msg| msg.name == name)
@@ -123,20 +494,27 @@ impl Schema {
         self.enums().find(|en| en.name == name)
     }
 
+    /// Find a union by name
+    pub fn find_union(&self, name: &str) -> Option<&Union> {
+        self.unions().find(|un| un.name == name)
+    }
+
+    /// Find a struct by name
+    pub fn find_struct(&self, name: &str) -> Option<&Struct> {
+        self.structs().find(|st| st.name == name)
+    }
+
     /// Validate the schema for basic consistency
     pub fn validate_basic(&self) -> Result<(), String> {
         let mut names = HashMap::new();
 
         for item in &self.items {
-            let name = match item {
-                SchemaItem::Message(msg) => &msg.name,
-                SchemaItem::Enum(en) => &en.name,
-            };
+            let name = item.name();
 
             if names.contains_key(name) {
                 return Err(format!("Duplicate name '{}' found", name));
             }
-            names.insert(name.clone(), ());
+            names.insert(name.to_string(), ());
         }
 
         // Validate field types
@@ -146,6 +524,18 @@ impl Schema {
             }
         }
 
+        for union_def in self.unions() {
+            for variant in &union_def.variants {
+                self.validate_field_type(&variant.variant_type)?;
+            }
+        }
+
+        for struct_def in self.structs() {
+            for field in &struct_def.fields {
+                self.validate_field_type(&field.field_type)?;
+            }
+        }
+
         Ok(())
     }
 
@@ -154,7 +544,7 @@ impl Schema {
         match field_type {
             FieldType::Scalar(_) => Ok(()),
             FieldType::UserDefined(name) => {
-                if self.find_message(name).is_none() && self.find_enum(name).is_none() {
+                if self.find_item(name).is_none() && self.find_alias(name).is_none() {
                     Err(format!("Unknown type '{}'", name))
                 } else {
                     Ok(())
@@ -165,12 +555,24 @@ impl Schema {
     }
 }
 
+impl Default for Schema {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Message {
     /// Create a new message
     pub fn new(name: String) -> Self {
         Self {
             name,
             fields: Vec::new(),
+            presence_bitmap: false,
+            reserved_numbers: Vec::new(),
+            reserved_names: Vec::new(),
+            attributes: Vec::new(),
+            docs: Vec::new(),
+            span: Span::default(),
         }
     }
 
@@ -183,6 +585,27 @@ impl Message {
     pub fn find_field(&self, name: &str) -> Option<&Field> {
         self.fields.iter().find(|field| field.name == name)
     }
+
+    /// Find an attribute by its dotted name
+    pub fn attribute(&self, name: &str) -> Option<&Attribute> {
+        self.attributes.iter().find(|attr| attr.name == name)
+    }
+
+    /// Wire index of each field, in declaration order
+    ///
+    /// Fields without an explicit `@N` take the number after the previous field's,
+    /// starting from 0. Numbers are widened so overflow past the limit can be reported.
+    pub fn field_numbers(&self) -> Vec<u32> {
+        let mut next = 0u32;
+        self.fields
+            .iter()
+            .map(|field| {
+                let number = field.number.map(u32::from).unwrap_or(next);
+                next = number + 1;
+                number
+            })
+            .collect()
+    }
 }
 
 impl Field {
@@ -193,6 +616,13 @@ impl Field {
             field_type,
             optional: false,
             default_value: None,
+            packed: false,
+            number: None,
+            deprecated: None,
+            attributes: Vec::new(),
+            docs: Vec::new(),
+            span: Span::default(),
+            type_span: Span::default(),
         }
     }
 
@@ -203,6 +633,13 @@ impl Field {
             field_type,
             optional: true,
             default_value: None,
+            packed: false,
+            number: None,
+            deprecated: None,
+            attributes: Vec::new(),
+            docs: Vec::new(),
+            span: Span::default(),
+            type_span: Span::default(),
         }
     }
 
@@ -213,21 +650,127 @@ impl Field {
             field_type,
             optional: false,
             default_value: Some(default),
+            packed: false,
+            number: None,
+            deprecated: None,
+            attributes: Vec::new(),
+            docs: Vec::new(),
+            span: Span::default(),
+            type_span: Span::default(),
         }
     }
 
+    /// Find an attribute by its dotted name
+    pub fn attribute(&self, name: &str) -> Option<&Attribute> {
+        self.attributes.iter().find(|attr| attr.name == name)
+    }
+
     /// Check if this field has a default value or is optional
     pub fn is_required(&self) -> bool {
         !self.optional && self.default_value.is_none()
     }
 }
 
+impl Attribute {
+    /// Create an attribute without arguments
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            args: Vec::new(),
+        }
+    }
+
+    /// The single string argument, if that's all the attribute has
+    pub fn string_arg(&self) -> Option<&str> {
+        match self.args.as_slice() {
+            [AttributeArg {
+                key: None,
+                value: AttributeValue::String(value),
+            }] => Some(value),
+            _ => None,
+        }
+    }
+
+    /// The arguments in Rust-like syntax, e.g. `Hash, serde::Serialize`
+    pub fn args_to_string(&self) -> String {
+        let args: Vec<_> = self.args.iter().map(ToString::to_string).collect();
+        args.join(", ")
+    }
+}
+
+impl fmt::Display for AttributeArg {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(key) = &self.key {
+            write!(f, "{} = ", key)?;
+        }
+        match &self.value {
+            AttributeValue::Path(path) => f.write_str(path),
+            AttributeValue::String(value) => write!(f, "{:?}", value),
+            AttributeValue::Integer(value) => write!(f, "{}", value),
+            AttributeValue::Float(value) => write!(f, "{:?}", value),
+            AttributeValue::Bool(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+impl fmt::Display for DefaultValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DefaultValue::Integer(value) => write!(f, "{}", value),
+            DefaultValue::Float(value) => write!(f, "{:?}", value),
+            DefaultValue::Bool(value) => write!(f, "{}", value),
+            DefaultValue::String(value) => write!(f, "{:?}", value),
+            DefaultValue::Constant(name) => f.write_str(name),
+        }
+    }
+}
+
+impl fmt::Display for Attribute {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "@{}", self.name)?;
+        if !self.args.is_empty() {
+            write!(f, "({})", self.args_to_string())?;
+        }
+        Ok(())
+    }
+}
+
+impl Struct {
+    /// Create a new struct
+    pub fn new(name: String) -> Self {
+        Self {
+            name,
+            fields: Vec::new(),
+            attributes: Vec::new(),
+            docs: Vec::new(),
+            span: Span::default(),
+        }
+    }
+
+    /// Add a field to the struct
+    pub fn add_field(&mut self, field: Field) {
+        self.fields.push(field);
+    }
+
+    /// Find a field by name
+    pub fn find_field(&self, name: &str) -> Option<&Field> {
+        self.fields.iter().find(|field| field.name == name)
+    }
+
+    /// Find an attribute by its dotted name
+    pub fn attribute(&self, name: &str) -> Option<&Attribute> {
+        self.attributes.iter().find(|attr| attr.name == name)
+    }
+}
+
 impl Enum {
     /// Create a new enum
     pub fn new(name: String) -> Self {
         Self {
             name,
             variants: Vec::new(),
+            docs: Vec::new(),
+            span: Span::default(),
         }
     }
 
@@ -245,7 +788,12 @@ impl Enum {
 impl EnumVariant {
     /// Create a new variant
     pub fn new(name: String) -> Self {
-        Self { name, value: None }
+        Self {
+            name,
+            value: None,
+            docs: Vec::new(),
+            span: Span::default(),
+        }
     }
 
     /// Create a new variant with an explicit value
@@ -253,6 +801,43 @@ impl EnumVariant {
         Self {
             name,
             value: Some(value),
+            docs: Vec::new(),
+            span: Span::default(),
+        }
+    }
+}
+
+impl Union {
+    /// Create a new union
+    pub fn new(name: String) -> Self {
+        Self {
+            name,
+            variants: Vec::new(),
+            docs: Vec::new(),
+            span: Span::default(),
+        }
+    }
+
+    /// Add a variant to the union
+    pub fn add_variant(&mut self, variant: UnionVariant) {
+        self.variants.push(variant);
+    }
+
+    /// Find a variant by name
+    pub fn find_variant(&self, name: &str) -> Option<&UnionVariant> {
+        self.variants.iter().find(|variant| variant.name == name)
+    }
+}
+
+impl UnionVariant {
+    /// Create a new union variant
+    pub fn new(name: String, variant_type: FieldType) -> Self {
+        Self {
+            name,
+            variant_type,
+            docs: Vec::new(),
+            span: Span::default(),
+            type_span: Span::default(),
         }
     }
 }
@@ -274,6 +859,11 @@ impl ScalarType {
             ScalarType::Bool => "bool",
             ScalarType::String => "&'a str",
             ScalarType::Bytes => "&'a [u8]",
+            ScalarType::Timestamp => "zeroproto::Timestamp",
+            ScalarType::Duration => "zeroproto::Duration",
+            ScalarType::Uuid => "zeroproto::Uuid",
+            ScalarType::Decimal => "zeroproto::Decimal",
+            ScalarType::Any => "zeroproto::AnyReader<'a>",
         }
     }
 
@@ -294,6 +884,26 @@ impl ScalarType {
             ScalarType::Bool => PrimitiveType::Bool as u8,
             ScalarType::String => PrimitiveType::String as u8,
             ScalarType::Bytes => PrimitiveType::Bytes as u8,
+            ScalarType::Timestamp => PrimitiveType::Timestamp as u8,
+            ScalarType::Duration => PrimitiveType::Duration as u8,
+            ScalarType::Uuid => PrimitiveType::Uuid as u8,
+            ScalarType::Decimal => PrimitiveType::Decimal as u8,
+            ScalarType::Any => PrimitiveType::Any as u8,
         }
     }
+
+    /// Smallest and largest value of an integer type, or `None` for other scalars
+    pub fn integer_range(&self) -> Option<(i128, i128)> {
+        Some(match self {
+            ScalarType::U8 => (0, u8::MAX.into()),
+            ScalarType::U16 => (0, u16::MAX.into()),
+            ScalarType::U32 => (0, u32::MAX.into()),
+            ScalarType::U64 => (0, u64::MAX.into()),
+            ScalarType::I8 => (i8::MIN.into(), i8::MAX.into()),
+            ScalarType::I16 => (i16::MIN.into(), i16::MAX.into()),
+            ScalarType::I32 => (i32::MIN.into(), i32::MAX.into()),
+            ScalarType::I64 => (i64::MIN.into(), i64::MAX.into()),
+            _ => return None,
+        })
+    }
 }
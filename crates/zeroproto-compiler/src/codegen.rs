@@ -1,15 +1,40 @@
 //! Code generation for ZeroProto schemas
 
+use crate::ast::{Attribute, ScalarType};
 use crate::ir::*;
 use proc_macro2::{Literal, TokenStream};
-use quote::{format_ident, quote};
+use quote::{format_ident, quote, ToTokens};
+use std::collections::{BTreeMap, BTreeSet};
 
 /// Generate Rust code from IR
 pub fn generate_rust_code(ir: &IrSchema) -> crate::Result<String> {
     let mut code = String::new();
 
     // Add imports
-    code.push_str("use zeroproto::*;\n\n");
+    code.push_str("use zeroproto::*;\n");
+    for (module, names) in external_uses(ir) {
+        let path = relative_module_path(&ir.module, &module);
+        let names: Vec<_> = names.into_iter().collect();
+        if names.len() == 1 {
+            code.push_str(&format!("use {}::{};\n", path, names[0]));
+        } else {
+            code.push_str(&format!("use {}::{{{}}};\n", path, names.join(", ")));
+        }
+    }
+    code.push('\n');
+
+    // Constants and aliases, so Rust code can share the schema's names for values and types
+    for constant in &ir.constants {
+        code.push_str(&generate_constant(constant));
+        code.push('\n');
+    }
+    for alias in &ir.aliases {
+        code.push_str(&generate_alias(alias));
+        code.push('\n');
+    }
+    if !ir.constants.is_empty() || !ir.aliases.is_empty() {
+        code.push('\n');
+    }
 
     // Generate enums first
     for enum_def in &ir.enums {
@@ -17,15 +42,277 @@ pub fn generate_rust_code(ir: &IrSchema) -> crate::Result<String> {
         code.push_str("\n\n");
     }
 
+    // Generate structs
+    for struct_def in &ir.structs {
+        code.push_str(&generate_struct(struct_def));
+        code.push_str("\n\n");
+    }
+
+    // Generate unions
+    for union_def in &ir.unions {
+        code.push_str(&generate_union(ir, union_def));
+        code.push_str("\n\n");
+    }
+
     // Generate messages
     for message in &ir.messages {
         code.push_str(&generate_message(ir, message));
         code.push_str("\n\n");
     }
 
+    if !ir.messages.is_empty() {
+        code.push_str(&generate_register_types(ir));
+        code.push('\n');
+    }
+
     Ok(code)
 }
 
+/// Generate `register_types`, which adds this schema's messages to a type registry so
+/// `any` fields holding them can be resolved
+fn generate_register_types(ir: &IrSchema) -> String {
+    let registrations = ir.messages.iter().map(|message| {
+        let descriptor_name = format_ident!("{}", message.descriptor_name);
+        let cfg = cfg_attribute(&message.attributes);
+        quote! {
+            #cfg
+            registry.register(&#descriptor_name);
+        }
+    });
+
+    quote! {
+        /// Register every message type defined by this schema
+        pub fn register_types(registry: &mut zeroproto::any::TypeRegistry) {
+            #(#registrations)*
+        }
+    }
+    .to_string()
+}
+
+/// Names defined in other generated modules that this schema's code refers to
+fn external_uses(ir: &IrSchema) -> BTreeMap<Vec<String>, BTreeSet<String>> {
+    let mut uses: BTreeMap<Vec<String>, BTreeSet<String>> = BTreeMap::new();
+    let mut add = |module: &[String], name: String| {
+        if module != ir.module.as_slice() {
+            uses.entry(module.to_vec()).or_default().insert(name);
+        }
+    };
+
+    for union_def in &ir.unions {
+        for variant in &union_def.variants {
+            add(&variant.module, format!("{}Reader", variant.message_type));
+            add(&variant.module, variant.message_type.clone());
+        }
+    }
+
+    for struct_def in &ir.structs {
+        for field in &struct_def.fields {
+            match &field.field_type {
+                IrFieldType::UserDefined {
+                    type_name,
+                    rust_type,
+                    ..
+                } => add(ir.module_of(type_name), rust_type.clone()),
+                IrFieldType::Struct {
+                    type_name,
+                    rust_type,
+                } => {
+                    add(ir.module_of(type_name), rust_type.clone());
+                    add(ir.module_of(type_name), format!("{}Ref", rust_type));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    for message in &ir.messages {
+        for field in &message.fields {
+            let field_type = match &field.field_type {
+                IrFieldType::Vector { element_type, .. } => element_type.as_ref(),
+                field_type => field_type,
+            };
+
+            match field_type {
+                IrFieldType::UserDefined {
+                    type_name,
+                    rust_type,
+                    is_message,
+                } => {
+                    let module = ir.module_of(type_name);
+                    if *is_message {
+                        add(module, format!("{}Reader", rust_type));
+                        add(module, descriptor_name(rust_type));
+                    }
+                    add(module, rust_type.clone());
+                }
+                IrFieldType::Union {
+                    type_name,
+                    rust_type,
+                } => {
+                    add(ir.module_of(type_name), format!("{}Reader", rust_type));
+                    add(ir.module_of(type_name), rust_type.clone());
+                    let union_def = ir
+                        .find_union(type_name)
+                        .expect("Unknown unions should have been caught by validator");
+                    for variant in &union_def.variants {
+                        add(&variant.module, format!("{}Builder", variant.message_type));
+                        add(&variant.module, descriptor_name(&variant.message_type));
+                    }
+                }
+                IrFieldType::Struct {
+                    type_name,
+                    rust_type,
+                } => {
+                    add(ir.module_of(type_name), rust_type.clone());
+                    add(ir.module_of(type_name), format!("{}Ref", rust_type));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // Aliases name the generated types of their target
+    for alias in &ir.aliases {
+        let target = match &alias.target {
+            IrFieldType::Vector { element_type, .. } => element_type.as_ref(),
+            target => target,
+        };
+        match target {
+            IrFieldType::UserDefined {
+                type_name,
+                rust_type,
+                is_message: true,
+            } => {
+                add(ir.module_of(type_name), format!("{}Reader", rust_type));
+                if !matches!(alias.target, IrFieldType::Vector { .. }) {
+                    add(ir.module_of(type_name), format!("{}Builder", rust_type));
+                    add(ir.module_of(type_name), rust_type.clone());
+                }
+            }
+            IrFieldType::UserDefined {
+                type_name,
+                rust_type,
+                is_message: false,
+            } => add(ir.module_of(type_name), rust_type.clone()),
+            IrFieldType::Union {
+                type_name,
+                rust_type,
+            } => {
+                add(ir.module_of(type_name), format!("{}Reader", rust_type));
+                if !matches!(alias.target, IrFieldType::Vector { .. }) {
+                    add(ir.module_of(type_name), rust_type.clone());
+                }
+            }
+            IrFieldType::Struct {
+                type_name,
+                rust_type,
+            } => {
+                if !matches!(alias.target, IrFieldType::Vector { .. }) {
+                    add(ir.module_of(type_name), rust_type.clone());
+                }
+                add(ir.module_of(type_name), format!("{}Ref", rust_type));
+            }
+            _ => {}
+        }
+    }
+
+    uses
+}
+
+/// Rust path from the module `from` to the module `to`, e.g. `super::users`
+fn relative_module_path(from: &[String], to: &[String]) -> String {
+    let shared = from
+        .iter()
+        .zip(to)
+        .take_while(|(from, to)| from == to)
+        .count();
+
+    let mut segments = vec!["super"; from.len() - shared];
+    if segments.is_empty() {
+        segments.push("self");
+    }
+    segments.extend(to[shared..].iter().map(String::as_str));
+    segments.join("::")
+}
+
+/// Generate a `pub const` holding a schema constant's value
+fn generate_constant(constant: &IrConstant) -> String {
+    let name = format_ident!("{}", constant.rust_name);
+    let rust_type = syn::parse_str::<syn::Type>(&constant.rust_type).unwrap();
+    let value = syn::parse_str::<syn::Expr>(&constant.value).expect("Invalid constant value");
+    let docs = schema_docs(&constant.docs);
+
+    quote! {
+        #docs
+        pub const #name: #rust_type = #value;
+    }
+    .to_string()
+}
+
+/// Generate `pub type` aliases for the types generated for an alias's target
+///
+/// An alias of a message names its owned type, reader and builder, and an alias of a
+/// union names its owned type and reader. An alias of a struct names both its owned type
+/// and its view.
+fn generate_alias(alias: &IrTypeAlias) -> String {
+    let docs = schema_docs(&alias.docs);
+    let name = format_ident!("{}", alias.rust_name);
+    let reader_name = format_ident!("{}Reader", alias.rust_name);
+
+    let code = match &alias.target {
+        IrFieldType::Scalar { rust_type, .. } | IrFieldType::Vector { rust_type, .. } => {
+            let target = syn::parse_str::<syn::Type>(rust_type).unwrap();
+            if rust_type.contains("'a") {
+                quote! { #docs pub type #name<'a> = #target; }
+            } else {
+                quote! { #docs pub type #name = #target; }
+            }
+        }
+        IrFieldType::UserDefined {
+            rust_type,
+            is_message: false,
+            ..
+        } => {
+            let target = format_ident!("{}", rust_type);
+            quote! { #docs pub type #name = #target; }
+        }
+        IrFieldType::UserDefined {
+            rust_type,
+            is_message: true,
+            ..
+        } => {
+            let builder_name = format_ident!("{}Builder", alias.rust_name);
+            let target = format_ident!("{}", rust_type);
+            let target_reader = format_ident!("{}Reader", rust_type);
+            let target_builder = format_ident!("{}Builder", rust_type);
+            quote! {
+                #docs pub type #name = #target;
+                #docs pub type #reader_name<'a> = #target_reader<'a>;
+                #docs pub type #builder_name = #target_builder;
+            }
+        }
+        IrFieldType::Union { rust_type, .. } => {
+            let target = format_ident!("{}", rust_type);
+            let target_reader = format_ident!("{}Reader", rust_type);
+            quote! {
+                #docs pub type #name = #target;
+                #docs pub type #reader_name<'a> = #target_reader<'a>;
+            }
+        }
+        IrFieldType::Struct { rust_type, .. } => {
+            let ref_name = format_ident!("{}Ref", alias.rust_name);
+            let target = format_ident!("{}", rust_type);
+            let target_ref = format_ident!("{}Ref", rust_type);
+            quote! {
+                #docs pub type #name = #target;
+                #docs pub type #ref_name<'a> = #target_ref<'a>;
+            }
+        }
+    };
+
+    code.to_string()
+}
+
 /// Generate code for an enum
 fn generate_enum(enum_def: &IrEnum) -> String {
     let name_ident = format_ident!("{}", enum_def.rust_name);
@@ -35,7 +322,21 @@ fn generate_enum(enum_def: &IrEnum) -> String {
         .map(|variant| {
             let variant_name = format_ident!("{}", variant.rust_name);
             let value = Literal::i64_unsuffixed(variant.value);
-            quote! { #variant_name = #value }
+            let docs = schema_docs(&variant.docs);
+            quote! { #docs #variant_name = #value }
+        })
+        .collect();
+    // The first variant is the default, which owned messages use for enum fields
+    let default_derive = (!variants.is_empty()).then(|| quote! { , Default });
+    let variants: Vec<_> = variants
+        .into_iter()
+        .enumerate()
+        .map(|(i, variant)| {
+            if i == 0 {
+                quote! { #[default] #variant }
+            } else {
+                variant
+            }
         })
         .collect();
 
@@ -60,8 +361,10 @@ fn generate_enum(enum_def: &IrEnum) -> String {
         })
         .collect();
 
+    let docs = schema_docs(&enum_def.docs);
     let code = quote! {
-        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        #docs
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash #default_derive)]
         pub enum #name_ident {
             #(#variants),*
         }
@@ -94,28 +397,662 @@ fn generate_enum(enum_def: &IrEnum) -> String {
     code.to_string()
 }
 
+/// Generate the owned type and zero-copy view for a struct
+fn generate_struct(struct_def: &IrStruct) -> String {
+    let name = format_ident!("{}", struct_def.rust_name);
+    let ref_name = format_ident!("{}", struct_def.ref_name);
+    let size = Literal::usize_unsuffixed(struct_def.size);
+    let derives = extra_derives(
+        &struct_def.attributes,
+        &["Debug", "Clone", "Copy", "PartialEq", "Default"],
+    );
+
+    let mut fields = Vec::new();
+    let mut writes = Vec::new();
+    let mut getters = Vec::new();
+    let mut values = Vec::new();
+    for field in &struct_def.fields {
+        let field_name = format_ident!("{}", field.rust_name);
+        let offset = Literal::usize_unsuffixed(field.offset);
+        let field_docs = schema_docs(&field.docs);
+        let getter_docs = doc_attributes(&format!("Get the `{}` field", field.name), &field.docs);
+
+        let (field_type, getter, value) = match &field.field_type {
+            IrFieldType::Scalar {
+                scalar_type,
+                rust_type,
+                ..
+            } if scalar_type.is_well_known() => {
+                let field_type = syn::parse_str::<syn::Type>(rust_type).unwrap();
+                (
+                    quote! { #field_type },
+                    quote! {
+                        #getter_docs
+                        pub fn #field_name(&self) -> zeroproto::Result<#field_type> {
+                            <#field_type as ZpRead>::read(self.bytes, #offset)
+                        }
+                    },
+                    quote! { #field_name: self.#field_name()? },
+                )
+            }
+            IrFieldType::Scalar {
+                scalar_type,
+                rust_type,
+                ..
+            } => {
+                let field_type = format_ident!("{}", rust_type);
+                let read = format_ident!("read_{}", scalar_type.name());
+                (
+                    quote! { #field_type },
+                    quote! {
+                        #getter_docs
+                        pub fn #field_name(&self) -> #field_type {
+                            Endian::Little.#read(self.bytes, #offset)
+                        }
+                    },
+                    quote! { #field_name: self.#field_name() },
+                )
+            }
+            IrFieldType::UserDefined {
+                rust_type,
+                is_message: false,
+                ..
+            } => {
+                let field_type = format_ident!("{}", rust_type);
+                (
+                    quote! { #field_type },
+                    quote! {
+                        #getter_docs
+                        pub fn #field_name(&self) -> zeroproto::Result<#field_type> {
+                            #field_type::read(self.bytes, #offset)
+                        }
+                    },
+                    quote! { #field_name: self.#field_name()? },
+                )
+            }
+            IrFieldType::Struct { rust_type, .. } => {
+                let field_type = format_ident!("{}", rust_type);
+                let field_ref = format_ident!("{}Ref", rust_type);
+                (
+                    quote! { #field_type },
+                    quote! {
+                        #getter_docs
+                        pub fn #field_name(&self) -> zeroproto::Result<#field_ref<'a>> {
+                            #field_ref::read(self.bytes, #offset)
+                        }
+                    },
+                    quote! { #field_name: self.#field_name()?.to_value()? },
+                )
+            }
+            _ => panic!("Variable-size struct fields should have been caught by validator"),
+        };
+
+        fields.push(quote! { #field_docs pub #field_name: #field_type });
+        let at = if field.offset == 0 {
+            quote! { offset }
+        } else {
+            quote! { offset + #offset }
+        };
+        writes.push(quote! { self.#field_name.write(buf, #at)?; });
+        getters.push(getter);
+        values.push(value);
+    }
+
+    let docs = schema_docs(&struct_def.docs);
+    let ref_docs = doc_attributes(
+        &format!("Zero-copy view of a `{}` struct", struct_def.name),
+        &struct_def.docs,
+    );
+    let code = quote! {
+        #docs
+        #[derive(Debug, Clone, Copy, PartialEq, Default #(, #derives)*)]
+        pub struct #name {
+            #(#fields),*
+        }
+
+        impl #name {
+            /// Encoded size in bytes
+            pub const SIZE: usize = #size;
+        }
+
+        impl<'a> ZpRead<'a> for #name {
+            fn read(buf: &'a [u8], offset: usize) -> zeroproto::Result<Self> {
+                #ref_name::read(buf, offset)?.to_value()
+            }
+
+            fn size() -> usize {
+                Self::SIZE
+            }
+        }
+
+        impl ZpWrite for #name {
+            fn write(&self, buf: &mut [u8], offset: usize) -> zeroproto::Result<()> {
+                if offset + Self::SIZE > buf.len() {
+                    return Err(zeroproto::Error::OutOfBounds);
+                }
+                #(#writes)*
+                Ok(())
+            }
+
+            fn size(&self) -> usize {
+                Self::SIZE
+            }
+        }
+
+        #ref_docs
+        #[derive(Debug, Clone, Copy)]
+        pub struct #ref_name<'a> {
+            bytes: &'a [u8],
+        }
+
+        impl<'a> ZpRead<'a> for #ref_name<'a> {
+            fn read(buf: &'a [u8], offset: usize) -> zeroproto::Result<Self> {
+                let bytes = buf
+                    .get(offset..offset + #name::SIZE)
+                    .ok_or(zeroproto::Error::OutOfBounds)?;
+                Ok(Self { bytes })
+            }
+
+            fn size() -> usize {
+                #name::SIZE
+            }
+        }
+
+        impl<'a> #ref_name<'a> {
+            #(#getters)*
+
+            /// Copy every field into an owned value
+            pub fn to_value(&self) -> zeroproto::Result<#name> {
+                Ok(#name {
+                    #(#values),*
+                })
+            }
+
+            /// The struct's encoded bytes
+            pub fn as_bytes(&self) -> &'a [u8] {
+                self.bytes
+            }
+        }
+    };
+
+    code.to_string()
+}
+
+/// Generate code for a union
+fn generate_union(ir: &IrSchema, union_def: &IrUnion) -> String {
+    let reader_name = format_ident!("{}", union_def.reader_name);
+
+    let variants: Vec<_> = union_def
+        .variants
+        .iter()
+        .map(|variant| {
+            let variant_name = format_ident!("{}", variant.rust_name);
+            let variant_reader = format_ident!("{}Reader", variant.message_type);
+            let docs = schema_docs(&variant.docs);
+            quote! { #docs #variant_name(#variant_reader<'a>) }
+        })
+        .collect();
+
+    let from_arms: Vec<_> = union_def
+        .variants
+        .iter()
+        .map(|variant| {
+            let variant_name = format_ident!("{}", variant.rust_name);
+            let variant_reader = format_ident!("{}Reader", variant.message_type);
+            let discriminant = variant.discriminant;
+            quote! { #discriminant => Ok(#reader_name::#variant_name(#variant_reader::new(reader))) }
+        })
+        .collect();
+
+    let discriminant_arms: Vec<_> = union_def
+        .variants
+        .iter()
+        .map(|variant| {
+            let variant_name = format_ident!("{}", variant.rust_name);
+            let discriminant = variant.discriminant;
+            quote! { #reader_name::#variant_name(_) => #discriminant }
+        })
+        .collect();
+
+    let owned = generate_owned_union(ir, union_def);
+    let docs = doc_attributes(
+        &format!(
+            "Zero-copy reader for the active variant of a `{}` union",
+            union_def.name
+        ),
+        &union_def.docs,
+    );
+    let code = quote! {
+        #owned
+
+        #docs
+        #[derive(Debug)]
+        pub enum #reader_name<'a> {
+            #(#variants),*
+        }
+
+        impl<'a> #reader_name<'a> {
+            /// Create a union reader from its discriminant and variant message
+            pub fn from_parts(discriminant: u16, reader: MessageReader<'a>) -> zeroproto::Result<Self> {
+                match discriminant {
+                    #(
+                        #from_arms,
+                    )*
+                    _ => Err(zeroproto::Error::InvalidFieldType),
+                }
+            }
+
+            /// Get the discriminant of the active variant
+            pub fn discriminant(&self) -> u16 {
+                match self {
+                    #(
+                        #discriminant_arms,
+                    )*
+                }
+            }
+        }
+    };
+
+    code.to_string()
+}
+
+/// Generate the owned enum for a union, holding an owned copy of the active variant
+fn generate_owned_union(ir: &IrSchema, union_def: &IrUnion) -> TokenStream {
+    let name = format_ident!("{}", union_def.rust_name);
+    let reader_name = format_ident!("{}", union_def.reader_name);
+
+    let mut variants = Vec::new();
+    let mut from_arms = Vec::new();
+    let mut discriminant_arms = Vec::new();
+    let mut encode_arms = Vec::new();
+    for variant in &union_def.variants {
+        let variant_name = format_ident!("{}", variant.rust_name);
+        let message = format_ident!("{}", variant.message_type);
+        let discriminant = variant.discriminant;
+        let docs = schema_docs(&variant.docs);
+
+        if is_recursive(ir, &variant.message_type, &union_def.rust_name) {
+            variants.push(quote! { #docs #variant_name(Box<#message>) });
+            from_arms.push(quote! {
+                #reader_name::#variant_name(variant) => #name::#variant_name(Box::new(#message::from_reader(variant)?))
+            });
+        } else {
+            variants.push(quote! { #docs #variant_name(#message) });
+            from_arms.push(quote! {
+                #reader_name::#variant_name(variant) => #name::#variant_name(#message::from_reader(variant)?)
+            });
+        }
+        discriminant_arms.push(quote! { #name::#variant_name(_) => #discriminant });
+        encode_arms.push(quote! { #name::#variant_name(variant) => variant.encode() });
+    }
+
+    let first_variant = format_ident!("{}", union_def.variants[0].rust_name);
+    let docs = doc_attributes(
+        &format!(
+            "Owned copy of the active variant of a `{}` union",
+            union_def.name
+        ),
+        &union_def.docs,
+    );
+    quote! {
+        #docs
+        #[derive(Debug, Clone, PartialEq)]
+        pub enum #name {
+            #(#variants),*
+        }
+
+        impl Default for #name {
+            /// The first variant, holding a default message
+            fn default() -> Self {
+                #name::#first_variant(Default::default())
+            }
+        }
+
+        impl #name {
+            /// Copy the active variant out of a union reader
+            pub fn from_reader(reader: &#reader_name<'_>) -> zeroproto::Result<Self> {
+                Ok(match reader {
+                    #(
+                        #from_arms,
+                    )*
+                })
+            }
+
+            /// Get the discriminant of the active variant
+            pub fn discriminant(&self) -> u16 {
+                match self {
+                    #(
+                        #discriminant_arms,
+                    )*
+                }
+            }
+
+            /// Encode the active variant's message
+            pub fn encode(&self) -> Vec<u8> {
+                match self {
+                    #(
+                        #encode_arms,
+                    )*
+                }
+            }
+        }
+    }
+}
+
+/// Whether the message or union `target` refers back to `owner` within this schema,
+/// so holding it inline would give `owner` an infinite size
+///
+/// Vectors hold their elements on the heap, so they don't count.
+fn is_recursive(ir: &IrSchema, target: &str, owner: &str) -> bool {
+    let mut stack = vec![target.to_string()];
+    let mut seen = BTreeSet::new();
+    while let Some(name) = stack.pop() {
+        if name == owner {
+            return true;
+        }
+        if !seen.insert(name.clone()) {
+            continue;
+        }
+
+        if let Some(message) = ir.messages.iter().find(|msg| msg.rust_name == name) {
+            for field in &message.fields {
+                match &field.field_type {
+                    IrFieldType::UserDefined {
+                        rust_type,
+                        is_message: true,
+                        ..
+                    }
+                    | IrFieldType::Union { rust_type, .. } => stack.push(rust_type.clone()),
+                    _ => {}
+                }
+            }
+        } else if let Some(union_def) = ir.unions.iter().find(|un| un.rust_name == name) {
+            stack.extend(
+                union_def
+                    .variants
+                    .iter()
+                    .map(|variant| variant.message_type.clone()),
+            );
+        }
+    }
+    false
+}
+
 /// Generate code for a message
 fn generate_message(ir: &IrSchema, message: &IrMessage) -> String {
     let reader_code = generate_reader(ir, message);
     let builder_code = generate_builder(ir, message);
-    let constants_code = generate_constants(message);
+    let owned_code = generate_owned_message(ir, message);
 
-    format!("{}\n\n{}\n\n{}", constants_code, reader_code, builder_code)
+    apply_message_attributes(
+        message,
+        format!("{}\n\n{}\n\n{}", reader_code, builder_code, owned_code),
+    )
 }
 
-/// Generate field offset constants
-fn generate_constants(message: &IrMessage) -> String {
-    let offsets = IrUtils::generate_field_offsets(message);
-    let mut code = String::new();
+/// Generate the owned type for a message, with conversions from its reader and to bytes
+fn generate_owned_message(ir: &IrSchema, message: &IrMessage) -> String {
+    let name = format_ident!("{}", message.rust_name);
+    let reader_name = format_ident!("{}", message.reader_name);
+    let builder_name = format_ident!("{}", message.builder_name);
 
-    for (constant_name, offset) in offsets {
-        code.push_str(&format!(
-            "pub const {}: usize = {};\n",
-            constant_name, offset
-        ));
+    let mut fields = Vec::new();
+    let mut defaults = Vec::new();
+    let mut reads = Vec::new();
+    let mut writes = Vec::new();
+    for field in &message.fields {
+        let field_name = format_ident!("{}", field.rust_name);
+        let field_index = field.index;
+        let cfg = cfg_attribute(&field.attributes);
+        let docs = schema_docs(&field.docs);
+        let (value_type, read, write) = owned_value(ir, message, field, &field.field_type);
+
+        if field.optional {
+            fields.push(quote! { #docs #cfg pub #field_name: Option<#value_type> });
+            defaults.push(quote! { #cfg #field_name: None });
+            reads.push(quote! {
+                #cfg #field_name: if reader.has_field(#field_index)? { Some(#read) } else { None }
+            });
+            writes.push(quote! {
+                #cfg
+                if let Some(value) = &self.#field_name {
+                    #write
+                }
+            });
+            continue;
+        }
+
+        let default = field.default_value.as_ref().map(|value| {
+            let value = syn::parse_str::<syn::Expr>(value).expect("Invalid default value");
+            match &field.field_type {
+                IrFieldType::Scalar {
+                    scalar_type: ScalarType::String,
+                    ..
+                } => quote! { #value.into() },
+                _ => quote! { #value },
+            }
+        });
+        fields.push(quote! { #docs #cfg pub #field_name: #value_type });
+        match default {
+            Some(default) => {
+                defaults.push(quote! { #cfg #field_name: #default });
+                reads.push(quote! {
+                    #cfg #field_name: if reader.has_field(#field_index)? { #read } else { #default }
+                });
+            }
+            // Deprecated fields aren't required, so they may be missing
+            None if field.deprecated.is_some() => {
+                defaults.push(quote! { #cfg #field_name: Default::default() });
+                reads.push(quote! {
+                    #cfg #field_name: if reader.has_field(#field_index)? { #read } else { Default::default() }
+                });
+            }
+            None => {
+                defaults.push(quote! { #cfg #field_name: Default::default() });
+                reads.push(quote! { #cfg #field_name: #read });
+            }
+        }
+        writes.push(quote! {
+            #cfg
+            {
+                let value = &self.#field_name;
+                #write
+            }
+        });
+    }
+
+    let derives = extra_derives(
+        &message.attributes,
+        &["Debug", "Clone", "PartialEq", "Default"],
+    );
+    let docs = doc_attributes(
+        &format!("Owned copy of a `{}` message", message.name),
+        &message.docs,
+    );
+    quote! {
+        #docs
+        #[derive(Debug, Clone, PartialEq #(, #derives)*)]
+        pub struct #name {
+            #(#fields),*
+        }
+
+        impl Default for #name {
+            /// Every field at its schema default, or its type's default if it has none
+            fn default() -> Self {
+                Self {
+                    #(#defaults),*
+                }
+            }
+        }
+
+        impl #name {
+            /// Copy every field out of a reader
+            pub fn from_reader(reader: &#reader_name<'_>) -> zeroproto::Result<Self> {
+                let reader = &reader.reader;
+                Ok(Self {
+                    #(#reads),*
+                })
+            }
+
+            /// Encode this message
+            pub fn encode(&self) -> Vec<u8> {
+                let mut message = #builder_name::new();
+                let builder = &mut message.builder;
+                #(#writes)*
+                // Every field without a default is written, so none can be missing
+                message.finish_unchecked()
+            }
+        }
+    }
+    .to_string()
+}
+
+/// Owned type of a message field, with an expression that reads it from `reader` and
+/// statements that write `value` to `builder`
+fn owned_value(
+    ir: &IrSchema,
+    message: &IrMessage,
+    field: &IrField,
+    field_type: &IrFieldType,
+) -> (TokenStream, TokenStream, TokenStream) {
+    let field_index = field.index;
+
+    match field_type {
+        IrFieldType::Scalar {
+            scalar_type: ScalarType::String,
+            ..
+        } => (
+            quote! { String },
+            quote! { reader.get_string(#field_index)?.into() },
+            quote! { builder.set_string(#field_index, value).unwrap(); },
+        ),
+        IrFieldType::Scalar {
+            scalar_type: ScalarType::Bytes,
+            ..
+        } => (
+            quote! { Vec<u8> },
+            quote! { reader.get_bytes(#field_index)?.into() },
+            quote! { builder.set_bytes(#field_index, value).unwrap(); },
+        ),
+        IrFieldType::Scalar {
+            scalar_type: ScalarType::Any,
+            ..
+        } => (
+            quote! { zeroproto::any::AnyMessage },
+            quote! { reader.get_any(#field_index)?.into() },
+            quote! { builder.set_any(#field_index, &value.type_name, &value.message).unwrap(); },
+        ),
+        IrFieldType::Scalar { rust_type, .. } => {
+            let value_type = syn::parse_str::<syn::Type>(rust_type).unwrap();
+            (
+                quote! { #value_type },
+                quote! { reader.get_scalar(#field_index)? },
+                quote! { builder.set_scalar(#field_index, *value).unwrap(); },
+            )
+        }
+        IrFieldType::UserDefined {
+            rust_type,
+            is_message: false,
+            ..
+        } => {
+            // Enums are stored as their u64 value
+            let value_type = format_ident!("{}", rust_type);
+            (
+                quote! { #value_type },
+                quote! { #value_type::read(&reader.get_scalar::<u64>(#field_index)?.to_le_bytes(), 0)? },
+                quote! { builder.set_scalar(#field_index, *value as u64).unwrap(); },
+            )
+        }
+        IrFieldType::UserDefined {
+            rust_type,
+            is_message: true,
+            ..
+        } => {
+            let value_type = format_ident!("{}", rust_type);
+            let reader_type = format_ident!("{}Reader", rust_type);
+            let read = quote! {
+                #value_type::from_reader(&#reader_type::new(reader.get_message(#field_index)?))?
+            };
+            let write = quote! { builder.set_message(#field_index, &value.encode()).unwrap(); };
+            if is_recursive(ir, rust_type, &message.rust_name) {
+                (
+                    quote! { Box<#value_type> },
+                    quote! { Box::new(#read) },
+                    write,
+                )
+            } else {
+                (quote! { #value_type }, read, write)
+            }
+        }
+        IrFieldType::Union { rust_type, .. } => {
+            let value_type = format_ident!("{}", rust_type);
+            let reader_type = format_ident!("{}Reader", rust_type);
+            let read = quote! {
+                {
+                    let (discriminant, variant) = reader.get_union(#field_index)?;
+                    #value_type::from_reader(&#reader_type::from_parts(discriminant, variant)?)?
+                }
+            };
+            let write = quote! {
+                builder.set_union(#field_index, value.discriminant(), &value.encode()).unwrap();
+            };
+            if is_recursive(ir, rust_type, &message.rust_name) {
+                (
+                    quote! { Box<#value_type> },
+                    quote! { Box::new(#read) },
+                    write,
+                )
+            } else {
+                (quote! { #value_type }, read, write)
+            }
+        }
+        IrFieldType::Struct { rust_type, .. } => {
+            let value_type = format_ident!("{}", rust_type);
+            (
+                quote! { #value_type },
+                quote! { reader.get_struct(#field_index)? },
+                quote! { builder.set_struct(#field_index, value).unwrap(); },
+            )
+        }
+        IrFieldType::Vector { .. } if field.packed => (
+            quote! { Vec<bool> },
+            quote! { reader.get_bit_vector(#field_index)?.iter().collect() },
+            quote! { builder.set_bit_vector(#field_index, value).unwrap(); },
+        ),
+        IrFieldType::Vector { element_type, .. } => {
+            let write = match element_type.as_ref() {
+                IrFieldType::Struct { .. } => {
+                    quote! { builder.set_struct_vector(#field_index, value).unwrap(); }
+                }
+                _ => quote! { builder.set_vector(#field_index, value).unwrap(); },
+            };
+            // Elements live on the heap already, so recursive ones needn't be boxed
+            let element_type = match element_type.as_ref() {
+                IrFieldType::UserDefined { rust_type, .. }
+                | IrFieldType::Struct { rust_type, .. } => {
+                    let element_type = format_ident!("{}", rust_type);
+                    quote! { #element_type }
+                }
+                element_type => owned_value(ir, message, field, element_type).0,
+            };
+            (
+                quote! { Vec<#element_type> },
+                quote! { reader.get_vector(#field_index)?.collect()? },
+                write,
+            )
+        }
     }
+}
 
-    code
+/// Generate field offset constants
+fn generate_constants(message: &IrMessage) -> Vec<TokenStream> {
+    IrUtils::generate_field_offsets(message)
+        .into_iter()
+        .map(|(constant_name, offset)| {
+            let constant_name = format_ident!("{}", constant_name);
+            quote! { pub const #constant_name: usize = #offset; }
+        })
+        .collect()
 }
 
 /// Generate reader code for a message
@@ -130,27 +1067,158 @@ fn generate_reader(ir: &IrSchema, message: &IrMessage) -> String {
 
     let new_method = generate_reader_new_method(message);
     let from_slice_method = generate_reader_from_slice_method(message);
+    let descriptor = generate_descriptor(ir, message);
+    let descriptor_name = format_ident!("{}", message.descriptor_name);
+    let field_count = message.field_count;
+    let field_numbers = fields.iter().map(|field| field.index);
+    let offsets = generate_constants(message);
+    let type_name = &message.type_name;
 
     let code = quote! {
+        #descriptor
+
         #new_method
 
         #from_slice_method
 
+        impl<'a> zeroproto::any::TypedReader<'a> for #reader_name<'a> {
+            const TYPE_NAME: &'static str = #type_name;
+
+            fn from_message(reader: MessageReader<'a>) -> Self {
+                Self::new(reader)
+            }
+        }
+
         impl<'a> #reader_name<'a> {
+            /// One past the highest field index this schema knows about
+            pub const FIELD_COUNT: u16 = #field_count;
+
+            /// Field indices this schema knows about, in declaration order
+            pub const FIELD_NUMBERS: &'static [u16] = &[#(#field_numbers),*];
+
+            #(#offsets)*
+
             #(#field_methods)*
+
+            /// Get the schema descriptor for this message type
+            pub fn descriptor() -> &'static MessageDescriptor {
+                &#descriptor_name
+            }
+
+            /// Compare this message with another, naming changed fields from the schema
+            pub fn diff(&self, other: &Self) -> zeroproto::Result<zeroproto::diff::Diff<'a>> {
+                zeroproto::diff::diff_with_schema(&self.reader, &other.reader, &#descriptor_name)
+            }
         }
     };
 
     code.to_string()
 }
 
+/// Generate the static schema descriptor for a message
+fn generate_descriptor(ir: &IrSchema, message: &IrMessage) -> TokenStream {
+    let descriptor_name = format_ident!("{}", message.descriptor_name);
+    let message_name = &message.name;
+    let type_name = &message.type_name;
+
+    // Descriptor of the message type with the given Rust name
+    let descriptor_of =
+        |rust_name: &str| match ir.messages.iter().find(|msg| msg.rust_name == rust_name) {
+            Some(target) => format_ident!("{}", target.descriptor_name),
+            None => format_ident!("{}", crate::ir::descriptor_name(rust_name)),
+        };
+
+    let fields: Vec<_> = message
+        .fields
+        .iter()
+        .map(|field| {
+            let field_name = &field.name;
+            let field_index = field.index;
+
+            let message_type = match &field.field_type {
+                IrFieldType::UserDefined {
+                    rust_type,
+                    is_message: true,
+                    ..
+                } => Some(rust_type),
+                IrFieldType::Vector { element_type, .. } => match element_type.as_ref() {
+                    IrFieldType::UserDefined {
+                        rust_type,
+                        is_message: true,
+                        ..
+                    } => Some(rust_type),
+                    _ => None,
+                },
+                _ => None,
+            };
+            let nested = match message_type {
+                Some(rust_type) => {
+                    let nested = descriptor_of(rust_type);
+                    quote! { Some(&#nested) }
+                }
+                None => quote! { None },
+            };
+
+            let variants: Vec<_> = match &field.field_type {
+                IrFieldType::Union { type_name, .. } => ir
+                    .find_union(type_name)
+                    .expect("Unknown unions should have been caught by validator")
+                    .variants
+                    .iter()
+                    .map(|variant| {
+                        let variant_name = &variant.name;
+                        let discriminant = variant.discriminant;
+                        let variant_message = descriptor_of(&variant.message_type);
+                        quote! {
+                            VariantDescriptor {
+                                name: #variant_name,
+                                discriminant: #discriminant,
+                                message: &#variant_message,
+                            }
+                        }
+                    })
+                    .collect(),
+                _ => Vec::new(),
+            };
+
+            quote! {
+                FieldDescriptor {
+                    name: #field_name,
+                    index: #field_index,
+                    message: #nested,
+                    variants: &[#(#variants),*],
+                }
+            }
+        })
+        .collect();
+
+    let docs = doc_attributes(
+        &format!("Schema descriptor for `{}` messages", message.name),
+        &[],
+    );
+
+    quote! {
+        #docs
+        pub static #descriptor_name: MessageDescriptor = MessageDescriptor {
+            name: #message_name,
+            type_name: #type_name,
+            fields: &[#(#fields),*],
+        };
+    }
+}
+
 /// Generate the new() method for readers
 fn generate_reader_new_method(message: &IrMessage) -> TokenStream {
     let reader_name = format_ident!("{}", message.reader_name);
 
+    let docs = doc_attributes(
+        &format!("Zero-copy reader for `{}` messages", message.name),
+        &message.docs,
+    );
+
     quote! {
-        /// Zero-copy reader for #reader_name messages
-        #[derive(Debug)]
+        #docs
+        #[derive(Debug, Clone, Copy)]
         pub struct #reader_name<'a> {
             reader: MessageReader<'a>,
         }
@@ -196,8 +1264,9 @@ fn generate_reader_field_method(_ir: &IrSchema, field: &IrField) -> TokenStream
 
     if field.optional {
         let has_method_name = format_ident!("has_{}", field.rust_name);
+        let docs = field_docs(field, "Check if the {} field is present");
         methods.push(quote! {
-            /// Check if the #method_name field is present
+            #docs
             pub fn #has_method_name(&self) -> zeroproto::Result<bool> {
                 self.reader.has_field(#field_index)
             }
@@ -205,30 +1274,65 @@ fn generate_reader_field_method(_ir: &IrSchema, field: &IrField) -> TokenStream
     }
 
     let field_method = match &field.field_type {
-        IrFieldType::Scalar { rust_type, .. } => {
+        IrFieldType::Scalar {
+            scalar_type: ScalarType::Any,
+            ..
+        } => {
+            if field.optional {
+                let docs = field_docs(field, "Get the {} field (if present)");
+                quote! {
+                    #docs
+                    pub fn #method_name(&self) -> zeroproto::Result<Option<zeroproto::AnyReader<'a>>> {
+                        self.reader.try_get_any(#field_index)
+                    }
+                }
+            } else {
+                let docs = field_docs(field, "Get the {} field");
+                quote! {
+                    #docs
+                    pub fn #method_name(&self) -> zeroproto::Result<zeroproto::AnyReader<'a>> {
+                        self.reader.get_any(#field_index)
+                    }
+                }
+            }
+        }
+        IrFieldType::Scalar {
+            scalar_type,
+            rust_type,
+            ..
+        } => {
             let return_type = syn::parse_str::<syn::Type>(rust_type).unwrap();
+            // Strings and bytes borrow from the buffer rather than implementing `ZpRead`
+            let (get, try_get) = match scalar_type {
+                ScalarType::String => (quote! { get_string }, quote! { try_get_string }),
+                ScalarType::Bytes => (quote! { get_bytes }, quote! { try_get_bytes }),
+                _ => (quote! { get_scalar }, quote! { try_get_scalar }),
+            };
             if field.optional {
+                let docs = field_docs(field, "Get the {} field (if present)");
                 quote! {
-                    /// Get the #method_name field (if present)
+                    #docs
                     pub fn #method_name(&self) -> zeroproto::Result<Option<#return_type>> {
-                        self.reader.try_get_scalar(#field_index)
+                        self.reader.#try_get(#field_index)
                     }
                 }
             } else if let Some(default_expr) = default_expr.clone() {
+                let docs = field_docs(field, "Get the {} field (or its default)");
                 quote! {
-                    /// Get the #method_name field (or its default)
+                    #docs
                     pub fn #method_name(&self) -> zeroproto::Result<#return_type> {
-                        match self.reader.try_get_scalar(#field_index)? {
+                        match self.reader.#try_get(#field_index)? {
                             Some(value) => Ok(value),
                             None => Ok(#default_expr),
                         }
                     }
                 }
             } else {
+                let docs = field_docs(field, "Get the {} field");
                 quote! {
-                    /// Get the #method_name field
+                    #docs
                     pub fn #method_name(&self) -> zeroproto::Result<#return_type> {
-                        self.reader.get_scalar(#field_index)
+                        self.reader.#get(#field_index)
                     }
                 }
             }
@@ -241,8 +1345,9 @@ fn generate_reader_field_method(_ir: &IrSchema, field: &IrField) -> TokenStream
             if *is_message {
                 let reader_type = format_ident!("{}Reader", rust_type);
                 if field.optional {
+                    let docs = field_docs(field, "Get the {} field (if present)");
                     quote! {
-                        /// Get the #method_name field (if present)
+                        #docs
                         pub fn #method_name(&self) -> zeroproto::Result<Option<#reader_type<'a>>> {
                             match self.reader.try_get_message(#field_index)? {
                                 Some(message_reader) => Ok(Some(#reader_type::new(message_reader))),
@@ -251,8 +1356,9 @@ fn generate_reader_field_method(_ir: &IrSchema, field: &IrField) -> TokenStream
                         }
                     }
                 } else {
+                    let docs = field_docs(field, "Get the {} field");
                     quote! {
-                        /// Get the #method_name field
+                        #docs
                         pub fn #method_name(&self) -> zeroproto::Result<#reader_type<'a>> {
                             let message_reader = self.reader.get_message(#field_index)?;
                             Ok(#reader_type::new(message_reader))
@@ -260,43 +1366,248 @@ fn generate_reader_field_method(_ir: &IrSchema, field: &IrField) -> TokenStream
                     }
                 }
             } else {
+                // Enums are stored as their u64 value
                 let return_type = syn::parse_str::<syn::Type>(rust_type).unwrap();
                 if field.optional {
+                    let docs = field_docs(field, "Get the {} field (if present)");
                     quote! {
-                        /// Get the #method_name field (if present)
+                        #docs
                         pub fn #method_name(&self) -> zeroproto::Result<Option<#return_type>> {
-                            self.reader.try_get_scalar(#field_index)
+                            match self.reader.try_get_scalar::<u64>(#field_index)? {
+                                Some(value) => #return_type::read(&value.to_le_bytes(), 0).map(Some),
+                                None => Ok(None),
+                            }
                         }
                     }
                 } else if let Some(default_expr) = default_expr.clone() {
+                    let docs = field_docs(field, "Get the {} field (or its default)");
                     quote! {
-                        /// Get the #method_name field (or its default)
+                        #docs
                         pub fn #method_name(&self) -> zeroproto::Result<#return_type> {
-                            match self.reader.try_get_scalar(#field_index)? {
-                                Some(value) => Ok(value),
+                            match self.reader.try_get_scalar::<u64>(#field_index)? {
+                                Some(value) => #return_type::read(&value.to_le_bytes(), 0),
                                 None => Ok(#default_expr),
                             }
                         }
                     }
                 } else {
+                    let docs = field_docs(field, "Get the {} field");
                     quote! {
-                        /// Get the #method_name field
+                        #docs
                         pub fn #method_name(&self) -> zeroproto::Result<#return_type> {
-                            self.reader.get_scalar(#field_index)
+                            let value = self.reader.get_scalar::<u64>(#field_index)?;
+                            #return_type::read(&value.to_le_bytes(), 0)
                         }
                     }
                 }
             }
         }
+        IrFieldType::Vector { .. } if field.packed => generate_bit_vector_reader_method(field),
         IrFieldType::Vector { element_type, .. } => {
             generate_vector_reader_method(field, element_type)
         }
+        IrFieldType::Struct { rust_type, .. } => {
+            let ref_type = format_ident!("{}Ref", rust_type);
+            if field.optional {
+                let docs = field_docs(field, "Get the {} field (if present)");
+                quote! {
+                    #docs
+                    pub fn #method_name(&self) -> zeroproto::Result<Option<#ref_type<'a>>> {
+                        self.reader.try_get_struct(#field_index)
+                    }
+                }
+            } else {
+                let docs = field_docs(field, "Get the {} field");
+                quote! {
+                    #docs
+                    pub fn #method_name(&self) -> zeroproto::Result<#ref_type<'a>> {
+                        self.reader.get_struct(#field_index)
+                    }
+                }
+            }
+        }
+        IrFieldType::Union { rust_type, .. } => {
+            let reader_type = format_ident!("{}Reader", rust_type);
+            if field.optional {
+                let docs = field_docs(field, "Get the active variant of the {} field (if present)");
+                quote! {
+                    #docs
+                    pub fn #method_name(&self) -> zeroproto::Result<Option<#reader_type<'a>>> {
+                        match self.reader.try_get_union(#field_index)? {
+                            Some((discriminant, message_reader)) => {
+                                Ok(Some(#reader_type::from_parts(discriminant, message_reader)?))
+                            }
+                            None => Ok(None),
+                        }
+                    }
+                }
+            } else {
+                let docs = field_docs(field, "Get the active variant of the {} field");
+                quote! {
+                    #docs
+                    pub fn #method_name(&self) -> zeroproto::Result<#reader_type<'a>> {
+                        let (discriminant, message_reader) = self.reader.get_union(#field_index)?;
+                        #reader_type::from_parts(discriminant, message_reader)
+                    }
+                }
+            }
+        }
     };
 
     methods.push(field_method);
 
-    quote! {
-        #(#methods)*
+    add_field_attributes(
+        field,
+        quote! {
+            #(#methods)*
+        },
+    )
+}
+
+/// `#[doc]` attributes for a generated item: a one-line summary, then the schema's doc comment
+fn doc_attributes(summary: &str, docs: &[String]) -> TokenStream {
+    let mut lines = vec![doc_line(summary)];
+    if !docs.is_empty() {
+        lines.push(String::new());
+        lines.extend(docs.iter().map(|line| doc_line(line)));
+    }
+    quote! { #(#[doc = #lines])* }
+}
+
+/// `#[doc]` attributes for a field method; the `{}` in `summary` becomes the field's name
+fn field_docs(field: &IrField, summary: &str) -> TokenStream {
+    let name = format!("`{}`", field.name);
+    doc_attributes(&summary.replacen("{}", &name, 1), &field.docs)
+}
+
+/// `#[doc]` attributes carrying just the schema's doc comment
+fn schema_docs(docs: &[String]) -> TokenStream {
+    let lines = docs.iter().map(|line| doc_line(line));
+    quote! { #(#[doc = #lines])* }
+}
+
+/// A doc line as rustdoc sees it after `///`, keeping blank lines blank
+fn doc_line(line: &str) -> String {
+    if line.is_empty() {
+        String::new()
+    } else {
+        format!(" {}", line)
+    }
+}
+
+/// Add the field's `#[deprecated]`, `@rust.cfg` and `@rust.inline` attributes to every
+/// method in `methods`
+fn add_field_attributes(field: &IrField, methods: TokenStream) -> TokenStream {
+    let mut attributes: Vec<syn::Attribute> = Vec::new();
+    match field.deprecated.as_deref() {
+        None => {}
+        Some("") => attributes.push(syn::parse_quote!(#[deprecated])),
+        Some(note) => attributes.push(syn::parse_quote!(#[deprecated(note = #note)])),
+    }
+    attributes.extend(cfg_attribute(&field.attributes));
+    if find_attribute(&field.attributes, "rust.inline").is_some() {
+        attributes.push(syn::parse_quote!(#[inline]));
+    }
+    if attributes.is_empty() {
+        return methods;
+    }
+
+    let mut block: syn::ItemImpl =
+        syn::parse2(quote! { impl Accessors { #methods } }).expect("Invalid generated methods");
+    for item in &mut block.items {
+        if let syn::ImplItem::Fn(method) = item {
+            method.attrs.extend(attributes.iter().cloned());
+        }
+    }
+
+    let items = block.items;
+    quote! { #(#items)* }
+}
+
+/// Find an attribute by its dotted name
+fn find_attribute<'a>(attributes: &'a [Attribute], name: &str) -> Option<&'a Attribute> {
+    attributes.iter().find(|attr| attr.name == name)
+}
+
+/// Paths named by `@rust.derive(...)`, leaving out the traits a type already implements
+fn extra_derives(attributes: &[Attribute], implemented: &[&str]) -> Vec<syn::Path> {
+    find_attribute(attributes, "rust.derive")
+        .map(|attr| {
+            attr.args
+                .iter()
+                .map(|arg| syn::parse_str(&arg.to_string()).expect("Invalid derive path"))
+                .filter(|path: &syn::Path| !implemented.iter().any(|name| path.is_ident(name)))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// `#[cfg(...)]` from an `@rust.cfg(...)` attribute, combining several predicates with `all`
+fn cfg_attribute(attributes: &[Attribute]) -> Option<syn::Attribute> {
+    let attr = find_attribute(attributes, "rust.cfg")?;
+    let predicate: TokenStream = attr
+        .args_to_string()
+        .parse()
+        .expect("Invalid cfg predicates should have been caught by validator");
+    Some(if attr.args.len() > 1 {
+        syn::parse_quote!(#[cfg(all(#predicate))])
+    } else {
+        syn::parse_quote!(#[cfg(#predicate)])
+    })
+}
+
+/// Apply a message's `@rust.vis` and `@rust.cfg` attributes to every generated item
+fn apply_message_attributes(message: &IrMessage, code: String) -> String {
+    let visibility = find_attribute(&message.attributes, "rust.vis")
+        .and_then(|attr| attr.string_arg())
+        .map(|vis| {
+            syn::parse_str::<syn::Visibility>(vis)
+                .expect("Invalid visibilities should have been caught by validator")
+        });
+    let cfg = cfg_attribute(&message.attributes);
+    if visibility.is_none() && cfg.is_none() {
+        return code;
+    }
+
+    let mut file: syn::File = syn::parse_str(&code).expect("Invalid generated code");
+    for item in &mut file.items {
+        let (attrs, vis) = match item {
+            syn::Item::Struct(item) => (&mut item.attrs, Some(&mut item.vis)),
+            syn::Item::Static(item) => (&mut item.attrs, Some(&mut item.vis)),
+            syn::Item::Const(item) => (&mut item.attrs, Some(&mut item.vis)),
+            syn::Item::Impl(item) => (&mut item.attrs, None),
+            _ => continue,
+        };
+        attrs.extend(cfg.clone());
+        if let (Some(vis), Some(visibility)) = (vis, &visibility) {
+            *vis = visibility.clone();
+        }
+    }
+
+    file.to_token_stream().to_string()
+}
+
+/// Generate bit-packed bool vector reader method
+fn generate_bit_vector_reader_method(field: &IrField) -> TokenStream {
+    let method_name = format_ident!("{}", field.rust_name);
+    let field_index = field.index;
+
+    if field.optional {
+        let docs = field_docs(field, "Get the {} field (if present)");
+        quote! {
+            #docs
+            pub fn #method_name(&self) -> zeroproto::Result<Option<BitVectorReader<'a>>> {
+                self.reader.try_get_bit_vector(#field_index)
+            }
+        }
+    } else {
+        let docs = field_docs(field, "Get the {} field");
+        quote! {
+            #docs
+            pub fn #method_name(&self) -> zeroproto::Result<BitVectorReader<'a>> {
+                self.reader.get_bit_vector(#field_index)
+            }
+        }
     }
 }
 
@@ -311,15 +1622,17 @@ fn generate_vector_reader_method(field: &IrField, element_type: &IrFieldType) ->
             let return_type = format!("VectorReader<'a, {}>", rust_type);
             let parsed_type = syn::parse_str::<syn::Type>(&return_type).unwrap();
             if optional {
+                let docs = field_docs(field, "Get the {} field (if present)");
                 quote! {
-                    /// Get the #method_name field (if present)
+                    #docs
                     pub fn #method_name(&self) -> zeroproto::Result<Option<#parsed_type>> {
                         self.reader.try_get_vector(#field_index)
                     }
                 }
             } else {
+                let docs = field_docs(field, "Get the {} field");
                 quote! {
-                    /// Get the #method_name field
+                    #docs
                     pub fn #method_name(&self) -> zeroproto::Result<#parsed_type> {
                         self.reader.get_vector(#field_index)
                     }
@@ -335,15 +1648,17 @@ fn generate_vector_reader_method(field: &IrField, element_type: &IrFieldType) ->
                 let return_type = format!("VectorReader<'a, {}Reader<'a>>", rust_type);
                 let parsed_type = syn::parse_str::<syn::Type>(&return_type).unwrap();
                 if optional {
+                    let docs = field_docs(field, "Get the {} field (if present)");
                     quote! {
-                        /// Get the #method_name field (if present)
+                        #docs
                         pub fn #method_name(&self) -> zeroproto::Result<Option<#parsed_type>> {
                             self.reader.try_get_vector(#field_index)
                         }
                     }
                 } else {
+                    let docs = field_docs(field, "Get the {} field");
                     quote! {
-                        /// Get the #method_name field
+                        #docs
                         pub fn #method_name(&self) -> zeroproto::Result<#parsed_type> {
                             self.reader.get_vector(#field_index)
                         }
@@ -353,15 +1668,17 @@ fn generate_vector_reader_method(field: &IrField, element_type: &IrFieldType) ->
                 let return_type = format!("VectorReader<'a, {}>", rust_type);
                 let parsed_type = syn::parse_str::<syn::Type>(&return_type).unwrap();
                 if optional {
+                    let docs = field_docs(field, "Get the {} field (if present)");
                     quote! {
-                        /// Get the #method_name field (if present)
+                        #docs
                         pub fn #method_name(&self) -> zeroproto::Result<Option<#parsed_type>> {
                             self.reader.try_get_vector(#field_index)
                         }
                     }
                 } else {
+                    let docs = field_docs(field, "Get the {} field");
                     quote! {
-                        /// Get the #method_name field
+                        #docs
                         pub fn #method_name(&self) -> zeroproto::Result<#parsed_type> {
                             self.reader.get_vector(#field_index)
                         }
@@ -369,14 +1686,37 @@ fn generate_vector_reader_method(field: &IrField, element_type: &IrFieldType) ->
                 }
             }
         }
+        IrFieldType::Struct { rust_type, .. } => {
+            let return_type = format!("VectorReader<'a, {}Ref<'a>>", rust_type);
+            let parsed_type = syn::parse_str::<syn::Type>(&return_type).unwrap();
+            if optional {
+                let docs = field_docs(field, "Get the {} field (if present)");
+                quote! {
+                    #docs
+                    pub fn #method_name(&self) -> zeroproto::Result<Option<#parsed_type>> {
+                        self.reader.try_get_vector(#field_index)
+                    }
+                }
+            } else {
+                let docs = field_docs(field, "Get the {} field");
+                quote! {
+                    #docs
+                    pub fn #method_name(&self) -> zeroproto::Result<#parsed_type> {
+                        self.reader.get_vector(#field_index)
+                    }
+                }
+            }
+        }
         IrFieldType::Vector { .. } => panic!("Nested vectors should have been caught by validator"),
+        IrFieldType::Union { .. } => {
+            panic!("Vectors of unions should have been caught by validator")
+        }
     }
 }
 
 /// Generate builder code for a message
 fn generate_builder(ir: &IrSchema, message: &IrMessage) -> String {
     let builder_name = format_ident!("{}", message.builder_name);
-    let _reader_name = &message.reader_name;
 
     let fields = &message.fields;
     let field_methods: Vec<_> = fields
@@ -385,7 +1725,10 @@ fn generate_builder(ir: &IrSchema, message: &IrMessage) -> String {
         .collect();
 
     let new_method = generate_builder_new_method(message);
+    let unknown_field_methods = generate_builder_unknown_field_methods(message);
+    let merge_methods = generate_builder_merge_methods(message);
     let finish_method = generate_builder_finish_method(message);
+    let type_name = &message.type_name;
 
     let code = quote! {
         #new_method
@@ -393,6 +1736,10 @@ fn generate_builder(ir: &IrSchema, message: &IrMessage) -> String {
         impl #builder_name {
             #(#field_methods)*
 
+            #unknown_field_methods
+
+            #merge_methods
+
             #finish_method
         }
 
@@ -401,6 +1748,14 @@ fn generate_builder(ir: &IrSchema, message: &IrMessage) -> String {
                 Self::new()
             }
         }
+
+        impl zeroproto::any::TypedBuilder for #builder_name {
+            const TYPE_NAME: &'static str = #type_name;
+
+            fn into_bytes(self) -> core::result::Result<Vec<u8>, zeroproto::MissingFields> {
+                self.finish()
+            }
+        }
     };
 
     code.to_string()
@@ -409,9 +1764,19 @@ fn generate_builder(ir: &IrSchema, message: &IrMessage) -> String {
 /// Generate the new() method for builders
 fn generate_builder_new_method(message: &IrMessage) -> TokenStream {
     let builder_name = format_ident!("{}", message.builder_name);
+    let inner_builder = if message.presence_bitmap {
+        quote! { MessageBuilder::with_layout(FieldTableLayout::Bitmap) }
+    } else {
+        quote! { MessageBuilder::new() }
+    };
+
+    let docs = doc_attributes(
+        &format!("Builder for `{}` messages", message.name),
+        &message.docs,
+    );
 
     quote! {
-        /// Builder for #builder_name messages
+        #docs
         #[derive(Debug)]
         pub struct #builder_name {
             builder: MessageBuilder,
@@ -421,34 +1786,120 @@ fn generate_builder_new_method(message: &IrMessage) -> TokenStream {
             /// Create a new builder
             pub fn new() -> Self {
                 Self {
-                    builder: MessageBuilder::new(),
+                    builder: #inner_builder,
                 }
             }
         }
     }
 }
 
-/// Generate the finish() method for builders
-fn generate_builder_finish_method(message: &IrMessage) -> TokenStream {
-    let builder_name = format_ident!("{}", message.builder_name);
+/// Generate the methods that carry unknown fields through a re-encode
+fn generate_builder_unknown_field_methods(message: &IrMessage) -> TokenStream {
     let reader_name = format_ident!("{}", message.reader_name);
+    let field_indices = message.fields.iter().map(|field| field.index);
 
     quote! {
-        /// Finish building and return the serialized message
-        pub fn finish(self) -> Vec<u8> {
-            self.builder.finish()
+        /// Create a builder from a reader, keeping fields this schema doesn't know about
+        pub fn from_reader_preserving_unknown(reader: &#reader_name<'_>) -> zeroproto::Result<Self> {
+            let mut builder = Self::new();
+            #(
+                builder.builder.copy_field(&reader.reader, #field_indices)?;
+            )*
+            builder.copy_unknown_fields_from(reader)?;
+            Ok(builder)
+        }
+
+        /// Copy fields this schema doesn't know about from `other` verbatim
+        pub fn copy_unknown_fields_from(&mut self, other: &#reader_name<'_>) -> zeroproto::Result<&mut Self> {
+            self.builder.copy_unknown_fields(&other.reader, #reader_name::FIELD_NUMBERS)?;
+            Ok(self)
+        }
+    }
+}
+
+/// Generate the merge_from() methods for builders
+fn generate_builder_merge_methods(message: &IrMessage) -> TokenStream {
+    let reader_name = format_ident!("{}", message.reader_name);
+
+    quote! {
+        /// Merge every field set in `other` into this builder, replacing vectors
+        pub fn merge_from(&mut self, other: &#reader_name<'_>) -> zeroproto::Result<&mut Self> {
+            self.merge_from_with(other, &zeroproto::merge::MergeOptions::default())
+        }
+
+        /// Merge every field set in `other` into this builder with the given options
+        pub fn merge_from_with(
+            &mut self,
+            other: &#reader_name<'_>,
+            options: &zeroproto::merge::MergeOptions,
+        ) -> zeroproto::Result<&mut Self> {
+            self.builder.merge_from(&other.reader, options)?;
+            Ok(self)
+        }
+    }
+}
+
+/// Generate the finish() method for builders
+fn generate_builder_finish_method(message: &IrMessage) -> TokenStream {
+    let message_name = &message.name;
+
+    // Fields that are neither optional nor defaulted must be set, unless they're on their
+    // way out
+    let checks: Vec<_> = message
+        .fields
+        .iter()
+        .filter(|field| {
+            !field.optional && field.default_value.is_none() && field.deprecated.is_none()
+        })
+        .map(|field| {
+            let field_index = field.index;
+            let field_name = &field.name;
+            let cfg = cfg_attribute(&field.attributes);
+            quote! {
+                #cfg
+                if !self.builder.has_field(#field_index) {
+                    missing.push(#field_name);
+                }
+            }
+        })
+        .collect();
+    let finish = if checks.is_empty() {
+        quote! {
+            /// Finish building and return the serialized message
+            pub fn finish(self) -> core::result::Result<Vec<u8>, zeroproto::MissingFields> {
+                Ok(self.builder.finish())
+            }
+        }
+    } else {
+        quote! {
+            /// Finish building and return the serialized message, or every required field
+            /// that hasn't been set
+            pub fn finish(self) -> core::result::Result<Vec<u8>, zeroproto::MissingFields> {
+                let mut missing = Vec::new();
+                #(#checks)*
+                if !missing.is_empty() {
+                    return Err(zeroproto::MissingFields {
+                        message: #message_name,
+                        fields: missing,
+                    });
+                }
+                Ok(self.builder.finish())
+            }
         }
+    };
+
+    quote! {
+        #finish
 
-        /// Finish building and return a reader
-        pub fn finish_reader(self) -> zeroproto::Result<#reader_name<'static>> {
-            let bytes = self.finish();
-            #reader_name::from_bytes(&bytes)
+        /// Finish building without checking that required fields are set
+        pub fn finish_unchecked(self) -> Vec<u8> {
+            self.builder.finish()
         }
     }
 }
 
 /// Generate a builder field method
-fn generate_builder_field_method(_ir: &IrSchema, field: &IrField) -> TokenStream {
+fn generate_builder_field_method(ir: &IrSchema, field: &IrField) -> TokenStream {
     let method_name = format_ident!("set_{}", field.rust_name);
     let field_index = field.index;
     let optional = field.optional;
@@ -456,12 +1907,54 @@ fn generate_builder_field_method(_ir: &IrSchema, field: &IrField) -> TokenStream
     let mut method_blocks = Vec::new();
 
     match &field.field_type {
-        IrFieldType::Scalar { rust_type, .. } => {
-            let param_type = syn::parse_str::<syn::Type>(rust_type).unwrap();
+        IrFieldType::Scalar {
+            scalar_type: ScalarType::Any,
+            ..
+        } => {
+            let docs = field_docs(field, "Set the {} field to a finished message of any type");
+            method_blocks.push(quote! {
+                #docs
+                pub fn #method_name<M: zeroproto::any::TypedBuilder>(
+                    &mut self,
+                    value: M,
+                ) -> core::result::Result<&mut Self, zeroproto::MissingFields> {
+                    let bytes = value.into_bytes()?;
+                    self.builder.set_any(#field_index, M::TYPE_NAME, &bytes).unwrap();
+                    Ok(self)
+                }
+            });
+
+            if optional {
+                let clear_method = format_ident!("clear_{}", field.rust_name);
+                let docs = field_docs(field, "Clear the {} field");
+                method_blocks.push(quote! {
+                    #docs
+                    pub fn #clear_method(&mut self) -> &mut Self {
+                        self.builder.clear_field(#field_index).unwrap();
+                        self
+                    }
+                });
+            }
+        }
+        IrFieldType::Scalar {
+            scalar_type,
+            rust_type,
+            ..
+        } => {
+            // Strings and bytes are copied in, so the builder needn't borrow them
+            let (param_type, set) = match scalar_type {
+                ScalarType::String => (quote! { &str }, quote! { set_string }),
+                ScalarType::Bytes => (quote! { &[u8] }, quote! { set_bytes }),
+                _ => {
+                    let param_type = syn::parse_str::<syn::Type>(rust_type).unwrap();
+                    (quote! { #param_type }, quote! { set_scalar })
+                }
+            };
+            let docs = field_docs(field, "Set the {} field");
             method_blocks.push(quote! {
-                /// Set the #method_name field
+                #docs
                 pub fn #method_name(&mut self, value: #param_type) -> &mut Self {
-                    self.builder.set_scalar(#field_index, value).unwrap();
+                    self.builder.#set(#field_index, value).unwrap();
                     self
                 }
             });
@@ -469,18 +1962,20 @@ fn generate_builder_field_method(_ir: &IrSchema, field: &IrField) -> TokenStream
             if optional {
                 let clear_method = format_ident!("clear_{}", field.rust_name);
                 let optional_method = format_ident!("set_optional_{}", field.rust_name);
+                let docs = field_docs(field, "Clear the {} field");
                 method_blocks.push(quote! {
-                    /// Clear the #method_name field
+                    #docs
                     pub fn #clear_method(&mut self) -> &mut Self {
                         self.builder.clear_field(#field_index).unwrap();
                         self
                     }
                 });
+                let docs = field_docs(field, "Set or clear the {} field");
                 method_blocks.push(quote! {
-                    /// Set or clear the #method_name field
+                    #docs
                     pub fn #optional_method(&mut self, value: Option<#param_type>) -> &mut Self {
                         if let Some(value) = value {
-                            self.builder.set_scalar(#field_index, value).unwrap();
+                            self.builder.#set(#field_index, value).unwrap();
                         } else {
                             self.builder.clear_field(#field_index).unwrap();
                         }
@@ -495,12 +1990,11 @@ fn generate_builder_field_method(_ir: &IrSchema, field: &IrField) -> TokenStream
             ..
         } => {
             if *is_message {
-                let type_ident = format_ident!("{}", rust_type);
+                let docs = field_docs(field, "Set the {} field to a finished message");
                 method_blocks.push(quote! {
-                    /// Set the #method_name field
-                    pub fn #method_name(&mut self, value: &#type_ident<'_>) -> &mut Self {
-                        let bytes = value.reader.finish();
-                        self.builder.set_message(#field_index, &bytes).unwrap();
+                    #docs
+                    pub fn #method_name(&mut self, value: &[u8]) -> &mut Self {
+                        self.builder.set_message(#field_index, value).unwrap();
                         self
                     }
                 });
@@ -508,19 +2002,20 @@ fn generate_builder_field_method(_ir: &IrSchema, field: &IrField) -> TokenStream
                 if optional {
                     let clear_method = format_ident!("clear_{}", field.rust_name);
                     let optional_method = format_ident!("set_optional_{}", field.rust_name);
+                    let docs = field_docs(field, "Clear the {} field");
                     method_blocks.push(quote! {
-                        /// Clear the #method_name field
+                        #docs
                         pub fn #clear_method(&mut self) -> &mut Self {
                             self.builder.clear_field(#field_index).unwrap();
                             self
                         }
                     });
+                    let docs = field_docs(field, "Set or clear the {} field");
                     method_blocks.push(quote! {
-                        /// Set or clear the #method_name field
-                        pub fn #optional_method(&mut self, value: Option<&#type_ident<'_>>) -> &mut Self {
+                        #docs
+                        pub fn #optional_method(&mut self, value: Option<&[u8]>) -> &mut Self {
                             if let Some(value) = value {
-                                let bytes = value.reader.finish();
-                                self.builder.set_message(#field_index, &bytes).unwrap();
+                                self.builder.set_message(#field_index, value).unwrap();
                             } else {
                                 self.builder.clear_field(#field_index).unwrap();
                             }
@@ -530,10 +2025,11 @@ fn generate_builder_field_method(_ir: &IrSchema, field: &IrField) -> TokenStream
                 }
             } else {
                 let param_type = syn::parse_str::<syn::Type>(rust_type).unwrap();
+                let docs = field_docs(field, "Set the {} field");
                 method_blocks.push(quote! {
-                    /// Set the #method_name field
+                    #docs
                     pub fn #method_name(&mut self, value: #param_type) -> &mut Self {
-                        self.builder.set_scalar(#field_index, value).unwrap();
+                        self.builder.set_scalar(#field_index, value as u64).unwrap();
                         self
                     }
                 });
@@ -541,18 +2037,20 @@ fn generate_builder_field_method(_ir: &IrSchema, field: &IrField) -> TokenStream
                 if optional {
                     let clear_method = format_ident!("clear_{}", field.rust_name);
                     let optional_method = format_ident!("set_optional_{}", field.rust_name);
+                    let docs = field_docs(field, "Clear the {} field");
                     method_blocks.push(quote! {
-                        /// Clear the #method_name field
+                        #docs
                         pub fn #clear_method(&mut self) -> &mut Self {
                             self.builder.clear_field(#field_index).unwrap();
                             self
                         }
                     });
+                    let docs = field_docs(field, "Set or clear the {} field");
                     method_blocks.push(quote! {
-                        /// Set or clear the #method_name field
+                        #docs
                         pub fn #optional_method(&mut self, value: Option<#param_type>) -> &mut Self {
                             if let Some(value) = value {
-                                self.builder.set_scalar(#field_index, value).unwrap();
+                                self.builder.set_scalar(#field_index, value as u64).unwrap();
                             } else {
                                 self.builder.clear_field(#field_index).unwrap();
                             }
@@ -562,14 +2060,106 @@ fn generate_builder_field_method(_ir: &IrSchema, field: &IrField) -> TokenStream
                 }
             }
         }
+        IrFieldType::Struct { rust_type, .. } => {
+            let type_ident = format_ident!("{}", rust_type);
+            let docs = field_docs(field, "Set the {} field");
+            method_blocks.push(quote! {
+                #docs
+                pub fn #method_name(&mut self, value: #type_ident) -> &mut Self {
+                    self.builder.set_struct(#field_index, &value).unwrap();
+                    self
+                }
+            });
+
+            if optional {
+                let clear_method = format_ident!("clear_{}", field.rust_name);
+                let optional_method = format_ident!("set_optional_{}", field.rust_name);
+                let docs = field_docs(field, "Clear the {} field");
+                method_blocks.push(quote! {
+                    #docs
+                    pub fn #clear_method(&mut self) -> &mut Self {
+                        self.builder.clear_field(#field_index).unwrap();
+                        self
+                    }
+                });
+                let docs = field_docs(field, "Set or clear the {} field");
+                method_blocks.push(quote! {
+                    #docs
+                    pub fn #optional_method(&mut self, value: Option<#type_ident>) -> &mut Self {
+                        if let Some(value) = value {
+                            self.builder.set_struct(#field_index, &value).unwrap();
+                        } else {
+                            self.builder.clear_field(#field_index).unwrap();
+                        }
+                        self
+                    }
+                });
+            }
+        }
+        IrFieldType::Vector { .. } if field.packed => {
+            let docs = field_docs(field, "Set the {} field");
+            method_blocks.push(quote! {
+                #docs
+                pub fn #method_name(&mut self, values: &[bool]) -> &mut Self {
+                    self.builder.set_bit_vector(#field_index, values).unwrap();
+                    self
+                }
+            });
+        }
         IrFieldType::Vector { element_type, .. } => {
             method_blocks.push(generate_vector_builder_method(field, element_type));
         }
-    }
+        IrFieldType::Union { type_name, .. } => {
+            let union_def = ir
+                .find_union(type_name)
+                .expect("Unknown unions should have been caught by validator");
 
-    quote! {
-        #(#method_blocks)*
+            // One setter per variant; they share the field slot so only the last one set survives
+            for variant in &union_def.variants {
+                let variant_method =
+                    format_ident!("set_{}_{}", field.rust_name, variant.method_name);
+                let variant_builder = format_ident!("{}Builder", variant.message_type);
+                let discriminant = variant.discriminant;
+                let docs = field_docs(
+                    field,
+                    &format!(
+                        "Set the {{}} field to the `{}` variant, replacing any other variant",
+                        variant.name
+                    ),
+                );
+                method_blocks.push(quote! {
+                    #docs
+                    pub fn #variant_method(
+                        &mut self,
+                        value: #variant_builder,
+                    ) -> core::result::Result<&mut Self, zeroproto::MissingFields> {
+                        let bytes = value.finish()?;
+                        self.builder.set_union(#field_index, #discriminant, &bytes).unwrap();
+                        Ok(self)
+                    }
+                });
+            }
+
+            if optional {
+                let clear_method = format_ident!("clear_{}", field.rust_name);
+                let docs = field_docs(field, "Clear the {} field");
+                method_blocks.push(quote! {
+                    #docs
+                    pub fn #clear_method(&mut self) -> &mut Self {
+                        self.builder.clear_field(#field_index).unwrap();
+                        self
+                    }
+                });
+            }
+        }
     }
+
+    add_field_attributes(
+        field,
+        quote! {
+            #(#method_blocks)*
+        },
+    )
 }
 
 /// Generate vector builder method
@@ -580,8 +2170,9 @@ fn generate_vector_builder_method(field: &IrField, element_type: &IrFieldType) -
     match element_type {
         IrFieldType::Scalar { rust_type, .. } => {
             let param_type = syn::parse_str::<syn::Type>(&format!("[{}]", rust_type)).unwrap();
+            let docs = field_docs(field, "Set the {} field");
             quote! {
-                /// Set the #method_name field
+                #docs
                 pub fn #method_name(&mut self, values: &#param_type) -> &mut Self {
                     self.builder.set_vector(#field_index, values).unwrap();
                     self
@@ -594,8 +2185,9 @@ fn generate_vector_builder_method(field: &IrField, element_type: &IrFieldType) -
             ..
         } => {
             if *is_message {
+                let docs = field_docs(field, "Set the {} field");
                 quote! {
-                    /// Set the #method_name field
+                    #docs
                     pub fn #method_name(&mut self, values: &[#rust_type<'_>]) -> &mut Self {
                         let bytes: Vec<_> = values.iter().map(|msg| {
                             let mut temp_builder = MessageBuilder::new();
@@ -609,8 +2201,9 @@ fn generate_vector_builder_method(field: &IrField, element_type: &IrFieldType) -
                 }
             } else {
                 let param_type = syn::parse_str::<syn::Type>(&format!("[{}]", rust_type)).unwrap();
+                let docs = field_docs(field, "Set the {} field");
                 quote! {
-                    /// Set the #method_name field
+                    #docs
                     pub fn #method_name(&mut self, values: &#param_type) -> &mut Self {
                         self.builder.set_vector(#field_index, values).unwrap();
                         self
@@ -618,6 +2211,1058 @@ fn generate_vector_builder_method(field: &IrField, element_type: &IrFieldType) -
                 }
             }
         }
+        IrFieldType::Struct { rust_type, .. } => {
+            let type_ident = format_ident!("{}", rust_type);
+            let docs = field_docs(field, "Set the {} field");
+            quote! {
+                #docs
+                pub fn #method_name(&mut self, values: &[#type_ident]) -> &mut Self {
+                    self.builder.set_struct_vector(#field_index, values).unwrap();
+                    self
+                }
+            }
+        }
         IrFieldType::Vector { .. } => panic!("Nested vectors should have been caught by validator"),
+        IrFieldType::Union { .. } => {
+            panic!("Vectors of unions should have been caught by validator")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Generated code, parsed so tests look at items rather than how tokens are spaced
+    struct Generated(syn::File);
+
+    impl Generated {
+        fn new(input: &str) -> Self {
+            let schema = crate::parser::parse(input).unwrap();
+            crate::validator::validate(&schema).unwrap();
+            let code = generate_rust_code(&crate::ir::lower_ast(&schema)).unwrap();
+            Self(syn::parse_file(&code).expect("generated code should be valid Rust"))
+        }
+
+        fn find(&self, name: &str) -> Option<&syn::Item> {
+            self.0.items.iter().find(|item| {
+                let ident = match item {
+                    syn::Item::Struct(item) => &item.ident,
+                    syn::Item::Enum(item) => &item.ident,
+                    syn::Item::Const(item) => &item.ident,
+                    syn::Item::Static(item) => &item.ident,
+                    syn::Item::Type(item) => &item.ident,
+                    syn::Item::Fn(item) => &item.sig.ident,
+                    _ => return false,
+                };
+                ident == name
+            })
+        }
+
+        /// The top-level struct, enum, const, static, type alias or function named `name`
+        #[track_caller]
+        fn item(&self, name: &str) -> &syn::Item {
+            self.find(name)
+                .unwrap_or_else(|| panic!("no item named `{name}`"))
+        }
+
+        fn has_item(&self, name: &str) -> bool {
+            self.find(name).is_some()
+        }
+
+        /// Every impl block for `self_ty`, trait impls included
+        fn impls<'s>(&'s self, self_ty: &str) -> impl Iterator<Item = &'s syn::ItemImpl> {
+            let self_ty = self_ty.to_owned();
+            self.0.items.iter().filter_map(move |item| match item {
+                syn::Item::Impl(block)
+                    if type_name(&block.self_ty).is_some_and(|ident| *ident == self_ty) =>
+                {
+                    Some(block)
+                }
+                _ => None,
+            })
+        }
+
+        #[track_caller]
+        fn trait_impl(&self, trait_name: &str, self_ty: &str) -> &syn::ItemImpl {
+            self.impls(self_ty)
+                .find(|block| {
+                    block.trait_.as_ref().is_some_and(|(_, path, _)| {
+                        path.segments
+                            .last()
+                            .is_some_and(|segment| segment.ident == trait_name)
+                    })
+                })
+                .unwrap_or_else(|| panic!("no `impl {trait_name} for {self_ty}`"))
+        }
+
+        fn find_method(&self, self_ty: &str, name: &str) -> Option<&syn::ImplItemFn> {
+            self.impls(self_ty)
+                .flat_map(|block| &block.items)
+                .find_map(|item| match item {
+                    syn::ImplItem::Fn(method) if method.sig.ident == name => Some(method),
+                    _ => None,
+                })
+        }
+
+        /// The method `name` from any impl block for `self_ty`
+        #[track_caller]
+        fn method(&self, self_ty: &str, name: &str) -> &syn::ImplItemFn {
+            self.find_method(self_ty, name)
+                .unwrap_or_else(|| panic!("no method `{self_ty}::{name}`"))
+        }
+
+        fn has_method(&self, self_ty: &str, name: &str) -> bool {
+            self.find_method(self_ty, name).is_some()
+        }
+
+        /// The associated const `name` from any impl block for `self_ty`
+        #[track_caller]
+        fn impl_const(&self, self_ty: &str, name: &str) -> &syn::ImplItemConst {
+            self.impls(self_ty)
+                .flat_map(|block| &block.items)
+                .find_map(|item| match item {
+                    syn::ImplItem::Const(constant) if constant.ident == name => Some(constant),
+                    _ => None,
+                })
+                .unwrap_or_else(|| panic!("no const `{self_ty}::{name}`"))
+        }
+    }
+
+    fn type_name(ty: &syn::Type) -> Option<&syn::Ident> {
+        match ty {
+            syn::Type::Path(path) => path.path.segments.last().map(|segment| &segment.ident),
+            _ => None,
+        }
+    }
+
+    fn item_attrs(item: &syn::Item) -> &[syn::Attribute] {
+        match item {
+            syn::Item::Struct(item) => &item.attrs,
+            syn::Item::Enum(item) => &item.attrs,
+            syn::Item::Const(item) => &item.attrs,
+            syn::Item::Static(item) => &item.attrs,
+            syn::Item::Type(item) => &item.attrs,
+            syn::Item::Fn(item) => &item.attrs,
+            _ => &[],
+        }
+    }
+
+    /// Traits named by the `#[derive]` attributes in `attrs`, in order
+    fn derives(attrs: &[syn::Attribute]) -> Vec<String> {
+        attrs
+            .iter()
+            .filter(|attr| attr.path().is_ident("derive"))
+            .flat_map(|attr| {
+                attr.parse_args_with(
+                    syn::punctuated::Punctuated::<syn::Path, syn::Token![,]>::parse_terminated,
+                )
+                .unwrap()
+            })
+            .map(|path| tokens(&path))
+            .collect()
+    }
+
+    /// Lines of the `#[doc]` attributes in `attrs`
+    fn docs(attrs: &[syn::Attribute]) -> Vec<String> {
+        attrs
+            .iter()
+            .filter_map(|attr| match &attr.meta {
+                syn::Meta::NameValue(meta) if meta.path.is_ident("doc") => match &meta.value {
+                    syn::Expr::Lit(syn::ExprLit {
+                        lit: syn::Lit::Str(doc),
+                        ..
+                    }) => Some(doc.value()),
+                    _ => None,
+                },
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn has_attr(attrs: &[syn::Attribute], expected: syn::Attribute) -> bool {
+        attrs.iter().any(|attr| tokens(attr) == tokens(&expected))
+    }
+
+    /// Print a syntax node, dropping trailing commas so they don't affect comparisons
+    fn tokens(node: &impl ToTokens) -> String {
+        node.to_token_stream()
+            .to_string()
+            .replace(" , }", " }")
+            .replace(" ,]", "]")
+            .replace(" ,)", ")")
+    }
+
+    /// Assert that `node` is `expected` once both are parsed, so spacing and `>>` don't matter
+    #[track_caller]
+    fn assert_tokens<T: syn::parse::Parse + ToTokens>(node: &T, expected: TokenStream) {
+        let expected: T = syn::parse2(expected).expect("expected tokens should parse");
+        assert_eq!(tokens(node), tokens(&expected));
+    }
+
+    /// Assert that the expression `expected` appears somewhere in `node`
+    #[track_caller]
+    fn assert_contains(node: &impl ToTokens, expected: TokenStream) {
+        let expected: syn::Expr = syn::parse2(expected).expect("expected tokens should parse");
+        let (node, expected) = (tokens(node), tokens(&expected));
+        assert!(
+            node.contains(&expected),
+            "`{expected}` not found in `{node}`"
+        );
+    }
+
+    #[test]
+    fn test_union_codegen() {
+        let code = Generated::new(
+            r#"
+            message StartCmd { delay_ms: u32; }
+            message StopCmd { force: bool; }
+            union Command {
+                Start: StartCmd;
+                Stop: StopCmd;
+            }
+            message Envelope {
+                command: Command;
+                fallback: Command?;
+            }
+        "#,
+        );
+
+        let syn::Item::Enum(reader) = code.item("CommandReader") else {
+            panic!("union readers should be enums");
+        };
+        assert_tokens(&reader.variants[0], quote!(Start(StartCmdReader<'a>)));
+        assert_tokens(&reader.variants[1], quote!(Stop(StopCmdReader<'a>)));
+
+        assert_tokens(
+            &code.method("EnvelopeBuilder", "set_command_start").sig,
+            quote!(fn set_command_start(
+                &mut self,
+                value: StartCmdBuilder,
+            ) -> core::result::Result<&mut Self, zeroproto::MissingFields>),
+        );
+        assert_contains(
+            &code.method("EnvelopeBuilder", "set_command_start").block,
+            quote!(value.finish()?),
+        );
+        assert!(code.has_method("EnvelopeBuilder", "set_command_stop"));
+        assert!(code.has_method("EnvelopeBuilder", "clear_fallback"));
+        assert_contains(
+            &code.method("EnvelopeReader", "fallback").block,
+            quote!(self.reader.try_get_union(1u16)),
+        );
+    }
+
+    #[test]
+    fn test_packed_and_bitmap_codegen() {
+        let code = Generated::new(
+            r#"
+            message Flags @bitmap {
+                bits: [bool] @packed;
+                plain: [bool];
+            }
+        "#,
+        );
+
+        assert_contains(
+            &code.method("FlagsBuilder", "new").block,
+            quote!(MessageBuilder::with_layout(FieldTableLayout::Bitmap)),
+        );
+        assert_tokens(
+            &code.method("FlagsReader", "bits").sig.output,
+            quote!(-> zeroproto::Result<BitVectorReader<'a>>),
+        );
+        assert_contains(
+            &code.method("FlagsBuilder", "set_bits").block,
+            quote!(set_bit_vector(0u16, values)),
+        );
+        assert_contains(
+            &code.method("FlagsBuilder", "set_plain").block,
+            quote!(set_vector(1u16, values)),
+        );
+    }
+
+    #[test]
+    fn test_descriptor_codegen() {
+        let code = Generated::new(
+            r#"
+            message StartCmd { delay_ms: u32; }
+            union Command { Start: StartCmd; }
+            message Node {
+                label: u32;
+                next: Node?;
+                command: Command;
+            }
+        "#,
+        );
+
+        let syn::Item::Static(descriptor) = code.item("NODE_DESCRIPTOR") else {
+            panic!("descriptors should be statics");
+        };
+        assert_tokens(&*descriptor.ty, quote!(MessageDescriptor));
+        assert_contains(
+            &descriptor.expr,
+            quote!(FieldDescriptor {
+                name: "next",
+                index: 1u16,
+                message: Some(&NODE_DESCRIPTOR),
+                variants: &[],
+            }),
+        );
+        assert_contains(
+            &descriptor.expr,
+            quote!(VariantDescriptor {
+                name: "Start",
+                discriminant: 0u16,
+                message: &START_CMD_DESCRIPTOR,
+            }),
+        );
+        assert_contains(
+            &code.method("NodeReader", "diff").block,
+            quote!(zeroproto::diff::diff_with_schema(
+                &self.reader,
+                &other.reader,
+                &NODE_DESCRIPTOR
+            )),
+        );
+    }
+
+    #[test]
+    fn test_unknown_field_codegen() {
+        let code = Generated::new("message Point { x: i32; y: i32; }");
+
+        assert_tokens(
+            &code.impl_const("PointReader", "FIELD_COUNT").expr,
+            quote!(2u16),
+        );
+        let preserving = code.method("PointBuilder", "from_reader_preserving_unknown");
+        assert_tokens(
+            &preserving.sig,
+            quote!(fn from_reader_preserving_unknown(reader: &PointReader<'_>) -> zeroproto::Result<Self>),
+        );
+        assert_contains(
+            &preserving.block,
+            quote!(builder.builder.copy_field(&reader.reader, 1u16)?),
+        );
+        assert_contains(
+            &code
+                .method("PointBuilder", "copy_unknown_fields_from")
+                .block,
+            quote!(self
+                .builder
+                .copy_unknown_fields(&other.reader, PointReader::FIELD_NUMBERS)?),
+        );
+    }
+
+    #[test]
+    fn test_explicit_field_numbers_codegen() {
+        let code = Generated::new(
+            r#"
+            message Contact {
+                email: string @3;
+                name: string @0;
+                phone: string?;
+            }
+        "#,
+        );
+
+        code.impl_const("ContactReader", "FIELD_3_OFFSET");
+        assert_tokens(
+            &code.impl_const("ContactReader", "FIELD_COUNT").expr,
+            quote!(4u16),
+        );
+        assert_tokens(
+            &code.impl_const("ContactReader", "FIELD_NUMBERS").expr,
+            quote!(&[3u16, 0u16, 1u16]),
+        );
+        let syn::Item::Static(descriptor) = code.item("CONTACT_DESCRIPTOR") else {
+            panic!("descriptors should be statics");
+        };
+        assert_contains(
+            &descriptor.expr,
+            quote!(FieldDescriptor {
+                name: "phone",
+                index: 1u16,
+                message: None,
+                variants: &[],
+            }),
+        );
+        assert_contains(
+            &code.method("ContactReader", "email").block,
+            quote!(self.reader.get_string(3u16)),
+        );
+        assert_contains(
+            &code.method("ContactBuilder", "clear_phone").block,
+            quote!(self.builder.clear_field(1u16)),
+        );
+    }
+
+    #[test]
+    fn test_deprecated_codegen() {
+        let code = Generated::new(
+            r#"
+            message Account {
+                reserved 1;
+                email: string @deprecated("use contact instead");
+                fax: string? @deprecated @3;
+                contact: string @2;
+            }
+        "#,
+        );
+
+        let noted: syn::Attribute = syn::parse_quote!(#[deprecated(note = "use contact instead")]);
+        assert!(has_attr(
+            &code.method("AccountReader", "email").attrs,
+            noted.clone()
+        ));
+        assert!(has_attr(
+            &code.method("AccountBuilder", "set_email").attrs,
+            noted
+        ));
+        assert!(has_attr(
+            &code.method("AccountReader", "has_fax").attrs,
+            syn::parse_quote!(#[deprecated])
+        ));
+        assert!(has_attr(
+            &code.method("AccountBuilder", "clear_fax").attrs,
+            syn::parse_quote!(#[deprecated])
+        ));
+        assert!(!code
+            .method("AccountReader", "contact")
+            .attrs
+            .iter()
+            .any(|attr| attr.path().is_ident("deprecated")));
+    }
+
+    #[test]
+    fn test_builder_merge_codegen() {
+        let code = Generated::new("message Point { x: i32; y: i32; }");
+
+        assert_tokens(
+            &code.method("PointBuilder", "merge_from").sig,
+            quote!(fn merge_from(&mut self, other: &PointReader<'_>) -> zeroproto::Result<&mut Self>),
+        );
+        assert_contains(
+            &code.method("PointBuilder", "merge_from_with").block,
+            quote!(self.builder.merge_from(&other.reader, options)?),
+        );
+    }
+
+    #[test]
+    fn test_relative_module_paths() {
+        let path = |from: &[&str], to: &[&str]| {
+            let from: Vec<_> = from.iter().map(|s| s.to_string()).collect();
+            let to: Vec<_> = to.iter().map(|s| s.to_string()).collect();
+            relative_module_path(&from, &to)
+        };
+
+        assert_eq!(path(&["orders"], &["users"]), "super::users");
+        assert_eq!(
+            path(&["acme", "orders"], &["acme", "users"]),
+            "super::users"
+        );
+        assert_eq!(path(&["acme", "orders"], &["users"]), "super::super::users");
+        assert_eq!(path(&["acme", "orders"], &["acme"]), "super");
+        assert_eq!(path(&["acme"], &["acme", "orders"]), "self::orders");
+    }
+
+    #[test]
+    fn test_rust_attribute_codegen() {
+        let code = Generated::new(
+            r#"
+            message Point @rust.name("Vec2") @rust.derive(Clone, Copy, Hash, Eq) @rust.vis("pub(crate)") {
+                x: i32 @rust.name("horizontal") @rust.inline;
+                y: i32 @rust.cfg(feature = "3d", unix);
+            }
+
+            message Shape @rust.cfg(feature = "shapes") {
+                origin: Point;
+            }
+        "#,
+        );
+
+        // Derives go on the owned struct, leaving out the ones it always has
+        assert_eq!(
+            derives(item_attrs(code.item("Vec2"))),
+            ["Debug", "Clone", "PartialEq", "Copy", "Hash", "Eq"]
+        );
+        assert_eq!(
+            derives(item_attrs(code.item("Vec2Reader"))),
+            ["Debug", "Clone", "Copy"]
+        );
+        for name in ["Vec2", "Vec2Reader", "Vec2Builder", "VEC2_DESCRIPTOR"] {
+            let vis = match code.item(name) {
+                syn::Item::Struct(item) => &item.vis,
+                syn::Item::Static(item) => &item.vis,
+                _ => panic!("`{name}` should be a struct or static"),
+            };
+            assert_tokens(vis, quote!(pub(crate)));
+        }
+        assert!(!code.has_item("PointReader"));
+
+        let inline: syn::Attribute = syn::parse_quote!(#[inline]);
+        assert!(has_attr(
+            &code.method("Vec2Reader", "horizontal").attrs,
+            inline.clone()
+        ));
+        assert!(has_attr(
+            &code.method("Vec2Builder", "set_horizontal").attrs,
+            inline
+        ));
+        let y = code.method("Vec2Reader", "y");
+        assert!(has_attr(
+            &y.attrs,
+            syn::parse_quote!(#[cfg(all(feature = "3d", unix))])
+        ));
+        assert_tokens(&y.sig.output, quote!(-> zeroproto::Result<i32>));
+
+        // Every item generated for a cfg-gated message carries the gate
+        let gate: syn::Attribute = syn::parse_quote!(#[cfg(feature = "shapes")]);
+        for name in ["Shape", "ShapeReader", "ShapeBuilder", "SHAPE_DESCRIPTOR"] {
+            assert!(
+                has_attr(item_attrs(code.item(name)), gate.clone()),
+                "`{name}`"
+            );
+        }
+        for self_ty in ["Shape", "ShapeReader", "ShapeBuilder"] {
+            for block in code.impls(self_ty) {
+                assert!(
+                    has_attr(&block.attrs, gate.clone()),
+                    "{}",
+                    tokens(&block.self_ty)
+                );
+            }
+        }
+        assert_tokens(
+            &code.method("ShapeReader", "origin").sig.output,
+            quote!(-> zeroproto::Result<Vec2Reader<'a>>),
+        );
+    }
+
+    #[test]
+    fn test_doc_comments_codegen() {
+        let code = Generated::new(
+            r#"
+            /// A registered user
+            message User {
+                /// Unique login name
+                name: string;
+                email: string?;
+            }
+
+            /// Display theme
+            enum Theme {
+                /// Follows the system
+                Auto = 0;
+            }
+        "#,
+        );
+
+        // Doc strings are built at codegen time, so placeholders must not leak into them
+        let printed = tokens(&code.0);
+        assert!(!printed.contains("#method_name"));
+        assert!(!printed.contains("#reader_name"));
+
+        assert_eq!(
+            docs(item_attrs(code.item("UserReader"))),
+            [
+                " Zero-copy reader for `User` messages",
+                "",
+                " A registered user"
+            ]
+        );
+        assert_eq!(
+            docs(item_attrs(code.item("UserBuilder"))),
+            [" Builder for `User` messages", "", " A registered user"]
+        );
+        assert_eq!(
+            docs(&code.method("UserReader", "name").attrs),
+            [" Get the `name` field", "", " Unique login name"]
+        );
+        assert_eq!(
+            docs(&code.method("UserBuilder", "set_name").attrs),
+            [" Set the `name` field", "", " Unique login name"]
+        );
+        assert_eq!(
+            docs(&code.method("UserReader", "email").attrs),
+            [" Get the `email` field (if present)"]
+        );
+
+        let syn::Item::Enum(theme) = code.item("Theme") else {
+            panic!("enums should be generated as enums");
+        };
+        assert_eq!(docs(&theme.attrs), [" Display theme"]);
+        assert_eq!(docs(&theme.variants[0].attrs), [" Follows the system"]);
+    }
+
+    #[test]
+    fn test_constants_and_aliases_codegen() {
+        let code = Generated::new(
+            r#"
+            /// Most friends a user can have
+            const MAX_FRIENDS: u32 = 500;
+            const maxNameLength: Size = 64;
+            const GREETING: string = "hi";
+            type Size = u16;
+            type UserId = u64;
+            type Name = string;
+            type Friends = [UserId];
+            type Owner = User;
+            message User {
+                uid: UserId;
+                name: Name;
+                friends: Friends;
+                friend_limit: u64 = MAX_FRIENDS;
+                name_limit: Size = maxNameLength;
+            }
+        "#,
+        );
+
+        assert_tokens(
+            code.item("MAX_FRIENDS"),
+            quote! {
+                #[doc = " Most friends a user can have"]
+                pub const MAX_FRIENDS: u32 = 500;
+            },
+        );
+        assert_tokens(
+            code.item("MAX_NAME_LENGTH"),
+            quote!(
+                pub const MAX_NAME_LENGTH: u16 = 64;
+            ),
+        );
+        assert_tokens(
+            code.item("GREETING"),
+            quote!(
+                pub const GREETING: &str = "hi";
+            ),
+        );
+        assert_tokens(
+            code.item("UserId"),
+            quote!(
+                pub type UserId = u64;
+            ),
+        );
+        assert_tokens(
+            code.item("Name"),
+            quote!(
+                pub type Name<'a> = &'a str;
+            ),
+        );
+        assert_tokens(
+            code.item("Friends"),
+            quote!(
+                pub type Friends<'a> = VectorReader<'a, u64>;
+            ),
+        );
+        assert_tokens(
+            code.item("OwnerReader"),
+            quote!(
+                pub type OwnerReader<'a> = UserReader<'a>;
+            ),
+        );
+        assert_tokens(
+            code.item("OwnerBuilder"),
+            quote!(
+                pub type OwnerBuilder = UserBuilder;
+            ),
+        );
+
+        // Fields use the aliased types and the constants' values
+        assert_tokens(
+            &code.method("UserReader", "uid").sig.output,
+            quote!(-> zeroproto::Result<u64>),
+        );
+        assert_contains(
+            &code.method("UserReader", "friend_limit").block,
+            quote!(Ok(500)),
+        );
+        assert_contains(
+            &code.method("UserReader", "name_limit").block,
+            quote!(Ok(64)),
+        );
+    }
+
+    #[test]
+    fn test_struct_codegen() {
+        let code = Generated::new(
+            r#"
+            enum Axis { X = 0; Y = 1; }
+            /// A point in space
+            struct Vec3 @rust.derive(Default) { x: f32; y: f32; z: f32 @rust.name("depth"); }
+            struct Ray { origin: Vec3; axis: Axis; visible: bool; }
+            type Point = Vec3;
+            message Scene { camera: Ray; points: [Point]; focus: Vec3?; }
+        "#,
+        );
+
+        assert_eq!(
+            derives(item_attrs(code.item("Vec3"))),
+            ["Debug", "Clone", "Copy", "PartialEq", "Default"]
+        );
+        assert_tokens(&code.impl_const("Vec3", "SIZE").expr, quote!(12));
+        assert_tokens(&code.impl_const("Ray", "SIZE").expr, quote!(21));
+        let syn::Item::Struct(view) = code.item("Vec3Ref") else {
+            panic!("struct views should be structs");
+        };
+        let syn::Fields::Named(fields) = &view.fields else {
+            panic!("struct views should have named fields");
+        };
+        assert_tokens(fields, quote!({ bytes: &'a [u8], }));
+        assert_eq!(
+            docs(&view.attrs),
+            [
+                " Zero-copy view of a `Vec3` struct",
+                "",
+                " A point in space"
+            ]
+        );
+
+        // Views read fields in place, at their offset in the struct
+        let depth = code.method("Vec3Ref", "depth");
+        assert_tokens(&depth.sig.output, quote!(-> f32));
+        assert_contains(&depth.block, quote!(Endian::Little.read_f32(self.bytes, 8)));
+        assert_tokens(
+            &code.method("RayRef", "origin").sig.output,
+            quote!(-> zeroproto::Result<Vec3Ref<'a>>),
+        );
+        let axis = code.method("RayRef", "axis");
+        assert_tokens(&axis.sig.output, quote!(-> zeroproto::Result<Axis>));
+        assert_contains(&axis.block, quote!(Axis::read(self.bytes, 12)));
+        assert_contains(
+            &code.method("Ray", "write").block,
+            quote!(self.visible.write(buf, offset + 20)?),
+        );
+        assert_contains(
+            &code.method("RayRef", "to_value").block,
+            quote!(self.origin()?.to_value()?),
+        );
+        assert_tokens(
+            code.item("Point"),
+            quote!(
+                pub type Point = Vec3;
+            ),
+        );
+        assert_tokens(
+            code.item("PointRef"),
+            quote!(
+                pub type PointRef<'a> = Vec3Ref<'a>;
+            ),
+        );
+
+        // Messages store structs inline and hand out views of them
+        let camera = code.method("SceneReader", "camera");
+        assert_tokens(&camera.sig.output, quote!(-> zeroproto::Result<RayRef<'a>>));
+        assert_contains(&camera.block, quote!(self.reader.get_struct(0u16)));
+        assert_tokens(
+            &code.method("SceneReader", "points").sig.output,
+            quote!(-> zeroproto::Result<VectorReader<'a, Vec3Ref<'a>>>),
+        );
+        assert_tokens(
+            &code.method("SceneReader", "focus").sig.output,
+            quote!(-> zeroproto::Result<Option<Vec3Ref<'a>>>),
+        );
+        assert_tokens(
+            &code.method("SceneBuilder", "set_camera").sig,
+            quote!(fn set_camera(&mut self, value: Ray) -> &mut Self),
+        );
+        assert_contains(
+            &code.method("SceneBuilder", "set_points").block,
+            quote!(self.builder.set_struct_vector(1u16, values)),
+        );
+        assert_tokens(
+            &code.method("SceneBuilder", "set_optional_focus").sig,
+            quote!(fn set_optional_focus(&mut self, value: Option<Vec3>) -> &mut Self),
+        );
+    }
+
+    #[test]
+    fn test_well_known_codegen() {
+        let code = Generated::new(
+            r#"
+            struct Span { start: timestamp; trace: uuid; }
+            message Event { at: timestamp; took: duration?; ids: [uuid]; total: decimal; span: Span; }
+        "#,
+        );
+
+        assert_tokens(
+            &code.method("EventReader", "at").sig.output,
+            quote!(-> zeroproto::Result<zeroproto::Timestamp>),
+        );
+        assert_tokens(
+            &code.method("EventReader", "took").sig.output,
+            quote!(-> zeroproto::Result<Option<zeroproto::Duration>>),
+        );
+        assert_tokens(
+            &code.method("EventReader", "ids").sig.output,
+            quote!(-> zeroproto::Result<VectorReader<'a, zeroproto::Uuid>>),
+        );
+        assert_tokens(
+            &code.method("EventBuilder", "set_total").sig,
+            quote!(fn set_total(&mut self, value: zeroproto::Decimal) -> &mut Self),
+        );
+        assert_tokens(
+            &code.method("EventBuilder", "set_ids").sig,
+            quote!(fn set_ids(&mut self, values: &[zeroproto::Uuid]) -> &mut Self),
+        );
+
+        // Struct fields read them through `ZpRead`, since they can be invalid
+        let syn::Item::Struct(span) = code.item("Span") else {
+            panic!("structs should be generated as structs");
+        };
+        assert_tokens(
+            &span.fields.iter().next().unwrap().ty,
+            quote!(zeroproto::Timestamp),
+        );
+        assert_tokens(&code.impl_const("Span", "SIZE").expr, quote!(28));
+        let trace = code.method("SpanRef", "trace");
+        assert_tokens(
+            &trace.sig.output,
+            quote!(-> zeroproto::Result<zeroproto::Uuid>),
+        );
+        assert_contains(
+            &trace.block,
+            quote!(<zeroproto::Uuid as ZpRead>::read(self.bytes, 12)),
+        );
+    }
+
+    #[test]
+    fn test_owned_codegen() {
+        let code = Generated::new(
+            r#"
+            enum Role { Admin = 0; Guest = 1; }
+            struct Vec2 { x: f32; y: f32; }
+            message Profile { bio: string; }
+            union Shape { node: Node; profile: Profile; }
+            message Node {
+                name: string = "root";
+                role: Role;
+                at: Vec2?;
+                friends: [u64];
+                profile: Profile;
+                shape: Shape;
+                next: Node?;
+            }
+        "#,
+        );
+
+        let syn::Item::Struct(node) = code.item("Node") else {
+            panic!("owned messages should be structs");
+        };
+        let syn::Fields::Named(fields) = &node.fields else {
+            panic!("owned messages should have named fields");
+        };
+        assert_tokens(
+            fields,
+            quote!({
+                pub name: String,
+                pub role: Role,
+                pub at: Option<Vec2>,
+                pub friends: Vec<u64>,
+                pub profile: Profile,
+                pub shape: Box<Shape>,
+                pub next: Option<Box<Node>>,
+            }),
+        );
+        let syn::Item::Enum(shape) = code.item("Shape") else {
+            panic!("owned unions should be enums");
+        };
+        assert_tokens(&shape.variants[0], quote!(Node(Box<Node>)));
+        assert_tokens(&shape.variants[1], quote!(Profile(Profile)));
+
+        // Defaults come from the schema, then from each type
+        assert_contains(
+            &code.method("Node", "default").block,
+            quote!(Self {
+                name: "root".into(),
+                role: Default::default(),
+                at: None,
+                friends: Default::default(),
+                profile: Default::default(),
+                shape: Default::default(),
+                next: None,
+            }),
+        );
+        let syn::Item::Enum(role) = code.item("Role") else {
+            panic!("enums should be generated as enums");
+        };
+        assert!(has_attr(
+            &role.variants[0].attrs,
+            syn::parse_quote!(#[default])
+        ));
+        assert_eq!(
+            derives(item_attrs(code.item("Vec2"))),
+            ["Debug", "Clone", "Copy", "PartialEq", "Default"]
+        );
+        assert_contains(
+            &code.method("Shape", "default").block,
+            quote!(Shape::Node(Default::default())),
+        );
+
+        let from_reader = code.method("Node", "from_reader");
+        assert_tokens(
+            &from_reader.sig,
+            quote!(fn from_reader(reader: &NodeReader<'_>) -> zeroproto::Result<Self>),
+        );
+        assert_contains(
+            &from_reader.block,
+            quote!(if reader.has_field(0u16)? {
+                reader.get_string(0u16)?.into()
+            } else {
+                "root".into()
+            }),
+        );
+        assert_contains(
+            &from_reader.block,
+            quote!(if reader.has_field(6u16)? {
+                Some(Box::new(Node::from_reader(&NodeReader::new(
+                    reader.get_message(6u16)?,
+                ))?))
+            } else {
+                None
+            }),
+        );
+        let encode = code.method("Node", "encode");
+        assert_contains(
+            &encode.block,
+            quote!(builder.set_scalar(1u16, *value as u64).unwrap()),
+        );
+        assert_contains(
+            &encode.block,
+            quote!(builder
+                .set_union(5u16, value.discriminant(), &value.encode())
+                .unwrap()),
+        );
+    }
+
+    #[test]
+    fn test_required_fields_codegen() {
+        let code = Generated::new(
+            r#"
+            message User {
+                user_id: u64;
+                nickname: string?;
+                role: u8 = 1;
+                email: string @rust.cfg(feature = "email");
+                legacy_id: u64 @deprecated;
+            }
+            message Empty { note: string?; }
+        "#,
+        );
+
+        let finish = code.method("UserBuilder", "finish");
+        assert_tokens(
+            &finish.sig,
+            quote!(fn finish(self) -> core::result::Result<Vec<u8>, zeroproto::MissingFields>),
+        );
+
+        // Only fields without `?`, a default or `@deprecated` are checked, in declaration order
+        let checks: Vec<_> = finish
+            .block
+            .stmts
+            .iter()
+            .filter_map(|stmt| match stmt {
+                syn::Stmt::Expr(syn::Expr::If(check), _)
+                    if tokens(&check.cond).contains("has_field") =>
+                {
+                    Some(check)
+                }
+                _ => None,
+            })
+            .collect();
+        assert_eq!(checks.len(), 2);
+        assert_tokens(&*checks[0].cond, quote!(!self.builder.has_field(0u16)));
+        assert_contains(&checks[0].then_branch, quote!(missing.push("user_id")));
+        assert!(checks[0].attrs.is_empty());
+        assert_tokens(&*checks[1].cond, quote!(!self.builder.has_field(3u16)));
+        assert_contains(&checks[1].then_branch, quote!(missing.push("email")));
+        assert!(has_attr(
+            &checks[1].attrs,
+            syn::parse_quote!(#[cfg(feature = "email")])
+        ));
+        assert_contains(
+            &finish.block,
+            quote!(zeroproto::MissingFields {
+                message: "User",
+                fields: missing,
+            }),
+        );
+
+        // Messages with nothing required always finish
+        assert_tokens(
+            &code.method("EmptyBuilder", "finish").block,
+            quote!({ Ok(self.builder.finish()) }),
+        );
+        assert_tokens(
+            &code.method("UserBuilder", "finish_unchecked").sig,
+            quote!(fn finish_unchecked(self) -> Vec<u8>),
+        );
+        assert!(!code.has_method("UserBuilder", "finish_reader"));
+    }
+
+    #[test]
+    fn test_any_codegen() {
+        let code = Generated::new(
+            r#"
+            package acme.events;
+            message Point { x: i32; }
+            message Envelope { body: any; extra: any?; }
+        "#,
+        );
+
+        assert_tokens(
+            &code.method("EnvelopeReader", "body").sig.output,
+            quote!(-> zeroproto::Result<zeroproto::AnyReader<'a>>),
+        );
+        assert_contains(
+            &code.method("EnvelopeReader", "extra").block,
+            quote!(self.reader.try_get_any(1u16)),
+        );
+        assert_tokens(
+            &code.method("EnvelopeBuilder", "set_body").sig,
+            quote!(fn set_body<M: zeroproto::any::TypedBuilder>(
+                &mut self,
+                value: M,
+            ) -> core::result::Result<&mut Self, zeroproto::MissingFields>),
+        );
+        assert_contains(
+            &code.method("EnvelopeBuilder", "into_bytes").block,
+            quote!(self.finish()),
+        );
+        assert!(code.has_method("EnvelopeBuilder", "clear_extra"));
+
+        // Messages are named by package, for downcasting and registration
+        let syn::Item::Static(descriptor) = code.item("POINT_DESCRIPTOR") else {
+            panic!("descriptors should be statics");
+        };
+        assert_contains(&descriptor.expr, quote!("acme.events.Point"));
+        let reader = code.trait_impl("TypedReader", "PointReader");
+        assert_tokens(
+            &reader.trait_.as_ref().unwrap().1,
+            quote!(zeroproto::any::TypedReader<'a>),
+        );
+        assert_tokens(
+            &code.impl_const("PointReader", "TYPE_NAME").expr,
+            quote!("acme.events.Point"),
+        );
+        assert_tokens(
+            &code
+                .trait_impl("TypedBuilder", "EnvelopeBuilder")
+                .trait_
+                .as_ref()
+                .unwrap()
+                .1,
+            quote!(zeroproto::any::TypedBuilder),
+        );
+        let syn::Item::Fn(register) = code.item("register_types") else {
+            panic!("`register_types` should be a function");
+        };
+        assert_tokens(
+            &register.block,
+            quote!({
+                registry.register(&POINT_DESCRIPTOR);
+                registry.register(&ENVELOPE_DESCRIPTOR);
+            }),
+        );
     }
 }
@@ -0,0 +1,755 @@
+//! Compatibility checking between two versions of a schema
+//!
+//! [`check`] compares an old and a new schema and classifies every change by which
+//! direction it stays compatible in:
+//!
+//! - *Backward compatible*: code generated from the new schema reads data written with the old one.
+//! - *Forward compatible*: code generated from the old schema reads data written with the new one.
+//!
+//! Fields are matched by name, so moving a field to a different number is reported as
+//! a move rather than as a removal plus an addition. Struct fields have no numbers and
+//! are matched by position instead, since their layout is the whole encoding.
+
+use crate::ast::{Enum, Field, Message, Schema, Struct, Union};
+use std::collections::HashMap;
+use std::fmt;
+
+/// How a change affects readers on either side of it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Compatibility {
+    /// Old and new readers can both read data from either side
+    Safe,
+    /// Old readers can read new data, but new readers may fail on old data
+    ForwardOnly,
+    /// New readers can read old data, but old readers may fail on new data
+    BackwardOnly,
+    /// Neither side can rely on reading the other's data
+    Breaking,
+}
+
+impl fmt::Display for Compatibility {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Compatibility::Safe => "safe",
+            Compatibility::ForwardOnly => "forward-only",
+            Compatibility::BackwardOnly => "backward-only",
+            Compatibility::Breaking => "breaking",
+        })
+    }
+}
+
+/// What changed between the two schemas
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChangeKind {
+    /// A message, enum, union, or struct was added
+    TypeAdded,
+    /// A message, enum, union, or struct was removed
+    TypeRemoved,
+    /// A field was added; `trailing` if its number is past every old field's
+    FieldAdded { number: u16, trailing: bool },
+    /// A field was removed; `reserved` if the new schema reserves its number
+    FieldRemoved { number: u16, reserved: bool },
+    /// A field kept its number (or struct position) but got a new name
+    FieldRenamed { old_name: String },
+    /// A field kept its name but got a new number
+    FieldMoved { old_number: u16, new_number: u16 },
+    /// A field, struct field, or union variant changed type
+    TypeChanged { old: String, new: String },
+    /// An optional (or defaulted) field became required
+    OptionalToRequired,
+    /// A required field became optional (or got a default)
+    RequiredToOptional,
+    /// An enum variant was added
+    EnumValueAdded { value: i64 },
+    /// An enum variant was removed
+    EnumValueRemoved { value: i64 },
+    /// An enum variant kept its name but got a new value
+    EnumValueChanged { old: i64, new: i64 },
+    /// A union variant was added
+    VariantAdded { discriminant: u16 },
+    /// A union variant was removed
+    VariantRemoved { discriminant: u16 },
+    /// A union variant kept its discriminant but got a new name
+    VariantRenamed { old_name: String },
+    /// A struct field was added, changing the struct's layout
+    StructFieldAdded { position: usize },
+    /// A struct field was removed, changing the struct's layout
+    StructFieldRemoved { position: usize },
+}
+
+impl fmt::Display for ChangeKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChangeKind::TypeAdded => write!(f, "added"),
+            ChangeKind::TypeRemoved => write!(f, "removed"),
+            ChangeKind::FieldAdded {
+                number,
+                trailing: true,
+            } => write!(f, "field added at the end as @{}", number),
+            ChangeKind::FieldAdded {
+                number,
+                trailing: false,
+            } => write!(f, "field added as @{}", number),
+            ChangeKind::FieldRemoved {
+                number,
+                reserved: true,
+            } => write!(f, "field @{} removed and reserved", number),
+            ChangeKind::FieldRemoved {
+                number,
+                reserved: false,
+            } => write!(
+                f,
+                "field @{} removed without reserving its number or name",
+                number
+            ),
+            ChangeKind::FieldRenamed { old_name } => write!(f, "renamed from '{}'", old_name),
+            ChangeKind::FieldMoved {
+                old_number,
+                new_number,
+            } => write!(f, "moved from @{} to @{}", old_number, new_number),
+            ChangeKind::TypeChanged { old, new } => {
+                write!(f, "type changed from {} to {}", old, new)
+            }
+            ChangeKind::OptionalToRequired => write!(f, "changed from optional to required"),
+            ChangeKind::RequiredToOptional => write!(f, "changed from required to optional"),
+            ChangeKind::EnumValueAdded { value } => write!(f, "value {} added", value),
+            ChangeKind::EnumValueRemoved { value } => write!(f, "value {} removed", value),
+            ChangeKind::EnumValueChanged { old, new } => {
+                write!(f, "value changed from {} to {}", old, new)
+            }
+            ChangeKind::VariantAdded { discriminant } => {
+                write!(f, "variant added with discriminant {}", discriminant)
+            }
+            ChangeKind::VariantRemoved { discriminant } => {
+                write!(f, "variant with discriminant {} removed", discriminant)
+            }
+            ChangeKind::VariantRenamed { old_name } => write!(f, "renamed from '{}'", old_name),
+            ChangeKind::StructFieldAdded { position } => {
+                write!(f, "struct field added at position {}", position)
+            }
+            ChangeKind::StructFieldRemoved { position } => {
+                write!(f, "struct field at position {} removed", position)
+            }
+        }
+    }
+}
+
+/// A single classified change
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Change {
+    /// Where the change is, such as `User`, `User.email`, or `Status::Active`
+    pub path: String,
+    /// What changed
+    pub kind: ChangeKind,
+    /// How the change affects old and new readers
+    pub compatibility: Compatibility,
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}: {}", self.compatibility, self.path, self.kind)
+    }
+}
+
+/// Every change between two schemas
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CompatReport {
+    /// Changes in schema order
+    pub changes: Vec<Change>,
+}
+
+impl CompatReport {
+    /// Check whether the schemas are identical on the wire and in names
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Compatibility of all changes taken together
+    ///
+    /// A mix of forward-only and backward-only changes is breaking, since neither
+    /// direction works any more.
+    pub fn overall(&self) -> Compatibility {
+        let forward = self.has(Compatibility::ForwardOnly);
+        let backward = self.has(Compatibility::BackwardOnly);
+
+        if self.has(Compatibility::Breaking) || (forward && backward) {
+            Compatibility::Breaking
+        } else if forward {
+            Compatibility::ForwardOnly
+        } else if backward {
+            Compatibility::BackwardOnly
+        } else {
+            Compatibility::Safe
+        }
+    }
+
+    /// Check whether the changes break compatibility in both directions
+    pub fn is_breaking(&self) -> bool {
+        self.overall() == Compatibility::Breaking
+    }
+
+    fn has(&self, compatibility: Compatibility) -> bool {
+        self.changes
+            .iter()
+            .any(|change| change.compatibility == compatibility)
+    }
+}
+
+impl fmt::Display for CompatReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.changes.is_empty() {
+            return write!(f, "no changes");
+        }
+
+        for (i, change) in self.changes.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", change)?;
+        }
+        Ok(())
+    }
+}
+
+/// Compare two versions of a schema
+pub fn check(old: &Schema, new: &Schema) -> CompatReport {
+    let mut checker = Checker {
+        old,
+        new,
+        changes: Vec::new(),
+    };
+
+    for message in new.messages() {
+        match old.find_message(&message.name) {
+            Some(old_message) => checker.messages(old_message, message),
+            None => checker.push(&message.name, ChangeKind::TypeAdded, Compatibility::Safe),
+        }
+    }
+    for message in old.messages() {
+        if new.find_message(&message.name).is_none() {
+            checker.push(&message.name, ChangeKind::TypeRemoved, Compatibility::Safe);
+        }
+    }
+
+    for enum_def in new.enums() {
+        match old.find_enum(&enum_def.name) {
+            Some(old_enum) => checker.enums(old_enum, enum_def),
+            None => checker.push(&enum_def.name, ChangeKind::TypeAdded, Compatibility::Safe),
+        }
+    }
+    for enum_def in old.enums() {
+        if new.find_enum(&enum_def.name).is_none() {
+            checker.push(&enum_def.name, ChangeKind::TypeRemoved, Compatibility::Safe);
+        }
+    }
+
+    for union_def in new.unions() {
+        match old.find_union(&union_def.name) {
+            Some(old_union) => checker.unions(old_union, union_def),
+            None => checker.push(&union_def.name, ChangeKind::TypeAdded, Compatibility::Safe),
+        }
+    }
+    for union_def in old.unions() {
+        if new.find_union(&union_def.name).is_none() {
+            checker.push(
+                &union_def.name,
+                ChangeKind::TypeRemoved,
+                Compatibility::Safe,
+            );
+        }
+    }
+
+    for struct_def in new.structs() {
+        match old.find_struct(&struct_def.name) {
+            Some(old_struct) => checker.structs(old_struct, struct_def),
+            None => checker.push(&struct_def.name, ChangeKind::TypeAdded, Compatibility::Safe),
+        }
+    }
+    for struct_def in old.structs() {
+        if new.find_struct(&struct_def.name).is_none() {
+            checker.push(
+                &struct_def.name,
+                ChangeKind::TypeRemoved,
+                Compatibility::Safe,
+            );
+        }
+    }
+
+    CompatReport {
+        changes: checker.changes,
+    }
+}
+
+struct Checker<'a> {
+    old: &'a Schema,
+    new: &'a Schema,
+    changes: Vec<Change>,
+}
+
+impl Checker<'_> {
+    fn push(&mut self, path: &str, kind: ChangeKind, compatibility: Compatibility) {
+        self.changes.push(Change {
+            path: path.to_string(),
+            kind,
+            compatibility,
+        });
+    }
+
+    fn messages(&mut self, old: &Message, new: &Message) {
+        let old_fields = numbered_fields(old);
+        let new_fields = numbered_fields(new);
+        let old_by_name: HashMap<&str, (u16, &Field)> = old_fields
+            .iter()
+            .map(|(number, field)| (field.name.as_str(), (*number, *field)))
+            .collect();
+        let new_by_name: HashMap<&str, (u16, &Field)> = new_fields
+            .iter()
+            .map(|(number, field)| (field.name.as_str(), (*number, *field)))
+            .collect();
+        let new_by_number: HashMap<u16, &Field> = new_fields.iter().copied().collect();
+        let old_max = old_fields.iter().map(|(number, _)| *number).max();
+
+        for (number, old_field) in &old_fields {
+            let path = format!("{}.{}", new.name, old_field.name);
+
+            if let Some((new_number, new_field)) = new_by_name.get(old_field.name.as_str()) {
+                if new_number != number {
+                    self.push(
+                        &path,
+                        ChangeKind::FieldMoved {
+                            old_number: *number,
+                            new_number: *new_number,
+                        },
+                        Compatibility::Breaking,
+                    );
+                } else {
+                    self.fields(&path, old_field, new_field);
+                }
+                continue;
+            }
+
+            // Same number under a name the old schema didn't have is a rename
+            match new_by_number.get(number) {
+                Some(new_field) if !old_by_name.contains_key(new_field.name.as_str()) => {
+                    let path = format!("{}.{}", new.name, new_field.name);
+                    self.push(
+                        &path,
+                        ChangeKind::FieldRenamed {
+                            old_name: old_field.name.clone(),
+                        },
+                        Compatibility::Safe,
+                    );
+                    self.fields(&path, old_field, new_field);
+                }
+                _ => {
+                    let reserved = new.reserved_numbers.contains(number);
+                    let compatibility = if old_field.is_required() {
+                        Compatibility::BackwardOnly
+                    } else {
+                        Compatibility::Safe
+                    };
+                    self.push(
+                        &path,
+                        ChangeKind::FieldRemoved {
+                            number: *number,
+                            reserved,
+                        },
+                        compatibility,
+                    );
+                }
+            }
+        }
+
+        for (number, new_field) in &new_fields {
+            if old_by_name.contains_key(new_field.name.as_str()) {
+                continue;
+            }
+            let renamed = old_fields.iter().any(|(old_number, old_field)| {
+                old_number == number && !new_by_name.contains_key(old_field.name.as_str())
+            });
+            if renamed {
+                continue;
+            }
+
+            let compatibility = if new_field.is_required() {
+                Compatibility::ForwardOnly
+            } else {
+                Compatibility::Safe
+            };
+            self.push(
+                &format!("{}.{}", new.name, new_field.name),
+                ChangeKind::FieldAdded {
+                    number: *number,
+                    trailing: old_max.map_or(true, |max| *number > max),
+                },
+                compatibility,
+            );
+        }
+    }
+
+    /// Compare two fields stored under the same number
+    fn fields(&mut self, path: &str, old: &Field, new: &Field) {
+        // Fields are compared by what they're stored as, whatever aliases name it
+        let old_type = field_type_name(old, self.old);
+        let new_type = field_type_name(new, self.new);
+        if old_type != new_type {
+            self.push(
+                path,
+                ChangeKind::TypeChanged {
+                    old: old_type,
+                    new: new_type,
+                },
+                Compatibility::Breaking,
+            );
+            return;
+        }
+
+        match (old.is_required(), new.is_required()) {
+            (false, true) => self.push(
+                path,
+                ChangeKind::OptionalToRequired,
+                Compatibility::ForwardOnly,
+            ),
+            (true, false) => self.push(
+                path,
+                ChangeKind::RequiredToOptional,
+                Compatibility::BackwardOnly,
+            ),
+            _ => {}
+        }
+    }
+
+    fn enums(&mut self, old: &Enum, new: &Enum) {
+        let old_values = enum_values(old);
+        let new_values = enum_values(new);
+
+        for (name, new_value) in &new_values {
+            let path = format!("{}::{}", new.name, name);
+            match old_values.iter().find(|(old_name, _)| old_name == name) {
+                Some((_, old_value)) if old_value != new_value => self.push(
+                    &path,
+                    ChangeKind::EnumValueChanged {
+                        old: *old_value,
+                        new: *new_value,
+                    },
+                    Compatibility::Breaking,
+                ),
+                Some(_) => {}
+                None => self.push(
+                    &path,
+                    ChangeKind::EnumValueAdded { value: *new_value },
+                    Compatibility::BackwardOnly,
+                ),
+            }
+        }
+
+        for (name, old_value) in &old_values {
+            if !new_values.iter().any(|(new_name, _)| new_name == name) {
+                self.push(
+                    &format!("{}::{}", new.name, name),
+                    ChangeKind::EnumValueRemoved { value: *old_value },
+                    Compatibility::ForwardOnly,
+                );
+            }
+        }
+    }
+
+    fn unions(&mut self, old: &Union, new: &Union) {
+        for (discriminant, new_variant) in new.variants.iter().enumerate() {
+            let path = format!("{}::{}", new.name, new_variant.name);
+            let discriminant = discriminant as u16;
+
+            let Some(old_variant) = old.variants.get(discriminant as usize) else {
+                self.push(
+                    &path,
+                    ChangeKind::VariantAdded { discriminant },
+                    Compatibility::BackwardOnly,
+                );
+                continue;
+            };
+
+            if old_variant.name != new_variant.name {
+                self.push(
+                    &path,
+                    ChangeKind::VariantRenamed {
+                        old_name: old_variant.name.clone(),
+                    },
+                    Compatibility::Safe,
+                );
+            }
+            let old_type = self.old.resolve_alias(&old_variant.variant_type);
+            let new_type = self.new.resolve_alias(&new_variant.variant_type);
+            if old_type != new_type {
+                self.push(
+                    &path,
+                    ChangeKind::TypeChanged {
+                        old: old_type.to_string(),
+                        new: new_type.to_string(),
+                    },
+                    Compatibility::Breaking,
+                );
+            }
+        }
+
+        for (discriminant, old_variant) in old.variants.iter().enumerate().skip(new.variants.len())
+        {
+            self.push(
+                &format!("{}::{}", new.name, old_variant.name),
+                ChangeKind::VariantRemoved {
+                    discriminant: discriminant as u16,
+                },
+                Compatibility::ForwardOnly,
+            );
+        }
+    }
+
+    fn structs(&mut self, old: &Struct, new: &Struct) {
+        for (position, new_field) in new.fields.iter().enumerate() {
+            let path = format!("{}.{}", new.name, new_field.name);
+
+            let Some(old_field) = old.fields.get(position) else {
+                self.push(
+                    &path,
+                    ChangeKind::StructFieldAdded { position },
+                    Compatibility::Breaking,
+                );
+                continue;
+            };
+
+            if old_field.name != new_field.name {
+                self.push(
+                    &path,
+                    ChangeKind::FieldRenamed {
+                        old_name: old_field.name.clone(),
+                    },
+                    Compatibility::Safe,
+                );
+            }
+            let old_type = self.old.resolve_alias(&old_field.field_type);
+            let new_type = self.new.resolve_alias(&new_field.field_type);
+            if old_type != new_type {
+                self.push(
+                    &path,
+                    ChangeKind::TypeChanged {
+                        old: old_type.to_string(),
+                        new: new_type.to_string(),
+                    },
+                    Compatibility::Breaking,
+                );
+            }
+        }
+
+        for (position, old_field) in old.fields.iter().enumerate().skip(new.fields.len()) {
+            self.push(
+                &format!("{}.{}", new.name, old_field.name),
+                ChangeKind::StructFieldRemoved { position },
+                Compatibility::Breaking,
+            );
+        }
+    }
+}
+
+/// Pair each field with its wire number
+fn numbered_fields(message: &Message) -> Vec<(u16, &Field)> {
+    message
+        .field_numbers()
+        .into_iter()
+        .map(|number| number as u16)
+        .zip(&message.fields)
+        .collect()
+}
+
+/// Name of a field's wire type, including `@packed` since it changes the encoding
+fn field_type_name(field: &Field, schema: &Schema) -> String {
+    let field_type = schema.resolve_alias(&field.field_type);
+    if field.packed {
+        format!("{} @packed", field_type)
+    } else {
+        field_type.to_string()
+    }
+}
+
+/// Value of each enum variant, in declaration order
+fn enum_values(enum_def: &Enum) -> Vec<(&str, i64)> {
+    enum_def
+        .variants
+        .iter()
+        .enumerate()
+        .map(|(i, variant)| (variant.name.as_str(), variant.value.unwrap_or(i as i64)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    fn compare(old: &str, new: &str) -> CompatReport {
+        check(&parse(old).unwrap(), &parse(new).unwrap())
+    }
+
+    #[test]
+    fn test_identical_schemas() {
+        let schema = "message User { user_id: u64; name: string?; }";
+        let report = compare(schema, schema);
+        assert!(report.is_empty());
+        assert_eq!(report.overall(), Compatibility::Safe);
+        assert_eq!(report.to_string(), "no changes");
+    }
+
+    #[test]
+    fn test_field_additions_and_removals() {
+        let report = compare(
+            "message User { user_id: u64; name: string; nickname: string?; }",
+            "message User { user_id: u64; reserved 1; reserved \"name\"; email: string? @3; age: u8 @4; }",
+        );
+
+        assert_eq!(
+            report.to_string(),
+            "backward-only: User.name: field @1 removed and reserved\n\
+             safe: User.nickname: field @2 removed without reserving its number or name\n\
+             safe: User.email: field added at the end as @3\n\
+             forward-only: User.age: field added at the end as @4"
+        );
+        assert!(report.is_breaking());
+    }
+
+    #[test]
+    fn test_optional_trailing_field_is_safe() {
+        let report = compare(
+            "message User { user_id: u64; }",
+            "message User { user_id: u64; email: string?; score: u32 = 0; }",
+        );
+        assert_eq!(report.overall(), Compatibility::Safe);
+        assert_eq!(report.changes.len(), 2);
+    }
+
+    #[test]
+    fn test_reorder_and_type_change() {
+        let report = compare(
+            "message User { user_id: u64; name: string; age: u8; }",
+            "message User { name: string; user_id: u64; age: u16; }",
+        );
+
+        assert_eq!(
+            report.to_string(),
+            "breaking: User.user_id: moved from @0 to @1\n\
+             breaking: User.name: moved from @1 to @0\n\
+             breaking: User.age: type changed from u8 to u16"
+        );
+
+        // Explicit numbers make the same reorder safe
+        let report = compare(
+            "message User { user_id: u64; name: string; }",
+            "message User { name: string @1; user_id: u64 @0; }",
+        );
+        assert!(report.is_empty());
+    }
+
+    #[test]
+    fn test_aliased_types() {
+        // Naming a type with an alias doesn't change its encoding
+        let report = compare(
+            "message User { user_id: u64; }",
+            "type UserId = u64; message User { user_id: UserId; }",
+        );
+        assert!(report.is_empty());
+
+        // Changing what an alias stands for changes every field that uses it
+        let report = compare(
+            "type UserId = u32; message User { user_id: UserId; }",
+            "type UserId = u64; message User { user_id: UserId; }",
+        );
+        assert_eq!(
+            report.to_string(),
+            "breaking: User.user_id: type changed from u32 to u64"
+        );
+    }
+
+    #[test]
+    fn test_rename_and_presence_changes() {
+        let report = compare(
+            "message User { user_id: u64; name: string?; age: u8; }",
+            "message User { user_id: u64; full_name: string; age: u8?; }",
+        );
+
+        assert_eq!(
+            report.to_string(),
+            "safe: User.full_name: renamed from 'name'\n\
+             forward-only: User.full_name: changed from optional to required\n\
+             backward-only: User.age: changed from required to optional"
+        );
+        assert!(report.is_breaking());
+
+        let report = compare(
+            "message User { flags: [bool]; }",
+            "message User { flags: [bool] @packed; }",
+        );
+        assert_eq!(
+            report.to_string(),
+            "breaking: User.flags: type changed from [bool] to [bool] @packed"
+        );
+    }
+
+    #[test]
+    fn test_enum_and_union_changes() {
+        let report = compare(
+            r#"
+            message Start {} message Stop {}
+            enum Status { Active = 1; Inactive = 2; Legacy = 3; }
+            union Command { Start: Start; Stop: Stop; }
+            "#,
+            r#"
+            message Start {} message Stop {} message Pause {}
+            enum Status { Active = 1; Inactive = 5; Banned = 6; }
+            union Command { Begin: Start; Stop: Pause; Resume: Start; }
+            "#,
+        );
+
+        assert_eq!(
+            report.to_string(),
+            "safe: Pause: added\n\
+             breaking: Status::Inactive: value changed from 2 to 5\n\
+             backward-only: Status::Banned: value 6 added\n\
+             forward-only: Status::Legacy: value 3 removed\n\
+             safe: Command::Begin: renamed from 'Start'\n\
+             breaking: Command::Stop: type changed from Stop to Pause\n\
+             backward-only: Command::Resume: variant added with discriminant 2"
+        );
+    }
+
+    #[test]
+    fn test_struct_changes() {
+        let report = compare(
+            "struct Vec3 { x: f32; y: f32; z: f32; } struct Rgb { r: u8; g: u8; b: u8; }",
+            "struct Vec3 { x: f32; height: f32; z: f64; } struct Rgb { r: u8; g: u8; } struct Uv { u: f32; }",
+        );
+
+        assert_eq!(
+            report.to_string(),
+            "safe: Vec3.height: renamed from 'y'\n\
+             breaking: Vec3.z: type changed from f32 to f64\n\
+             breaking: Rgb.b: struct field at position 2 removed\n\
+             safe: Uv: added"
+        );
+
+        let report = compare("struct Rgb { r: u8; }", "struct Rgb { r: u8; g: u8; }");
+        assert_eq!(
+            report.to_string(),
+            "breaking: Rgb.g: struct field added at position 1"
+        );
+    }
+
+    #[test]
+    fn test_overall_compatibility() {
+        let backward_only = compare(
+            "enum Status { Active = 1; }",
+            "enum Status { Active = 1; Banned = 2; }",
+        );
+        assert_eq!(backward_only.overall(), Compatibility::BackwardOnly);
+        assert!(!backward_only.is_breaking());
+
+        let forward_only = compare("message User {}", "message User { user_id: u64; }");
+        assert_eq!(forward_only.overall(), Compatibility::ForwardOnly);
+    }
+}
@@ -0,0 +1,180 @@
+//! Source locations and rendered diagnostics for schema errors
+
+use std::fmt;
+
+/// A byte range in a schema's source text
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    /// Create a span from byte offsets
+    pub fn new(start: usize, end: usize) -> Self {
+        Self { start, end }
+    }
+
+    /// The span from the start of `self` to the end of `other`
+    pub fn to(self, other: Span) -> Span {
+        Span::new(self.start, other.end.max(self.start))
+    }
+
+    /// 1-based line and column (in characters) where the span starts in `source`
+    pub fn location(&self, source: &str) -> (usize, usize) {
+        let start = floor_char_boundary(source, self.start);
+        let before = &source[..start];
+        let line = before.matches('\n').count() + 1;
+        let line_start = before.rfind('\n').map_or(0, |newline| newline + 1);
+        let column = before[line_start..].chars().count() + 1;
+        (line, column)
+    }
+}
+
+/// A single error in a schema, and where it happened
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub message: String,
+    pub span: Span,
+}
+
+impl Diagnostic {
+    /// Create a diagnostic for `span`
+    pub fn new(message: impl Into<String>, span: Span) -> Self {
+        Self {
+            message: message.into(),
+            span,
+        }
+    }
+}
+
+/// Every error found in one schema source
+///
+/// Displays as a list of errors, each with the offending source line underlined. `Debug`
+/// renders the same way, so a build script returning the error from `main` shows the
+/// snippets too.
+#[derive(Clone)]
+pub struct Diagnostics {
+    /// The file the source came from, shown in locations
+    pub file: Option<String>,
+    pub source: String,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl Diagnostics {
+    /// Diagnostics for source that didn't come from a file
+    pub fn new(source: impl Into<String>, diagnostics: Vec<Diagnostic>) -> Self {
+        Self {
+            file: None,
+            source: source.into(),
+            diagnostics,
+        }
+    }
+
+    /// Name the file the source came from
+    pub fn with_file(mut self, file: impl Into<String>) -> Self {
+        self.file = Some(file.into());
+        self
+    }
+
+    fn render(&self, f: &mut fmt::Formatter<'_>, diagnostic: &Diagnostic) -> fmt::Result {
+        let (line, column) = diagnostic.span.location(&self.source);
+        let text = self.source.lines().nth(line - 1).unwrap_or("");
+        let width = line.to_string().len();
+        let gutter = " ".repeat(width);
+
+        // Underline the span, stopping at the end of its first line
+        let start = floor_char_boundary(&self.source, diagnostic.span.start);
+        let end = floor_char_boundary(&self.source, diagnostic.span.end.max(start));
+        let underlined = self.source[start..end]
+            .split('\n')
+            .next()
+            .unwrap_or("")
+            .chars()
+            .count()
+            .max(1);
+        let indent: String = text
+            .chars()
+            .take(column - 1)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+
+        writeln!(f, "error: {}", diagnostic.message)?;
+        writeln!(
+            f,
+            "{}--> {}:{}:{}",
+            gutter,
+            self.file.as_deref().unwrap_or("<schema>"),
+            line,
+            column
+        )?;
+        writeln!(f, "{} |", gutter)?;
+        writeln!(f, "{} | {}", line, text)?;
+        write!(f, "{} | {}{}", gutter, indent, "^".repeat(underlined))
+    }
+}
+
+impl fmt::Display for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, diagnostic) in self.diagnostics.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+                writeln!(f)?;
+            }
+            self.render(f, diagnostic)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f)?;
+        fmt::Display::fmt(self, f)?;
+        writeln!(f)
+    }
+}
+
+/// The largest char boundary in `source` at or before `index`
+fn floor_char_boundary(source: &str, index: usize) -> usize {
+    let mut index = index.min(source.len());
+    while !source.is_char_boundary(index) {
+        index -= 1;
+    }
+    index
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_span_location() {
+        let source = "message A {\n    a: ü8;\n}";
+        assert_eq!(Span::new(0, 7).location(source), (1, 1));
+        assert_eq!(Span::new(19, 22).location(source), (2, 8));
+        assert_eq!(Span::new(100, 100).location(source), (3, 2));
+    }
+
+    #[test]
+    fn test_render() {
+        let source = "message User {\n    name string;\n}\n";
+        let diagnostics = Diagnostics::new(
+            source,
+            vec![Diagnostic::new(
+                "Expected ':', found 'string'",
+                Span::new(24, 30),
+            )],
+        )
+        .with_file("user.zp");
+
+        assert_eq!(
+            diagnostics.to_string(),
+            "error: Expected ':', found 'string'\n \
+             --> user.zp:2:10\n  \
+             |\n\
+             2 |     name string;\n  \
+             |          ^^^^^^"
+        );
+    }
+}
@@ -0,0 +1,479 @@
+//! Pretty-printer for ZeroProto schemas
+
+use crate::diagnostic::Span;
+use crate::parser::{lex, Token};
+use crate::Result;
+use std::ops::Range;
+
+/// Indentation of each nesting level
+const INDENT: &str = "    ";
+
+/// Format a schema in the canonical style
+///
+/// Bodies are indented by four spaces, tokens are spaced consistently, declarations with a
+/// body are separated by a blank line, and the types of consecutive fields (and the values
+/// of consecutive enum variants) are aligned. Comments and doc comments stay where they
+/// were, and at most one blank line is kept wherever the source has blank lines.
+///
+/// Input that doesn't parse is returned unchanged; use [`try_format`] to get its errors.
+pub fn format(input: &str) -> String {
+    try_format(input).unwrap_or_else(|_| input.to_string())
+}
+
+/// Format a schema, or report why it doesn't parse
+pub fn try_format(input: &str) -> Result<String> {
+    crate::parse(input)?;
+
+    let lexed = lex(input);
+    let mut formatter = Formatter {
+        source: input,
+        tokens: lexed.tokens,
+        spans: lexed.spans,
+        comments: lexed.comments,
+        position: 0,
+        comment: 0,
+        last_end: 0,
+    };
+
+    let mut lines = formatter.lines(false);
+    separate_blocks(&mut lines);
+
+    let mut output = String::new();
+    formatter.render(&lines, 0, &mut output);
+    Ok(output)
+}
+
+/// One line of output, or a declaration with a body
+enum Line {
+    Blank,
+    Comment(Span),
+    /// Tokens up to and including a `;`, and comments after it on the same line
+    Statement {
+        tokens: Range<usize>,
+        trailing: Vec<Span>,
+    },
+    /// A message, enum, union or struct
+    Block {
+        /// Tokens before the `{`
+        header: Range<usize>,
+        /// Comments after the `{` on the same line
+        open: Vec<Span>,
+        body: Vec<Line>,
+        /// Comments after the `}` on the same line
+        close: Vec<Span>,
+    },
+}
+
+/// Walks the tokens of a schema that is known to parse, placing comments between them
+struct Formatter<'a> {
+    source: &'a str,
+    /// Tokens of the input, always ending with `Token::Eof`
+    tokens: Vec<Token>,
+    spans: Vec<Span>,
+    comments: Vec<Span>,
+    position: usize,
+    /// Index of the first comment not placed yet
+    comment: usize,
+    /// Byte offset where the last placed token or comment ends
+    last_end: usize,
+}
+
+impl Formatter<'_> {
+    /// Collect the lines of the top level, or of a body up to its closing `}`
+    fn lines(&mut self, in_body: bool) -> Vec<Line> {
+        let mut lines = Vec::new();
+        loop {
+            let next = self.spans[self.position].start;
+            self.leading_comments(next, &mut lines);
+            match self.tokens[self.position] {
+                Token::Eof => break,
+                Token::RightBrace if in_body => break,
+                _ => {}
+            }
+
+            self.blank_line(next, &mut lines);
+            let line = match &self.tokens[self.position] {
+                Token::Message | Token::Enum | Token::Union => self.block(&mut lines),
+                // `struct` is only a keyword at the top level; a field may be named that
+                Token::Identifier(word) if word == "struct" && !in_body => self.block(&mut lines),
+                _ => self.statement(&mut lines),
+            };
+            lines.push(line);
+        }
+        lines
+    }
+
+    fn statement(&mut self, lines: &mut Vec<Line>) -> Line {
+        let start = self.position;
+        while !matches!(self.tokens[self.position], Token::Semicolon | Token::Eof) {
+            self.position += 1;
+        }
+        if self.tokens[self.position] == Token::Semicolon {
+            self.position += 1;
+        }
+        let tokens = start..self.position;
+
+        let end = self.spans[self.position - 1].end;
+        self.inner_comments(end, lines);
+        self.last_end = end;
+
+        // Commas between fields are optional; drop them
+        while self.tokens[self.position] == Token::Comma {
+            self.position += 1;
+            self.last_end = self.spans[self.position - 1].end;
+        }
+
+        let trailing = self.trailing_comments();
+        Line::Statement { tokens, trailing }
+    }
+
+    fn block(&mut self, lines: &mut Vec<Line>) -> Line {
+        let start = self.position;
+        while !matches!(self.tokens[self.position], Token::LeftBrace | Token::Eof) {
+            self.position += 1;
+        }
+        let header = start..self.position;
+
+        let brace = self.spans[self.position].end;
+        self.inner_comments(brace, lines);
+        self.position += 1;
+        self.last_end = brace;
+        let open = self.trailing_comments();
+
+        let body = self.lines(true);
+
+        self.position += 1;
+        self.last_end = self.spans[self.position - 1].end;
+        let close = self.trailing_comments();
+
+        Line::Block {
+            header,
+            open,
+            body,
+            close,
+        }
+    }
+
+    /// Place the comments that start before byte offset `before` on lines of their own
+    fn leading_comments(&mut self, before: usize, lines: &mut Vec<Line>) {
+        while let Some(&comment) = self.comments.get(self.comment) {
+            if comment.start >= before {
+                break;
+            }
+            self.blank_line(comment.start, lines);
+            lines.push(Line::Comment(comment));
+            self.comment += 1;
+            self.last_end = comment.end;
+        }
+    }
+
+    /// Move comments written between the tokens of a declaration to the lines above it
+    fn inner_comments(&mut self, end: usize, lines: &mut Vec<Line>) {
+        while let Some(&comment) = self.comments.get(self.comment) {
+            if comment.start >= end {
+                break;
+            }
+            lines.push(Line::Comment(comment));
+            self.comment += 1;
+        }
+    }
+
+    /// Take the comments that follow the last placed token on the same line
+    fn trailing_comments(&mut self) -> Vec<Span> {
+        let mut trailing = Vec::new();
+        while let Some(&comment) = self.comments.get(self.comment) {
+            let gap = &self.source[self.last_end..comment.start];
+            if gap.contains('\n') || !gap.trim().is_empty() {
+                break;
+            }
+            trailing.push(comment);
+            self.comment += 1;
+            self.last_end = comment.end;
+        }
+        trailing
+    }
+
+    /// Keep a single blank line where the source has one before byte offset `start`
+    fn blank_line(&self, start: usize, lines: &mut Vec<Line>) {
+        let gap = &self.source[self.last_end..start];
+        if gap.matches('\n').count() > 1 && !matches!(lines.last(), None | Some(Line::Blank)) {
+            lines.push(Line::Blank);
+        }
+    }
+
+    fn render(&self, lines: &[Line], depth: usize, output: &mut String) {
+        let indent = INDENT.repeat(depth);
+        let widths = self.label_widths(lines);
+
+        for (line, width) in lines.iter().zip(widths) {
+            match line {
+                Line::Blank => {}
+                Line::Comment(span) => {
+                    output.push_str(&indent);
+                    output.push_str(&self.comment_text(*span));
+                }
+                Line::Statement { tokens, trailing } => {
+                    output.push_str(&indent);
+                    output.push_str(&self.statement_text(tokens.clone(), width));
+                    self.push_trailing(trailing, output);
+                }
+                Line::Block {
+                    header,
+                    open,
+                    body,
+                    close,
+                } => {
+                    output.push_str(&indent);
+                    output.push_str(&self.join(header.clone()));
+                    if body.is_empty() && open.is_empty() {
+                        output.push_str(" {}");
+                    } else {
+                        output.push_str(" {");
+                        self.push_trailing(open, output);
+                        output.push('\n');
+                        self.render(body, depth + 1, output);
+                        output.push_str(&indent);
+                        output.push('}');
+                    }
+                    self.push_trailing(close, output);
+                }
+            }
+            output.push('\n');
+        }
+    }
+
+    /// The column width to pad each line's field or variant name to
+    ///
+    /// Names are aligned across runs of fields or variants; blank lines and other
+    /// declarations end a run, comments don't.
+    fn label_widths(&self, lines: &[Line]) -> Vec<usize> {
+        let mut widths = vec![0; lines.len()];
+        let mut run = Vec::new();
+        let mut width = 0;
+
+        for (i, line) in lines.iter().enumerate() {
+            match line {
+                Line::Comment(_) => continue,
+                Line::Statement { tokens, .. } => {
+                    if let Some(label) = self.label(tokens.clone()) {
+                        run.push(i);
+                        width = width.max(label.chars().count());
+                        continue;
+                    }
+                }
+                _ => {}
+            }
+            for &j in &run {
+                widths[j] = width;
+            }
+            run.clear();
+            width = 0;
+        }
+        for &j in &run {
+            widths[j] = width;
+        }
+        widths
+    }
+
+    /// The name of a field (`name: type`) or enum variant (`Name = value`)
+    fn label(&self, tokens: Range<usize>) -> Option<&str> {
+        match (
+            &self.tokens[tokens.start],
+            self.tokens.get(tokens.start + 1),
+        ) {
+            (Token::Identifier(_), Some(Token::Colon | Token::Equals)) if tokens.len() > 2 => {
+                Some(self.text(tokens.start))
+            }
+            _ => None,
+        }
+    }
+
+    fn statement_text(&self, tokens: Range<usize>, width: usize) -> String {
+        let Some(label) = self.label(tokens.clone()) else {
+            return self.join(tokens);
+        };
+        let padding = " ".repeat(width.saturating_sub(label.chars().count()));
+        if self.tokens[tokens.start + 1] == Token::Colon {
+            format!(
+                "{}:{} {}",
+                label,
+                padding,
+                self.join(tokens.start + 2..tokens.end)
+            )
+        } else {
+            format!(
+                "{}{} {}",
+                label,
+                padding,
+                self.join(tokens.start + 1..tokens.end)
+            )
+        }
+    }
+
+    /// Tokens as written in the source, with normalized spacing between them
+    fn join(&self, tokens: Range<usize>) -> String {
+        let mut text = String::new();
+        for i in tokens.clone() {
+            if i > tokens.start && self.spaced(i) {
+                text.push(' ');
+            }
+            text.push_str(self.text(i));
+        }
+        text
+    }
+
+    /// Whether token `i` is separated from the token before it by a space
+    fn spaced(&self, i: usize) -> bool {
+        let before = &self.tokens[i - 1];
+        match self.tokens[i] {
+            Token::Semicolon
+            | Token::Comma
+            | Token::Colon
+            | Token::Question
+            | Token::Dot
+            | Token::LeftParen
+            | Token::RightParen
+            | Token::RightBracket => false,
+            // Paths in attribute arguments, like `serde::Serialize`
+            _ if *before == Token::Colon && i >= 2 && self.tokens[i - 2] == Token::Colon => false,
+            _ => !matches!(
+                before,
+                Token::LeftParen | Token::LeftBracket | Token::At | Token::Dot
+            ),
+        }
+    }
+
+    fn text(&self, i: usize) -> &str {
+        let span = self.spans[i];
+        &self.source[span.start..span.end]
+    }
+
+    /// A comment as written, without trailing whitespace on any of its lines
+    fn comment_text(&self, span: Span) -> String {
+        self.source[span.start..span.end]
+            .lines()
+            .map(str::trim_end)
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn push_trailing(&self, comments: &[Span], output: &mut String) {
+        for &comment in comments {
+            output.push(' ');
+            output.push_str(&self.comment_text(comment));
+        }
+    }
+}
+
+/// Put a blank line before and after each top-level declaration with a body
+///
+/// Comments directly above a declaration stay attached to it.
+fn separate_blocks(lines: &mut Vec<Line>) {
+    let mut i = 0;
+    while i < lines.len() {
+        if matches!(lines[i], Line::Block { .. }) {
+            let mut start = i;
+            while start > 0 && matches!(lines[start - 1], Line::Comment(_)) {
+                start -= 1;
+            }
+            if start > 0 && !matches!(lines[start - 1], Line::Blank) {
+                lines.insert(start, Line::Blank);
+                i += 1;
+            }
+            if i + 1 < lines.len() && !matches!(lines[i + 1], Line::Blank) {
+                lines.insert(i + 1, Line::Blank);
+            }
+        }
+        i += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format() {
+        let input = r#"package   acme . orders ;
+import "users.zp";
+/// A customer order
+message Order @ rust . derive ( Hash , Eq ) {  // keyed by id
+  reserved 4 , "legacy";
+      id : u64 @ 1 ;
+  /// Shipping address
+  address:Address ? ;,
+  tags : [ string ] ;
+
+  note:string = "a \"quoted\"\tnote" @deprecated( "unused" ); /* old */
+}
+enum Status{Pending=0;Shipped = 0x1 ;
+
+
+  // Terminal states
+  Cancelled=-1;}
+message Empty {   }
+struct Vec3{x:f32;y:f32;
+  depth : f32 ;}
+"#;
+
+        let expected = r#"package acme.orders;
+import "users.zp";
+
+/// A customer order
+message Order @rust.derive(Hash, Eq) { // keyed by id
+    reserved 4, "legacy";
+    id:      u64 @1;
+    /// Shipping address
+    address: Address?;
+    tags:    [string];
+
+    note: string = "a \"quoted\"\tnote" @deprecated("unused"); /* old */
+}
+
+enum Status {
+    Pending = 0;
+    Shipped = 0x1;
+
+    // Terminal states
+    Cancelled = -1;
+}
+
+message Empty {}
+
+struct Vec3 {
+    x:     f32;
+    y:     f32;
+    depth: f32;
+}
+"#;
+
+        let formatted = format(input);
+        assert_eq!(formatted, expected);
+        assert_eq!(format(&formatted), formatted);
+
+        // Formatting never changes what the schema means
+        let lower =
+            |source: &str| format!("{:?}", crate::ir::lower_ast(&crate::parse(source).unwrap()));
+        assert_eq!(lower(&formatted), lower(input));
+    }
+
+    #[test]
+    fn test_format_comments() {
+        let input = "// Header comment\n\n\n\nunion Owner {\n    user: User; // a person\n    /* inline */ team : Team;\n    // Nothing follows\n}\n\n// Footer\n";
+        let expected = "// Header comment\n\nunion Owner {\n    user: User; // a person\n    /* inline */\n    team: Team;\n    // Nothing follows\n}\n\n// Footer\n";
+
+        assert_eq!(format(input), expected);
+        assert_eq!(format(expected), expected);
+        assert_eq!(format(""), "");
+    }
+
+    #[test]
+    fn test_format_invalid() {
+        let input = "message User {\n    name string;\n}\n";
+        assert_eq!(format(input), input);
+        assert!(matches!(
+            try_format(input),
+            Err(crate::CompilerError::Diagnostics(_))
+        ));
+    }
+}
@@ -1,13 +1,67 @@
 //! Intermediate Representation for ZeroProto code generation
 
 use crate::ast::*;
-use std::collections::HashMap;
 
 /// Intermediate representation of a ZeroProto schema
 #[derive(Debug, Clone)]
 pub struct IrSchema {
+    /// Path of the generated module, e.g. `["acme", "orders"]`
+    pub module: Vec<String>,
     pub messages: Vec<IrMessage>,
     pub enums: Vec<IrEnum>,
+    pub unions: Vec<IrUnion>,
+    pub structs: Vec<IrStruct>,
+    pub constants: Vec<IrConstant>,
+    pub aliases: Vec<IrTypeAlias>,
+    /// Types defined by imported schemas
+    pub externals: Vec<IrExternalType>,
+}
+
+/// A type defined by an imported schema
+#[derive(Debug, Clone)]
+pub struct IrExternalType {
+    pub name: String,
+    pub rust_name: String,
+    /// Path of the generated module that defines the type
+    pub module: Vec<String>,
+    pub kind: IrExternalKind,
+}
+
+/// What kind of type an imported name refers to
+#[derive(Debug, Clone)]
+pub enum IrExternalKind {
+    Message,
+    Enum,
+    Union(IrUnion),
+    Struct(IrStruct),
+    /// A type alias, with its target as written in the imported schema
+    Alias(FieldType),
+    /// A constant, with its value as written in the imported schema
+    Constant(DefaultValue),
+}
+
+/// Intermediate representation of a constant
+#[derive(Debug, Clone)]
+pub struct IrConstant {
+    pub name: String,
+    pub rust_name: String,
+    /// Rust type of the generated `const`
+    pub rust_type: String,
+    /// Value as Rust code
+    pub value: String,
+    /// Doc comment lines from the schema
+    pub docs: Vec<String>,
+}
+
+/// Intermediate representation of a type alias
+#[derive(Debug, Clone)]
+pub struct IrTypeAlias {
+    pub name: String,
+    pub rust_name: String,
+    /// The aliased type, with any aliases it names resolved
+    pub target: IrFieldType,
+    /// Doc comment lines from the schema
+    pub docs: Vec<String>,
 }
 
 /// Intermediate representation of a message
@@ -18,6 +72,18 @@ pub struct IrMessage {
     pub fields: Vec<IrField>,
     pub reader_name: String,
     pub builder_name: String,
+    /// Name of the generated static `MessageDescriptor`
+    pub descriptor_name: String,
+    /// Name qualified by the schema's package, as stored in `any` fields
+    pub type_name: String,
+    /// Whether the field table is written as a presence bitmap
+    pub presence_bitmap: bool,
+    /// One past the highest field index the schema declares
+    pub field_count: u16,
+    /// Namespaced attributes, including `rust.*` ones the code generator honors
+    pub attributes: Vec<Attribute>,
+    /// Doc comment lines from the schema
+    pub docs: Vec<String>,
 }
 
 /// Intermediate representation of a field
@@ -32,6 +98,43 @@ pub struct IrField {
     pub optional: bool,
     /// Default value for this field (as Rust code string)
     pub default_value: Option<String>,
+    /// Whether this `[bool]` field is bit-packed
+    pub packed: bool,
+    /// Deprecation reason, empty if the field is deprecated without one
+    pub deprecated: Option<String>,
+    /// Namespaced attributes, including `rust.*` ones the code generator honors
+    pub attributes: Vec<Attribute>,
+    /// Doc comment lines from the schema
+    pub docs: Vec<String>,
+}
+
+/// Intermediate representation of a struct
+#[derive(Debug, Clone)]
+pub struct IrStruct {
+    pub name: String,
+    /// Name of the generated owned type
+    pub rust_name: String,
+    /// Name of the generated zero-copy view
+    pub ref_name: String,
+    pub fields: Vec<IrStructField>,
+    /// Encoded size in bytes
+    pub size: usize,
+    /// Namespaced attributes, including `rust.*` ones the code generator honors
+    pub attributes: Vec<Attribute>,
+    /// Doc comment lines from the schema
+    pub docs: Vec<String>,
+}
+
+/// Intermediate representation of a struct field
+#[derive(Debug, Clone)]
+pub struct IrStructField {
+    pub name: String,
+    pub rust_name: String,
+    pub field_type: IrFieldType,
+    /// Byte offset of the field within the struct
+    pub offset: usize,
+    /// Doc comment lines from the schema
+    pub docs: Vec<String>,
 }
 
 /// Intermediate representation of a field type
@@ -52,6 +155,14 @@ pub enum IrFieldType {
         rust_type: String,
         reader_type: String,
     },
+    Union {
+        type_name: String,
+        rust_type: String,
+    },
+    Struct {
+        type_name: String,
+        rust_type: String,
+    },
 }
 
 /// Intermediate representation of an enum
@@ -60,6 +171,8 @@ pub struct IrEnum {
     pub name: String,
     pub rust_name: String,
     pub variants: Vec<IrEnumVariant>,
+    /// Doc comment lines from the schema
+    pub docs: Vec<String>,
 }
 
 /// Intermediate representation of an enum variant
@@ -68,30 +181,238 @@ pub struct IrEnumVariant {
     pub name: String,
     pub rust_name: String,
     pub value: i64,
+    /// Doc comment lines from the schema
+    pub docs: Vec<String>,
+}
+
+/// Intermediate representation of a union
+#[derive(Debug, Clone)]
+pub struct IrUnion {
+    pub name: String,
+    pub rust_name: String,
+    pub reader_name: String,
+    pub variants: Vec<IrUnionVariant>,
+    /// Doc comment lines from the schema
+    pub docs: Vec<String>,
+}
+
+/// Intermediate representation of a union variant
+#[derive(Debug, Clone)]
+pub struct IrUnionVariant {
+    pub name: String,
+    pub rust_name: String,
+    /// snake_case name used in generated setter names
+    pub method_name: String,
+    /// Discriminant written on the wire (declaration order)
+    pub discriminant: u16,
+    /// PascalCase name of the variant's message type
+    pub message_type: String,
+    /// Path of the generated module that defines the variant's message type
+    pub module: Vec<String>,
+    /// Doc comment lines from the schema
+    pub docs: Vec<String>,
+}
+
+impl IrSchema {
+    /// Find a union defined by this schema or one of its imports
+    pub fn find_union(&self, name: &str) -> Option<&IrUnion> {
+        self.unions.iter().find(|un| un.name == name).or_else(|| {
+            self.externals
+                .iter()
+                .find_map(|external| match &external.kind {
+                    IrExternalKind::Union(union_def) if external.name == name => Some(union_def),
+                    _ => None,
+                })
+        })
+    }
+
+    /// Find a struct defined by this schema or one of its imports
+    pub fn find_struct(&self, name: &str) -> Option<&IrStruct> {
+        self.structs.iter().find(|st| st.name == name).or_else(|| {
+            self.externals
+                .iter()
+                .find_map(|external| match &external.kind {
+                    IrExternalKind::Struct(struct_def) if external.name == name => Some(struct_def),
+                    _ => None,
+                })
+        })
+    }
+
+    /// Find an imported type by name
+    pub fn find_external(&self, name: &str) -> Option<&IrExternalType> {
+        self.externals.iter().find(|external| external.name == name)
+    }
+
+    /// Add the types of another schema generated into the same module
+    pub fn append(&mut self, other: IrSchema) {
+        self.messages.extend(other.messages);
+        self.enums.extend(other.enums);
+        self.unions.extend(other.unions);
+        self.structs.extend(other.structs);
+        self.constants.extend(other.constants);
+        self.aliases.extend(other.aliases);
+        for external in other.externals {
+            if self.find_external(&external.name).is_none() {
+                self.externals.push(external);
+            }
+        }
+    }
+
+    /// Path of the generated module that defines the named type
+    pub fn module_of(&self, name: &str) -> &[String] {
+        match self.find_external(name) {
+            Some(external) => &external.module,
+            None => &self.module,
+        }
+    }
 }
 
 /// Convert AST to IR
 pub fn lower_ast(schema: &Schema) -> IrSchema {
+    lower_ast_with_imports(schema, &[], Vec::new())
+}
+
+/// Convert AST to IR for a schema generated into `module` that uses the imported `externals`
+pub fn lower_ast_with_imports(
+    schema: &Schema,
+    module: &[String],
+    externals: Vec<IrExternalType>,
+) -> IrSchema {
     let mut ir = IrSchema {
+        module: module.to_vec(),
         messages: Vec::new(),
         enums: Vec::new(),
+        unions: Vec::new(),
+        structs: Vec::new(),
+        constants: Vec::new(),
+        aliases: Vec::new(),
+        externals,
     };
 
-    // First, convert all enums (needed for message field types)
+    // First, convert all enums and unions (needed for message field types)
     for enum_def in schema.enums() {
         ir.enums.push(lower_enum(enum_def));
     }
 
+    let unions: Vec<_> = schema
+        .unions()
+        .map(|union_def| lower_union(union_def, schema, module, &ir.externals))
+        .collect();
+    ir.unions = unions;
+
+    let structs: Vec<_> = schema
+        .structs()
+        .map(|struct_def| lower_struct(struct_def, schema, &ir))
+        .collect();
+    ir.structs = structs;
+
     // Then convert all messages
-    for message in schema.messages() {
-        ir.messages.push(lower_message(message, &ir.enums));
-    }
+    let messages: Vec<_> = schema
+        .messages()
+        .map(|message| lower_message(message, schema, &ir))
+        .collect();
+    ir.messages = messages;
+
+    let constants: Vec<_> = schema
+        .constants
+        .iter()
+        .map(|constant| lower_constant(constant, schema, &ir.externals))
+        .collect();
+    ir.constants = constants;
+
+    let aliases: Vec<_> = schema
+        .aliases
+        .iter()
+        .map(|alias| IrTypeAlias {
+            name: alias.name.clone(),
+            rust_name: alias_rust_name(alias),
+            target: lower_field_type(
+                &resolve_alias(&alias.target, schema, &ir.externals),
+                schema,
+                &ir,
+            ),
+            docs: alias.docs.clone(),
+        })
+        .collect();
+    ir.aliases = aliases;
 
     ir
 }
 
+/// Convert an AST constant to IR
+fn lower_constant(
+    constant: &Constant,
+    schema: &Schema,
+    externals: &[IrExternalType],
+) -> IrConstant {
+    let value_type = resolve_alias(&constant.value_type, schema, externals);
+    let value = resolve_constant(&constant.value, schema, externals);
+    let rust_type = match &value_type {
+        FieldType::Scalar(ScalarType::String) => "&str".to_string(),
+        FieldType::Scalar(scalar) => scalar.rust_type().to_string(),
+        _ => panic!("Non-scalar constants should have been caught by validator"),
+    };
+
+    IrConstant {
+        name: constant.name.clone(),
+        rust_name: constant_rust_name(constant),
+        rust_type,
+        value: lower_default_value(&value, &value_type),
+        docs: constant.docs.clone(),
+    }
+}
+
+/// Follow the type aliases `field_type` names, inside vectors too, to the types they stand for
+pub fn resolve_alias(
+    field_type: &FieldType,
+    schema: &Schema,
+    externals: &[IrExternalType],
+) -> FieldType {
+    match field_type {
+        FieldType::Scalar(_) => field_type.clone(),
+        FieldType::UserDefined(name) => {
+            let target = match schema.find_alias(name) {
+                Some(alias) => Some(&alias.target),
+                None => externals.iter().find_map(|external| match &external.kind {
+                    IrExternalKind::Alias(target) if external.name == *name => Some(target),
+                    _ => None,
+                }),
+            };
+            match target {
+                Some(target) => resolve_alias(target, schema, externals),
+                None => field_type.clone(),
+            }
+        }
+        FieldType::Vector(inner) => {
+            FieldType::Vector(Box::new(resolve_alias(inner, schema, externals)))
+        }
+    }
+}
+
+/// Follow constant references in a default value to the literal they stand for
+pub fn resolve_constant(
+    value: &DefaultValue,
+    schema: &Schema,
+    externals: &[IrExternalType],
+) -> DefaultValue {
+    let DefaultValue::Constant(name) = value else {
+        return value.clone();
+    };
+    let target = match schema.find_constant(name) {
+        Some(constant) => &constant.value,
+        None => externals
+            .iter()
+            .find_map(|external| match &external.kind {
+                IrExternalKind::Constant(value) if external.name == *name => Some(value),
+                _ => None,
+            })
+            .expect("Unknown constants should have been caught by validator"),
+    };
+    resolve_constant(target, schema, externals)
+}
+
 /// Convert an AST enum to IR
-fn lower_enum(enum_def: &Enum) -> IrEnum {
+pub fn lower_enum(enum_def: &Enum) -> IrEnum {
     let rust_name = to_pascal_case(&enum_def.name);
     let variants: Vec<_> = enum_def
         .variants
@@ -101,6 +422,7 @@ fn lower_enum(enum_def: &Enum) -> IrEnum {
             name: variant.name.clone(),
             rust_name: to_pascal_case(&variant.name),
             value: variant.value.unwrap_or(i as i64),
+            docs: variant.docs.clone(),
         })
         .collect();
 
@@ -108,36 +430,170 @@ fn lower_enum(enum_def: &Enum) -> IrEnum {
         name: enum_def.name.clone(),
         rust_name,
         variants,
+        docs: enum_def.docs.clone(),
+    }
+}
+
+/// Convert an AST union from `schema`, generated into `module`, to IR
+///
+/// `externals` are the types `schema` imports; they place variants whose messages are
+/// defined elsewhere.
+pub fn lower_union(
+    union_def: &Union,
+    schema: &Schema,
+    module: &[String],
+    externals: &[IrExternalType],
+) -> IrUnion {
+    let rust_name = to_pascal_case(&union_def.name);
+    let variants: Vec<_> = union_def
+        .variants
+        .iter()
+        .enumerate()
+        .map(|(i, variant)| {
+            let type_name = match resolve_alias(&variant.variant_type, schema, externals) {
+                FieldType::UserDefined(type_name) => type_name,
+                _ => panic!("Non-message union variants should have been caught by validator"),
+            };
+            let external = externals.iter().find(|external| external.name == type_name);
+            IrUnionVariant {
+                name: variant.name.clone(),
+                rust_name: to_pascal_case(&variant.name),
+                method_name: to_snake_case(&variant.name),
+                discriminant: i as u16,
+                message_type: type_rust_name(&type_name, schema, externals),
+                module: match external {
+                    Some(external) => external.module.clone(),
+                    None => module.to_vec(),
+                },
+                docs: variant.docs.clone(),
+            }
+        })
+        .collect();
+
+    IrUnion {
+        name: union_def.name.clone(),
+        reader_name: format!("{}Reader", rust_name),
+        rust_name,
+        variants,
+        docs: union_def.docs.clone(),
+    }
+}
+
+/// Convert an AST struct to IR, laying its fields out in declaration order
+fn lower_struct(struct_def: &Struct, schema: &Schema, ir: &IrSchema) -> IrStruct {
+    let rust_name = to_pascal_case(&struct_def.name);
+    let mut offset = 0;
+    let fields: Vec<_> = struct_def
+        .fields
+        .iter()
+        .map(|field| {
+            let resolved_type = resolve_alias(&field.field_type, schema, &ir.externals);
+            let field_offset = offset;
+            offset += struct_field_size(&resolved_type, schema, &ir.externals);
+            IrStructField {
+                name: field.name.clone(),
+                rust_name: match field
+                    .attribute("rust.name")
+                    .and_then(|attr| attr.string_arg())
+                {
+                    Some(rust_name) => rust_name.to_string(),
+                    None => to_snake_case(&field.name),
+                },
+                field_type: lower_field_type(&resolved_type, schema, ir),
+                offset: field_offset,
+                docs: field.docs.clone(),
+            }
+        })
+        .collect();
+
+    IrStruct {
+        name: struct_def.name.clone(),
+        ref_name: format!("{}Ref", rust_name),
+        rust_name,
+        fields,
+        size: offset,
+        attributes: struct_def.attributes.clone(),
+        docs: struct_def.docs.clone(),
+    }
+}
+
+/// Encoded size of a struct field: a fixed-size scalar, an enum or a struct
+fn struct_field_size(
+    field_type: &FieldType,
+    schema: &Schema,
+    externals: &[IrExternalType],
+) -> usize {
+    match resolve_alias(field_type, schema, externals) {
+        FieldType::Scalar(scalar) => scalar.size(),
+        FieldType::UserDefined(name) => {
+            if let Some(struct_def) = schema.find_struct(&name) {
+                return struct_def
+                    .fields
+                    .iter()
+                    .map(|field| struct_field_size(&field.field_type, schema, externals))
+                    .sum();
+            }
+            match externals.iter().find(|external| external.name == name) {
+                Some(IrExternalType {
+                    kind: IrExternalKind::Struct(struct_def),
+                    ..
+                }) => struct_def.size,
+                // Enums are stored as u64
+                _ => 8,
+            }
+        }
+        FieldType::Vector(_) => panic!("Vector struct fields should have been caught by validator"),
     }
 }
 
 /// Convert an AST message to IR
-fn lower_message(message: &Message, enums: &[IrEnum]) -> IrMessage {
-    let rust_name = to_pascal_case(&message.name);
+fn lower_message(message: &Message, schema: &Schema, ir: &IrSchema) -> IrMessage {
+    let rust_name = message_rust_name(message);
     let reader_name = format!("{}Reader", rust_name);
     let builder_name = format!("{}Builder", rust_name);
+    let descriptor_name = descriptor_name(&rust_name);
+    let type_name = match &schema.package {
+        Some(package) => format!("{}.{}", package, message.name),
+        None => message.name.clone(),
+    };
 
     let fields: Vec<_> = message
         .fields
         .iter()
-        .enumerate()
-        .map(|(i, field)| {
-            let field_type = lower_field_type(&field.field_type, enums);
-            let default_value = field
-                .default_value
-                .as_ref()
-                .map(|dv| lower_default_value(dv, &field.field_type));
+        .zip(message.field_numbers())
+        .map(|(field, number)| {
+            let resolved_type = resolve_alias(&field.field_type, schema, &ir.externals);
+            let field_type = lower_field_type(&resolved_type, schema, ir);
+            let default_value = field.default_value.as_ref().map(|dv| {
+                let value = resolve_constant(dv, schema, &ir.externals);
+                lower_default_value(&value, &resolved_type)
+            });
             IrField {
                 name: field.name.clone(),
-                rust_name: to_snake_case(&field.name),
+                rust_name: match field
+                    .attribute("rust.name")
+                    .and_then(|attr| attr.string_arg())
+                {
+                    Some(rust_name) => rust_name.to_string(),
+                    None => to_snake_case(&field.name),
+                },
                 field_type,
-                index: i as u16,
-                offset_constant: format!("FIELD_{}_OFFSET", i),
+                index: number as u16,
+                offset_constant: format!("FIELD_{}_OFFSET", number),
                 optional: field.optional,
                 default_value,
+                packed: field.packed,
+                deprecated: field.deprecated.clone(),
+                attributes: field.attributes.clone(),
+                docs: field.docs.clone(),
             }
         })
         .collect();
+    let field_count = fields
+        .iter()
+        .map(|field| field.index + 1)
+        .max()
+        .unwrap_or(0);
 
     IrMessage {
         name: message.name.clone(),
@@ -145,31 +601,79 @@ fn lower_message(message: &Message, enums: &[IrEnum]) -> IrMessage {
         fields,
         reader_name,
         builder_name,
+        descriptor_name,
+        type_name,
+        presence_bitmap: message.presence_bitmap,
+        field_count,
+        attributes: message.attributes.clone(),
+        docs: message.docs.clone(),
+    }
+}
+
+/// Rust name of a message's generated types: its `@rust.name`, or its name in PascalCase
+pub fn message_rust_name(message: &Message) -> String {
+    match message
+        .attribute("rust.name")
+        .and_then(|attr| attr.string_arg())
+    {
+        Some(rust_name) => rust_name.to_string(),
+        None => to_pascal_case(&message.name),
+    }
+}
+
+/// Rust name of a type alias: its name in PascalCase
+pub fn alias_rust_name(alias: &TypeAlias) -> String {
+    to_pascal_case(&alias.name)
+}
+
+/// Rust name of a constant: its name in SCREAMING_SNAKE_CASE
+pub fn constant_rust_name(constant: &Constant) -> String {
+    if constant.name.chars().any(char::is_lowercase) {
+        to_snake_case(&constant.name).to_uppercase()
+    } else {
+        constant.name.clone()
+    }
+}
+
+/// Rust name of a type defined in `schema` or one of its imports
+fn type_rust_name(type_name: &str, schema: &Schema, externals: &[IrExternalType]) -> String {
+    if let Some(message) = schema.find_message(type_name) {
+        return message_rust_name(message);
+    }
+    match externals.iter().find(|external| external.name == type_name) {
+        Some(external) => external.rust_name.clone(),
+        None => to_pascal_case(type_name),
     }
 }
 
+/// Name of the static `MessageDescriptor` generated for a message
+pub fn descriptor_name(message_name: &str) -> String {
+    format!("{}_DESCRIPTOR", to_snake_case(message_name).to_uppercase())
+}
+
 /// Convert a default value to Rust code
-fn lower_default_value(default: &crate::ast::DefaultValue, _field_type: &FieldType) -> String {
+fn lower_default_value(default: &crate::ast::DefaultValue, field_type: &FieldType) -> String {
     use crate::ast::DefaultValue;
 
+    let is_float = matches!(
+        field_type,
+        FieldType::Scalar(ScalarType::F32 | ScalarType::F64)
+    );
     match default {
+        // Float fields accept integer literals, which need a float's syntax in Rust
+        DefaultValue::Integer(val) if is_float => format!("{:?}", *val as f64),
         DefaultValue::Integer(val) => val.to_string(),
-        DefaultValue::Float(val) => {
-            // Ensure float literals have a decimal point
-            let s = val.to_string();
-            if s.contains('.') {
-                s
-            } else {
-                format!("{}.0", s)
-            }
-        }
+        // `Debug` always includes a decimal point or an exponent
+        DefaultValue::Float(val) => format!("{:?}", val),
         DefaultValue::Bool(val) => val.to_string(),
-        DefaultValue::String(val) => format!("\"{}\"", val),
+        // `Debug` escapes quotes, backslashes and control characters as Rust does
+        DefaultValue::String(val) => format!("{:?}", val),
+        DefaultValue::Constant(_) => panic!("Constants should be resolved before lowering"),
     }
 }
 
 /// Convert an AST field type to IR
-fn lower_field_type(field_type: &FieldType, enums: &[IrEnum]) -> IrFieldType {
+fn lower_field_type(field_type: &FieldType, schema: &Schema, ir: &IrSchema) -> IrFieldType {
     match field_type {
         FieldType::Scalar(scalar_type) => IrFieldType::Scalar {
             scalar_type: scalar_type.clone(),
@@ -177,8 +681,28 @@ fn lower_field_type(field_type: &FieldType, enums: &[IrEnum]) -> IrFieldType {
             primitive_id: scalar_type.primitive_type_id(),
         },
         FieldType::UserDefined(type_name) => {
-            let rust_type = to_pascal_case(type_name);
-            let is_message = !enums.iter().any(|en| en.name == *type_name);
+            let rust_type = type_rust_name(type_name, schema, &ir.externals);
+            if ir.find_union(type_name).is_some() {
+                return IrFieldType::Union {
+                    type_name: type_name.clone(),
+                    rust_type,
+                };
+            }
+            let is_struct = match ir.find_external(type_name) {
+                Some(external) => matches!(external.kind, IrExternalKind::Struct(_)),
+                None => schema.find_struct(type_name).is_some(),
+            };
+            if is_struct {
+                return IrFieldType::Struct {
+                    type_name: type_name.clone(),
+                    rust_type,
+                };
+            }
+            let is_enum = match ir.find_external(type_name) {
+                Some(external) => matches!(external.kind, IrExternalKind::Enum),
+                None => ir.enums.iter().any(|en| en.name == *type_name),
+            };
+            let is_message = !is_enum;
 
             IrFieldType::UserDefined {
                 type_name: type_name.clone(),
@@ -187,7 +711,7 @@ fn lower_field_type(field_type: &FieldType, enums: &[IrEnum]) -> IrFieldType {
             }
         }
         FieldType::Vector(inner) => {
-            let element_type = Box::new(lower_field_type(inner, enums));
+            let element_type = Box::new(lower_field_type(inner, schema, ir));
             let rust_type = match element_type.as_ref() {
                 IrFieldType::Scalar { rust_type, .. } => {
                     format!("VectorReader<'a, {}>", rust_type)
@@ -203,15 +727,21 @@ fn lower_field_type(field_type: &FieldType, enums: &[IrEnum]) -> IrFieldType {
                         format!("VectorReader<'a, {}>", rust_type)
                     }
                 }
+                IrFieldType::Struct { rust_type, .. } => {
+                    format!("VectorReader<'a, {}Ref<'a>>", rust_type)
+                }
                 IrFieldType::Vector { .. } => {
                     panic!("Nested vectors should have been caught by validator")
                 }
+                IrFieldType::Union { .. } => {
+                    panic!("Vectors of unions should have been caught by validator")
+                }
             };
 
             IrFieldType::Vector {
                 element_type,
                 rust_type,
-                reader_type: format!("VectorReader<'a, _>"),
+                reader_type: "VectorReader<'a, _>".to_string(),
             }
         }
     }
@@ -269,12 +799,12 @@ impl IrUtils {
     /// Get all dependencies of a message
     pub fn get_message_dependencies<'a>(
         message: &'a IrMessage,
-        schema: &'a IrSchema,
+        _schema: &'a IrSchema,
     ) -> Vec<&'a str> {
         let mut dependencies = Vec::new();
 
         for field in &message.fields {
-            Self::collect_field_dependencies(&field.field_type, schema, &mut dependencies);
+            Self::collect_field_dependencies(&field.field_type, &mut dependencies);
         }
 
         dependencies.sort();
@@ -285,23 +815,24 @@ impl IrUtils {
     /// Collect dependencies from a field type
     fn collect_field_dependencies<'a>(
         field_type: &'a IrFieldType,
-        schema: &'a IrSchema,
         dependencies: &mut Vec<&'a str>,
     ) {
         match field_type {
             IrFieldType::Scalar { .. } => {}
-            IrFieldType::UserDefined { type_name, .. } => {
+            IrFieldType::UserDefined { type_name, .. }
+            | IrFieldType::Union { type_name, .. }
+            | IrFieldType::Struct { type_name, .. } => {
                 dependencies.push(type_name.as_str());
             }
             IrFieldType::Vector { element_type, .. } => {
-                Self::collect_field_dependencies(element_type, schema, dependencies);
+                Self::collect_field_dependencies(element_type, dependencies);
             }
         }
     }
 
     /// Calculate the field table size for a message
     pub fn field_table_size(message: &IrMessage) -> usize {
-        message.fields.len() * 5 // type_id (1) + offset (4)
+        message.field_count as usize * 5 // type_id (1) + offset (4)
     }
 
     /// Generate field offset constants
@@ -321,16 +852,13 @@ impl IrUtils {
     pub fn field_size(field_type: &IrFieldType) -> usize {
         match field_type {
             IrFieldType::Scalar { scalar_type, .. } => scalar_type.size(),
-            IrFieldType::UserDefined { is_message, .. } => {
-                if *is_message {
-                    4 // Length prefix for nested message
-                } else {
-                    4 // Length prefix for enum
-                }
-            }
+            // Length prefix for nested messages; enums are stored inline
+            IrFieldType::UserDefined { .. } => 4,
             IrFieldType::Vector { element_type, .. } => {
                 4 + Self::field_size(element_type) // count + one element
             }
+            IrFieldType::Union { .. } => 6, // Discriminant + length prefix
+            IrFieldType::Struct { .. } => 2, // Size prefix
         }
     }
 
@@ -338,10 +866,14 @@ impl IrUtils {
     pub fn requires_lifetime(field_type: &IrFieldType) -> bool {
         match field_type {
             IrFieldType::Scalar { scalar_type, .. } => {
-                matches!(scalar_type, ScalarType::String | ScalarType::Bytes)
+                matches!(
+                    scalar_type,
+                    ScalarType::String | ScalarType::Bytes | ScalarType::Any
+                )
             }
             IrFieldType::UserDefined { is_message, .. } => *is_message,
             IrFieldType::Vector { element_type, .. } => Self::requires_lifetime(element_type),
+            IrFieldType::Union { .. } | IrFieldType::Struct { .. } => true,
         }
     }
 }
@@ -385,4 +917,57 @@ mod tests {
         assert_eq!(ir.messages[0].builder_name, "UserBuilder");
         assert_eq!(ir.messages[0].fields.len(), 2);
     }
+
+    #[test]
+    fn test_union_lowering() {
+        let mut schema = Schema::new();
+        schema.add_item(SchemaItem::Message(Message::new("start_cmd".to_string())));
+        schema.add_item(SchemaItem::Message(Message::new("stop_cmd".to_string())));
+        let mut command = Union::new("command".to_string());
+        command.add_variant(UnionVariant::new(
+            "start".to_string(),
+            FieldType::UserDefined("start_cmd".to_string()),
+        ));
+        command.add_variant(UnionVariant::new(
+            "stop".to_string(),
+            FieldType::UserDefined("stop_cmd".to_string()),
+        ));
+        schema.add_item(SchemaItem::Union(command));
+        let mut envelope = Message::new("envelope".to_string());
+        envelope.add_field(Field::new(
+            "command".to_string(),
+            FieldType::UserDefined("command".to_string()),
+        ));
+        schema.add_item(SchemaItem::Message(envelope));
+
+        let ir = lower_ast(&schema);
+        assert_eq!(ir.unions.len(), 1);
+        assert_eq!(ir.unions[0].reader_name, "CommandReader");
+        assert_eq!(ir.unions[0].variants[1].rust_name, "Stop");
+        assert_eq!(ir.unions[0].variants[1].discriminant, 1);
+        assert_eq!(ir.unions[0].variants[1].message_type, "StopCmd");
+        assert!(matches!(
+            ir.messages[2].fields[0].field_type,
+            IrFieldType::Union { .. }
+        ));
+    }
+
+    #[test]
+    fn test_default_value_lowering() {
+        let schema = crate::parse(
+            r#"message M { a: string = "say \"hi\"\n"; b: f32 = 2; c: f64 = 1e-3; d: u64 = 0xFF; }"#,
+        )
+        .unwrap();
+
+        let ir = lower_ast(&schema);
+        let defaults: Vec<_> = ir.messages[0]
+            .fields
+            .iter()
+            .map(|field| field.default_value.clone().unwrap())
+            .collect();
+        assert_eq!(defaults, ["\"say \\\"hi\\\"\\n\"", "2.0", "0.001", "255"]);
+        for default in &defaults {
+            assert!(syn::parse_str::<syn::Expr>(default).is_ok());
+        }
+    }
 }
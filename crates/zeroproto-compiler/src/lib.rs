@@ -2,14 +2,20 @@
 
 pub mod ast;
 pub mod codegen;
+pub mod compat;
+pub mod diagnostic;
+pub mod formatter;
 pub mod ir;
+pub mod loader;
 pub mod parser;
 pub mod primitives;
 pub mod validator;
 
 // Re-export commonly used functions
+pub use formatter::format;
 pub use parser::parse;
 
+use std::collections::BTreeMap;
 use std::path::{Path, PathBuf};
 use thiserror::Error;
 
@@ -22,8 +28,15 @@ pub enum CompilerError {
     #[error("Parse error: {0}")]
     Parse(String),
 
-    #[error("Validation error: {0}")]
-    Validation(String),
+    /// Errors located in a schema's source, rendered with snippets when displayed
+    #[error("{0}")]
+    Diagnostics(diagnostic::Diagnostics),
+
+    /// A schema that parsed but isn't valid, located in its source
+    ///
+    /// Callers holding the source turn it into `Diagnostics` with [`CompilerError::with_source`].
+    #[error("Validation error: {}", .0.message)]
+    Validation(diagnostic::Diagnostic),
 
     #[error("Code generation error: {0}")]
     Codegen(String),
@@ -35,76 +48,139 @@ pub enum CompilerError {
     FileNotFound(String),
 }
 
-/// Compile a ZeroProto schema file to Rust code
-pub fn compile<P: AsRef<Path>>(schema_path: P, output_dir: P) -> Result<()> {
-    let schema_path = schema_path.as_ref();
-    let output_dir = output_dir.as_ref();
-
-    // Read schema file
-    let schema_content = std::fs::read_to_string(schema_path)
-        .map_err(|_| CompilerError::FileNotFound(schema_path.to_string_lossy().to_string()))?;
+impl CompilerError {
+    /// Render a validation error against `source`, the schema text it was found in
+    pub fn with_source(self, source: &str) -> Self {
+        match self {
+            CompilerError::Validation(diagnostic) => {
+                CompilerError::Diagnostics(diagnostic::Diagnostics::new(source, vec![diagnostic]))
+            }
+            err => err,
+        }
+    }
 
-    // Parse schema
-    let ast = parser::parse(&schema_content)?;
+    /// Attribute a parse error, or a validation error given its source, to the file at `path`
+    pub fn in_file(self, path: &Path) -> Self {
+        match self {
+            CompilerError::Parse(message) => {
+                CompilerError::Parse(format!("{}: {}", path.display(), message))
+            }
+            CompilerError::Diagnostics(diagnostics) => {
+                CompilerError::Diagnostics(diagnostics.with_file(path.display().to_string()))
+            }
+            err => err,
+        }
+    }
+}
 
-    // Validate AST
-    validator::validate(&ast)?;
+/// Compile a ZeroProto schema file, and the schemas it imports, to Rust code
+pub fn compile<P: AsRef<Path>>(schema_path: P, output_dir: P) -> Result<()> {
+    compile_with_includes(schema_path.as_ref(), output_dir.as_ref(), &[])
+}
 
-    // Convert to IR
-    let ir = ir::lower_ast(&ast);
+/// Compile a schema file, also resolving imports against `include_paths`
+///
+/// Each schema is written to a module named after its package, or after the file when it
+/// has no package, so `package acme.orders;` generates `acme/orders.rs`.
+pub fn compile_with_includes(
+    schema_path: &Path,
+    output_dir: &Path,
+    include_paths: &[PathBuf],
+) -> Result<()> {
+    let set = loader::SchemaSet::load(&[schema_path], include_paths)?;
+    generate_modules(&set)?.write(output_dir)
+}
 
-    // Generate code
-    let generated_code = codegen::generate_rust_code(&ir)?;
+/// Compile multiple schema files
+pub fn compile_multiple<P: AsRef<Path>>(schema_files: &[P], output_dir: P) -> Result<()> {
+    compile_multiple_with_includes(schema_files, output_dir.as_ref(), &[])
+}
 
-    // Create output directory if it doesn't exist
-    std::fs::create_dir_all(output_dir)?;
+/// Compile multiple schema files, also resolving imports against `include_paths`
+///
+/// Writes a `mod.rs` declaring the top-level modules alongside the generated code.
+pub fn compile_multiple_with_includes<P: AsRef<Path>>(
+    schema_files: &[P],
+    output_dir: &Path,
+    include_paths: &[PathBuf],
+) -> Result<()> {
+    let set = loader::SchemaSet::load(schema_files, include_paths)?;
+    let modules = generate_modules(&set)?;
+    modules.write(output_dir)?;
 
-    // Write generated code
-    let output_path = output_dir.join(format!(
-        "{}.rs",
-        schema_path.file_stem().unwrap().to_string_lossy()
-    ));
-    std::fs::write(output_path, generated_code)?;
+    // Generate mod.rs file
+    std::fs::write(output_dir.join("mod.rs"), modules.declarations())?;
 
     Ok(())
 }
 
-/// Compile multiple schema files
-pub fn compile_multiple<P: AsRef<Path>>(schema_files: &[P], output_dir: P) -> Result<()> {
-    for schema_file in schema_files {
-        compile(schema_file, &output_dir)?;
+/// Generated code for a module and the modules nested inside it
+#[derive(Debug, Default)]
+struct ModuleTree {
+    code: String,
+    children: BTreeMap<String, ModuleTree>,
+}
+
+impl ModuleTree {
+    /// `pub mod` lines for the child modules
+    fn declarations(&self) -> String {
+        self.children
+            .keys()
+            .map(|name| format!("pub mod {};\n", name))
+            .collect()
     }
 
-    // Generate mod.rs file
-    generate_mod_rs(output_dir)?;
+    /// Write the child modules into `dir`
+    ///
+    /// A module without children is written as `name.rs`, one with children as `name/mod.rs`.
+    fn write(&self, dir: &Path) -> Result<()> {
+        std::fs::create_dir_all(dir)?;
 
-    Ok(())
-}
+        for (name, child) in &self.children {
+            if child.children.is_empty() {
+                std::fs::write(dir.join(format!("{}.rs", name)), &child.code)?;
+            } else {
+                let child_dir = dir.join(name);
+                child.write(&child_dir)?;
 
-/// Generate mod.rs file for the output directory
-fn generate_mod_rs<P: AsRef<Path>>(output_dir: P) -> Result<()> {
-    let output_dir = output_dir.as_ref();
+                let mut content = child.declarations();
+                if !child.code.is_empty() {
+                    content.push('\n');
+                    content.push_str(&child.code);
+                }
+                std::fs::write(child_dir.join("mod.rs"), content)?;
+            }
+        }
 
-    let mut mod_content = String::new();
+        Ok(())
+    }
+}
 
-    // Read all .rs files in the directory
-    for entry in std::fs::read_dir(output_dir)? {
-        let entry = entry?;
-        let path = entry.path();
+/// Validate a schema set and generate the code for every module it defines
+fn generate_modules(set: &loader::SchemaSet) -> Result<ModuleTree> {
+    set.validate()?;
 
-        if path.extension().map_or(false, |ext| ext == "rs")
-            && path.file_name().map_or(false, |name| name != "mod.rs")
-        {
-            if let Some(stem) = path.file_stem() {
-                mod_content.push_str(&format!("pub mod {};\n", stem.to_string_lossy()));
+    // Files in the same package share a module
+    let mut modules: BTreeMap<Vec<String>, ir::IrSchema> = BTreeMap::new();
+    for index in 0..set.files.len() {
+        let ir = set.lower(index);
+        match modules.get_mut(&ir.module) {
+            Some(existing) => existing.append(ir),
+            None => {
+                modules.insert(ir.module.clone(), ir);
             }
         }
     }
 
-    let mod_path = output_dir.join("mod.rs");
-    std::fs::write(mod_path, mod_content)?;
+    let mut tree = ModuleTree::default();
+    for (module, ir) in modules {
+        let node = module.iter().fold(&mut tree, |node, segment| {
+            node.children.entry(segment.clone()).or_default()
+        });
+        node.code = codegen::generate_rust_code(&ir)?;
+    }
 
-    Ok(())
+    Ok(tree)
 }
 
 /// Convenience function for build.rs scripts
@@ -118,7 +194,7 @@ pub fn build() -> Result<()> {
                 .filter_map(|entry| {
                     let entry = entry.ok()?;
                     let path = entry.path();
-                    if path.extension().map_or(false, |ext| ext == "zp") {
+                    if path.extension().is_some_and(|ext| ext == "zp") {
                         Some(path)
                     } else {
                         None
@@ -136,3 +212,57 @@ pub fn build() -> Result<()> {
         "No schema files found".to_string(),
     ))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compile_package_tree() {
+        let schemas = tempfile::tempdir().unwrap();
+        let output = tempfile::tempdir().unwrap();
+        std::fs::write(
+            schemas.path().join("users.zp"),
+            "enum Role { Admin = 0; } message User { name: string; role: Role; }",
+        )
+        .unwrap();
+        std::fs::write(
+            schemas.path().join("orders.zp"),
+            r#"
+                package acme.orders;
+                import "users.zp";
+
+                union Party { person: User; }
+                message Order { buyer: User; role: Role; party: Party; }
+            "#,
+        )
+        .unwrap();
+        std::fs::write(
+            schemas.path().join("acme.zp"),
+            "package acme; message Tenant { slug: string; }",
+        )
+        .unwrap();
+
+        compile_multiple(
+            &[
+                schemas.path().join("orders.zp"),
+                schemas.path().join("acme.zp"),
+            ],
+            output.path().to_path_buf(),
+        )
+        .unwrap();
+
+        let read = |path: &str| std::fs::read_to_string(output.path().join(path)).unwrap();
+        assert_eq!(read("mod.rs"), "pub mod acme;\npub mod users;\n");
+
+        let acme = read("acme/mod.rs");
+        assert!(acme.starts_with("pub mod orders;\n\nuse zeroproto::*;"));
+        assert!(acme.contains("TenantReader"));
+
+        let orders = read("acme/orders.rs");
+        assert!(orders.contains(
+            "use super::super::users::{Role, USER_DESCRIPTOR, User, UserBuilder, UserReader};"
+        ));
+        assert!(read("users.rs").contains("UserReader"));
+    }
+}
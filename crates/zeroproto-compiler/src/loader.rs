@@ -0,0 +1,385 @@
+//! Loading schema files together with the schemas they import
+
+use crate::ast::*;
+use crate::diagnostic::Diagnostic;
+use crate::ir::{self, IrExternalKind, IrExternalType, IrSchema};
+use crate::{CompilerError, Result};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// A parsed schema file and where its generated code lives
+#[derive(Debug, Clone)]
+pub struct SchemaFile {
+    /// Canonical path of the schema file
+    pub path: PathBuf,
+    /// Text of the schema file, for rendering errors found in it
+    pub source: String,
+    pub schema: Schema,
+    /// Path of the generated module: the package segments, or the file stem without a package
+    pub module: Vec<String>,
+    /// Indices of the files this one imports
+    pub imports: Vec<usize>,
+}
+
+/// A set of schema files closed over their imports
+#[derive(Debug, Clone, Default)]
+pub struct SchemaSet {
+    pub files: Vec<SchemaFile>,
+}
+
+impl SchemaSet {
+    /// Load `roots` and every schema they import, directly or transitively
+    ///
+    /// Imports are resolved relative to the importing file first, then against each of
+    /// `include_paths` in order. A file reached through several paths is loaded once.
+    pub fn load<P: AsRef<Path>>(roots: &[P], include_paths: &[PathBuf]) -> Result<Self> {
+        let mut set = SchemaSet::default();
+        let mut indices = HashMap::new();
+
+        for root in roots {
+            let path = canonicalize(root.as_ref())?;
+            set.load_file(path, include_paths, &mut indices)?;
+        }
+
+        Ok(set)
+    }
+
+    /// Load a single file and its imports, returning its index
+    fn load_file(
+        &mut self,
+        path: PathBuf,
+        include_paths: &[PathBuf],
+        indices: &mut HashMap<PathBuf, usize>,
+    ) -> Result<usize> {
+        if let Some(&index) = indices.get(&path) {
+            return Ok(index);
+        }
+
+        let content = std::fs::read_to_string(&path)
+            .map_err(|_| CompilerError::FileNotFound(path.to_string_lossy().to_string()))?;
+        let schema = crate::parse(&content).map_err(|err| err.in_file(&path))?;
+        let module = match &schema.package {
+            Some(package) => package.split('.').map(str::to_string).collect(),
+            None => vec![path
+                .file_stem()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string()],
+        };
+
+        let index = self.files.len();
+        indices.insert(path.clone(), index);
+        self.files.push(SchemaFile {
+            path,
+            source: content,
+            schema,
+            module,
+            imports: Vec::new(),
+        });
+
+        let import_paths = self.files[index].schema.imports.clone();
+        for import in import_paths {
+            let resolved = resolve_import(&self.files[index].path, &import, include_paths)?;
+            let imported = self.load_file(resolved, include_paths, indices)?;
+            if !self.files[index].imports.contains(&imported) {
+                self.files[index].imports.push(imported);
+            }
+        }
+
+        Ok(index)
+    }
+
+    /// Validate every file against the types it imports
+    pub fn validate(&self) -> Result<()> {
+        for file in &self.files {
+            let imports: Vec<&Schema> = file
+                .imports
+                .iter()
+                .map(|&import| &self.files[import].schema)
+                .collect();
+            crate::validator::validate_with_imports(&file.schema, &imports)
+                .map_err(|err| err.with_source(&file.source).in_file(&file.path))?;
+        }
+
+        // Files sharing a package are generated into one module, so their names can't clash
+        let mut defined: HashMap<(&[String], &str), &Path> = HashMap::new();
+        for file in &self.files {
+            for name in file.schema.type_names() {
+                if let Some(other) = defined.insert((&file.module, name), &file.path) {
+                    let message = format!(
+                        "Type '{}' is also defined in {}, which generates module '{}' too",
+                        name,
+                        other.display(),
+                        file.module.join("::")
+                    );
+                    let span = file.schema.type_span(name).unwrap_or_default();
+                    return Err(CompilerError::Validation(Diagnostic::new(message, span))
+                        .with_source(&file.source)
+                        .in_file(&file.path));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Convert the file at `index` to IR, including the types it imports
+    pub fn lower(&self, index: usize) -> IrSchema {
+        let file = &self.files[index];
+        let externals = file
+            .imports
+            .iter()
+            .flat_map(|&import| self.external_types(import))
+            .collect();
+        ir::lower_ast_with_imports(&file.schema, &file.module, externals)
+    }
+
+    /// The types defined by the file at `index`, as seen by a file importing it
+    fn external_types(&self, index: usize) -> Vec<IrExternalType> {
+        let file = &self.files[index];
+
+        // Union variants may be messages the file itself imports
+        let imported_messages: Vec<IrExternalType> = file
+            .imports
+            .iter()
+            .flat_map(|&import| {
+                let imported = &self.files[import];
+                imported.schema.messages().map(move |msg| IrExternalType {
+                    name: msg.name.clone(),
+                    rust_name: ir::message_rust_name(msg),
+                    module: imported.module.clone(),
+                    kind: IrExternalKind::Message,
+                })
+            })
+            .collect();
+
+        // Struct layouts depend on the sizes of structs the file imports
+        let structs = if file.schema.structs().next().is_some() {
+            self.lower(index).structs
+        } else {
+            Vec::new()
+        };
+
+        file.schema
+            .items
+            .iter()
+            .map(|item| {
+                let (name, rust_name, kind) = match item {
+                    SchemaItem::Message(msg) => (
+                        &msg.name,
+                        ir::message_rust_name(msg),
+                        IrExternalKind::Message,
+                    ),
+                    SchemaItem::Enum(en) => {
+                        (&en.name, ir::lower_enum(en).rust_name, IrExternalKind::Enum)
+                    }
+                    SchemaItem::Union(un) => {
+                        let union_def =
+                            ir::lower_union(un, &file.schema, &file.module, &imported_messages);
+                        (
+                            &un.name,
+                            union_def.rust_name.clone(),
+                            IrExternalKind::Union(union_def),
+                        )
+                    }
+                    SchemaItem::Struct(st) => {
+                        let struct_def = structs
+                            .iter()
+                            .find(|struct_def| struct_def.name == st.name)
+                            .expect("Every struct is lowered")
+                            .clone();
+                        (
+                            &st.name,
+                            struct_def.rust_name.clone(),
+                            IrExternalKind::Struct(struct_def),
+                        )
+                    }
+                };
+                IrExternalType {
+                    name: name.clone(),
+                    rust_name,
+                    module: file.module.clone(),
+                    kind,
+                }
+            })
+            .chain(file.schema.aliases.iter().map(|alias| IrExternalType {
+                name: alias.name.clone(),
+                rust_name: ir::alias_rust_name(alias),
+                module: file.module.clone(),
+                kind: IrExternalKind::Alias(alias.target.clone()),
+            }))
+            .chain(file.schema.constants.iter().map(|constant| IrExternalType {
+                name: constant.name.clone(),
+                rust_name: ir::constant_rust_name(constant),
+                module: file.module.clone(),
+                kind: IrExternalKind::Constant(constant.value.clone()),
+            }))
+            .collect()
+    }
+}
+
+/// Find an imported file next to the importing file or under one of the include paths
+pub fn resolve_import(importer: &Path, import: &str, include_paths: &[PathBuf]) -> Result<PathBuf> {
+    let local = importer.parent().map(|dir| dir.join(import));
+    let candidates = local
+        .into_iter()
+        .chain(include_paths.iter().map(|dir| dir.join(import)));
+
+    for candidate in candidates {
+        if candidate.is_file() {
+            return canonicalize(&candidate);
+        }
+    }
+
+    Err(CompilerError::FileNotFound(format!(
+        "{} (imported by {})",
+        import,
+        importer.display()
+    )))
+}
+
+fn canonicalize(path: &Path) -> Result<PathBuf> {
+    path.canonicalize()
+        .map_err(|_| CompilerError::FileNotFound(path.to_string_lossy().to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(dir: &Path, name: &str, content: &str) -> PathBuf {
+        let path = dir.join(name);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, content).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_load_resolves_imports() {
+        let dir = tempfile::tempdir().unwrap();
+        let include = tempfile::tempdir().unwrap();
+        write(
+            include.path(),
+            "money.zp",
+            "package acme.money; message Money { cents: i64; }",
+        );
+        write(dir.path(), "users.zp", "message User { name: string; }");
+        let orders = write(
+            dir.path(),
+            "orders.zp",
+            r#"
+                package acme.orders;
+                import "users.zp";
+                import "money.zp";
+
+                message Order {
+                    buyer: User;
+                    total: Money;
+                }
+            "#,
+        );
+
+        let set = SchemaSet::load(&[&orders], &[include.path().to_path_buf()]).unwrap();
+        set.validate().unwrap();
+
+        assert_eq!(set.files.len(), 3);
+        assert_eq!(set.files[0].module, ["acme", "orders"]);
+        assert_eq!(set.files[0].imports, [1, 2]);
+        assert_eq!(set.files[1].module, ["users"]);
+        assert_eq!(set.files[2].module, ["acme", "money"]);
+
+        let ir = set.lower(0);
+        assert_eq!(ir.module_of("User"), ["users"]);
+        assert_eq!(ir.module_of("Order"), ["acme", "orders"]);
+    }
+
+    #[test]
+    fn test_imported_constants_and_aliases() {
+        let dir = tempfile::tempdir().unwrap();
+        write(
+            dir.path(),
+            "common.zp",
+            "const LIMIT: u16 = 20; type UserId = u64; type Owner = User; message User {}",
+        );
+        let orders = write(
+            dir.path(),
+            "orders.zp",
+            r#"
+                import "common.zp";
+                message Order {
+                    buyer: UserId;
+                    owner: Owner;
+                    max_items: u32 = LIMIT;
+                }
+            "#,
+        );
+
+        let set = SchemaSet::load(&[&orders], &[]).unwrap();
+        set.validate().unwrap();
+
+        let ir = set.lower(0);
+        let fields = &ir.messages[0].fields;
+        assert!(matches!(
+            fields[0].field_type,
+            ir::IrFieldType::Scalar {
+                scalar_type: ScalarType::U64,
+                ..
+            }
+        ));
+        assert!(matches!(
+            &fields[1].field_type,
+            ir::IrFieldType::UserDefined { type_name, is_message: true, .. } if type_name == "User"
+        ));
+        assert_eq!(fields[2].default_value.as_deref(), Some("20"));
+        assert_eq!(ir.module_of("User"), ["common"]);
+    }
+
+    #[test]
+    fn test_load_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let orders = write(dir.path(), "orders.zp", "import \"missing.zp\";");
+        let error = SchemaSet::load(&[&orders], &[]).unwrap_err().to_string();
+        assert!(error.contains("missing.zp"));
+
+        // Types are only visible through a direct import
+        write(dir.path(), "b.zp", "import \"c.zp\"; message B { c: C; }");
+        write(dir.path(), "c.zp", "message C { x: u8; }");
+        let top = write(
+            dir.path(),
+            "top.zp",
+            "import \"b.zp\"; message Top { c: C; }",
+        );
+        let set = SchemaSet::load(&[&top], &[]).unwrap();
+        let error = set.validate().unwrap_err().to_string();
+        assert!(error.contains("Unknown type 'C'"));
+        assert!(error.contains("top.zp:1:33"));
+
+        // Files in the same package share a module
+        let one = write(
+            dir.path(),
+            "one.zp",
+            "package shared; message Same { x: u8; }",
+        );
+        let two = write(
+            dir.path(),
+            "two.zp",
+            "package shared; message Same { y: u8; }",
+        );
+        let set = SchemaSet::load(&[&one, &two], &[]).unwrap();
+        let error = set.validate().unwrap_err().to_string();
+        assert!(error.contains("generates module 'shared' too"));
+        assert!(error.contains("two.zp:1:25"));
+    }
+
+    #[test]
+    fn test_import_cycles_load_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let a = write(dir.path(), "a.zp", "import \"b.zp\"; message A { b: B?; }");
+        write(dir.path(), "b.zp", "import \"a.zp\"; message B { a: A?; }");
+
+        let set = SchemaSet::load(&[&a], &[]).unwrap();
+        set.validate().unwrap();
+        assert_eq!(set.files.len(), 2);
+        assert_eq!(set.files[1].imports, [0]);
+    }
+}
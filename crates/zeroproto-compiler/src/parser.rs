@@ -1,31 +1,79 @@
 //! Parser for ZeroProto schema language
 
 use crate::ast::*;
+use crate::diagnostic::{Diagnostic, Diagnostics, Span};
 use crate::Result;
+use std::collections::HashMap;
 
 // Import the specific types we need
 use crate::ast::{FieldType, ScalarType};
 
 /// Parse a ZeroProto schema string into an AST
+///
+/// The parser recovers from errors at the next field or declaration, so the returned
+/// `CompilerError::Diagnostics` lists every error in the input rather than just the first.
 pub fn parse(input: &str) -> Result<Schema> {
     let mut parser = SchemaParser::new();
     parser.parse(input)
 }
 
+/// Parse as much of a schema as possible, returning every error found along the way
+///
+/// Declarations and fields that fail to parse are left out of the schema, so editors
+/// and other tools can keep working with the rest of it.
+pub fn parse_with_recovery(input: &str) -> (Schema, Vec<Diagnostic>) {
+    let mut parser = SchemaParser::new();
+    parser.parse_with_recovery(input)
+}
+
+/// The tokens and comments of a schema, for tools that reproduce its source text
+pub(crate) struct Lexed {
+    /// Tokens of the input, always ending with `Token::Eof`
+    pub tokens: Vec<Token>,
+    /// Source location of each token
+    pub spans: Vec<Span>,
+    /// Source location of each comment, in order
+    pub comments: Vec<Span>,
+}
+
+/// Split a schema into tokens and comments without parsing it
+pub(crate) fn lex(input: &str) -> Lexed {
+    let mut parser = SchemaParser::new();
+    parser.tokenize(input);
+    Lexed {
+        tokens: parser.tokens,
+        spans: parser.spans,
+        comments: parser.comments,
+    }
+}
+
+/// Result of parsing one construct; errors are collected and recovered from by the caller
+type ParseResult<T> = std::result::Result<T, Diagnostic>;
+
 /// Simple hand-written parser for ZeroProto schema language
 struct SchemaParser {
+    /// Tokens of the input, always ending with `Token::Eof`
     tokens: Vec<Token>,
+    /// Source location of each token
+    spans: Vec<Span>,
     position: usize,
+    /// `///` doc comment lines, keyed by the index of the token they precede
+    docs: HashMap<usize, Vec<String>>,
+    /// Source location of every comment
+    comments: Vec<Span>,
+    /// Errors found so far
+    errors: Vec<Diagnostic>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
-enum Token {
+pub(crate) enum Token {
     Identifier(String),
-    Integer(i64),
+    Integer(i128),
     Float(f64),
     StringLiteral(String),
     Message,
     Enum,
+    Union,
     True,
     False,
     Colon,
@@ -37,31 +85,105 @@ enum Token {
     RightBracket,
     Equals,
     Question,
+    At,
+    LeftParen,
+    RightParen,
+    Dot,
+    Eof,
+}
+
+impl Token {
+    /// The token as written, for error messages
+    fn describe(&self) -> String {
+        match self {
+            Token::Identifier(name) => format!("'{}'", name),
+            Token::Integer(value) => format!("'{}'", value),
+            Token::Float(value) => format!("'{}'", value),
+            Token::StringLiteral(value) => format!("{:?}", value),
+            Token::Message => "'message'".to_string(),
+            Token::Enum => "'enum'".to_string(),
+            Token::Union => "'union'".to_string(),
+            Token::True => "'true'".to_string(),
+            Token::False => "'false'".to_string(),
+            Token::Colon => "':'".to_string(),
+            Token::Semicolon => "';'".to_string(),
+            Token::Comma => "','".to_string(),
+            Token::LeftBrace => "'{'".to_string(),
+            Token::RightBrace => "'}'".to_string(),
+            Token::LeftBracket => "'['".to_string(),
+            Token::RightBracket => "']'".to_string(),
+            Token::Equals => "'='".to_string(),
+            Token::Question => "'?'".to_string(),
+            Token::At => "'@'".to_string(),
+            Token::LeftParen => "'('".to_string(),
+            Token::RightParen => "')'".to_string(),
+            Token::Dot => "'.'".to_string(),
+            Token::Eof => "end of input".to_string(),
+        }
+    }
+}
+
+/// An `@` annotation: a named attribute like `@packed`, `@deprecated("reason")` or
+/// `@rust.derive(Hash)`, or a field number like `@3`
+#[derive(Debug, Clone, PartialEq)]
+enum Annotation {
+    Named(Attribute),
+    Number(i128),
 }
 
 impl SchemaParser {
     fn new() -> Self {
         Self {
             tokens: Vec::new(),
+            spans: Vec::new(),
             position: 0,
+            docs: HashMap::new(),
+            comments: Vec::new(),
+            errors: Vec::new(),
         }
     }
 
-    fn tokenize(&mut self, input: &str) -> Result<()> {
+    fn tokenize(&mut self, input: &str) {
         self.tokens.clear();
+        self.spans.clear();
         self.position = 0;
+        self.docs.clear();
+        self.comments.clear();
+        self.errors.clear();
 
         let chars: Vec<char> = input.chars().collect();
+        let offsets: Vec<usize> = input
+            .char_indices()
+            .map(|(offset, _)| offset)
+            .chain(std::iter::once(input.len()))
+            .collect();
+        let span = |start: usize, end: usize| Span::new(offsets[start], offsets[end]);
         let mut i = 0;
 
         while i < chars.len() {
-            match chars[i] {
-                ' ' | '\t' | '\n' | '\r' => i += 1,
+            let start = i;
+            let token = match chars[i] {
+                ' ' | '\t' | '\n' | '\r' => {
+                    i += 1;
+                    continue;
+                }
                 '/' if i + 1 < chars.len() && chars[i + 1] == '/' => {
-                    // Line comment
+                    // Line comment; `///` (but not `////`) is a doc comment
                     while i < chars.len() && chars[i] != '\n' {
                         i += 1;
                     }
+                    self.comments.push(span(start, i));
+                    let comment: String = chars[start..i].iter().collect();
+                    if let Some(doc) = comment.strip_prefix("///") {
+                        if !doc.starts_with('/') {
+                            let doc = doc.strip_prefix(' ').unwrap_or(doc).trim_end();
+                            self.docs
+                                .entry(self.tokens.len())
+                                .or_default()
+                                .push(doc.to_string());
+                        }
+                    }
+                    continue;
                 }
                 '/' if i + 1 < chars.len() && chars[i + 1] == '*' => {
                     // Block comment
@@ -69,161 +191,423 @@ impl SchemaParser {
                     while i + 1 < chars.len() && !(chars[i] == '*' && chars[i + 1] == '/') {
                         i += 1;
                     }
-                    i += 2;
+                    if i + 1 >= chars.len() {
+                        self.errors.push(Diagnostic::new(
+                            "Unterminated block comment",
+                            span(start, start + 2),
+                        ));
+                        i = chars.len();
+                    } else {
+                        i += 2;
+                    }
+                    self.comments.push(span(start, i));
+                    continue;
                 }
                 'a'..='z' | 'A'..='Z' | '_' => {
-                    let start = i;
                     while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
                         i += 1;
                     }
                     let ident: String = chars[start..i].iter().collect();
 
-                    let token = match ident.as_str() {
+                    match ident.as_str() {
                         "message" => Token::Message,
                         "enum" => Token::Enum,
+                        "union" => Token::Union,
                         "true" => Token::True,
                         "false" => Token::False,
                         _ => Token::Identifier(ident),
-                    };
-                    self.tokens.push(token);
-                }
-                '0'..='9' | '-' if chars[i] == '-' || chars[i].is_ascii_digit() => {
-                    let start = i;
-                    if chars[i] == '-' {
-                        i += 1;
-                    }
-                    while i < chars.len() && chars[i].is_ascii_digit() {
-                        i += 1;
                     }
-                    // Check for float
-                    if i < chars.len() && chars[i] == '.' {
-                        i += 1;
-                        while i < chars.len() && chars[i].is_ascii_digit() {
-                            i += 1;
-                        }
-                        let num_str: String = chars[start..i].iter().collect();
-                        let num: f64 = num_str.parse().expect("valid float");
-                        self.tokens.push(Token::Float(num));
-                    } else {
-                        let num_str: String = chars[start..i].iter().collect();
-                        let num: i64 = num_str.parse().expect("valid integer");
-                        self.tokens.push(Token::Integer(num));
-                    }
-                }
-                ':' => {
-                    self.tokens.push(Token::Colon);
-                    i += 1;
                 }
-                ';' => {
-                    self.tokens.push(Token::Semicolon);
-                    i += 1;
-                }
-                '{' => {
-                    self.tokens.push(Token::LeftBrace);
-                    i += 1;
-                }
-                '}' => {
-                    self.tokens.push(Token::RightBrace);
-                    i += 1;
-                }
-                '[' => {
-                    self.tokens.push(Token::LeftBracket);
-                    i += 1;
-                }
-                ']' => {
-                    self.tokens.push(Token::RightBracket);
-                    i += 1;
-                }
-                '=' => {
-                    self.tokens.push(Token::Equals);
-                    i += 1;
-                }
-                ',' => {
-                    self.tokens.push(Token::Comma);
-                    i += 1;
-                }
-                '?' => {
-                    self.tokens.push(Token::Question);
-                    i += 1;
+                '0'..='9' | '-'
+                    if chars[i].is_ascii_digit()
+                        || chars.get(i + 1).is_some_and(char::is_ascii_digit) =>
+                {
+                    let (end, token) = lex_number(&chars, start);
+                    i = end;
+                    token.unwrap_or_else(|message| {
+                        self.errors.push(Diagnostic::new(message, span(start, end)));
+                        Token::Integer(0)
+                    })
                 }
                 '"' => {
-                    // String literal
+                    let (end, value, errors) = lex_string(&chars, start);
+                    for (message, from, to) in errors {
+                        self.errors.push(Diagnostic::new(message, span(from, to)));
+                    }
+                    i = end;
+                    Token::StringLiteral(value)
+                }
+                c => {
                     i += 1;
-                    let start = i;
-                    while i < chars.len() && chars[i] != '"' {
-                        if chars[i] == '\\' && i + 1 < chars.len() {
-                            i += 2; // Skip escaped character
-                        } else {
-                            i += 1;
+                    match punctuation(c) {
+                        Some(token) => token,
+                        None => {
+                            self.errors.push(Diagnostic::new(
+                                format!("Unexpected character '{}'", c),
+                                span(start, i),
+                            ));
+                            continue;
                         }
                     }
-                    let string_content: String = chars[start..i].iter().collect();
-                    self.tokens.push(Token::StringLiteral(string_content));
-                    i += 1; // Skip closing quote
                 }
-                _ => {
-                    return Err(crate::CompilerError::Parse(format!(
-                        "Unexpected character: {}",
-                        chars[i]
-                    )));
-                }
-            }
+            };
+            self.tokens.push(token);
+            self.spans.push(span(start, i));
         }
-        Ok(())
+
+        self.tokens.push(Token::Eof);
+        self.spans.push(span(chars.len(), chars.len()));
     }
 
     fn parse(&mut self, input: &str) -> Result<Schema> {
-        self.tokenize(input)?;
+        let (schema, errors) = self.parse_with_recovery(input);
+        if errors.is_empty() {
+            Ok(schema)
+        } else {
+            Err(crate::CompilerError::Diagnostics(Diagnostics::new(
+                input, errors,
+            )))
+        }
+    }
 
-        let mut items = Vec::new();
+    fn parse_with_recovery(&mut self, input: &str) -> (Schema, Vec<Diagnostic>) {
+        self.tokenize(input);
+
+        let mut schema = Schema::new();
 
         while !self.at_end() {
-            if self.peek() == Token::Message {
-                items.push(self.parse_message()?);
-            } else if self.peek() == Token::Enum {
-                items.push(self.parse_enum()?);
-            } else {
-                return Err(crate::CompilerError::Parse(
-                    "Expected message or enum".to_string(),
-                ));
+            if let Err(diagnostic) = self.parse_item(&mut schema) {
+                self.errors.push(diagnostic);
+                self.recover_item();
+            }
+        }
+
+        // Tokenizer errors come first; report everything in source order
+        let mut errors = std::mem::take(&mut self.errors);
+        errors.sort_by_key(|diagnostic| diagnostic.span.start);
+        (schema, errors)
+    }
+
+    fn parse_item(&mut self, schema: &mut Schema) -> ParseResult<()> {
+        if self.at_declaration("package") {
+            self.parse_package(schema)?;
+        } else if self.at_declaration("import") {
+            self.consume_identifier()?;
+            let path = self.consume_string()?;
+            let span = self.previous_span();
+            self.consume(Token::Semicolon)?;
+            schema.imports.push(path);
+            schema.import_spans.push(span);
+        } else if self.at_declaration("const") {
+            schema.constants.push(self.parse_constant()?);
+        } else if self.at_declaration("type") {
+            schema.aliases.push(self.parse_type_alias()?);
+        } else if self.at_declaration("struct") {
+            schema.items.push(self.parse_struct()?);
+        } else if self.peek() == Token::Message {
+            schema.items.push(self.parse_message()?);
+        } else if self.peek() == Token::Enum {
+            schema.items.push(self.parse_enum()?);
+        } else if self.peek() == Token::Union {
+            schema.items.push(self.parse_union()?);
+        } else {
+            return Err(
+                self.expected("message, enum, union, struct, const, type, import or package")
+            );
+        }
+        Ok(())
+    }
+
+    /// Skip to the start of the next top-level declaration after an error
+    fn recover_item(&mut self) {
+        let mut depth = 0usize;
+        loop {
+            match self.peek() {
+                Token::Eof | Token::Message | Token::Enum | Token::Union => return,
+                Token::LeftBrace => depth += 1,
+                Token::RightBrace if depth <= 1 => {
+                    self.position += 1;
+                    return;
+                }
+                Token::RightBrace => depth -= 1,
+                Token::Semicolon if depth == 0 => {
+                    self.position += 1;
+                    return;
+                }
+                _ => {}
+            }
+            self.position += 1;
+        }
+    }
+
+    /// Skip past the rest of a field or variant after an error, stopping at the end of
+    /// the enclosing body
+    fn recover_statement(&mut self) {
+        while !self.at_body_end() {
+            let token = self.peek();
+            self.position += 1;
+            if token == Token::Semicolon {
+                return;
             }
         }
+    }
+
+    /// Check for the end of a message, enum or union body
+    ///
+    /// A top-level keyword also ends the body, so a missing `}` is reported once instead
+    /// of swallowing the declarations after it.
+    fn at_body_end(&self) -> bool {
+        matches!(
+            self.peek(),
+            Token::RightBrace | Token::Eof | Token::Message | Token::Enum | Token::Union
+        )
+    }
+
+    /// Check for a top-level declaration introduced by the contextual keyword `word`
+    fn at_declaration(&self, word: &str) -> bool {
+        matches!(self.tokens.get(self.position), Some(Token::Identifier(ident)) if ident == word)
+    }
+
+    fn parse_package(&mut self, schema: &mut Schema) -> ParseResult<()> {
+        self.consume_identifier()?;
+
+        let start = self.current_span();
+        let mut segments = vec![self.consume_identifier()?];
+        while self.peek() == Token::Dot {
+            self.consume(Token::Dot)?;
+            segments.push(self.consume_identifier()?);
+        }
+        let span = start.to(self.previous_span());
+        self.consume(Token::Semicolon)?;
+
+        let package = segments.join(".");
+        if let Some(previous) = &schema.package {
+            return Err(Diagnostic::new(
+                format!(
+                    "Schema declares package '{}' after package '{}'",
+                    package, previous
+                ),
+                span,
+            ));
+        }
+        schema.package = Some(package);
+        schema.package_span = span;
+        Ok(())
+    }
+
+    fn parse_constant(&mut self) -> ParseResult<Constant> {
+        let docs = self.take_docs();
+        self.consume_identifier()?;
+        let name = self.consume_identifier()?;
+        let span = self.previous_span();
+        self.consume(Token::Colon)?;
+        let type_start = self.current_span();
+        let value_type = self.parse_type()?;
+        let type_span = type_start.to(self.previous_span());
+        self.consume(Token::Equals)?;
+        let value = self.parse_default_value()?;
+        self.consume(Token::Semicolon)?;
+
+        Ok(Constant {
+            name,
+            value_type,
+            value,
+            docs,
+            span,
+            type_span,
+        })
+    }
+
+    fn parse_type_alias(&mut self) -> ParseResult<TypeAlias> {
+        let docs = self.take_docs();
+        self.consume_identifier()?;
+        let name = self.consume_identifier()?;
+        let span = self.previous_span();
+        self.consume(Token::Equals)?;
+        let type_start = self.current_span();
+        let target = self.parse_type()?;
+        let type_span = type_start.to(self.previous_span());
+        self.consume(Token::Semicolon)?;
 
-        Ok(Schema { items })
+        Ok(TypeAlias {
+            name,
+            target,
+            docs,
+            span,
+            type_span,
+        })
     }
 
-    fn parse_message(&mut self) -> Result<SchemaItem> {
+    fn parse_message(&mut self) -> ParseResult<SchemaItem> {
+        let docs = self.take_docs();
         self.consume(Token::Message)?;
         let name = self.consume_identifier()?;
+        let span = self.previous_span();
+
+        let mut presence_bitmap = false;
+        let mut attributes = Vec::new();
+        for (annotation, annotation_span) in self.parse_annotations()? {
+            match annotation {
+                Annotation::Named(attr) if attr.name == "bitmap" && attr.args.is_empty() => {
+                    presence_bitmap = true
+                }
+                Annotation::Named(attr) if attr.name.contains('.') => attributes.push(attr),
+                Annotation::Named(attr) => self.errors.push(Diagnostic::new(
+                    format!("Unknown message attribute '@{}'", attr.name),
+                    annotation_span,
+                )),
+                Annotation::Number(_) => self.errors.push(Diagnostic::new(
+                    format!("Message '{}' cannot have a field number", name),
+                    annotation_span,
+                )),
+            }
+        }
+
         self.consume(Token::LeftBrace)?;
 
-        let fields = self.parse_message_fields()?;
+        let mut message = Message::new(name);
+        message.presence_bitmap = presence_bitmap;
+        message.attributes = attributes;
+        message.docs = docs;
+        message.span = span;
+        self.parse_message_body(&mut message);
+
+        self.consume(Token::RightBrace)?;
+        Ok(SchemaItem::Message(message))
+    }
+
+    fn parse_struct(&mut self) -> ParseResult<SchemaItem> {
+        let docs = self.take_docs();
+        self.consume_identifier()?;
+        let name = self.consume_identifier()?;
+        let span = self.previous_span();
+
+        let mut attributes = Vec::new();
+        for (annotation, annotation_span) in self.parse_annotations()? {
+            match annotation {
+                Annotation::Named(attr) if attr.name.contains('.') => attributes.push(attr),
+                Annotation::Named(attr) => self.errors.push(Diagnostic::new(
+                    format!("Unknown struct attribute '@{}'", attr.name),
+                    annotation_span,
+                )),
+                Annotation::Number(_) => self.errors.push(Diagnostic::new(
+                    format!("Struct '{}' cannot have a field number", name),
+                    annotation_span,
+                )),
+            }
+        }
+
+        self.consume(Token::LeftBrace)?;
+
+        let mut fields = Vec::new();
+        while !self.at_body_end() {
+            match self.parse_field() {
+                Ok(field) => fields.push(field),
+                Err(diagnostic) => {
+                    self.errors.push(diagnostic);
+                    self.recover_statement();
+                    continue;
+                }
+            }
+            // Optional comma between fields
+            if self.peek() == Token::Comma {
+                self.position += 1;
+            }
+        }
 
         self.consume(Token::RightBrace)?;
-        Ok(SchemaItem::Message(Message { name, fields }))
+        Ok(SchemaItem::Struct(Struct {
+            name,
+            fields,
+            attributes,
+            docs,
+            span,
+        }))
     }
 
-    fn parse_enum(&mut self) -> Result<SchemaItem> {
+    fn parse_enum(&mut self) -> ParseResult<SchemaItem> {
+        let docs = self.take_docs();
         self.consume(Token::Enum)?;
         let name = self.consume_identifier()?;
+        let span = self.previous_span();
         self.consume(Token::LeftBrace)?;
 
         let mut variants = Vec::new();
-        while !self.at_end() && self.peek() != Token::RightBrace {
-            variants.push(self.parse_enum_variant()?);
+        while !self.at_body_end() {
+            match self.parse_enum_variant() {
+                Ok(variant) => variants.push(variant),
+                Err(diagnostic) => {
+                    self.errors.push(diagnostic);
+                    self.recover_statement();
+                }
+            }
         }
 
         self.consume(Token::RightBrace)?;
-        Ok(SchemaItem::Enum(Enum { name, variants }))
+        Ok(SchemaItem::Enum(Enum {
+            name,
+            variants,
+            docs,
+            span,
+        }))
     }
 
-    fn parse_field(&mut self) -> Result<Field> {
+    fn parse_union(&mut self) -> ParseResult<SchemaItem> {
+        let docs = self.take_docs();
+        self.consume(Token::Union)?;
         let name = self.consume_identifier()?;
+        let span = self.previous_span();
+        self.consume(Token::LeftBrace)?;
+
+        let mut variants = Vec::new();
+        while !self.at_body_end() {
+            match self.parse_union_variant() {
+                Ok(variant) => variants.push(variant),
+                Err(diagnostic) => {
+                    self.errors.push(diagnostic);
+                    self.recover_statement();
+                }
+            }
+        }
+
+        self.consume(Token::RightBrace)?;
+        Ok(SchemaItem::Union(Union {
+            name,
+            variants,
+            docs,
+            span,
+        }))
+    }
+
+    fn parse_union_variant(&mut self) -> ParseResult<UnionVariant> {
+        let docs = self.take_docs();
+        let name = self.consume_identifier()?;
+        let span = self.previous_span();
         self.consume(Token::Colon)?;
+        let type_start = self.current_span();
+        let variant_type = self.parse_type()?;
+        let type_span = type_start.to(self.previous_span());
+        self.consume(Token::Semicolon)?;
+        Ok(UnionVariant {
+            name,
+            variant_type,
+            docs,
+            span,
+            type_span,
+        })
+    }
+
+    fn parse_field(&mut self) -> ParseResult<Field> {
+        let docs = self.take_docs();
+        let name = self.consume_identifier()?;
+        let span = self.previous_span();
+        self.consume(Token::Colon)?;
+        let type_start = self.current_span();
         let field_type = self.parse_type()?;
+        let type_span = type_start.to(self.previous_span());
 
         // Check for optional marker (?)
-        let optional = if !self.at_end() && self.peek() == Token::Question {
+        let optional = if self.peek() == Token::Question {
             self.consume(Token::Question)?;
             true
         } else {
@@ -231,13 +615,57 @@ impl SchemaParser {
         };
 
         // Check for default value (= value)
-        let default_value = if !self.at_end() && self.peek() == Token::Equals {
+        let default_value = if self.peek() == Token::Equals {
             self.consume(Token::Equals)?;
             Some(self.parse_default_value()?)
         } else {
             None
         };
 
+        let mut packed = false;
+        let mut number = None;
+        let mut deprecated = None;
+        let mut attributes = Vec::new();
+        for (annotation, annotation_span) in self.parse_annotations()? {
+            match annotation {
+                Annotation::Named(attr) if attr.name == "packed" && attr.args.is_empty() => {
+                    packed = true
+                }
+                Annotation::Named(attr) if attr.name == "deprecated" && attr.args.is_empty() => {
+                    deprecated = Some(String::new())
+                }
+                Annotation::Named(attr)
+                    if attr.name == "deprecated" && attr.string_arg().is_some() =>
+                {
+                    deprecated = attr.string_arg().map(str::to_string)
+                }
+                Annotation::Named(attr) if attr.name.contains('.') => attributes.push(attr),
+                Annotation::Named(attr) => self.errors.push(Diagnostic::new(
+                    format!("Unknown field attribute '{}'", attr),
+                    annotation_span,
+                )),
+                Annotation::Number(_) if number.is_some() => self.errors.push(Diagnostic::new(
+                    format!("Field '{}' has more than one field number", name),
+                    annotation_span,
+                )),
+                Annotation::Number(value) => {
+                    match u16::try_from(value)
+                        .ok()
+                        .filter(|value| *value <= MAX_FIELD_NUMBER)
+                    {
+                        Some(value) => number = Some(value),
+                        None => self.errors.push(Diagnostic::new(
+                            format!(
+                                "Field number {} of '{}' is out of range (0 to {})",
+                                value, name, MAX_FIELD_NUMBER
+                            ),
+                            annotation_span,
+                        )),
+                    }
+                }
+            }
+        }
+
         self.consume(Token::Semicolon)?;
 
         Ok(Field {
@@ -245,70 +673,214 @@ impl SchemaParser {
             field_type,
             optional,
             default_value,
+            packed,
+            number,
+            deprecated,
+            attributes,
+            docs,
+            span,
+            type_span,
         })
     }
 
-    fn parse_default_value(&mut self) -> Result<crate::ast::DefaultValue> {
-        use crate::ast::DefaultValue;
+    /// Parse any `@` annotations, along with where each one is written
+    fn parse_annotations(&mut self) -> ParseResult<Vec<(Annotation, Span)>> {
+        let mut annotations = Vec::new();
+        while self.peek() == Token::At {
+            let start = self.current_span();
+            self.consume(Token::At)?;
+            if let Token::Integer(_) = self.peek() {
+                let number = self.consume_integer()?;
+                annotations.push((Annotation::Number(number), start.to(self.previous_span())));
+                continue;
+            }
+
+            let mut name = self.consume_identifier()?;
+            while self.peek() == Token::Dot {
+                self.consume(Token::Dot)?;
+                name.push('.');
+                name.push_str(&self.consume_identifier()?);
+            }
 
-        if self.at_end() {
-            return Err(crate::CompilerError::Parse(
-                "Expected default value".to_string(),
+            let mut args = Vec::new();
+            if self.peek() == Token::LeftParen {
+                self.consume(Token::LeftParen)?;
+                while self.peek() != Token::RightParen {
+                    args.push(self.parse_attribute_arg()?);
+                    if self.peek() == Token::Comma {
+                        self.consume(Token::Comma)?;
+                    } else {
+                        break;
+                    }
+                }
+                self.consume(Token::RightParen)?;
+            }
+            annotations.push((
+                Annotation::Named(Attribute { name, args }),
+                start.to(self.previous_span()),
             ));
         }
+        Ok(annotations)
+    }
 
-        match self.peek() {
-            Token::Integer(val) => {
-                self.position += 1;
-                Ok(DefaultValue::Integer(val))
+    fn parse_attribute_arg(&mut self) -> ParseResult<AttributeArg> {
+        let key = match (
+            self.tokens.get(self.position),
+            self.tokens.get(self.position + 1),
+        ) {
+            (Some(Token::Identifier(key)), Some(Token::Equals)) => {
+                let key = key.clone();
+                self.position += 2;
+                Some(key)
             }
-            Token::Float(val) => {
-                self.position += 1;
-                Ok(DefaultValue::Float(val))
+            _ => None,
+        };
+
+        let value = match self.peek() {
+            Token::StringLiteral(value) => AttributeValue::String(value),
+            Token::Integer(value) => match i64::try_from(value) {
+                Ok(value) => AttributeValue::Integer(value),
+                Err(_) => {
+                    return Err(Diagnostic::new(
+                        format!("Attribute argument {} is out of range", value),
+                        self.current_span(),
+                    ))
+                }
+            },
+            Token::Float(value) => AttributeValue::Float(value),
+            Token::True => AttributeValue::Bool(true),
+            Token::False => AttributeValue::Bool(false),
+            Token::Identifier(_) => {
+                let mut path = self.consume_identifier()?;
+                while self.tokens.get(self.position) == Some(&Token::Colon)
+                    && self.tokens.get(self.position + 1) == Some(&Token::Colon)
+                {
+                    self.position += 2;
+                    path.push_str("::");
+                    path.push_str(&self.consume_identifier()?);
+                }
+                return Ok(AttributeArg {
+                    key,
+                    value: AttributeValue::Path(path),
+                });
             }
-            Token::True => {
-                self.position += 1;
-                Ok(DefaultValue::Bool(true))
+            _ => return Err(self.expected("attribute argument")),
+        };
+        self.position += 1;
+
+        Ok(AttributeArg { key, value })
+    }
+
+    fn parse_default_value(&mut self) -> ParseResult<crate::ast::DefaultValue> {
+        use crate::ast::DefaultValue;
+
+        let value = match self.peek() {
+            Token::Integer(val) => DefaultValue::Integer(val),
+            Token::Float(val) => DefaultValue::Float(val),
+            Token::True => DefaultValue::Bool(true),
+            Token::False => DefaultValue::Bool(false),
+            Token::StringLiteral(s) => DefaultValue::String(s),
+            Token::Identifier(name) => DefaultValue::Constant(name),
+            _ => {
+                return Err(
+                    self.expected("default value (integer, float, bool, string or constant)")
+                );
             }
-            Token::False => {
-                self.position += 1;
-                Ok(DefaultValue::Bool(false))
+        };
+        self.position += 1;
+        Ok(value)
+    }
+
+    fn parse_message_body(&mut self, message: &mut Message) {
+        while !self.at_body_end() {
+            let result = if self.at_reserved() {
+                self.parse_reserved(message)
+            } else {
+                self.parse_field().map(|field| message.fields.push(field))
+            };
+            if let Err(diagnostic) = result {
+                self.errors.push(diagnostic);
+                self.recover_statement();
+                continue;
             }
-            Token::StringLiteral(s) => {
-                let s = s.clone();
+            // Optional comma between fields
+            if self.peek() == Token::Comma {
                 self.position += 1;
-                Ok(DefaultValue::String(s))
             }
-            _ => Err(crate::CompilerError::Parse(
-                "Expected default value (integer, float, bool, or string)".to_string(),
-            )),
         }
     }
 
-    fn parse_message_fields(&mut self) -> Result<Vec<Field>> {
-        let mut fields = Vec::new();
-        while !self.at_end() && self.peek() != Token::RightBrace {
-            fields.push(self.parse_field()?);
-            // Optional comma between fields
+    /// Check for a `reserved` declaration (a field named `reserved` is followed by a colon)
+    fn at_reserved(&self) -> bool {
+        matches!(self.tokens.get(self.position), Some(Token::Identifier(word)) if word == "reserved")
+            && self.tokens.get(self.position + 1) != Some(&Token::Colon)
+    }
+
+    fn parse_reserved(&mut self, message: &mut Message) -> ParseResult<()> {
+        self.consume_identifier()?;
+
+        loop {
+            match self.peek() {
+                Token::Integer(value) => {
+                    self.position += 1;
+                    match u16::try_from(value)
+                        .ok()
+                        .filter(|number| *number <= MAX_FIELD_NUMBER)
+                    {
+                        Some(number) => message.reserved_numbers.push(number),
+                        None => self.errors.push(Diagnostic::new(
+                            format!(
+                                "Reserved field number {} in message '{}' is out of range (0 to {})",
+                                value, message.name, MAX_FIELD_NUMBER
+                            ),
+                            self.previous_span(),
+                        )),
+                    }
+                }
+                Token::StringLiteral(name) => {
+                    self.position += 1;
+                    message.reserved_names.push(name);
+                }
+                _ => {
+                    return Err(self.expected(&format!(
+                        "field number or name after 'reserved' in message '{}'",
+                        message.name
+                    )))
+                }
+            }
+
             if self.peek() == Token::Comma {
                 self.consume(Token::Comma)?;
+            } else {
+                break;
             }
         }
-        Ok(fields)
+
+        self.consume(Token::Semicolon)
     }
 
-    fn parse_enum_variant(&mut self) -> Result<EnumVariant> {
+    fn parse_enum_variant(&mut self) -> ParseResult<EnumVariant> {
+        let docs = self.take_docs();
         let name = self.consume_identifier()?;
+        let span = self.previous_span();
         self.consume(Token::Equals)?;
         let value = self.consume_integer()?;
+        let value = i64::try_from(value).map_err(|_| {
+            Diagnostic::new(
+                format!("Value {} of enum variant '{}' is out of range", value, name),
+                self.previous_span(),
+            )
+        })?;
         self.consume(Token::Semicolon)?;
         Ok(EnumVariant {
             name,
             value: Some(value),
+            docs,
+            span,
         })
     }
 
-    fn parse_type(&mut self) -> Result<FieldType> {
+    fn parse_type(&mut self) -> ParseResult<FieldType> {
         if self.peek() == Token::LeftBracket {
             self.consume(Token::LeftBracket)?;
             let inner = self.parse_type()?;
@@ -316,90 +888,281 @@ impl SchemaParser {
             Ok(FieldType::Vector(Box::new(inner)))
         } else {
             let ident = self.consume_identifier()?;
-            let scalar_type = match ident.as_str() {
-                "u8" => ScalarType::U8,
-                "u16" => ScalarType::U16,
-                "u32" => ScalarType::U32,
-                "u64" => ScalarType::U64,
-                "i8" => ScalarType::I8,
-                "i16" => ScalarType::I16,
-                "i32" => ScalarType::I32,
-                "i64" => ScalarType::I64,
-                "f32" => ScalarType::F32,
-                "f64" => ScalarType::F64,
-                "bool" => ScalarType::Bool,
-                "string" => ScalarType::String,
-                "bytes" => ScalarType::Bytes,
-                _ => return Ok(FieldType::UserDefined(ident)),
-            };
-            Ok(FieldType::Scalar(scalar_type))
+            Ok(match ScalarType::from_name(&ident) {
+                Some(scalar_type) => FieldType::Scalar(scalar_type),
+                None => FieldType::UserDefined(ident),
+            })
         }
     }
 
+    /// Take the doc comment written just before the current token
+    fn take_docs(&mut self) -> Vec<String> {
+        self.docs.remove(&self.position).unwrap_or_default()
+    }
+
     fn at_end(&self) -> bool {
-        self.position >= self.tokens.len()
+        self.peek() == Token::Eof
     }
 
     fn peek(&self) -> Token {
-        if self.at_end() {
-            panic!("Unexpected end of input");
-        }
-        self.tokens[self.position].clone()
+        // The trailing `Eof` is never consumed, so the index stays in bounds
+        self.tokens[self.position.min(self.tokens.len() - 1)].clone()
     }
 
-    fn consume(&mut self, expected: Token) -> Result<()> {
-        if self.at_end() {
-            return Err(crate::CompilerError::Parse(format!(
-                "Expected {:?}, found end of input",
-                expected
-            )));
-        }
+    fn current_span(&self) -> Span {
+        self.spans[self.position.min(self.spans.len() - 1)]
+    }
+
+    /// Span of the token just consumed
+    fn previous_span(&self) -> Span {
+        self.spans[self.position.saturating_sub(1)]
+    }
 
-        let token = self.tokens[self.position].clone();
+    /// An "Expected ..., found ..." error at the current token
+    fn expected(&self, what: &str) -> Diagnostic {
+        Diagnostic::new(
+            format!("Expected {}, found {}", what, self.peek().describe()),
+            self.current_span(),
+        )
+    }
+
+    fn consume(&mut self, expected: Token) -> ParseResult<()> {
+        let token = self.peek();
         if std::mem::discriminant(&token) == std::mem::discriminant(&expected) {
             self.position += 1;
             Ok(())
         } else {
-            Err(crate::CompilerError::Parse(format!(
-                "Expected {:?}, found {:?}",
-                expected, token
-            )))
+            Err(self.expected(&expected.describe()))
         }
     }
 
-    fn consume_identifier(&mut self) -> Result<String> {
-        if self.at_end() {
-            return Err(crate::CompilerError::Parse(
-                "Expected identifier, found end of input".to_string(),
-            ));
-        }
-
-        match &self.tokens[self.position] {
+    fn consume_identifier(&mut self) -> ParseResult<String> {
+        match self.peek() {
             Token::Identifier(name) => {
                 self.position += 1;
-                Ok(name.clone())
+                Ok(name)
             }
-            _ => Err(crate::CompilerError::Parse(
-                "Expected identifier".to_string(),
-            )),
+            _ => Err(self.expected("identifier")),
         }
     }
 
-    fn consume_integer(&mut self) -> Result<i64> {
-        if self.at_end() {
-            return Err(crate::CompilerError::Parse(
-                "Expected integer, found end of input".to_string(),
-            ));
+    fn consume_string(&mut self) -> ParseResult<String> {
+        match self.peek() {
+            Token::StringLiteral(value) => {
+                self.position += 1;
+                Ok(value)
+            }
+            _ => Err(self.expected("string literal")),
         }
+    }
 
-        match &self.tokens[self.position] {
+    fn consume_integer(&mut self) -> ParseResult<i128> {
+        match self.peek() {
             Token::Integer(value) => {
                 self.position += 1;
-                Ok(*value)
+                Ok(value)
+            }
+            _ => Err(self.expected("integer")),
+        }
+    }
+}
+
+/// Lex a number starting at `start`: an optional `-`, then a decimal integer or float
+/// (`1_000`, `2.5`, `1e-3`) or a `0x`, `0o` or `0b` integer
+///
+/// Returns the index just past the literal, and the token or what's wrong with it.
+fn lex_number(chars: &[char], start: usize) -> (usize, std::result::Result<Token, String>) {
+    let digit_at = |i: usize| {
+        chars
+            .get(i)
+            .is_some_and(|c| c.is_ascii_digit() || *c == '_')
+    };
+    let word_at = |i: usize| {
+        chars
+            .get(i)
+            .is_some_and(|c| c.is_alphanumeric() || *c == '_')
+    };
+
+    let mut i = start;
+    let negative = chars[i] == '-';
+    if negative {
+        i += 1;
+    }
+
+    let radix = match (chars[i], chars.get(i + 1)) {
+        ('0', Some('x' | 'X')) => Some((16, "hexadecimal")),
+        ('0', Some('o' | 'O')) => Some((8, "octal")),
+        ('0', Some('b' | 'B')) => Some((2, "binary")),
+        _ => None,
+    };
+
+    if let Some((radix, kind)) = radix {
+        i += 2;
+        let digits_start = i;
+        while word_at(i) {
+            i += 1;
+        }
+        let literal: String = chars[start..i].iter().collect();
+        let digits: String = chars[digits_start..i]
+            .iter()
+            .filter(|c| **c != '_')
+            .collect();
+
+        let result = if digits.is_empty() {
+            Err(format!("Missing digits in {} literal '{}'", kind, literal))
+        } else if !digits.chars().all(|c| c.is_digit(radix)) {
+            Err(format!("Invalid {} literal '{}'", kind, literal))
+        } else {
+            i128::from_str_radix(&digits, radix)
+                .map(|value| Token::Integer(if negative { -value } else { value }))
+                .map_err(|_| format!("Integer literal '{}' is out of range", literal))
+        };
+        return (i, result);
+    }
+
+    while digit_at(i) {
+        i += 1;
+    }
+    let mut float = false;
+    // `1.` is a float, but not when a name or another dot follows the dot
+    if chars.get(i) == Some(&'.')
+        && !chars
+            .get(i + 1)
+            .is_some_and(|c| c.is_alphabetic() || *c == '_' || *c == '.')
+    {
+        float = true;
+        i += 1;
+        while digit_at(i) {
+            i += 1;
+        }
+    }
+    if matches!(chars.get(i), Some('e' | 'E')) {
+        let sign = usize::from(matches!(chars.get(i + 1), Some('+' | '-')));
+        if chars.get(i + 1 + sign).is_some_and(char::is_ascii_digit) {
+            float = true;
+            i += 1 + sign;
+            while digit_at(i) {
+                i += 1;
             }
-            _ => Err(crate::CompilerError::Parse("Expected integer".to_string())),
         }
     }
+
+    // Letters straight after a number (`12ab`, `1.5f`) make one malformed literal
+    let end = i;
+    while word_at(i) {
+        i += 1;
+    }
+    let literal: String = chars[start..i].iter().collect();
+    let cleaned: String = chars[start..end].iter().filter(|c| **c != '_').collect();
+
+    let result = if i > end {
+        Err(format!("Invalid number literal '{}'", literal))
+    } else if float {
+        match cleaned.parse::<f64>() {
+            Ok(value) if value.is_finite() => Ok(Token::Float(value)),
+            _ => Err(format!("Float literal '{}' is out of range", literal)),
+        }
+    } else {
+        cleaned
+            .parse()
+            .map(Token::Integer)
+            .map_err(|_| format!("Integer literal '{}' is out of range", literal))
+    };
+    (i, result)
+}
+
+/// Lex a string literal starting at its opening quote, resolving escape sequences
+///
+/// Returns the index just past the closing quote, the string's value, and any errors
+/// along with the character range each one covers.
+fn lex_string(chars: &[char], start: usize) -> (usize, String, Vec<(String, usize, usize)>) {
+    let mut value = String::new();
+    let mut errors = Vec::new();
+    let mut i = start + 1;
+
+    loop {
+        match chars.get(i) {
+            None => {
+                errors.push(("Unterminated string literal".to_string(), start, i));
+                return (i, value, errors);
+            }
+            Some('"') => return (i + 1, value, errors),
+            Some('\\') => {
+                let escape_start = i;
+                let Some(&escape) = chars.get(i + 1) else {
+                    i += 1;
+                    continue;
+                };
+                i += 2;
+                match escape {
+                    'n' => value.push('\n'),
+                    'r' => value.push('\r'),
+                    't' => value.push('\t'),
+                    '0' => value.push('\0'),
+                    '\\' | '"' | '\'' => value.push(escape),
+                    'u' if chars.get(i) == Some(&'{') => {
+                        let close = chars[i..]
+                            .iter()
+                            .position(|c| *c == '}' || *c == '"')
+                            .map(|offset| i + offset)
+                            .filter(|close| chars[*close] == '}');
+                        let Some(close) = close else {
+                            errors.push((
+                                "Unterminated unicode escape".to_string(),
+                                escape_start,
+                                i,
+                            ));
+                            continue;
+                        };
+                        let digits: String = chars[i + 1..close].iter().collect();
+                        i = close + 1;
+                        let unicode = Some(&digits)
+                            .filter(|digits| (1..=6).contains(&digits.len()))
+                            .filter(|digits| digits.chars().all(|c| c.is_ascii_hexdigit()))
+                            .and_then(|digits| u32::from_str_radix(digits, 16).ok())
+                            .and_then(char::from_u32);
+                        match unicode {
+                            Some(c) => value.push(c),
+                            None => errors.push((
+                                format!("Invalid unicode escape '\\u{{{}}}'", digits),
+                                escape_start,
+                                i,
+                            )),
+                        }
+                    }
+                    other => errors.push((
+                        format!("Unknown escape sequence '\\{}'", other),
+                        escape_start,
+                        i,
+                    )),
+                }
+            }
+            Some(&c) => {
+                value.push(c);
+                i += 1;
+            }
+        }
+    }
+}
+
+/// The token for a single punctuation character
+fn punctuation(c: char) -> Option<Token> {
+    Some(match c {
+        ':' => Token::Colon,
+        ';' => Token::Semicolon,
+        '{' => Token::LeftBrace,
+        '}' => Token::RightBrace,
+        '[' => Token::LeftBracket,
+        ']' => Token::RightBracket,
+        '=' => Token::Equals,
+        ',' => Token::Comma,
+        '?' => Token::Question,
+        '@' => Token::At,
+        '(' => Token::LeftParen,
+        ')' => Token::RightParen,
+        '.' => Token::Dot,
+        _ => return None,
+    })
 }
 
 #[cfg(test)]
@@ -523,6 +1286,316 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_union() {
+        let input = r#"
+            union Command {
+                Start: StartCmd;
+                Stop: StopCmd;
+            }
+
+            message Envelope {
+                command: Command;
+            }
+        "#;
+
+        let schema = parse(input).unwrap();
+        assert_eq!(schema.items.len(), 2);
+
+        if let SchemaItem::Union(un) = &schema.items[0] {
+            assert_eq!(un.name, "Command");
+            assert_eq!(un.variants.len(), 2);
+            assert_eq!(un.variants[0].name, "Start");
+            assert_eq!(
+                un.variants[1].variant_type,
+                FieldType::UserDefined("StopCmd".to_string())
+            );
+        } else {
+            panic!("Expected union");
+        }
+    }
+
+    #[test]
+    fn test_packed_and_bitmap_attributes() {
+        let input = r#"
+            message Flags @bitmap {
+                bits: [bool] @packed;
+                label: string?;
+            }
+        "#;
+
+        let schema = parse(input).unwrap();
+
+        if let SchemaItem::Message(msg) = &schema.items[0] {
+            assert!(msg.presence_bitmap);
+            assert!(msg.fields[0].packed);
+            assert!(!msg.fields[1].packed);
+            assert!(msg.fields[1].optional);
+        } else {
+            panic!("Expected message");
+        }
+
+        assert!(parse("message M { a: u8 @zipped; }").is_err());
+    }
+
+    #[test]
+    fn test_field_numbers() {
+        let input = r#"
+            message Contact {
+                email: string @3;
+                name: string @0;
+                phone: string?;
+                tags: [bool] @packed @7;
+            }
+        "#;
+
+        let schema = parse(input).unwrap();
+
+        if let SchemaItem::Message(msg) = &schema.items[0] {
+            assert_eq!(msg.fields[0].number, Some(3));
+            assert_eq!(msg.fields[2].number, None);
+            assert!(msg.fields[3].packed);
+            assert_eq!(msg.field_numbers(), [3, 0, 1, 7]);
+        } else {
+            panic!("Expected message");
+        }
+
+        assert!(parse("message M { a: u8 @1 @2; }").is_err());
+        assert!(parse("message M { a: u8 @65534; }").is_err());
+        assert!(parse("message M { a: u8 @-1; }").is_err());
+        assert!(parse("message M @1 { a: u8; }").is_err());
+    }
+
+    #[test]
+    fn test_reserved_and_deprecated() {
+        let input = r#"
+            message Account {
+                reserved 4, 7;
+                reserved "legacy_name", 9;
+                email: string @deprecated("use contact instead");
+                fax: string? @deprecated;
+                reserved: bool;
+            }
+        "#;
+
+        let schema = parse(input).unwrap();
+
+        if let SchemaItem::Message(msg) = &schema.items[0] {
+            assert_eq!(msg.reserved_numbers, [4, 7, 9]);
+            assert_eq!(msg.reserved_names, ["legacy_name"]);
+            assert_eq!(
+                msg.fields[0].deprecated.as_deref(),
+                Some("use contact instead")
+            );
+            assert_eq!(msg.fields[1].deprecated.as_deref(), Some(""));
+            // `reserved` is still usable as a field name
+            assert_eq!(msg.fields[2].name, "reserved");
+            assert_eq!(msg.fields[2].deprecated, None);
+        } else {
+            panic!("Expected message");
+        }
+
+        assert!(parse("message M { reserved; }").is_err());
+        assert!(parse("message M { reserved 70000; }").is_err());
+        assert!(parse("message M { a: u8 @packed(\"x\"); }").is_err());
+    }
+
+    #[test]
+    fn test_namespaced_attributes() {
+        let input = r#"
+            message User @rust.name("UserRecord") @rust.derive(Clone, serde::Serialize) {
+                user_name: string @rust.cfg(feature = "names") @docs.hidden;
+                age: u8 @packed.not;
+            }
+        "#;
+
+        let schema = parse(input).unwrap();
+
+        if let SchemaItem::Message(msg) = &schema.items[0] {
+            assert_eq!(msg.attributes.len(), 2);
+            assert_eq!(
+                msg.attribute("rust.name").unwrap().string_arg(),
+                Some("UserRecord")
+            );
+            assert_eq!(
+                msg.attribute("rust.derive").unwrap().args,
+                [
+                    AttributeArg {
+                        key: None,
+                        value: AttributeValue::Path("Clone".to_string()),
+                    },
+                    AttributeArg {
+                        key: None,
+                        value: AttributeValue::Path("serde::Serialize".to_string()),
+                    },
+                ]
+            );
+
+            let cfg = msg.fields[0].attribute("rust.cfg").unwrap();
+            assert_eq!(cfg.args[0].key.as_deref(), Some("feature"));
+            assert_eq!(cfg.to_string(), "@rust.cfg(feature = \"names\")");
+            // Attributes outside the `rust` namespace are kept for plugins
+            assert_eq!(msg.fields[0].attribute("docs.hidden").unwrap().args, []);
+            assert!(!msg.fields[1].packed);
+            assert!(msg.fields[1].attribute("packed.not").is_some());
+        } else {
+            panic!("Expected message");
+        }
+
+        assert!(parse("message M { a: u8 @rust.derive(; }").is_err());
+        assert!(parse("message M { a: u8 @rust.; }").is_err());
+    }
+
+    #[test]
+    fn test_package_and_imports() {
+        let input = r#"
+            package acme.orders;
+            import "users.zp";
+            import "common/money.zp";
+
+            message Order {
+                buyer: User;
+            }
+        "#;
+
+        let schema = parse(input).unwrap();
+        assert_eq!(schema.package.as_deref(), Some("acme.orders"));
+        assert_eq!(schema.imports, ["users.zp", "common/money.zp"]);
+        assert_eq!(schema.items.len(), 1);
+        let span = schema.package_span;
+        assert_eq!(&input[span.start..span.end], "acme.orders");
+        let span = schema.import_spans[1];
+        assert_eq!(&input[span.start..span.end], "\"common/money.zp\"");
+
+        assert!(parse("package a; package b;").is_err());
+        assert!(parse("package acme.;").is_err());
+        assert!(parse("import users;").is_err());
+        // A message may still be called `package`
+        assert!(parse("message Holder { package: u8; }").is_ok());
+    }
+
+    #[test]
+    fn test_constants_and_aliases() {
+        let input = r#"
+            /// Most friends a user can have
+            const MAX_FRIENDS: u32 = 500;
+            type UserId = u64;
+            type Friends = [UserId];
+
+            message User {
+                id_value: UserId;
+                friend_limit: u32 = MAX_FRIENDS;
+            }
+        "#;
+
+        let schema = parse(input).unwrap();
+        assert_eq!(schema.constants.len(), 1);
+        let constant = &schema.constants[0];
+        assert_eq!(constant.name, "MAX_FRIENDS");
+        assert_eq!(constant.value_type, FieldType::Scalar(ScalarType::U32));
+        assert_eq!(constant.value, DefaultValue::Integer(500));
+        assert_eq!(constant.docs, ["Most friends a user can have"]);
+
+        assert_eq!(schema.aliases.len(), 2);
+        assert_eq!(schema.aliases[0].target, FieldType::Scalar(ScalarType::U64));
+        assert_eq!(
+            schema.aliases[1].target,
+            FieldType::Vector(Box::new(FieldType::UserDefined("UserId".to_string())))
+        );
+        assert_eq!(
+            &input[schema.aliases[1].type_span.start..schema.aliases[1].type_span.end],
+            "[UserId]"
+        );
+
+        let user = schema.find_message("User").unwrap();
+        assert_eq!(
+            user.fields[1].default_value,
+            Some(DefaultValue::Constant("MAX_FRIENDS".to_string()))
+        );
+
+        assert!(parse("const LIMIT: u32;").is_err());
+        assert!(parse("type Id u64;").is_err());
+        // Fields may still be called `const` or `type`
+        assert!(parse("message M { const: u8; type: u8; }").is_ok());
+    }
+
+    #[test]
+    fn test_structs() {
+        let input = r#"
+            /// A point in space
+            struct Vec3 @rust.derive(Hash) {
+                x: f32;
+                y: f32;
+                z: Meters;
+            }
+            message Shape { struct: u8; points: [Vec3]; }
+        "#;
+
+        let schema = parse(input).unwrap();
+        let vec3 = schema.find_struct("Vec3").unwrap();
+        assert_eq!(vec3.docs, ["A point in space"]);
+        assert_eq!(
+            vec3.attributes,
+            [Attribute {
+                name: "rust.derive".to_string(),
+                args: vec![AttributeArg {
+                    key: None,
+                    value: AttributeValue::Path("Hash".to_string()),
+                }],
+            }]
+        );
+        assert_eq!(vec3.fields.len(), 3);
+        assert_eq!(
+            vec3.fields[2].field_type,
+            FieldType::UserDefined("Meters".to_string())
+        );
+        assert_eq!(&input[vec3.span.start..vec3.span.end], "Vec3");
+        assert_eq!(schema.items[0].keyword(), "struct");
+
+        // `struct` is only a keyword where a declaration can start
+        let shape = schema.find_message("Shape").unwrap();
+        assert_eq!(shape.fields[0].name, "struct");
+
+        let errors = diagnostics("struct S @packed @2 { a: u8; }");
+        assert_eq!(errors[0].0, "Unknown struct attribute '@packed'");
+        assert_eq!(errors[1].0, "Struct 'S' cannot have a field number");
+    }
+
+    #[test]
+    fn test_well_known_types() {
+        let schema =
+            parse("message Event { at: timestamp; took: duration?; ids: [uuid]; total: decimal; }")
+                .unwrap();
+        let event = schema.find_message("Event").unwrap();
+        assert_eq!(
+            event.fields[0].field_type,
+            FieldType::Scalar(ScalarType::Timestamp)
+        );
+        assert_eq!(
+            event.fields[1].field_type,
+            FieldType::Scalar(ScalarType::Duration)
+        );
+        assert_eq!(
+            event.fields[2].field_type,
+            FieldType::Vector(Box::new(FieldType::Scalar(ScalarType::Uuid)))
+        );
+        assert_eq!(
+            event.fields[3].field_type,
+            FieldType::Scalar(ScalarType::Decimal)
+        );
+    }
+
+    #[test]
+    fn test_any_type() {
+        let schema = parse("message Envelope { body: any; extra: any?; }").unwrap();
+        let envelope = schema.find_message("Envelope").unwrap();
+        assert_eq!(
+            envelope.fields[0].field_type,
+            FieldType::Scalar(ScalarType::Any)
+        );
+        assert!(envelope.fields[1].optional);
+    }
+
     #[test]
     fn test_optional_with_default() {
         let input = r#"
@@ -544,4 +1617,202 @@ mod tests {
             panic!("Expected message");
         }
     }
+
+    #[test]
+    fn test_doc_comments() {
+        let input = r#"
+            /// A registered user.
+            ///
+            /// Users are created on sign-up.
+            message User @bitmap {
+                /// Unique login name
+                name: string;
+                // Not a doc comment
+                age: u8;
+                //// Not a doc comment either
+                email: string?;
+            }
+
+            /// Display theme
+            enum Theme {
+                ///Follows the system
+                Auto = 0;
+                Dark = 1;
+            }
+
+            /// Who placed an order
+            union Party {
+                /// A person
+                person: User;
+            }
+        "#;
+
+        let schema = parse(input).unwrap();
+        let SchemaItem::Message(msg) = &schema.items[0] else {
+            panic!("Expected message");
+        };
+        assert_eq!(
+            msg.docs,
+            ["A registered user.", "", "Users are created on sign-up."]
+        );
+        assert_eq!(msg.fields[0].docs, ["Unique login name"]);
+        assert!(msg.fields[1].docs.is_empty());
+        assert!(msg.fields[2].docs.is_empty());
+
+        let SchemaItem::Enum(en) = &schema.items[1] else {
+            panic!("Expected enum");
+        };
+        assert_eq!(en.docs, ["Display theme"]);
+        assert_eq!(en.variants[0].docs, ["Follows the system"]);
+        assert!(en.variants[1].docs.is_empty());
+
+        let SchemaItem::Union(un) = &schema.items[2] else {
+            panic!("Expected union");
+        };
+        assert_eq!(un.docs, ["Who placed an order"]);
+        assert_eq!(un.variants[0].docs, ["A person"]);
+    }
+
+    fn diagnostics(input: &str) -> Vec<(String, (usize, usize))> {
+        match parse(input) {
+            Err(crate::CompilerError::Diagnostics(diagnostics)) => diagnostics
+                .diagnostics
+                .iter()
+                .map(|d| (d.message.clone(), d.span.location(input)))
+                .collect(),
+            other => panic!("Expected diagnostics, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_error_locations_and_recovery() {
+        let input = "message User {\n    id: u64\n    name: string;\n}\n\nmessage Post {\n    title: string @zipped;\n    body: ;\n}\n";
+
+        assert_eq!(
+            diagnostics(input),
+            [
+                ("Expected ';', found 'name'".to_string(), (3, 5)),
+                ("Unknown field attribute '@zipped'".to_string(), (7, 19)),
+                ("Expected identifier, found ';'".to_string(), (8, 11)),
+            ]
+        );
+
+        // Whatever parsed is still available
+        let (schema, errors) = parse_with_recovery(input);
+        assert_eq!(errors.len(), 3);
+        assert_eq!(schema.type_names().collect::<Vec<_>>(), ["User", "Post"]);
+        match &schema.items[1] {
+            SchemaItem::Message(post) => assert_eq!(post.fields[0].name, "title"),
+            _ => panic!("Expected message"),
+        }
+
+        // A missing closing brace is reported once, and the next message still parses
+        let errors = diagnostics("message A { a: u8;\nmessage B { b: u8 }\n");
+        assert_eq!(
+            errors,
+            [
+                ("Expected '}', found 'message'".to_string(), (2, 1)),
+                ("Expected ';', found '}'".to_string(), (2, 19)),
+            ]
+        );
+
+        let errors =
+            diagnostics("message A { a: u8 @9999999999999999999999999999999999999999; b: u8 @-; }");
+        assert_eq!(
+            errors[0].0,
+            "Integer literal '9999999999999999999999999999999999999999' is out of range"
+        );
+        assert_eq!(errors[1].0, "Unexpected character '-'");
+    }
+
+    #[test]
+    fn test_no_panics_on_malformed_input() {
+        let schema = r#"
+            /// Docs
+            package acme.users;
+            import "other.zp";
+            message User @bitmap @rust.derive(Hash) {
+                id: u64 @1;
+                name: string? = "anon" @deprecated("x");
+                tags: [string];
+                reserved 4, "old";
+            }
+            enum Role { Admin = 0; }
+            union Party { user: User; }
+            struct Vec2 @rust.derive(Hash) { x: f32; y: f32; }
+            /* block */
+        "#;
+
+        // Every prefix of a valid schema is either valid or a clean error
+        for (end, _) in schema.char_indices() {
+            let _ = parse(&schema[..end]);
+        }
+        for input in [
+            "-",
+            "@",
+            "\"open",
+            "/* open",
+            "}}}",
+            "message A { a: [u8; }",
+            "é",
+            "1.2.3",
+        ] {
+            assert!(parse(input).is_err(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_literals() {
+        let input = r#"
+            message M {
+                a: string = "say \"hi\"\n\t\\ \u{1F600}";
+                b: u32 = 0xFF_FF;
+                c: u8 = 0b1010;
+                d: i32 = -0o17;
+                e: u64 = 1_000_000;
+                f: f64 = 1e-3;
+                g: f64 = -2.5E+2;
+                h: f32 = 1.;
+            }
+        "#;
+
+        let schema = parse(input).unwrap();
+        let SchemaItem::Message(msg) = &schema.items[0] else {
+            panic!("Expected message");
+        };
+        let defaults: Vec<_> = msg
+            .fields
+            .iter()
+            .map(|field| field.default_value.clone().unwrap())
+            .collect();
+        assert_eq!(
+            defaults,
+            [
+                DefaultValue::String("say \"hi\"\n\t\\ \u{1F600}".to_string()),
+                DefaultValue::Integer(0xFFFF),
+                DefaultValue::Integer(10),
+                DefaultValue::Integer(-15),
+                DefaultValue::Integer(1_000_000),
+                DefaultValue::Float(1e-3),
+                DefaultValue::Float(-250.0),
+                DefaultValue::Float(1.0),
+            ]
+        );
+
+        let errors = diagnostics(
+            r#"message M { a: string = "\q"; b: u8 = 0x; c: u8 = 0b12; d: u8 = 12ab; e: f64 = 1e999; f: string = "\u{D800}"; }"#,
+        );
+        let messages: Vec<_> = errors.iter().map(|(message, _)| message.as_str()).collect();
+        assert_eq!(
+            messages,
+            [
+                "Unknown escape sequence '\\q'",
+                "Missing digits in hexadecimal literal '0x'",
+                "Invalid binary literal '0b12'",
+                "Invalid number literal '12ab'",
+                "Float literal '1e999' is out of range",
+                "Invalid unicode escape '\\u{D800}'",
+            ]
+        );
+    }
 }
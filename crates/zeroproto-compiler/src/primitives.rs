@@ -17,4 +17,9 @@ pub enum PrimitiveType {
     Bool = 10,
     String = 11,
     Bytes = 12,
+    Timestamp = 18,
+    Duration = 19,
+    Uuid = 20,
+    Decimal = 21,
+    Any = 22,
 }
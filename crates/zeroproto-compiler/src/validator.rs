@@ -1,18 +1,65 @@
 //! Validator for ZeroProto schema AST
 
 use crate::ast::*;
-use crate::Result;
+use crate::diagnostic::{Diagnostic, Span};
+use crate::{CompilerError, Result};
 use std::collections::HashMap;
 
 /// Validate a schema AST
 pub fn validate(schema: &Schema) -> Result<()> {
+    validate_with_imports(schema, &[])
+}
+
+/// Validate a schema AST whose fields may refer to types defined by `imports`
+pub fn validate_with_imports(schema: &Schema, imports: &[&Schema]) -> Result<()> {
     let mut validator = SchemaValidator::new();
+    validator.collect_imported_names(schema, imports)?;
     validator.validate(schema)
 }
 
+/// A validation error at `span`
+fn error(message: String, span: Span) -> CompilerError {
+    CompilerError::Validation(Diagnostic::new(message, span))
+}
+
+/// `rust.*` attributes the code generator understands on messages
+const RUST_MESSAGE_ATTRIBUTES: &[&str] = &["rust.name", "rust.derive", "rust.vis", "rust.cfg"];
+
+/// `rust.*` attributes the code generator understands on fields
+const RUST_FIELD_ATTRIBUTES: &[&str] = &["rust.name", "rust.cfg", "rust.inline"];
+
+/// `rust.*` attributes the code generator understands on structs
+const RUST_STRUCT_ATTRIBUTES: &[&str] = &["rust.derive"];
+
+/// `rust.*` attributes the code generator understands on struct fields
+const RUST_STRUCT_FIELD_ATTRIBUTES: &[&str] = &["rust.name"];
+
+/// Derivable standard traits whose support depends on the owned type's fields
+///
+/// `Debug`, `Clone`, `PartialEq` and `Default` are always implemented; other derives,
+/// such as `serde::Serialize`, are passed through unchecked.
+const CHECKED_DERIVES: &[&str] = &["Copy", "Eq", "Hash", "PartialOrd", "Ord"];
+
+/// Rust keywords, which can't name the modules generated for package segments
+const RUST_KEYWORDS: &[&str] = &[
+    "abstract", "as", "async", "await", "become", "box", "break", "const", "continue", "crate",
+    "do", "dyn", "else", "enum", "extern", "false", "final", "fn", "for", "gen", "if", "impl",
+    "in", "let", "loop", "macro", "match", "mod", "move", "mut", "override", "priv", "pub", "ref",
+    "return", "self", "Self", "static", "struct", "super", "trait", "true", "try", "type",
+    "typeof", "unsafe", "unsized", "use", "virtual", "where", "while", "yield",
+];
+
 /// Schema validation context
 struct SchemaValidator {
     type_names: HashMap<String, TypeKind>,
+    /// Target of every type alias in scope
+    aliases: HashMap<String, FieldType>,
+    /// Value of every constant in scope
+    constants: HashMap<String, DefaultValue>,
+    /// Field types of every struct in scope, in layout order
+    structs: HashMap<String, Vec<FieldType>>,
+    /// Traits named by `@rust.derive` on every message and struct in scope
+    derives: HashMap<String, Vec<String>>,
     current_message: Option<String>,
 }
 
@@ -21,6 +68,9 @@ struct SchemaValidator {
 enum TypeKind {
     Message,
     Enum,
+    Union,
+    Struct,
+    Alias,
 }
 
 impl SchemaValidator {
@@ -28,110 +78,502 @@ impl SchemaValidator {
     fn new() -> Self {
         Self {
             type_names: HashMap::new(),
+            aliases: HashMap::new(),
+            constants: HashMap::new(),
+            structs: HashMap::new(),
+            derives: HashMap::new(),
             current_message: None,
         }
     }
 
     /// Validate the entire schema
     fn validate(&mut self, schema: &Schema) -> Result<()> {
+        // Each package segment becomes a Rust module
+        if let Some(package) = &schema.package {
+            if let Some(segment) = package
+                .split('.')
+                .find(|segment| RUST_KEYWORDS.contains(segment))
+            {
+                return Err(error(
+                    format!(
+                        "Package '{}' can't be generated as Rust modules: '{}' is a Rust keyword",
+                        package, segment
+                    ),
+                    schema.package_span,
+                ));
+            }
+        }
+
         // First pass: collect all type names
         self.collect_type_names(schema)?;
 
+        // Renamed messages can't collide in the generated code
+        let mut rust_names: HashMap<String, &str> = HashMap::new();
+        for message in schema.messages() {
+            let rust_name = match message
+                .attribute("rust.name")
+                .and_then(|attr| attr.string_arg())
+            {
+                Some(rust_name) => rust_name.to_string(),
+                None => message.name.clone(),
+            };
+            if let Some(previous) = rust_names.insert(rust_name.clone(), &message.name) {
+                return Err(error(
+                    format!(
+                        "Messages '{}' and '{}' would both generate Rust type '{}'",
+                        previous, message.name, rust_name
+                    ),
+                    message.span,
+                ));
+            }
+        }
+
+        // Aliases and constants are checked before the fields that use them
+        for alias in &schema.aliases {
+            self.validate_alias(alias)?;
+        }
+        for constant in &schema.constants {
+            self.validate_constant(constant)?;
+        }
+
         // Second pass: validate all items
         for item in &schema.items {
             match item {
                 SchemaItem::Message(msg) => self.validate_message(msg)?,
                 SchemaItem::Enum(en) => self.validate_enum(en)?,
+                SchemaItem::Union(un) => self.validate_union(un)?,
+                SchemaItem::Struct(st) => self.validate_struct(st)?,
             }
         }
 
         Ok(())
     }
 
-    /// Collect all type names from the schema
-    fn collect_type_names(&mut self, schema: &Schema) -> Result<()> {
-        for item in &schema.items {
-            let name = match item {
-                SchemaItem::Message(msg) => &msg.name,
-                SchemaItem::Enum(en) => &en.name,
-            };
+    /// Collect the type names made visible by the schemas `schema` imports
+    fn collect_imported_names(&mut self, schema: &Schema, imports: &[&Schema]) -> Result<()> {
+        // Clashing names come from other files, so point at the import declarations
+        let span = match (schema.import_spans.first(), schema.import_spans.last()) {
+            (Some(first), Some(last)) => first.to(*last),
+            _ => Span::default(),
+        };
+        for import in imports {
+            for item in &import.items {
+                let (name, kind) = match item {
+                    SchemaItem::Message(msg) => (&msg.name, TypeKind::Message),
+                    SchemaItem::Enum(en) => (&en.name, TypeKind::Enum),
+                    SchemaItem::Union(un) => (&un.name, TypeKind::Union),
+                    SchemaItem::Struct(st) => (&st.name, TypeKind::Struct),
+                };
 
-            if self.type_names.contains_key(name) {
-                return Err(crate::CompilerError::Validation(format!(
-                    "Duplicate type name '{}' found",
-                    name
-                )));
+                if self.type_names.insert(name.clone(), kind).is_some() {
+                    return Err(error(
+                        format!("Type '{}' is defined by more than one import", name),
+                        span,
+                    ));
+                }
             }
+            self.collect_structs(import);
+            self.collect_derives(import);
+
+            for alias in &import.aliases {
+                if self
+                    .type_names
+                    .insert(alias.name.clone(), TypeKind::Alias)
+                    .is_some()
+                {
+                    return Err(error(
+                        format!("Type '{}' is defined by more than one import", alias.name),
+                        span,
+                    ));
+                }
+                self.aliases
+                    .insert(alias.name.clone(), alias.target.clone());
+            }
+
+            for constant in &import.constants {
+                if self
+                    .constants
+                    .insert(constant.name.clone(), constant.value.clone())
+                    .is_some()
+                {
+                    return Err(error(
+                        format!(
+                            "Constant '{}' is defined by more than one import",
+                            constant.name
+                        ),
+                        span,
+                    ));
+                }
+            }
+        }
+
+        Ok(())
+    }
 
+    /// Collect all type and constant names from the schema
+    fn collect_type_names(&mut self, schema: &Schema) -> Result<()> {
+        let imported: Vec<String> = self.type_names.keys().cloned().collect();
+
+        let items = schema.items.iter().map(|item| {
             let kind = match item {
                 SchemaItem::Message(_) => TypeKind::Message,
                 SchemaItem::Enum(_) => TypeKind::Enum,
+                SchemaItem::Union(_) => TypeKind::Union,
+                SchemaItem::Struct(_) => TypeKind::Struct,
             };
+            (item.name(), kind, item.span())
+        });
+        let aliases = schema
+            .aliases
+            .iter()
+            .map(|alias| (alias.name.as_str(), TypeKind::Alias, alias.span));
+
+        for (name, kind, span) in items.chain(aliases) {
+            if imported.iter().any(|imported| imported == name) {
+                return Err(error(
+                    format!("Type '{}' is already defined by an import", name),
+                    span,
+                ));
+            }
+            if self.type_names.contains_key(name) {
+                return Err(error(format!("Duplicate type name '{}' found", name), span));
+            }
+
+            self.type_names.insert(name.to_string(), kind);
+        }
 
-            self.type_names.insert(name.clone(), kind);
+        for alias in &schema.aliases {
+            self.aliases
+                .insert(alias.name.clone(), alias.target.clone());
+        }
+        self.collect_structs(schema);
+        self.collect_derives(schema);
+
+        let imported: Vec<String> = self.constants.keys().cloned().collect();
+        for constant in &schema.constants {
+            if imported.contains(&constant.name) {
+                return Err(error(
+                    format!(
+                        "Constant '{}' is already defined by an import",
+                        constant.name
+                    ),
+                    constant.span,
+                ));
+            }
+            if self
+                .constants
+                .insert(constant.name.clone(), constant.value.clone())
+                .is_some()
+            {
+                return Err(error(
+                    format!("Duplicate constant name '{}' found", constant.name),
+                    constant.span,
+                ));
+            }
         }
 
         Ok(())
     }
 
+    /// Remember the field types of a schema's structs, for checking their layouts
+    fn collect_structs(&mut self, schema: &Schema) {
+        for struct_def in schema.structs() {
+            let field_types = struct_def
+                .fields
+                .iter()
+                .map(|field| field.field_type.clone())
+                .collect();
+            self.structs.insert(struct_def.name.clone(), field_types);
+        }
+    }
+
+    /// Remember the traits each message and struct derives, for checking the types using them
+    fn collect_derives(&mut self, schema: &Schema) {
+        let messages = schema
+            .messages()
+            .map(|message| (&message.name, message.attribute("rust.derive")));
+        let structs = schema
+            .structs()
+            .map(|struct_def| (&struct_def.name, struct_def.attribute("rust.derive")));
+        for (name, attr) in messages.chain(structs) {
+            let derives = attr
+                .map(|attr| attr.args.iter().filter_map(derive_name).collect())
+                .unwrap_or_default();
+            self.derives.insert(name.clone(), derives);
+        }
+    }
+
+    /// Follow type aliases, inside vectors too, until only messages, enums, unions,
+    /// structs and scalars are named
+    ///
+    /// `span` locates the type being resolved, for reporting an alias cycle.
+    fn resolve_type(&self, field_type: &FieldType, span: Span) -> Result<FieldType> {
+        self.resolve_type_via(field_type, span, &mut Vec::new())
+    }
+
+    /// Resolve `field_type`, having already followed the aliases in `path`
+    fn resolve_type_via(
+        &self,
+        field_type: &FieldType,
+        span: Span,
+        path: &mut Vec<String>,
+    ) -> Result<FieldType> {
+        match field_type {
+            FieldType::Scalar(_) => Ok(field_type.clone()),
+            FieldType::UserDefined(name) => {
+                let Some(target) = self.aliases.get(name) else {
+                    return Ok(field_type.clone());
+                };
+                if path.contains(name) {
+                    return Err(error(
+                        format!("Type alias '{}' refers to itself", name),
+                        span,
+                    ));
+                }
+                path.push(name.clone());
+                let resolved = self.resolve_type_via(target, span, path);
+                path.pop();
+                resolved
+            }
+            FieldType::Vector(inner) => Ok(FieldType::Vector(Box::new(
+                self.resolve_type_via(inner, span, path)?,
+            ))),
+        }
+    }
+
+    /// Follow constant references until a literal is reached, reporting problems at `span`
+    fn resolve_value(&self, value: &DefaultValue, span: Span) -> Result<DefaultValue> {
+        let mut path = Vec::new();
+        let mut value = value;
+        while let DefaultValue::Constant(name) = value {
+            if path.contains(&name) {
+                return Err(error(format!("Constant '{}' refers to itself", name), span));
+            }
+            path.push(name);
+            value = self
+                .constants
+                .get(name)
+                .ok_or_else(|| error(format!("Unknown constant '{}'", name), span))?;
+        }
+        Ok(value.clone())
+    }
+
+    /// Validate a type alias
+    fn validate_alias(&self, alias: &TypeAlias) -> Result<()> {
+        let target = self.resolve_type(&FieldType::UserDefined(alias.name.clone()), alias.span)?;
+        self.validate_field_type(&target, alias.type_span)
+    }
+
+    /// Validate a constant: a scalar type, and a value that fits it
+    fn validate_constant(&self, constant: &Constant) -> Result<()> {
+        let value_type = self.resolve_type(&constant.value_type, constant.type_span)?;
+        self.validate_field_type(&value_type, constant.type_span)?;
+        if !matches!(value_type, FieldType::Scalar(_)) {
+            return Err(error(
+                format!(
+                    "Constant '{}' must have a scalar type, not '{}'",
+                    constant.name, constant.value_type
+                ),
+                constant.type_span,
+            ));
+        }
+
+        let value = self.resolve_value(&constant.value, constant.span)?;
+        validate_default_value(&value, &value_type).map_err(|problem| {
+            error(
+                format!(
+                    "Value {} of constant '{}' {}",
+                    constant.value, constant.name, problem
+                ),
+                constant.span,
+            )
+        })
+    }
+
     /// Validate a message definition
     fn validate_message(&mut self, message: &Message) -> Result<()> {
         self.current_message = Some(message.name.clone());
 
+        validate_rust_attributes(
+            &message.attributes,
+            RUST_MESSAGE_ATTRIBUTES,
+            &format!("message '{}'", message.name),
+            message.span,
+        )?;
+        for field in &message.fields {
+            validate_rust_attributes(
+                &field.attributes,
+                RUST_FIELD_ATTRIBUTES,
+                &format!("field '{}' in message '{}'", field.name, message.name),
+                field.span,
+            )?;
+        }
+
         // Check for reserved field names
         let reserved_names = ["type", "id", "data", "buffer"];
         for field in &message.fields {
             if reserved_names.contains(&field.name.as_str()) {
-                return Err(crate::CompilerError::Validation(format!(
-                    "Field name '{}' is reserved in message '{}'",
-                    field.name, message.name
-                )));
+                return Err(error(
+                    format!(
+                        "Field name '{}' is reserved in message '{}'",
+                        field.name, message.name
+                    ),
+                    field.span,
+                ));
             }
         }
 
-        // Validate field types
-        for field in &message.fields {
-            self.validate_field_type(&field.field_type)?;
+        // Validate field types, looking through aliases
+        let field_types = message
+            .fields
+            .iter()
+            .map(|field| self.resolve_type(&field.field_type, field.type_span))
+            .collect::<Result<Vec<_>>>()?;
+        for (field, field_type) in message.fields.iter().zip(&field_types) {
+            self.validate_field_type(field_type, field.type_span)?;
+        }
+        self.validate_derives(
+            message.attribute("rust.derive"),
+            &field_types,
+            &format!("message '{}'", message.name),
+            message.span,
+        )?;
+
+        // Defaults must be literals or constants of the field's type, within its range
+        for (field, field_type) in message.fields.iter().zip(&field_types) {
+            if let Some(default) = &field.default_value {
+                let value = self.resolve_value(default, field.span)?;
+                validate_default_value(&value, field_type).map_err(|problem| {
+                    error(
+                        format!(
+                            "Default value {} of field '{}' in message '{}' {}",
+                            default, field.name, message.name, problem
+                        ),
+                        field.span,
+                    )
+                })?;
+            }
+        }
+
+        // Only bool vectors can be bit-packed
+        for (field, field_type) in message.fields.iter().zip(&field_types) {
+            let is_bool_vector = matches!(
+                field_type,
+                FieldType::Vector(inner) if **inner == FieldType::Scalar(ScalarType::Bool)
+            );
+            if field.packed && !is_bool_vector {
+                return Err(error(
+                    format!(
+                        "Field '{}' in message '{}' is marked @packed but is not a [bool] vector",
+                        field.name, message.name
+                    ),
+                    field.span,
+                ));
+            }
         }
 
         // Check for duplicate field names
         let mut field_names = HashMap::new();
         for field in &message.fields {
             if field_names.contains_key(&field.name) {
-                return Err(crate::CompilerError::Validation(format!(
-                    "Duplicate field name '{}' in message '{}'",
-                    field.name, message.name
-                )));
+                return Err(error(
+                    format!(
+                        "Duplicate field name '{}' in message '{}'",
+                        field.name, message.name
+                    ),
+                    field.span,
+                ));
             }
             field_names.insert(field.name.clone(), ());
         }
 
+        // Check that field numbers are unique and in range
+        let mut field_numbers: HashMap<u32, &str> = HashMap::new();
+        for (field, number) in message.fields.iter().zip(message.field_numbers()) {
+            if number > u32::from(MAX_FIELD_NUMBER) {
+                return Err(error(
+                    format!(
+                        "Field '{}' in message '{}' would be numbered {}, above the limit of {}",
+                        field.name, message.name, number, MAX_FIELD_NUMBER
+                    ),
+                    field.span,
+                ));
+            }
+            if let Some(previous) = field_numbers.insert(number, &field.name) {
+                return Err(error(
+                    format!(
+                        "Field number {} is used by both '{}' and '{}' in message '{}'",
+                        number, previous, field.name, message.name
+                    ),
+                    field.span,
+                ));
+            }
+        }
+
+        // Retired numbers and names can't come back
+        for (field, number) in message.fields.iter().zip(message.field_numbers()) {
+            if message
+                .reserved_numbers
+                .iter()
+                .any(|reserved| u32::from(*reserved) == number)
+            {
+                return Err(error(
+                    format!(
+                        "Field '{}' in message '{}' uses reserved field number {}",
+                        field.name, message.name, number
+                    ),
+                    field.span,
+                ));
+            }
+            if message.reserved_names.contains(&field.name) {
+                return Err(error(
+                    format!(
+                        "Field name '{}' was retired with `reserved` in message '{}'",
+                        field.name, message.name
+                    ),
+                    field.span,
+                ));
+            }
+        }
+
         self.current_message = None;
         Ok(())
     }
 
-    /// Validate a field type
-    fn validate_field_type(&self, field_type: &FieldType) -> Result<()> {
+    /// Validate a field type written at `span`
+    fn validate_field_type(&self, field_type: &FieldType, span: Span) -> Result<()> {
         match field_type {
             FieldType::Scalar(_) => Ok(()),
             FieldType::UserDefined(name) => {
                 if !self.type_names.contains_key(name) {
-                    return Err(crate::CompilerError::Validation(format!(
-                        "Unknown type '{}' used in field",
-                        name
-                    )));
+                    return Err(error(
+                        format!("Unknown type '{}' used in field", name),
+                        span,
+                    ));
                 }
                 Ok(())
             }
             FieldType::Vector(inner) => {
                 // Validate the inner type
-                self.validate_field_type(inner)?;
+                self.validate_field_type(inner, span)?;
 
                 // Check for nested vectors (not allowed)
                 if matches!(inner.as_ref(), FieldType::Vector(_)) {
-                    return Err(crate::CompilerError::Validation(
-                        "Nested vectors are not allowed".to_string(),
-                    ));
+                    return Err(error("Nested vectors are not allowed".to_string(), span));
+                }
+
+                if **inner == FieldType::Scalar(ScalarType::Any) {
+                    return Err(error("Vectors of any are not allowed".to_string(), span));
+                }
+
+                // Unions carry their own discriminant and cannot be packed into vectors
+                if let FieldType::UserDefined(name) = inner.as_ref() {
+                    if matches!(self.type_names.get(name), Some(TypeKind::Union)) {
+                        return Err(error(
+                            format!("Vectors of union '{}' are not allowed", name),
+                            span,
+                        ));
+                    }
                 }
 
                 Ok(())
@@ -139,16 +581,248 @@ impl SchemaValidator {
         }
     }
 
+    /// Validate a struct definition: a fixed layout of scalars, enums and structs
+    fn validate_struct(&self, struct_def: &Struct) -> Result<()> {
+        validate_rust_attributes(
+            &struct_def.attributes,
+            RUST_STRUCT_ATTRIBUTES,
+            &format!("struct '{}'", struct_def.name),
+            struct_def.span,
+        )?;
+
+        if struct_def.fields.is_empty() {
+            return Err(error(
+                format!("Struct '{}' must have at least one field", struct_def.name),
+                struct_def.span,
+            ));
+        }
+
+        let reserved_names = ["type", "id", "data", "buffer", "bytes"];
+        let mut field_names = HashMap::new();
+        for field in &struct_def.fields {
+            let context = format!("field '{}' in struct '{}'", field.name, struct_def.name);
+            validate_rust_attributes(
+                &field.attributes,
+                RUST_STRUCT_FIELD_ATTRIBUTES,
+                &context,
+                field.span,
+            )?;
+
+            if reserved_names.contains(&field.name.as_str()) {
+                return Err(error(
+                    format!(
+                        "Field name '{}' is reserved in struct '{}'",
+                        field.name, struct_def.name
+                    ),
+                    field.span,
+                ));
+            }
+            if field_names.insert(field.name.clone(), ()).is_some() {
+                return Err(error(
+                    format!(
+                        "Duplicate field name '{}' in struct '{}'",
+                        field.name, struct_def.name
+                    ),
+                    field.span,
+                ));
+            }
+
+            // Every field is always present at a fixed offset
+            let marker = if field.optional {
+                Some("optional")
+            } else if field.default_value.is_some() {
+                Some("given a default value")
+            } else if field.number.is_some() {
+                Some("given a field number")
+            } else if field.packed {
+                Some("marked @packed")
+            } else if field.deprecated.is_some() {
+                Some("marked @deprecated")
+            } else {
+                None
+            };
+            if let Some(marker) = marker {
+                return Err(error(
+                    format!(
+                        "Field '{}' in struct '{}' cannot be {}",
+                        field.name, struct_def.name, marker
+                    ),
+                    field.span,
+                ));
+            }
+
+            let field_type = self.resolve_type(&field.field_type, field.type_span)?;
+            self.validate_field_type(&field_type, field.type_span)?;
+            let fixed_size = match &field_type {
+                FieldType::Scalar(scalar) => !matches!(
+                    scalar,
+                    ScalarType::String | ScalarType::Bytes | ScalarType::Any
+                ),
+                FieldType::UserDefined(name) => matches!(
+                    self.type_names.get(name),
+                    Some(TypeKind::Enum | TypeKind::Struct)
+                ),
+                FieldType::Vector(_) => false,
+            };
+            if !fixed_size {
+                return Err(error(
+                    format!(
+                        "Field '{}' in struct '{}' has type '{}'; struct fields must be \
+                         fixed-size scalars, enums or structs",
+                        field.name, struct_def.name, field.field_type
+                    ),
+                    field.type_span,
+                ));
+            }
+        }
+
+        let field_types = struct_def
+            .fields
+            .iter()
+            .map(|field| self.resolve_type(&field.field_type, field.type_span))
+            .collect::<Result<Vec<_>>>()?;
+        self.validate_derives(
+            struct_def.attribute("rust.derive"),
+            &field_types,
+            &format!("struct '{}'", struct_def.name),
+            struct_def.span,
+        )?;
+
+        let size = self.struct_size(&struct_def.name, struct_def.span, &mut Vec::new())?;
+        if size > u64::from(u16::MAX) {
+            return Err(error(
+                format!(
+                    "Struct '{}' is {} bytes, above the limit of {}",
+                    struct_def.name,
+                    size,
+                    u16::MAX
+                ),
+                struct_def.span,
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Check that the owned type of a message or struct can derive each trait in `attr`
+    fn validate_derives(
+        &self,
+        attr: Option<&Attribute>,
+        field_types: &[FieldType],
+        context: &str,
+        span: Span,
+    ) -> Result<()> {
+        let Some(attr) = attr else {
+            return Ok(());
+        };
+        let derives: Vec<String> = attr.args.iter().filter_map(derive_name).collect();
+        if derives.iter().any(|derive| derive == "Ord")
+            && !(derives.iter().any(|derive| derive == "Eq")
+                && derives.iter().any(|derive| derive == "PartialOrd"))
+        {
+            return Err(error(
+                format!(
+                    "Attribute '{}' on {} derives Ord, which also needs Eq and PartialOrd",
+                    attr, context
+                ),
+                span,
+            ));
+        }
+
+        for derive in derives
+            .iter()
+            .filter(|derive| CHECKED_DERIVES.contains(&derive.as_str()))
+        {
+            if let Some(field_type) = field_types
+                .iter()
+                .find(|field_type| !self.supports_derive(field_type, derive))
+            {
+                return Err(error(
+                    format!(
+                        "Attribute '{}' on {} derives {}, which fields of type '{}' don't \
+                         implement",
+                        attr, context, derive, field_type
+                    ),
+                    span,
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Whether the owned Rust type generated for a resolved field type implements `derive`
+    fn supports_derive(&self, field_type: &FieldType, derive: &str) -> bool {
+        let declares = |name: &str| {
+            self.derives
+                .get(name)
+                .is_some_and(|derives| derives.iter().any(|declared| declared == derive))
+        };
+        match field_type {
+            FieldType::Scalar(scalar) => {
+                let derives: &[&str] = match scalar {
+                    ScalarType::F32 | ScalarType::F64 => &["Copy", "PartialOrd"],
+                    ScalarType::String | ScalarType::Bytes => &["Eq", "Hash", "PartialOrd", "Ord"],
+                    ScalarType::Decimal => &["Copy", "Eq", "Hash"],
+                    ScalarType::Any => &["Eq"],
+                    _ => CHECKED_DERIVES,
+                };
+                derives.contains(&derive)
+            }
+            FieldType::UserDefined(name) => match self.type_names.get(name) {
+                Some(TypeKind::Enum) => ["Copy", "Eq", "Hash"].contains(&derive),
+                // Structs are always Copy
+                Some(TypeKind::Struct) => derive == "Copy" || declares(name),
+                // Nested messages may be boxed, so are never Copy
+                Some(TypeKind::Message) => derive != "Copy" && declares(name),
+                _ => false,
+            },
+            FieldType::Vector(inner) => derive != "Copy" && self.supports_derive(inner, derive),
+        }
+    }
+
+    /// Encoded size of a struct, having already entered the structs in `path`
+    ///
+    /// `span` locates the struct being validated, for reporting a cycle.
+    fn struct_size(&self, name: &str, span: Span, path: &mut Vec<String>) -> Result<u64> {
+        if path.iter().any(|entered| entered == name) {
+            return Err(error(format!("Struct '{}' contains itself", name), span));
+        }
+        let Some(field_types) = self.structs.get(name) else {
+            return Ok(0);
+        };
+
+        path.push(name.to_string());
+        let mut size = 0;
+        for field_type in field_types {
+            size += match self.resolve_type(field_type, span)? {
+                FieldType::Scalar(scalar) => scalar.size() as u64,
+                FieldType::UserDefined(name)
+                    if matches!(self.type_names.get(&name), Some(TypeKind::Struct)) =>
+                {
+                    self.struct_size(&name, span, path)?
+                }
+                // Enums are stored as u64
+                _ => 8,
+            };
+        }
+        path.pop();
+        Ok(size)
+    }
+
     /// Validate an enum definition
     fn validate_enum(&self, enum_def: &Enum) -> Result<()> {
         // Check for duplicate variant names
         let mut variant_names = HashMap::new();
         for variant in &enum_def.variants {
             if variant_names.contains_key(&variant.name) {
-                return Err(crate::CompilerError::Validation(format!(
-                    "Duplicate variant name '{}' in enum '{}'",
-                    variant.name, enum_def.name
-                )));
+                return Err(error(
+                    format!(
+                        "Duplicate variant name '{}' in enum '{}'",
+                        variant.name, enum_def.name
+                    ),
+                    variant.span,
+                ));
             }
             variant_names.insert(variant.name.clone(), ());
         }
@@ -164,10 +838,10 @@ impl SchemaValidator {
             };
 
             if used_values.contains_key(&value) {
-                return Err(crate::CompilerError::Validation(format!(
-                    "Duplicate enum value {} in enum '{}'",
-                    value, enum_def.name
-                )));
+                return Err(error(
+                    format!("Duplicate enum value {} in enum '{}'", value, enum_def.name),
+                    variant.span,
+                ));
             }
             used_values.insert(value, ());
         }
@@ -175,14 +849,182 @@ impl SchemaValidator {
         // Check for reserved enum names
         let reserved_names = ["Result", "Option", "Status"];
         if reserved_names.contains(&enum_def.name.as_str()) {
-            return Err(crate::CompilerError::Validation(format!(
-                "Enum name '{}' is reserved",
-                enum_def.name
-            )));
+            return Err(error(
+                format!("Enum name '{}' is reserved", enum_def.name),
+                enum_def.span,
+            ));
         }
 
         Ok(())
     }
+
+    /// Validate a union definition
+    fn validate_union(&self, union_def: &Union) -> Result<()> {
+        if union_def.variants.is_empty() {
+            return Err(error(
+                format!("Union '{}' must have at least one variant", union_def.name),
+                union_def.span,
+            ));
+        }
+
+        if union_def.variants.len() > u16::MAX as usize {
+            return Err(error(
+                format!("Union '{}' has too many variants", union_def.name),
+                union_def.span,
+            ));
+        }
+
+        let mut variant_names = HashMap::new();
+        for variant in &union_def.variants {
+            if variant_names.contains_key(&variant.name) {
+                return Err(error(
+                    format!(
+                        "Duplicate variant name '{}' in union '{}'",
+                        variant.name, union_def.name
+                    ),
+                    variant.span,
+                ));
+            }
+            variant_names.insert(variant.name.clone(), ());
+
+            // Each variant is stored as a nested message behind the discriminant
+            let variant_type = self.resolve_type(&variant.variant_type, variant.type_span)?;
+            let is_message = match &variant_type {
+                FieldType::UserDefined(name) => {
+                    matches!(self.type_names.get(name), Some(TypeKind::Message))
+                }
+                _ => false,
+            };
+            if !is_message {
+                self.validate_field_type(&variant_type, variant.type_span)?;
+                return Err(error(
+                    format!(
+                        "Variant '{}' in union '{}' must be a message type",
+                        variant.name, union_def.name
+                    ),
+                    variant.type_span,
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Check a default value against its field's type, describing any problem
+fn validate_default_value(
+    default: &DefaultValue,
+    field_type: &FieldType,
+) -> std::result::Result<(), String> {
+    let FieldType::Scalar(scalar) = field_type else {
+        return Err("is not allowed; only scalar fields have defaults".to_string());
+    };
+
+    let in_range = match (default, scalar) {
+        (DefaultValue::Integer(value), _) if scalar.integer_range().is_some() => scalar
+            .integer_range()
+            .is_some_and(|(min, max)| (min..=max).contains(value)),
+        (DefaultValue::Integer(value), ScalarType::F32) => (*value as f64).abs() <= f32::MAX as f64,
+        (DefaultValue::Float(value), ScalarType::F32) => value.abs() <= f32::MAX as f64,
+        (DefaultValue::Integer(_) | DefaultValue::Float(_), ScalarType::F64)
+        | (DefaultValue::Bool(_), ScalarType::Bool)
+        | (DefaultValue::String(_), ScalarType::String) => true,
+        _ => return Err(format!("is not a {} value", scalar.name())),
+    };
+
+    if in_range {
+        Ok(())
+    } else {
+        Err(format!("is out of range for {}", scalar.name()))
+    }
+}
+
+/// Trait named by one `@rust.derive` argument, without its module path
+fn derive_name(arg: &AttributeArg) -> Option<String> {
+    match &arg.value {
+        AttributeValue::Path(path) => path.rsplit("::").next().map(str::to_string),
+        _ => None,
+    }
+}
+
+/// Check the `rust.*` attributes of an item named at `span`; other namespaces are left to
+/// plugins
+fn validate_rust_attributes(
+    attributes: &[Attribute],
+    known: &[&str],
+    context: &str,
+    span: Span,
+) -> Result<()> {
+    let invalid = |attr: &Attribute, problem: &str| {
+        Err(error(
+            format!("Attribute '{}' on {} {}", attr, context, problem),
+            span,
+        ))
+    };
+
+    for (i, attr) in attributes.iter().enumerate() {
+        if !attr.name.starts_with("rust.") {
+            continue;
+        }
+        if !known.contains(&attr.name.as_str()) {
+            return invalid(attr, "is not a known rust attribute");
+        }
+        if attributes[..i].iter().any(|other| other.name == attr.name) {
+            return invalid(attr, "is given more than once");
+        }
+
+        match attr.name.as_str() {
+            "rust.name" => {
+                // The parser skips whitespace and comments, so the whole string must be the
+                // name; raw identifiers can't take the prefixes and suffixes codegen adds
+                if !attr.string_arg().is_some_and(|name| {
+                    !name.starts_with("r#")
+                        && syn::parse_str::<syn::Ident>(name).is_ok_and(|ident| ident == name)
+                }) {
+                    return invalid(
+                        attr,
+                        "needs a Rust identifier, e.g. @rust.name(\"UserRecord\")",
+                    );
+                }
+            }
+            "rust.derive" => {
+                let paths = attr
+                    .args
+                    .iter()
+                    .all(|arg| arg.key.is_none() && matches!(arg.value, AttributeValue::Path(_)));
+                if attr.args.is_empty() || !paths {
+                    return invalid(attr, "needs trait names, e.g. @rust.derive(Hash)");
+                }
+            }
+            "rust.vis" => {
+                if !attr
+                    .string_arg()
+                    .is_some_and(|vis| syn::parse_str::<syn::Visibility>(vis).is_ok())
+                {
+                    return invalid(
+                        attr,
+                        "needs a Rust visibility, e.g. @rust.vis(\"pub(crate)\")",
+                    );
+                }
+            }
+            "rust.cfg" => {
+                let predicate = format!("cfg({})", attr.args_to_string());
+                if attr.args.is_empty() || syn::parse_str::<syn::Meta>(&predicate).is_err() {
+                    return invalid(
+                        attr,
+                        "needs a cfg predicate, e.g. @rust.cfg(feature = \"serde\")",
+                    );
+                }
+            }
+            _ => {
+                if !attr.args.is_empty() {
+                    return invalid(attr, "takes no arguments");
+                }
+            }
+        }
+    }
+
+    Ok(())
 }
 
 /// Additional validation utilities
@@ -207,6 +1049,11 @@ impl ValidationUtils {
                     | ScalarType::Bool
                     | ScalarType::String
                     | ScalarType::Bytes
+                    | ScalarType::Timestamp
+                    | ScalarType::Duration
+                    | ScalarType::Uuid
+                    | ScalarType::Decimal
+                    | ScalarType::Any
             ),
             FieldType::UserDefined(_) => true, // Will be validated elsewhere
             FieldType::Vector(inner) => Self::is_zero_copy_compatible(inner),
@@ -235,6 +1082,10 @@ impl ScalarType {
             ScalarType::U32 | ScalarType::I32 | ScalarType::F32 => 4,
             ScalarType::U64 | ScalarType::I64 | ScalarType::F64 => 8,
             ScalarType::String | ScalarType::Bytes => 4, // Length prefix only
+            ScalarType::Timestamp | ScalarType::Duration => 12,
+            ScalarType::Uuid => 16,
+            ScalarType::Decimal => 17,
+            ScalarType::Any => 6, // Name and message length prefixes only
         }
     }
 }
@@ -282,6 +1133,136 @@ mod tests {
         assert!(validate(&schema).is_err());
     }
 
+    #[test]
+    fn test_union_variants_must_be_messages() {
+        let mut schema = Schema::new();
+        schema.add_item(SchemaItem::Message(Message::new("StartCmd".to_string())));
+        let mut command = Union::new("Command".to_string());
+        command.add_variant(UnionVariant::new(
+            "Start".to_string(),
+            FieldType::UserDefined("StartCmd".to_string()),
+        ));
+        schema.add_item(SchemaItem::Union(command.clone()));
+        assert!(validate(&schema).is_ok());
+
+        command.add_variant(UnionVariant::new(
+            "Code".to_string(),
+            FieldType::Scalar(ScalarType::U32),
+        ));
+        schema.items[1] = SchemaItem::Union(command);
+        assert!(validate(&schema).is_err());
+    }
+
+    #[test]
+    fn test_vector_of_union() {
+        let mut schema = Schema::new();
+        schema.add_item(SchemaItem::Message(Message::new("StartCmd".to_string())));
+        let mut command = Union::new("Command".to_string());
+        command.add_variant(UnionVariant::new(
+            "Start".to_string(),
+            FieldType::UserDefined("StartCmd".to_string()),
+        ));
+        schema.add_item(SchemaItem::Union(command));
+        let mut message = Message::new("Batch".to_string());
+        message.add_field(Field::new(
+            "commands".to_string(),
+            FieldType::Vector(Box::new(FieldType::UserDefined("Command".to_string()))),
+        ));
+        schema.add_item(SchemaItem::Message(message));
+
+        assert!(validate(&schema).is_err());
+    }
+
+    #[test]
+    fn test_packed_requires_bool_vector() {
+        let mut packed_bools = Field::new(
+            "flags".to_string(),
+            FieldType::Vector(Box::new(FieldType::Scalar(ScalarType::Bool))),
+        );
+        packed_bools.packed = true;
+        let mut message = Message::new("Flags".to_string());
+        message.add_field(packed_bools);
+        let mut schema = Schema::new();
+        schema.add_item(SchemaItem::Message(message));
+        assert!(validate(&schema).is_ok());
+
+        let mut packed_ints = Field::new(
+            "counts".to_string(),
+            FieldType::Vector(Box::new(FieldType::Scalar(ScalarType::U32))),
+        );
+        packed_ints.packed = true;
+        let mut message = Message::new("Counts".to_string());
+        message.add_field(packed_ints);
+        let mut schema = Schema::new();
+        schema.add_item(SchemaItem::Message(message));
+        assert!(validate(&schema).is_err());
+    }
+
+    #[test]
+    fn test_duplicate_field_numbers() {
+        let numbered = |name: &str, number: Option<u16>| {
+            let mut field = Field::new(name.to_string(), FieldType::Scalar(ScalarType::U32));
+            field.number = number;
+            field
+        };
+
+        let mut message = Message::new("Contact".to_string());
+        message.add_field(numbered("a", Some(2)));
+        message.add_field(numbered("b", Some(0)));
+        let mut schema = Schema::new();
+        schema.add_item(SchemaItem::Message(message));
+        assert!(validate(&schema).is_ok());
+
+        // `b` follows `a`, so it takes number 3 and collides with `c`
+        let mut message = Message::new("Contact".to_string());
+        message.add_field(numbered("a", Some(2)));
+        message.add_field(numbered("b", None));
+        message.add_field(numbered("c", Some(3)));
+        let mut schema = Schema::new();
+        schema.add_item(SchemaItem::Message(message));
+        let error = validate(&schema).unwrap_err().to_string();
+        assert!(error.contains("Field number 3 is used by both 'b' and 'c'"));
+
+        // Implicit numbering can't run past the limit
+        let mut message = Message::new("Contact".to_string());
+        message.add_field(numbered("a", Some(MAX_FIELD_NUMBER)));
+        message.add_field(numbered("b", None));
+        let mut schema = Schema::new();
+        schema.add_item(SchemaItem::Message(message));
+        assert!(validate(&schema).is_err());
+    }
+
+    #[test]
+    fn test_reserved_fields() {
+        let schema_with = |fields: Vec<Field>| {
+            let mut message = Message::new("Account".to_string());
+            message.reserved_numbers = vec![1, 4];
+            message.reserved_names = vec!["legacy".to_string()];
+            for field in fields {
+                message.add_field(field);
+            }
+            let mut schema = Schema::new();
+            schema.add_item(SchemaItem::Message(message));
+            schema
+        };
+        let field = |name: &str, number: Option<u16>| {
+            let mut field = Field::new(name.to_string(), FieldType::Scalar(ScalarType::U32));
+            field.number = number;
+            field
+        };
+
+        assert!(validate(&schema_with(vec![field("a", Some(0)), field("b", Some(2))])).is_ok());
+
+        // `b` is numbered 1 implicitly
+        let error = validate(&schema_with(vec![field("a", None), field("b", None)]))
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("uses reserved field number 1"));
+
+        assert!(validate(&schema_with(vec![field("a", Some(4))])).is_err());
+        assert!(validate(&schema_with(vec![field("legacy", Some(9))])).is_err());
+    }
+
     #[test]
     fn test_nested_vector() {
         let mut schema = Schema::new();
@@ -294,4 +1275,323 @@ mod tests {
 
         assert!(validate(&schema).is_err());
     }
+
+    #[test]
+    fn test_imported_types() {
+        let users = crate::parse(
+            "message User { name: string; } enum Role { Admin = 0; } union Actor { user: User; }",
+        )
+        .unwrap();
+        let orders =
+            crate::parse("message Order { buyer: User; role: Role; actor: Actor; }").unwrap();
+
+        assert!(validate(&orders).is_err());
+        assert!(validate_with_imports(&orders, &[&users]).is_ok());
+
+        // Imported unions still can't be vector elements
+        let bad = crate::parse("message Order { actors: [Actor]; }").unwrap();
+        assert!(validate_with_imports(&bad, &[&users]).is_err());
+
+        let shadowing = crate::parse("message User { age: u8; }").unwrap();
+        let error = validate_with_imports(&shadowing, &[&users])
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("already defined by an import"));
+
+        let error = validate_with_imports(&orders, &[&users, &users])
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("more than one import"));
+    }
+
+    #[test]
+    fn test_rust_attributes() {
+        let check = |input: &str| validate(&crate::parse(input).unwrap());
+
+        assert!(check(
+            r#"message User @rust.name("UserRecord") @rust.derive(Clone, Hash, Eq) @rust.vis("pub(crate)") @rust.cfg(feature = "users") {
+                user_name: string @rust.name("name") @rust.cfg(unix) @rust.inline @lint.skip;
+            }"#
+        )
+        .is_ok());
+
+        let error = check("message M @rust.colour(\"red\") { a: u8; }")
+            .unwrap_err()
+            .to_string();
+        assert!(
+            error.contains("'@rust.colour(\"red\")' on message 'M' is not a known rust attribute")
+        );
+
+        assert!(check("message M { a: u8 @rust.vis(\"pub\"); }").is_err());
+        assert!(check("message M @rust.name(\"not valid\") { a: u8; }").is_err());
+        assert!(check("message M @rust.name(Ident) { a: u8; }").is_err());
+        assert!(check("message M @rust.name(\" Record\") { a: u8; }").is_err());
+        assert!(check("message M @rust.name(\"\\nRecord\") { a: u8; }").is_err());
+        assert!(check("message M { a: u8 @rust.name(\"hashy//renamed\"); }").is_err());
+        assert!(check("message M { a: u8 @rust.name(\"r#type\"); }").is_err());
+        assert!(check("message M @rust.derive(\"Hash\") { a: u8; }").is_err());
+        assert!(check("message M @rust.vis(\"public\") { a: u8; }").is_err());
+        assert!(check("message M @rust.cfg() { a: u8; }").is_err());
+        assert!(check("message M { a: u8 @rust.inline(always); }").is_err());
+        assert!(check("message M { a: u8 @rust.inline @rust.inline; }").is_err());
+
+        let error = check("message M @rust.derive(Copy) { name: string; }")
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains(
+            "'@rust.derive(Copy)' on message 'M' derives Copy, which fields of type 'string' \
+             don't implement"
+        ));
+        assert!(check("message M @rust.derive(Hash, Eq) { ratio: f64; }").is_err());
+        assert!(check("message M @rust.derive(Ord) { a: u8; }").is_err());
+        assert!(check("message M @rust.derive(Eq, PartialOrd, Ord) { a: [u8]; }").is_ok());
+        assert!(check("message M @rust.derive(serde::Serialize) { ratio: f64; }").is_ok());
+        assert!(check("struct S @rust.derive(Hash) { ratio: f32; }").is_err());
+
+        // Nested types must derive the trait themselves
+        let nested = |derive: &str| {
+            check(&format!(
+                "enum Kind {{ A = 0; }} struct P {} {{ x: i32; }} \
+                 message Inner {} {{ kind: Kind; }} \
+                 message Outer @rust.derive(Hash, Eq) {{ p: P; inner: Inner; }}",
+                derive, derive
+            ))
+        };
+        assert!(nested("@rust.derive(Hash, Eq)").is_ok());
+        assert!(nested("").is_err());
+        assert!(
+            check("message Inner { a: u8; } message M @rust.derive(Copy) { i: Inner; }").is_err()
+        );
+
+        let error = check("message A @rust.name(\"B\") { a: u8; } message B { b: u8; }")
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("would both generate Rust type 'B'"));
+    }
+
+    #[test]
+    fn test_default_values() {
+        let check =
+            |field: &str| validate(&crate::parse(&format!("message M {{ {} }}", field)).unwrap());
+
+        assert!(check("a: u8 = 255;").is_ok());
+        assert!(check("a: i8 = -128;").is_ok());
+        assert!(check("a: u64 = 0xFFFF_FFFF_FFFF_FFFF;").is_ok());
+        assert!(check("a: i64 = -0x8000_0000_0000_0000;").is_ok());
+        assert!(check("a: f32 = 3;").is_ok());
+        assert!(check("a: f64 = 1e300;").is_ok());
+        assert!(check("a: string? = \"x\";").is_ok());
+
+        let error = check("a: u8 = 256;").unwrap_err().to_string();
+        assert!(
+            error.contains("Default value 256 of field 'a' in message 'M' is out of range for u8")
+        );
+        assert!(check("a: i8 = -129;").is_err());
+        assert!(check("a: u32 = -1;").is_err());
+        assert!(check("a: u64 = 0x1_0000_0000_0000_0000;").is_err());
+        assert!(check("a: f32 = 1e39;").is_err());
+
+        let error = check("a: u8 = 1.5;").unwrap_err().to_string();
+        assert!(error.contains("is not a u8 value"));
+        assert!(check("a: bool = 1;").is_err());
+        assert!(check("a: string = true;").is_err());
+        assert!(check("a: bytes = \"raw\";").is_err());
+        assert!(check("a: [u8] = 1;").is_err());
+    }
+
+    #[test]
+    fn test_constants_and_aliases() {
+        let check = |schema: &str| validate(&crate::parse(schema).unwrap());
+
+        let valid = r#"
+            const MAX_FRIENDS: u32 = 500;
+            const LIMIT: Count = MAX_FRIENDS;
+            const GREETING: string = "hi";
+            type Count = u32;
+            type UserId = u64;
+            type Friends = [UserId];
+            type Owner = User;
+            message User { uid: UserId; friends: Friends; limit: Count = LIMIT; }
+            message Group { owner: Owner?; size: u16 = MAX_FRIENDS; }
+            union Member { person: Owner; }
+        "#;
+        assert!(check(valid).is_ok());
+
+        // Constants are checked against the field they're used by, and their own type
+        let error = check("const BIG: u32 = 500; message M { a: u8 = BIG; }")
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("Default value BIG of field 'a' in message 'M' is out of range"));
+        let error = check("const BIG: u8 = 500;").unwrap_err().to_string();
+        assert!(error.contains("Value 500 of constant 'BIG' is out of range for u8"));
+        assert!(check("const NAME: string = 1;").is_err());
+        assert!(check("message M { a: u8 = MISSING; }")
+            .unwrap_err()
+            .to_string()
+            .contains("Unknown constant 'MISSING'"));
+        assert!(check("const A: u8 = B; const B: u8 = A;")
+            .unwrap_err()
+            .to_string()
+            .contains("refers to itself"));
+        assert!(check("const A: u8 = 1; const A: u8 = 2;").is_err());
+        assert!(check("message M {} const C: M = 1;")
+            .unwrap_err()
+            .to_string()
+            .contains("must have a scalar type"));
+
+        // Aliases resolve before fields are checked
+        assert!(check("type A = B; type B = [A];")
+            .unwrap_err()
+            .to_string()
+            .contains("Type alias 'A' refers to itself"));
+        assert!(check("type Ids = [u64]; message M { a: [Ids]; }").is_err());
+        assert!(check("type Flags = [bool]; message M { a: Flags @packed; }").is_ok());
+        assert!(check("type Id = u64; message M { a: [Id] @packed; }").is_err());
+        assert!(check("type Id = Missing;").is_err());
+        assert!(check("type M = u8; message M {}").is_err());
+        assert!(check("type Id = u64; union U { a: Id; }").is_err());
+
+        let common =
+            crate::parse("const LIMIT: u32 = 10; type UserId = u64; message User {}").unwrap();
+        let orders = crate::parse("message Order { buyer: UserId; max: u32 = LIMIT; }").unwrap();
+        assert!(validate_with_imports(&orders, &[&common]).is_ok());
+        assert!(validate(&orders).is_err());
+        let shadowing = crate::parse("const LIMIT: u32 = 5;").unwrap();
+        assert!(validate_with_imports(&shadowing, &[&common]).is_err());
+    }
+
+    #[test]
+    fn test_structs() {
+        let check = |schema: &str| validate(&crate::parse(schema).unwrap());
+        let error = |schema: &str| check(schema).unwrap_err().to_string();
+
+        let valid = r#"
+            enum Axis { X = 0; Y = 1; }
+            type Meters = f32;
+            struct Vec3 @rust.derive(Default) { x: Meters; y: f32; z: f32 @rust.name("depth"); }
+            struct Ray { origin: Vec3; direction: Vec3; axis: Axis; visible: bool; }
+            type Point = Vec3;
+            message Scene { camera: Ray; points: [Point]; focus: Vec3?; }
+        "#;
+        assert!(check(valid).is_ok());
+
+        assert!(error("struct Empty {}").contains("Struct 'Empty' must have at least one field"));
+        assert!(error("struct S { name: string; }")
+            .contains("Field 'name' in struct 'S' has type 'string'; struct fields must be"));
+        assert!(check("struct S { raw: bytes; }").is_err());
+        assert!(check("struct S { xs: [u8]; }").is_err());
+        assert!(check("message M {} struct S { m: M; }").is_err());
+        assert!(
+            error("struct S { a: u8?; }").contains("Field 'a' in struct 'S' cannot be optional")
+        );
+        assert!(check("struct S { a: u8 = 1; }").is_err());
+        assert!(check("struct S { a: u8 @1; }").is_err());
+        assert!(check("struct S { a: u8 @deprecated; }").is_err());
+        assert!(check("struct S { a: u8; a: u16; }").is_err());
+        assert!(check("struct S { type: u8; }").is_err());
+        assert!(check("struct S @rust.vis(\"pub\") { a: u8; }").is_err());
+
+        // Well-known types are fixed-size
+        assert!(check(
+            "struct Span { start: timestamp; length: duration; trace: uuid; cost: decimal; }"
+        )
+        .is_ok());
+
+        assert!(error("struct A { b: B; } struct B { a: A; }").contains("contains itself"));
+        assert!(error("struct S { s: S; }").contains("Struct 'S' contains itself"));
+
+        // Doubling a u64 thirteen times passes the u16 size limit
+        let mut nested = "struct S0 { a: u64; }".to_string();
+        for i in 1..=13 {
+            nested += &format!(" struct S{} {{ a: S{1}; b: S{1}; }}", i, i - 1);
+        }
+        assert!(error(&nested).contains("Struct 'S13' is 65536 bytes, above the limit of 65535"));
+
+        // Structs aren't messages, so they can't be union variants or have defaults
+        assert!(check("struct S { a: u8; } union U { s: S; }").is_err());
+        assert!(check("struct S { a: u8; } message M { s: S = 1; }").is_err());
+
+        let geometry = crate::parse("struct Vec2 { x: f32; y: f32; }").unwrap();
+        let shapes = crate::parse("struct Line { from: Vec2; to: Vec2; }").unwrap();
+        assert!(validate_with_imports(&shapes, &[&geometry]).is_ok());
+    }
+
+    #[test]
+    fn test_any_fields() {
+        let check = |schema: &str| validate(&crate::parse(schema).unwrap());
+        let error = |schema: &str| check(schema).unwrap_err().to_string();
+
+        assert!(
+            check("type Payload = any; message Envelope { body: any; extra: Payload?; }").is_ok()
+        );
+        assert!(error("message M { items: [any]; }").contains("Vectors of any are not allowed"));
+        assert!(check("struct S { body: any; }").is_err());
+        assert!(error("message M { body: any = 1; }").contains("is not a any value"));
+    }
+
+    #[test]
+    fn test_package_keywords() {
+        let check = |schema: &str| validate(&crate::parse(schema).unwrap());
+
+        assert!(check("package acme.orders; message M { x: u8; }").is_ok());
+        let error = check("package fn.type; message M { x: u8; }")
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("'fn' is a Rust keyword"));
+        assert!(check("package acme.self; message M { x: u8; }").is_err());
+    }
+
+    #[test]
+    fn test_error_locations() {
+        // The source text each error points at
+        let located = |input: &str| match validate(&crate::parse(input).unwrap()) {
+            Err(crate::CompilerError::Validation(error)) => {
+                input[error.span.start..error.span.end].to_string()
+            }
+            result => panic!("expected a validation error, got {:?}", result),
+        };
+
+        assert_eq!(located("package acme.fn;"), "acme.fn");
+        assert_eq!(located("message M { a: Missing; }"), "Missing");
+        assert_eq!(located("message M { a: [[u8]]; }"), "[[u8]]");
+        assert_eq!(located("message M { a: u8; b: u8 = 256; }"), "b");
+        assert_eq!(located("message M { a: u8 @rust.inline(always); }"), "a");
+        assert_eq!(located("message M @rust.derive(Ord) { a: u8; }"), "M");
+        assert_eq!(located("enum Status { A = 0; }"), "Status");
+        assert_eq!(located("enum E { A = 0; B = 0; }"), "B");
+        assert_eq!(located("message M {} union U { m: M; n: u8; }"), "u8");
+        assert_eq!(located("struct S { a: S; }"), "S");
+        assert_eq!(
+            located("const C: u8 = 1; message C {} const C: u8 = 2;"),
+            "C"
+        );
+        assert_eq!(located("type A = B; type B = A;"), "A");
+
+        // Names clashing between imports point at the imports
+        let shared = crate::parse("message Shared {}").unwrap();
+        let input = "import \"a.zp\";\nimport \"b.zp\";\nmessage M { s: Shared; }";
+        let error = validate_with_imports(&crate::parse(input).unwrap(), &[&shared, &shared]);
+        match error {
+            Err(crate::CompilerError::Validation(error)) => assert_eq!(
+                &input[error.span.start..error.span.end],
+                "\"a.zp\";\nimport \"b.zp\""
+            ),
+            result => panic!("expected a validation error, got {:?}", result),
+        }
+
+        // Rendered against the source, they read like parse errors
+        let input = "message User {\n    name: Strng;\n}\n";
+        let error = validate(&crate::parse(input).unwrap())
+            .unwrap_err()
+            .with_source(input)
+            .in_file(std::path::Path::new("user.zp"));
+        assert_eq!(
+            error.to_string(),
+            "error: Unknown type 'Strng' used in field\n \
+             --> user.zp:2:11\n  \
+             |\n\
+             2 |     name: Strng;\n  \
+             |           ^^^^^"
+        );
+    }
 }
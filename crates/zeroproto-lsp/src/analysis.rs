@@ -0,0 +1,426 @@
+//! Language features for a document and the schemas it imports
+
+use crate::document::{Declaration, Document};
+use lsp_types::{
+    CompletionItem, CompletionItemKind, DiagnosticSeverity, Hover, HoverContents, Location,
+    MarkupContent, MarkupKind, Range, TextEdit, WorkspaceEdit,
+};
+use std::collections::HashMap;
+use zeroproto_compiler::ast::{Field, FieldType, ScalarType, Schema, SchemaItem};
+use zeroproto_compiler::diagnostic::Span;
+use zeroproto_compiler::{validator, CompilerError};
+
+/// Type ID of nested messages on the wire
+const MESSAGE_TYPE_ID: u8 = 13;
+/// Type ID of vectors on the wire
+const VECTOR_TYPE_ID: u8 = 14;
+/// Type ID of unions on the wire
+const UNION_TYPE_ID: u8 = 15;
+/// Type ID of bit-packed bool vectors on the wire
+const BIT_VECTOR_TYPE_ID: u8 = 16;
+/// Type ID of inline structs on the wire
+const STRUCT_TYPE_ID: u8 = 17;
+
+/// Keywords that can't be used as type names
+const KEYWORDS: &[&str] = &["message", "enum", "union", "true", "false"];
+
+/// A document, with the schemas it imports loaded so names resolve across files
+pub struct Analysis<'a> {
+    document: &'a Document,
+    /// Imported documents, with the import path as written
+    imports: Vec<(String, Document)>,
+    /// Imports that couldn't be loaded, with the reason
+    missing: Vec<(String, String)>,
+}
+
+impl<'a> Analysis<'a> {
+    pub fn new(
+        document: &'a Document,
+        imports: Vec<(String, Document)>,
+        missing: Vec<(String, String)>,
+    ) -> Self {
+        Self {
+            document,
+            imports,
+            missing,
+        }
+    }
+
+    /// This document, then the ones it imports
+    fn documents(&self) -> impl Iterator<Item = &Document> {
+        std::iter::once(self.document).chain(self.imports.iter().map(|(_, document)| document))
+    }
+
+    /// The declaration of type `name`, and the document it's in
+    fn declaring(&self, name: &str) -> Option<(&Document, Declaration<'_>)> {
+        self.documents()
+            .find_map(|document| Some((document, document.declaration(name)?)))
+    }
+
+    /// Parse errors, import problems, and (once those are fixed) the validator's verdict
+    pub fn diagnostics(&self) -> Vec<lsp_types::Diagnostic> {
+        let mut diagnostics: Vec<_> = self
+            .document
+            .errors
+            .iter()
+            .map(|error| self.diagnostic(error.span, error.message.clone()))
+            .collect();
+
+        for (import, reason) in &self.missing {
+            diagnostics.push(self.diagnostic(self.document.import_span(import), reason.clone()));
+        }
+        for (import, document) in &self.imports {
+            if !document.errors.is_empty() {
+                diagnostics.push(self.diagnostic(
+                    self.document.import_span(import),
+                    format!(
+                        "Imported schema '{}' has {} errors",
+                        import,
+                        document.errors.len()
+                    ),
+                ));
+            }
+        }
+
+        if diagnostics.is_empty() {
+            let imports: Vec<&Schema> = self
+                .imports
+                .iter()
+                .map(|(_, document)| &document.schema)
+                .collect();
+            if let Err(err) = validator::validate_with_imports(&self.document.schema, &imports) {
+                diagnostics.push(match err {
+                    CompilerError::Validation(error) => self.diagnostic(error.span, error.message),
+                    err => self.diagnostic(Span::default(), err.to_string()),
+                });
+            }
+        }
+
+        diagnostics
+    }
+
+    fn diagnostic(&self, span: Span, message: String) -> lsp_types::Diagnostic {
+        lsp_types::Diagnostic {
+            range: self.document.range(span),
+            severity: Some(DiagnosticSeverity::ERROR),
+            source: Some("zeroproto".to_string()),
+            message,
+            ..Default::default()
+        }
+    }
+
+    /// Where the type named at `offset` is declared
+    pub fn definition(&self, offset: usize) -> Option<Location> {
+        let name = self.document.name_at(offset)?;
+        let (document, declaration) = self.declaring(&name.name)?;
+        Some(Location::new(
+            document.uri.clone(),
+            document.range(declaration.span()),
+        ))
+    }
+
+    /// Every use of the type named at `offset`, in this document and its imports
+    pub fn references(&self, offset: usize, include_declaration: bool) -> Vec<Location> {
+        let Some(name) = self.document.name_at(offset) else {
+            return Vec::new();
+        };
+
+        self.documents()
+            .flat_map(|document| {
+                document
+                    .occurrences(&name.name)
+                    .filter(|occurrence| include_declaration || !occurrence.declaration)
+                    .map(|occurrence| {
+                        Location::new(document.uri.clone(), document.range(occurrence.span))
+                    })
+            })
+            .collect()
+    }
+
+    /// Describe the type, field or variant at `offset`
+    pub fn hover(&self, offset: usize) -> Option<Hover> {
+        if let Some(name) = self.document.name_at(offset) {
+            let (_, declaration) = self.declaring(&name.name)?;
+            return Some(self.hover_text(
+                &declaration.signature(),
+                None,
+                declaration.docs(),
+                name.span,
+            ));
+        }
+
+        let contains = |span: Span| span.start <= offset && offset <= span.end;
+        for item in &self.document.schema.items {
+            match item {
+                SchemaItem::Message(message) => {
+                    let numbers = message.field_numbers();
+                    for (field, number) in message.fields.iter().zip(numbers) {
+                        if contains(field.span) || contains(field.type_span) {
+                            let signature = format!(
+                                "{}: {}{}",
+                                field.name,
+                                field.field_type,
+                                if field.optional { "?" } else { "" }
+                            );
+                            let details = format!(
+                                "Field index {}, wire type {}",
+                                number,
+                                self.wire_type(field)
+                            );
+                            let span = field.span.to(field.type_span);
+                            return Some(self.hover_text(
+                                &signature,
+                                Some(&details),
+                                &field.docs,
+                                span,
+                            ));
+                        }
+                    }
+                }
+                SchemaItem::Enum(enum_def) => {
+                    for variant in &enum_def.variants {
+                        if contains(variant.span) {
+                            let signature = match variant.value {
+                                Some(value) => format!("{} = {}", variant.name, value),
+                                None => variant.name.clone(),
+                            };
+                            let details = format!("Variant of enum {}", enum_def.name);
+                            return Some(self.hover_text(
+                                &signature,
+                                Some(&details),
+                                &variant.docs,
+                                variant.span,
+                            ));
+                        }
+                    }
+                }
+                SchemaItem::Struct(struct_def) => {
+                    let mut offset = 0;
+                    for field in &struct_def.fields {
+                        let size = self.struct_field_size(&field.field_type, &mut Vec::new());
+                        if contains(field.span) || contains(field.type_span) {
+                            let signature = format!("{}: {}", field.name, field.field_type);
+                            let details = format!(
+                                "Field of struct {} at byte offset {}, {} bytes",
+                                struct_def.name,
+                                offset,
+                                size.map_or("?".to_string(), |size| size.to_string())
+                            );
+                            let span = field.span.to(field.type_span);
+                            return Some(self.hover_text(
+                                &signature,
+                                Some(&details),
+                                &field.docs,
+                                span,
+                            ));
+                        }
+                        offset += size.unwrap_or(0);
+                    }
+                }
+                SchemaItem::Union(union_def) => {
+                    for variant in &union_def.variants {
+                        if contains(variant.span) || contains(variant.type_span) {
+                            let signature = format!("{}: {}", variant.name, variant.variant_type);
+                            let details = format!("Variant of union {}", union_def.name);
+                            return Some(self.hover_text(
+                                &signature,
+                                Some(&details),
+                                &variant.docs,
+                                variant.span.to(variant.type_span),
+                            ));
+                        }
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    fn hover_text(
+        &self,
+        signature: &str,
+        details: Option<&str>,
+        docs: &[String],
+        span: Span,
+    ) -> Hover {
+        let mut sections = vec![format!("```zp\n{}\n```", signature)];
+        sections.extend(details.map(str::to_string));
+        if !docs.is_empty() {
+            sections.push(docs.join("\n"));
+        }
+
+        Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: sections.join("\n\n"),
+            }),
+            range: Some(self.document.range(span)),
+        }
+    }
+
+    /// Follow type aliases to the type they stand for, giving up on a cycle
+    fn resolve(&self, field_type: &FieldType) -> FieldType {
+        let mut seen = Vec::new();
+        let mut field_type = field_type.clone();
+        while let FieldType::UserDefined(name) = &field_type {
+            match self.declaring(name) {
+                Some((_, Declaration::Alias(alias))) if !seen.contains(name) => {
+                    seen.push(name.clone());
+                    field_type = alias.target.clone();
+                }
+                _ => break,
+            }
+        }
+        match field_type {
+            FieldType::Vector(inner) => FieldType::Vector(Box::new(self.resolve(&inner))),
+            field_type => field_type,
+        }
+    }
+
+    /// Encoded size of a struct field, if its type is known and fixed-size
+    ///
+    /// `path` holds the structs already entered, so one that contains itself has no size.
+    fn struct_field_size(&self, field_type: &FieldType, path: &mut Vec<String>) -> Option<usize> {
+        match self.resolve(field_type) {
+            FieldType::Scalar(ScalarType::String | ScalarType::Bytes | ScalarType::Any)
+            | FieldType::Vector(_) => None,
+            FieldType::Scalar(scalar) => Some(scalar.size()),
+            FieldType::UserDefined(name) => match self.declaring(&name)?.1 {
+                Declaration::Item(SchemaItem::Enum(_)) => Some(8),
+                Declaration::Item(SchemaItem::Struct(struct_def)) if !path.contains(&name) => {
+                    path.push(name);
+                    let size = struct_def
+                        .fields
+                        .iter()
+                        .map(|field| self.struct_field_size(&field.field_type, path))
+                        .sum();
+                    path.pop();
+                    size
+                }
+                _ => None,
+            },
+        }
+    }
+
+    /// How a field is stored on the wire
+    fn wire_type(&self, field: &Field) -> String {
+        match &self.resolve(&field.field_type) {
+            FieldType::Scalar(scalar) => {
+                format!(
+                    "`{}` (type ID {})",
+                    scalar.name(),
+                    scalar.primitive_type_id()
+                )
+            }
+            FieldType::Vector(inner)
+                if field.packed && **inner == FieldType::Scalar(ScalarType::Bool) =>
+            {
+                format!("packed bool vector (type ID {})", BIT_VECTOR_TYPE_ID)
+            }
+            FieldType::Vector(_) => format!("vector (type ID {})", VECTOR_TYPE_ID),
+            FieldType::UserDefined(name) => match self.declaring(name).map(|(_, item)| item) {
+                Some(Declaration::Item(SchemaItem::Message(_))) => {
+                    format!("nested message (type ID {})", MESSAGE_TYPE_ID)
+                }
+                Some(Declaration::Item(SchemaItem::Union(_))) => {
+                    format!("union (type ID {})", UNION_TYPE_ID)
+                }
+                Some(Declaration::Item(SchemaItem::Struct(_))) => {
+                    format!("struct, stored inline (type ID {})", STRUCT_TYPE_ID)
+                }
+                Some(Declaration::Item(SchemaItem::Enum(_))) => format!(
+                    "enum, stored as `u64` (type ID {})",
+                    ScalarType::U64.primitive_type_id()
+                ),
+                Some(Declaration::Alias(_)) | None => "unknown".to_string(),
+            },
+        }
+    }
+
+    /// Scalar types, then every message, enum, union, struct and alias in scope
+    pub fn completions(&self) -> Vec<CompletionItem> {
+        let scalars = ScalarType::ALL.iter().map(|scalar| CompletionItem {
+            label: scalar.name().to_string(),
+            kind: Some(CompletionItemKind::KEYWORD),
+            detail: Some(if scalar.is_well_known() {
+                "well-known type".to_string()
+            } else {
+                "scalar".to_string()
+            }),
+            ..Default::default()
+        });
+
+        let mut seen = Vec::new();
+        let declared = self
+            .documents()
+            .flat_map(|document| document.declarations())
+            .filter(|declaration| {
+                let name = declaration.name().to_string();
+                let new = !seen.contains(&name);
+                seen.push(name);
+                new
+            })
+            .map(|declaration| CompletionItem {
+                label: declaration.name().to_string(),
+                kind: Some(match declaration {
+                    Declaration::Item(SchemaItem::Message(_) | SchemaItem::Struct(_)) => {
+                        CompletionItemKind::STRUCT
+                    }
+                    Declaration::Item(SchemaItem::Enum(_) | SchemaItem::Union(_)) => {
+                        CompletionItemKind::ENUM
+                    }
+                    Declaration::Alias(_) => CompletionItemKind::TYPE_PARAMETER,
+                }),
+                detail: Some(declaration.keyword().to_string()),
+                documentation: (!declaration.docs().is_empty())
+                    .then(|| lsp_types::Documentation::String(declaration.docs().join("\n"))),
+                ..Default::default()
+            });
+
+        scalars.chain(declared).collect()
+    }
+
+    /// The range of the type name at `offset`, if it can be renamed
+    pub fn prepare_rename(&self, offset: usize) -> Option<Range> {
+        let name = self.document.name_at(offset)?;
+        self.declaring(&name.name)?;
+        Some(self.document.range(name.span))
+    }
+
+    /// Rename the type named at `offset`, along with its declaration and every use of it
+    pub fn rename(&self, offset: usize, new_name: &str) -> Result<Option<WorkspaceEdit>, String> {
+        let Some(name) = self.document.name_at(offset) else {
+            return Ok(None);
+        };
+        if self.declaring(&name.name).is_none() {
+            return Err(format!("Type '{}' is not declared", name.name));
+        }
+
+        // The tokenizer only starts identifiers with ASCII letters or '_'
+        let mut chars = new_name.chars();
+        let valid = chars
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && chars.all(|c| c.is_alphanumeric() || c == '_')
+            && ScalarType::from_name(new_name).is_none()
+            && !KEYWORDS.contains(&new_name);
+        if !valid {
+            return Err(format!("'{}' is not a valid type name", new_name));
+        }
+        if self.declaring(new_name).is_some() {
+            return Err(format!("A type named '{}' already exists", new_name));
+        }
+
+        let mut changes = HashMap::new();
+        for document in self.documents() {
+            let edits: Vec<_> = document
+                .occurrences(&name.name)
+                .map(|occurrence| TextEdit::new(document.range(occurrence.span), new_name.into()))
+                .collect();
+            if !edits.is_empty() {
+                changes.insert(document.uri.clone(), edits);
+            }
+        }
+        Ok(Some(WorkspaceEdit::new(changes)))
+    }
+}
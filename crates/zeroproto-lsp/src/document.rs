@@ -0,0 +1,296 @@
+//! Schema documents, parsed as far as their text allows
+
+use lsp_types::{Position, Range, Url};
+use zeroproto_compiler::ast::{FieldType, Message, Schema, SchemaItem, Struct, TypeAlias};
+use zeroproto_compiler::diagnostic::{Diagnostic, Span};
+use zeroproto_compiler::parser::parse_with_recovery;
+
+/// The text of a schema file and everything parsed from it
+#[derive(Debug, Clone)]
+pub struct Document {
+    pub uri: Url,
+    pub text: String,
+    /// Every declaration that parsed, even when the text has errors
+    pub schema: Schema,
+    /// Parse errors
+    pub errors: Vec<Diagnostic>,
+    /// Every message, enum, union, struct and alias name in the text, in source order
+    pub names: Vec<Name>,
+    /// Byte offset where each line starts
+    line_starts: Vec<usize>,
+}
+
+/// A type name written in a schema: where it's declared, or a field type that uses it
+#[derive(Debug, Clone)]
+pub struct Name {
+    pub name: String,
+    pub span: Span,
+    pub declaration: bool,
+}
+
+/// Something a type name can be declared by
+#[derive(Debug, Clone, Copy)]
+pub enum Declaration<'a> {
+    Item(&'a SchemaItem),
+    Alias(&'a TypeAlias),
+}
+
+impl Declaration<'_> {
+    pub fn name(&self) -> &str {
+        match self {
+            Declaration::Item(item) => item.name(),
+            Declaration::Alias(alias) => &alias.name,
+        }
+    }
+
+    /// The keyword that declares it
+    pub fn keyword(&self) -> &'static str {
+        match self {
+            Declaration::Item(item) => item.keyword(),
+            Declaration::Alias(_) => "type",
+        }
+    }
+
+    /// How the declaration reads in the schema, e.g. `message User` or `type UserId = u64`
+    pub fn signature(&self) -> String {
+        match self {
+            Declaration::Item(item) => format!("{} {}", item.keyword(), item.name()),
+            Declaration::Alias(alias) => format!("type {} = {}", alias.name, alias.target),
+        }
+    }
+
+    pub fn docs(&self) -> &[String] {
+        match self {
+            Declaration::Item(item) => item.docs(),
+            Declaration::Alias(alias) => &alias.docs,
+        }
+    }
+
+    pub fn span(&self) -> Span {
+        match self {
+            Declaration::Item(item) => item.span(),
+            Declaration::Alias(alias) => alias.span,
+        }
+    }
+}
+
+impl Document {
+    /// Parse `text`, recovering from errors
+    pub fn new(uri: Url, text: String) -> Self {
+        let (schema, errors) = parse_with_recovery(&text);
+        let names = type_names(&schema, &text);
+        let line_starts = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(offset, _)| offset + 1))
+            .collect();
+
+        Self {
+            uri,
+            text,
+            schema,
+            errors,
+            names,
+            line_starts,
+        }
+    }
+
+    /// The type name under byte offset `offset`, if any
+    pub fn name_at(&self, offset: usize) -> Option<&Name> {
+        self.names
+            .iter()
+            .find(|name| name.span.start <= offset && offset <= name.span.end)
+    }
+
+    /// Where `name` appears in this document
+    pub fn occurrences<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Name> {
+        self.names
+            .iter()
+            .filter(move |occurrence| occurrence.name == name)
+    }
+
+    /// The declaration of the type named `name`, if this document has one
+    pub fn declaration(&self, name: &str) -> Option<Declaration<'_>> {
+        match self.schema.find_item(name) {
+            Some(item) => Some(Declaration::Item(item)),
+            None => self.schema.find_alias(name).map(Declaration::Alias),
+        }
+    }
+
+    /// Every type this document declares, in source order
+    pub fn declarations(&self) -> Vec<Declaration<'_>> {
+        let mut declarations: Vec<_> = self
+            .schema
+            .items
+            .iter()
+            .map(Declaration::Item)
+            .chain(self.schema.aliases.iter().map(Declaration::Alias))
+            .collect();
+        declarations.sort_by_key(|declaration| declaration.span().start);
+        declarations
+    }
+
+    /// Byte offset of an LSP position, which counts UTF-16 code units
+    pub fn offset(&self, position: Position) -> usize {
+        let Some(&line_start) = self.line_starts.get(position.line as usize) else {
+            return self.text.len();
+        };
+
+        let mut units = 0;
+        for (offset, c) in self.text[line_start..].char_indices() {
+            if units >= position.character as usize || c == '\n' {
+                return line_start + offset;
+            }
+            units += c.len_utf16();
+        }
+        self.text.len()
+    }
+
+    /// LSP position of a byte offset
+    pub fn position(&self, offset: usize) -> Position {
+        let offset = offset.min(self.text.len());
+        let line = self.line_starts.partition_point(|&start| start <= offset) - 1;
+        let line_start = self.line_starts[line];
+        let character = self.text[line_start..offset]
+            .chars()
+            .map(char::len_utf16)
+            .sum::<usize>();
+        Position::new(line as u32, character as u32)
+    }
+
+    /// LSP range of a span
+    pub fn range(&self, span: Span) -> Range {
+        Range::new(self.position(span.start), self.position(span.end))
+    }
+
+    /// Where an import's path string is written, for reporting problems with it
+    pub fn import_span(&self, import: &str) -> Span {
+        let quoted = format!("{:?}", import);
+        self.text.find(&quoted).map_or(Span::default(), |start| {
+            Span::new(start, start + quoted.len())
+        })
+    }
+}
+
+/// Collect the declarations of, and references to, every type in a schema
+fn type_names(schema: &Schema, text: &str) -> Vec<Name> {
+    let mut names = Vec::new();
+
+    for item in &schema.items {
+        names.push(Name {
+            name: item.name().to_string(),
+            span: item.span(),
+            declaration: true,
+        });
+        match item {
+            SchemaItem::Message(Message { fields, .. })
+            | SchemaItem::Struct(Struct { fields, .. }) => {
+                for field in fields {
+                    push_reference(&mut names, &field.field_type, field.type_span, text);
+                }
+            }
+            SchemaItem::Union(union_def) => {
+                for variant in &union_def.variants {
+                    push_reference(&mut names, &variant.variant_type, variant.type_span, text);
+                }
+            }
+            SchemaItem::Enum(_) => {}
+        }
+    }
+
+    for alias in &schema.aliases {
+        names.push(Name {
+            name: alias.name.clone(),
+            span: alias.span,
+            declaration: true,
+        });
+        push_reference(&mut names, &alias.target, alias.type_span, text);
+    }
+    for constant in &schema.constants {
+        push_reference(&mut names, &constant.value_type, constant.type_span, text);
+    }
+
+    names.sort_by_key(|name| name.span.start);
+    names
+}
+
+/// Record where a field, variant or alias type names a message, enum, union, struct or alias
+///
+/// The type's span covers brackets too (`[User]`); the name is the one identifier in it.
+fn push_reference(names: &mut Vec<Name>, field_type: &FieldType, type_span: Span, text: &str) {
+    let Some(name) = field_type.user_type() else {
+        return;
+    };
+    let written = text.get(type_span.start..type_span.end).unwrap_or("");
+    if let Some(at) = written.find(name) {
+        let start = type_span.start + at;
+        names.push(Name {
+            name: name.to_string(),
+            span: Span::new(start, start + name.len()),
+            declaration: false,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn document(text: &str) -> Document {
+        Document::new(Url::parse("file:///schema.zp").unwrap(), text.to_string())
+    }
+
+    #[test]
+    fn test_positions() {
+        // 'é' is one UTF-16 unit but two bytes; '𝄞' is two units and four bytes
+        let doc = document("// é𝄞\nmessage A {}\n");
+        assert_eq!(doc.position(3), Position::new(0, 3));
+        assert_eq!(doc.position(5), Position::new(0, 4));
+        assert_eq!(doc.position(9), Position::new(0, 6));
+        assert_eq!(doc.position(10), Position::new(1, 0));
+        assert_eq!(doc.offset(Position::new(0, 6)), 9);
+        assert_eq!(doc.offset(Position::new(1, 8)), 18);
+        // Positions past the end of a line or the text are clamped
+        assert_eq!(doc.offset(Position::new(0, 99)), 9);
+        assert_eq!(doc.offset(Position::new(9, 0)), doc.text.len());
+    }
+
+    #[test]
+    fn test_type_names() {
+        let doc = document("message A { b: [B]?; }\nmessage B { a: A; c: u8; }\n");
+        let names: Vec<_> = doc
+            .names
+            .iter()
+            .map(|name| (name.name.as_str(), name.span.start, name.declaration))
+            .collect();
+        assert_eq!(
+            names,
+            [
+                ("A", 8, true),
+                ("B", 16, false),
+                ("B", 31, true),
+                ("A", 38, false)
+            ]
+        );
+        assert_eq!(doc.name_at(17).unwrap().name, "B");
+        assert!(doc.name_at(13).is_none());
+
+        let doc = document("type Id = u64;\ntype Ids = [Id];\nconst N: Id = 1;\n");
+        let names: Vec<_> = doc
+            .names
+            .iter()
+            .map(|name| (name.name.as_str(), name.span.start, name.declaration))
+            .collect();
+        assert_eq!(
+            names,
+            [
+                ("Id", 5, true),
+                ("Ids", 20, true),
+                ("Id", 27, false),
+                ("Id", 41, false)
+            ]
+        );
+        assert_eq!(
+            doc.declaration("Ids").unwrap().signature(),
+            "type Ids = [Id]"
+        );
+    }
+}
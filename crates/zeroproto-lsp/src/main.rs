@@ -0,0 +1,19 @@
+//! ZeroProto language server - editor support for .zp schema files over stdio
+
+mod analysis;
+mod document;
+mod server;
+
+use lsp_server::Connection;
+use std::error::Error;
+
+fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
+    let (connection, io_threads) = Connection::stdio();
+
+    let params = connection.initialize(serde_json::to_value(server::capabilities())?)?;
+    let params: lsp_types::InitializeParams = serde_json::from_value(params)?;
+    server::Server::new(connection, &params).run()?;
+
+    io_threads.join()?;
+    Ok(())
+}
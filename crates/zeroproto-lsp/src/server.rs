@@ -0,0 +1,283 @@
+//! The message loop and request handlers
+
+use crate::analysis::Analysis;
+use crate::document::Document;
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, Response};
+use lsp_types::notification::{
+    DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument,
+    Notification as LspNotification, PublishDiagnostics,
+};
+use lsp_types::request::{
+    Completion, GotoDefinition, HoverRequest, PrepareRenameRequest, References, Rename,
+    Request as LspRequest,
+};
+use lsp_types::{
+    CompletionOptions, CompletionParams, CompletionResponse, DidChangeTextDocumentParams,
+    DidCloseTextDocumentParams, DidOpenTextDocumentParams, GotoDefinitionParams,
+    GotoDefinitionResponse, Hover, HoverParams, HoverProviderCapability, InitializeParams,
+    Location, OneOf, PrepareRenameResponse, PublishDiagnosticsParams, ReferenceParams,
+    RenameOptions, RenameParams, ServerCapabilities, TextDocumentPositionParams,
+    TextDocumentSyncCapability, TextDocumentSyncKind, Url, WorkspaceEdit,
+};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use zeroproto_compiler::loader;
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+/// What the server supports, sent in reply to `initialize`
+pub fn capabilities() -> ServerCapabilities {
+    ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        definition_provider: Some(OneOf::Left(true)),
+        references_provider: Some(OneOf::Left(true)),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        completion_provider: Some(CompletionOptions::default()),
+        rename_provider: Some(OneOf::Right(RenameOptions {
+            prepare_provider: Some(true),
+            work_done_progress_options: Default::default(),
+        })),
+        ..Default::default()
+    }
+}
+
+/// A language server session with one editor
+pub struct Server {
+    connection: Connection,
+    /// Text of the documents open in the editor
+    documents: HashMap<Url, Document>,
+    /// Additional directories to search for imported schemas
+    include_paths: Vec<PathBuf>,
+}
+
+impl Server {
+    /// Start a session; `initializationOptions.importPaths` works like `zeroproto compile -I`
+    pub fn new(connection: Connection, params: &InitializeParams) -> Self {
+        let include_paths = params
+            .initialization_options
+            .as_ref()
+            .and_then(|options| options.get("importPaths"))
+            .and_then(|paths| paths.as_array())
+            .map(|paths| {
+                paths
+                    .iter()
+                    .filter_map(|path| path.as_str())
+                    .map(PathBuf::from)
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Self {
+            connection,
+            documents: HashMap::new(),
+            include_paths,
+        }
+    }
+
+    /// Handle messages until the editor shuts the server down
+    pub fn run(mut self) -> Result<(), Error> {
+        let receiver = self.connection.receiver.clone();
+        for message in receiver {
+            match message {
+                Message::Request(request) => {
+                    if self.connection.handle_shutdown(&request)? {
+                        return Ok(());
+                    }
+                    let response = self.handle_request(request);
+                    self.connection.sender.send(response.into())?;
+                }
+                Message::Notification(notification) => self.handle_notification(notification)?,
+                Message::Response(_) => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn handle_request(&self, request: Request) -> Response {
+        match request.method.as_str() {
+            GotoDefinition::METHOD => self.respond::<GotoDefinition>(request, Self::definition),
+            References::METHOD => self.respond::<References>(request, Self::references),
+            HoverRequest::METHOD => self.respond::<HoverRequest>(request, Self::hover),
+            Completion::METHOD => self.respond::<Completion>(request, Self::completion),
+            PrepareRenameRequest::METHOD => {
+                self.respond::<PrepareRenameRequest>(request, Self::prepare_rename)
+            }
+            Rename::METHOD => self.respond::<Rename>(request, Self::rename),
+            method => Response::new_err(
+                request.id,
+                ErrorCode::MethodNotFound as i32,
+                format!("Unsupported request '{}'", method),
+            ),
+        }
+    }
+
+    /// Decode a request's parameters, run its handler, and encode the reply
+    fn respond<R: LspRequest>(
+        &self,
+        request: Request,
+        handler: fn(&Self, R::Params) -> Result<R::Result, String>,
+    ) -> Response {
+        let params = match serde_json::from_value(request.params) {
+            Ok(params) => params,
+            Err(err) => {
+                return Response::new_err(
+                    request.id,
+                    ErrorCode::InvalidParams as i32,
+                    err.to_string(),
+                )
+            }
+        };
+        match handler(self, params) {
+            Ok(result) => Response::new_ok(request.id, result),
+            Err(message) => Response::new_err(request.id, ErrorCode::RequestFailed as i32, message),
+        }
+    }
+
+    fn definition(
+        &self,
+        params: GotoDefinitionParams,
+    ) -> Result<Option<GotoDefinitionResponse>, String> {
+        self.at_position(&params.text_document_position_params, |analysis, offset| {
+            Ok(analysis
+                .definition(offset)
+                .map(GotoDefinitionResponse::Scalar))
+        })
+    }
+
+    fn references(&self, params: ReferenceParams) -> Result<Option<Vec<Location>>, String> {
+        let include_declaration = params.context.include_declaration;
+        self.at_position(&params.text_document_position, |analysis, offset| {
+            Ok(Some(analysis.references(offset, include_declaration)))
+        })
+    }
+
+    fn hover(&self, params: HoverParams) -> Result<Option<Hover>, String> {
+        self.at_position(&params.text_document_position_params, |analysis, offset| {
+            Ok(analysis.hover(offset))
+        })
+    }
+
+    fn completion(&self, params: CompletionParams) -> Result<Option<CompletionResponse>, String> {
+        self.at_position(&params.text_document_position, |analysis, _| {
+            Ok(Some(CompletionResponse::Array(analysis.completions())))
+        })
+    }
+
+    fn prepare_rename(
+        &self,
+        params: TextDocumentPositionParams,
+    ) -> Result<Option<PrepareRenameResponse>, String> {
+        self.at_position(&params, |analysis, offset| {
+            Ok(analysis
+                .prepare_rename(offset)
+                .map(PrepareRenameResponse::Range))
+        })
+    }
+
+    fn rename(&self, params: RenameParams) -> Result<Option<WorkspaceEdit>, String> {
+        self.at_position(&params.text_document_position, |analysis, offset| {
+            analysis.rename(offset, &params.new_name)
+        })
+    }
+
+    /// Analyze the open document a request points into
+    fn at_position<T>(
+        &self,
+        position: &TextDocumentPositionParams,
+        handler: impl FnOnce(&Analysis, usize) -> Result<T, String>,
+    ) -> Result<T, String> {
+        let uri = &position.text_document.uri;
+        let document = self
+            .documents
+            .get(uri)
+            .ok_or_else(|| format!("Document is not open: {}", uri))?;
+        handler(&self.analyze(document), document.offset(position.position))
+    }
+
+    fn handle_notification(&mut self, notification: Notification) -> Result<(), Error> {
+        match notification.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                let params: DidOpenTextDocumentParams =
+                    serde_json::from_value(notification.params)?;
+                self.update(params.text_document.uri, params.text_document.text)?;
+            }
+            DidChangeTextDocument::METHOD => {
+                let mut params: DidChangeTextDocumentParams =
+                    serde_json::from_value(notification.params)?;
+                // With full sync, the last change holds the whole text
+                if let Some(change) = params.content_changes.pop() {
+                    self.update(params.text_document.uri, change.text)?;
+                }
+            }
+            DidCloseTextDocument::METHOD => {
+                let params: DidCloseTextDocumentParams =
+                    serde_json::from_value(notification.params)?;
+                self.documents.remove(&params.text_document.uri);
+                self.publish(params.text_document.uri, Vec::new())?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Reparse a document and publish fresh diagnostics for it
+    ///
+    /// Open documents with imports are checked again too, since one of them may import it.
+    fn update(&mut self, uri: Url, text: String) -> Result<(), Error> {
+        self.documents
+            .insert(uri.clone(), Document::new(uri.clone(), text));
+
+        let mut uris = vec![uri.clone()];
+        uris.extend(
+            self.documents
+                .values()
+                .filter(|document| document.uri != uri && !document.schema.imports.is_empty())
+                .map(|document| document.uri.clone()),
+        );
+        for uri in uris {
+            let diagnostics = self.analyze(&self.documents[&uri]).diagnostics();
+            self.publish(uri, diagnostics)?;
+        }
+        Ok(())
+    }
+
+    fn publish(&self, uri: Url, diagnostics: Vec<lsp_types::Diagnostic>) -> Result<(), Error> {
+        let params = PublishDiagnosticsParams::new(uri, diagnostics, None);
+        let notification = Notification::new(PublishDiagnostics::METHOD.to_string(), params);
+        self.connection.sender.send(notification.into())?;
+        Ok(())
+    }
+
+    /// Load a document's imports so its names resolve
+    fn analyze<'a>(&self, document: &'a Document) -> Analysis<'a> {
+        let mut imports = Vec::new();
+        let mut missing = Vec::new();
+        for import in &document.schema.imports {
+            match self.load_import(document, import) {
+                Ok(imported) => imports.push((import.clone(), imported)),
+                Err(reason) => missing.push((import.clone(), reason)),
+            }
+        }
+        Analysis::new(document, imports, missing)
+    }
+
+    /// Read an imported schema, preferring the editor's copy when it's open
+    fn load_import(&self, document: &Document, import: &str) -> Result<Document, String> {
+        let not_found = || format!("Cannot find imported schema '{}'", import);
+        let path = document
+            .uri
+            .to_file_path()
+            .map_err(|_| format!("Cannot resolve import '{}' from an unsaved file", import))?;
+        let resolved =
+            loader::resolve_import(&path, import, &self.include_paths).map_err(|_| not_found())?;
+        let uri = Url::from_file_path(&resolved).map_err(|_| not_found())?;
+
+        if let Some(open) = self.documents.get(&uri) {
+            return Ok(open.clone());
+        }
+        let text = fs::read_to_string(&resolved)
+            .map_err(|err| format!("Cannot read imported schema '{}': {}", import, err))?;
+        Ok(Document::new(uri, text))
+    }
+}
@@ -0,0 +1,363 @@
+//! Drive the language server over stdio the way an editor does
+
+use lsp_types::Url;
+use serde_json::{json, Value};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::Path;
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+
+const USERS: &str =
+    "/// Someone who can sign in\nmessage User {\n    uid: u64;\n    name: string;\n}\n";
+
+const ORDERS: &str = r#"import "users.zp";
+
+message Order {
+    number: u64 @1;
+    /// Who placed the order
+    buyer: User;
+    items: [LineItem];
+    status: Phase?;
+}
+
+message LineItem {
+    sku: string;
+}
+
+enum Phase {
+    Open = 0;
+}
+
+/// Stock keeping unit
+type Sku = string;
+"#;
+
+/// A scripted editor talking to a server process
+struct Client {
+    server: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+    next_id: i64,
+}
+
+impl Client {
+    fn start() -> Self {
+        let mut server = Command::new(env!("CARGO_BIN_EXE_zeroproto-lsp"))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let stdin = server.stdin.take().unwrap();
+        let stdout = BufReader::new(server.stdout.take().unwrap());
+        let mut client = Self {
+            server,
+            stdin,
+            stdout,
+            next_id: 1,
+        };
+
+        let capabilities = client.request("initialize", json!({ "capabilities": {} }));
+        assert_eq!(capabilities["capabilities"]["hoverProvider"], true);
+        client.notify("initialized", json!({}));
+        client
+    }
+
+    fn send(&mut self, message: Value) {
+        let body = message.to_string();
+        write!(self.stdin, "Content-Length: {}\r\n\r\n{}", body.len(), body).unwrap();
+        self.stdin.flush().unwrap();
+    }
+
+    fn receive(&mut self) -> Value {
+        let mut length = 0;
+        loop {
+            let mut line = String::new();
+            self.stdout.read_line(&mut line).unwrap();
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            if let Some(value) = line.strip_prefix("Content-Length: ") {
+                length = value.parse().unwrap();
+            }
+        }
+        let mut body = vec![0; length];
+        self.stdout.read_exact(&mut body).unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    fn notify(&mut self, method: &str, params: Value) {
+        self.send(json!({ "jsonrpc": "2.0", "method": method, "params": params }));
+    }
+
+    /// Send a request and return its response, skipping notifications
+    fn call(&mut self, method: &str, params: Value) -> Value {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.send(json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params }));
+        loop {
+            let message = self.receive();
+            if message["id"] == id {
+                return message;
+            }
+        }
+    }
+
+    fn request(&mut self, method: &str, params: Value) -> Value {
+        let response = self.call(method, params);
+        assert!(
+            response["error"].is_null(),
+            "{} failed: {}",
+            method,
+            response
+        );
+        response["result"].clone()
+    }
+
+    /// Wait for the diagnostics published for `uri`
+    fn diagnostics(&mut self, uri: &Url) -> Vec<Value> {
+        loop {
+            let message = self.receive();
+            if message["method"] == "textDocument/publishDiagnostics"
+                && message["params"]["uri"] == uri.as_str()
+            {
+                return message["params"]["diagnostics"].as_array().unwrap().clone();
+            }
+        }
+    }
+
+    fn open(&mut self, uri: &Url, text: &str) -> Vec<Value> {
+        self.notify(
+            "textDocument/didOpen",
+            json!({ "textDocument": { "uri": uri, "languageId": "zeroproto", "version": 1, "text": text } }),
+        );
+        self.diagnostics(uri)
+    }
+
+    fn change(&mut self, uri: &Url, version: i32, text: &str) -> Vec<Value> {
+        self.notify(
+            "textDocument/didChange",
+            json!({ "textDocument": { "uri": uri, "version": version }, "contentChanges": [{ "text": text }] }),
+        );
+        self.diagnostics(uri)
+    }
+
+    fn shutdown(mut self) {
+        self.request("shutdown", Value::Null);
+        self.notify("exit", Value::Null);
+        assert!(self.server.wait().unwrap().success());
+    }
+}
+
+/// Position parameters for the `nth` occurrence of `needle` in `text`, plus `offset` characters
+fn at(uri: &Url, text: &str, needle: &str, nth: usize, offset: usize) -> Value {
+    let start = text.match_indices(needle).nth(nth).unwrap().0 + offset;
+    let line = text[..start].matches('\n').count();
+    let character = start - text[..start].rfind('\n').map_or(0, |newline| newline + 1);
+    json!({ "textDocument": { "uri": uri }, "position": { "line": line, "character": character } })
+}
+
+fn range(line: u32, start: u32, end: u32) -> Value {
+    json!({ "start": { "line": line, "character": start }, "end": { "line": line, "character": end } })
+}
+
+/// Write the schemas to a directory and return their URIs
+fn workspace(dir: &Path) -> (Url, Url) {
+    let dir = dir.canonicalize().unwrap();
+    std::fs::write(dir.join("users.zp"), USERS).unwrap();
+    std::fs::write(dir.join("orders.zp"), ORDERS).unwrap();
+    (
+        Url::from_file_path(dir.join("users.zp")).unwrap(),
+        Url::from_file_path(dir.join("orders.zp")).unwrap(),
+    )
+}
+
+#[test]
+fn test_diagnostics_on_change() {
+    let dir = tempfile::tempdir().unwrap();
+    let (_, orders) = workspace(dir.path());
+    let mut client = Client::start();
+
+    assert_eq!(client.open(&orders, ORDERS), Vec::<Value>::new());
+
+    // Parse errors are located, and every one is reported
+    let broken = ORDERS
+        .replace("sku: string;", "sku string;")
+        .replace("Open = 0;", "Open = ;");
+    let diagnostics = client.change(&orders, 2, &broken);
+    assert_eq!(diagnostics.len(), 2);
+    assert_eq!(diagnostics[0]["message"], "Expected ':', found 'string'");
+    assert_eq!(diagnostics[0]["range"], range(11, 8, 14));
+    assert_eq!(diagnostics[0]["severity"], 1);
+    assert_eq!(diagnostics[1]["range"]["start"]["line"], 15);
+
+    // Validation errors point at the node they're about, here the field's type
+    let unknown = ORDERS.replace("[LineItem]", "[LineItm]");
+    let diagnostics = client.change(&orders, 3, &unknown);
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(
+        diagnostics[0]["message"],
+        "Unknown type 'LineItm' used in field"
+    );
+    assert_eq!(diagnostics[0]["range"], range(6, 11, 20));
+
+    let missing = ORDERS.replace("users.zp", "people.zp");
+    let diagnostics = client.change(&orders, 4, &missing);
+    assert_eq!(
+        diagnostics[0]["message"],
+        "Cannot find imported schema 'people.zp'"
+    );
+    assert_eq!(diagnostics[0]["range"], range(0, 7, 18));
+
+    assert_eq!(client.change(&orders, 5, ORDERS), Vec::<Value>::new());
+    client.shutdown();
+}
+
+#[test]
+fn test_definition_and_references() {
+    let dir = tempfile::tempdir().unwrap();
+    let (users, orders) = workspace(dir.path());
+    let mut client = Client::start();
+    client.open(&orders, ORDERS);
+
+    // Imported types resolve to the file that declares them
+    let definition = client.request(
+        "textDocument/definition",
+        at(&orders, ORDERS, "User;", 0, 2),
+    );
+    assert_eq!(definition["uri"], users.as_str());
+    assert_eq!(definition["range"], range(1, 8, 12));
+
+    let definition = client.request(
+        "textDocument/definition",
+        at(&orders, ORDERS, "LineItem", 0, 0),
+    );
+    assert_eq!(definition["uri"], orders.as_str());
+    assert_eq!(definition["range"], range(10, 8, 16));
+
+    // Nothing to go to from a scalar type
+    let definition = client.request("textDocument/definition", at(&orders, ORDERS, "u64", 0, 1));
+    assert!(definition.is_null());
+
+    let mut params = at(&orders, ORDERS, "LineItem {", 0, 3);
+    params["context"] = json!({ "includeDeclaration": true });
+    let references = client.request("textDocument/references", params.clone());
+    assert_eq!(
+        references,
+        json!([
+            { "uri": orders, "range": range(6, 12, 20) },
+            { "uri": orders, "range": range(10, 8, 16) },
+        ])
+    );
+
+    params["context"] = json!({ "includeDeclaration": false });
+    let references = client.request("textDocument/references", params);
+    assert_eq!(references.as_array().unwrap().len(), 1);
+    client.shutdown();
+}
+
+#[test]
+fn test_hover_and_completion() {
+    let dir = tempfile::tempdir().unwrap();
+    let (_, orders) = workspace(dir.path());
+    let mut client = Client::start();
+    client.open(&orders, ORDERS);
+
+    let hover = client.request("textDocument/hover", at(&orders, ORDERS, "buyer", 0, 1));
+    assert_eq!(hover["contents"]["kind"], "markdown");
+    assert_eq!(
+        hover["contents"]["value"],
+        "```zp\nbuyer: User\n```\n\nField index 2, wire type nested message (type ID 13)\n\nWho placed the order"
+    );
+    assert_eq!(hover["range"], range(5, 4, 15));
+
+    let hover = client.request("textDocument/hover", at(&orders, ORDERS, "status", 0, 0));
+    assert_eq!(
+        hover["contents"]["value"],
+        "```zp\nstatus: Phase?\n```\n\nField index 4, wire type enum, stored as `u64` (type ID 3)"
+    );
+
+    let hover = client.request(
+        "textDocument/hover",
+        at(&orders, ORDERS, "number: u64", 0, 5),
+    );
+    assert!(hover["contents"]["value"]
+        .as_str()
+        .unwrap()
+        .contains("Field index 1, wire type `u64` (type ID 3)"));
+
+    // Type names show their declaration and docs, even from another file
+    let hover = client.request("textDocument/hover", at(&orders, ORDERS, "User;", 0, 0));
+    assert_eq!(
+        hover["contents"]["value"],
+        "```zp\nmessage User\n```\n\nSomeone who can sign in"
+    );
+
+    // Aliases show what they stand for
+    let hover = client.request("textDocument/hover", at(&orders, ORDERS, "Sku", 0, 1));
+    assert_eq!(
+        hover["contents"]["value"],
+        "```zp\ntype Sku = string\n```\n\nStock keeping unit"
+    );
+
+    let completions = client.request(
+        "textDocument/completion",
+        at(&orders, ORDERS, "User;", 0, 0),
+    );
+    let labels: Vec<_> = completions
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|item| item["label"].as_str().unwrap())
+        .collect();
+    for expected in [
+        "u8", "string", "bytes", "Order", "LineItem", "Phase", "User", "Sku",
+    ] {
+        assert!(labels.contains(&expected), "missing {}", expected);
+    }
+    client.shutdown();
+}
+
+#[test]
+fn test_rename() {
+    let dir = tempfile::tempdir().unwrap();
+    let (users, orders) = workspace(dir.path());
+    let mut client = Client::start();
+    client.open(&orders, ORDERS);
+
+    let range_to_rename = client.request(
+        "textDocument/prepareRename",
+        at(&orders, ORDERS, "LineItem", 1, 0),
+    );
+    assert_eq!(range_to_rename, range(10, 8, 16));
+
+    let mut params = at(&orders, ORDERS, "LineItem", 0, 4);
+    params["newName"] = json!("Item");
+    let edit = client.request("textDocument/rename", params);
+    assert_eq!(
+        edit["changes"][orders.as_str()],
+        json!([
+            { "range": range(6, 12, 20), "newText": "Item" },
+            { "range": range(10, 8, 16), "newText": "Item" },
+        ])
+    );
+
+    // Renaming an imported type edits the file that declares it too
+    let mut params = at(&orders, ORDERS, "User;", 0, 0);
+    params["newName"] = json!("Person");
+    let edit = client.request("textDocument/rename", params.clone());
+    assert_eq!(
+        edit["changes"][users.as_str()],
+        json!([{ "range": range(1, 8, 12), "newText": "Person" }])
+    );
+    assert_eq!(
+        edit["changes"][orders.as_str()].as_array().unwrap().len(),
+        1
+    );
+
+    for bad in ["u8", "message", "9lives", "Phase", "Über"] {
+        params["newName"] = json!(bad);
+        let response = client.call("textDocument/rename", params.clone());
+        assert!(response["error"]["message"].is_string(), "{} accepted", bad);
+    }
+    client.shutdown();
+}
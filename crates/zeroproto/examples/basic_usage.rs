@@ -1,5 +1,8 @@
 //! Basic usage example for ZeroProto
 
+// Sample values, not approximations of constants
+#![allow(clippy::approx_constant)]
+
 use zeroproto::{MessageBuilder, MessageReader};
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
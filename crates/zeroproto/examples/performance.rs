@@ -107,7 +107,7 @@ fn benchmark_zero_copy(data: &[u8], _field_count: usize) -> Result<(), Box<dyn s
 }
 
 fn benchmark_strings() -> Result<(), Box<dyn std::error::Error>> {
-    let test_strings = vec![
+    let test_strings = [
         "Hello",
         "Hello, World!",
         "This is a longer string with more content",
@@ -0,0 +1,344 @@
+//! Fields that hold a message of any type
+//!
+//! An `any` field stores the fully qualified schema name of a message (such as
+//! `acme.orders.Order`) next to the message itself. Readers get an [`AnyReader`], which can
+//! be downcast to a generated reader once its type name is known, or resolved to a
+//! [`MessageDescriptor`] through a [`TypeRegistry`] filled by generated `register_types`
+//! functions.
+
+#[cfg(all(feature = "alloc", not(feature = "std")))]
+use alloc::{collections::BTreeMap, string::String, vec::Vec};
+#[cfg(feature = "std")]
+use std::{collections::BTreeMap, string::String, vec::Vec};
+
+#[cfg(feature = "alloc")]
+use crate::{builder::MessageBuilder, descriptor::MessageDescriptor, errors::MissingFields};
+use crate::{
+    errors::{Error, Result},
+    primitives::Endian,
+    reader::MessageReader,
+};
+
+/// A generated message reader that an [`AnyReader`] can be downcast to
+pub trait TypedReader<'a>: Sized {
+    /// Fully qualified schema name of the message, e.g. `acme.orders.Order`
+    const TYPE_NAME: &'static str;
+
+    /// Wrap a reader for a message of this type
+    fn from_message(reader: MessageReader<'a>) -> Self;
+}
+
+/// A generated message builder whose output can be stored in an `any` field
+#[cfg(feature = "alloc")]
+pub trait TypedBuilder {
+    /// Fully qualified schema name of the message, e.g. `acme.orders.Order`
+    const TYPE_NAME: &'static str;
+
+    /// Finish building and return the serialized message, or every required field that
+    /// hasn't been set
+    fn into_bytes(self) -> core::result::Result<Vec<u8>, MissingFields>;
+}
+
+/// A zero-copy reader for an `any` field
+#[derive(Debug, Clone, Copy)]
+pub struct AnyReader<'a> {
+    type_name: &'a str,
+    message: MessageReader<'a>,
+    bytes: &'a [u8],
+}
+
+impl<'a> AnyReader<'a> {
+    /// Parse an `any` payload: a `u16` name length, the name, a `u32` length and the message
+    pub(crate) fn parse(payload: &'a [u8]) -> Result<Self> {
+        let end = Self::payload_len(payload)?;
+        let name_end = 2 + Endian::Little.read_u16(payload, 0) as usize;
+        let type_name = core::str::from_utf8(&payload[2..name_end])?;
+        let bytes = payload.get(name_end + 4..end).ok_or(Error::OutOfBounds)?;
+
+        Ok(Self {
+            type_name,
+            message: MessageReader::new(bytes)?,
+            bytes,
+        })
+    }
+
+    /// Size of an `any` payload, from its two length prefixes
+    pub(crate) fn payload_len(payload: &[u8]) -> Result<usize> {
+        if payload.len() < 2 {
+            return Err(Error::OutOfBounds);
+        }
+        let name_end = 2 + Endian::Little.read_u16(payload, 0) as usize;
+        if name_end + 4 > payload.len() {
+            return Err(Error::OutOfBounds);
+        }
+        Ok(name_end + 4 + Endian::Little.read_u32(payload, name_end) as usize)
+    }
+
+    /// Fully qualified schema name of the stored message
+    pub fn type_name(&self) -> &'a str {
+        self.type_name
+    }
+
+    /// Reader for the stored message, without schema information
+    pub fn message(&self) -> MessageReader<'a> {
+        self.message
+    }
+
+    /// The stored message's encoded bytes
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.bytes
+    }
+
+    /// Whether the stored message is a `T`
+    pub fn is<T: TypedReader<'a>>(&self) -> bool {
+        self.type_name == T::TYPE_NAME
+    }
+
+    /// The stored message as a `T`, or `None` if it has another type
+    pub fn downcast<T: TypedReader<'a>>(&self) -> Option<T> {
+        self.is::<T>().then(|| T::from_message(self.message))
+    }
+}
+
+/// An owned copy of an `any` field, as held by generated owned message types
+#[cfg(feature = "alloc")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AnyMessage {
+    /// Fully qualified schema name of the message
+    pub type_name: String,
+    /// The message's encoded bytes
+    pub message: Vec<u8>,
+}
+
+#[cfg(feature = "alloc")]
+impl AnyMessage {
+    /// Finish a generated builder and keep its output along with its type name
+    pub fn new<B: TypedBuilder>(builder: B) -> core::result::Result<Self, MissingFields> {
+        Ok(Self {
+            type_name: B::TYPE_NAME.into(),
+            message: builder.into_bytes()?,
+        })
+    }
+
+    /// Reader for the stored message, to downcast like a borrowed `any` field
+    pub fn reader(&self) -> Result<AnyReader<'_>> {
+        Ok(AnyReader {
+            type_name: &self.type_name,
+            message: MessageReader::new(&self.message)?,
+            bytes: &self.message,
+        })
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl Default for AnyMessage {
+    /// An empty message with no type name, which still reads back as a valid `any` field
+    fn default() -> Self {
+        Self {
+            type_name: String::new(),
+            message: MessageBuilder::new().finish(),
+        }
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl From<AnyReader<'_>> for AnyMessage {
+    fn from(any: AnyReader<'_>) -> Self {
+        Self {
+            type_name: any.type_name.into(),
+            message: any.bytes.into(),
+        }
+    }
+}
+
+/// Message descriptors by fully qualified type name
+///
+/// Generated modules have a `register_types` function that adds every message they
+/// define.
+#[cfg(feature = "alloc")]
+#[derive(Debug, Clone, Default)]
+pub struct TypeRegistry {
+    types: BTreeMap<&'static str, &'static MessageDescriptor>,
+}
+
+#[cfg(feature = "alloc")]
+impl TypeRegistry {
+    /// Create an empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a message type under its [`type_name`](MessageDescriptor::type_name)
+    pub fn register(&mut self, descriptor: &'static MessageDescriptor) -> &mut Self {
+        self.types.insert(descriptor.type_name, descriptor);
+        self
+    }
+
+    /// Look up a message type by its fully qualified name
+    pub fn get(&self, type_name: &str) -> Option<&'static MessageDescriptor> {
+        self.types.get(type_name).copied()
+    }
+
+    /// Look up the type of the message stored in an `any` field
+    pub fn resolve(&self, any: &AnyReader<'_>) -> Option<&'static MessageDescriptor> {
+        self.get(any.type_name())
+    }
+
+    /// Number of registered types
+    pub fn len(&self) -> usize {
+        self.types.len()
+    }
+
+    /// Whether no types are registered
+    pub fn is_empty(&self) -> bool {
+        self.types.is_empty()
+    }
+}
+
+#[cfg(all(test, feature = "alloc"))]
+mod tests {
+    use super::*;
+    use crate::{FieldDescriptor, MessageBuilder};
+    #[cfg(not(feature = "std"))]
+    use alloc::string::ToString;
+    #[cfg(feature = "std")]
+    use std::string::ToString;
+
+    static POINT: MessageDescriptor = MessageDescriptor {
+        name: "Point",
+        type_name: "geo.Point",
+        fields: &[FieldDescriptor {
+            name: "x",
+            index: 0,
+            message: None,
+            variants: &[],
+        }],
+    };
+
+    struct PointReader<'a>(MessageReader<'a>);
+
+    impl<'a> TypedReader<'a> for PointReader<'a> {
+        const TYPE_NAME: &'static str = "geo.Point";
+
+        fn from_message(reader: MessageReader<'a>) -> Self {
+            Self(reader)
+        }
+    }
+
+    struct LineReader;
+
+    impl<'a> TypedReader<'a> for LineReader {
+        const TYPE_NAME: &'static str = "geo.Line";
+
+        fn from_message(_: MessageReader<'a>) -> Self {
+            Self
+        }
+    }
+
+    #[test]
+    fn test_any_roundtrip() -> Result<()> {
+        let mut point = MessageBuilder::new();
+        point.set_scalar(0, 7i32)?;
+        let point = point.finish();
+
+        let mut builder = MessageBuilder::new();
+        builder.set_any(0, "geo.Point", &point)?;
+        builder.set_scalar(1, 1u8)?;
+        let bytes = builder.finish();
+
+        let reader = MessageReader::new(&bytes)?;
+        let any = reader.get_any(0)?;
+        assert_eq!(any.type_name(), "geo.Point");
+        assert_eq!(any.as_bytes(), &point[..]);
+        assert!(any.is::<PointReader>());
+        assert!(any.downcast::<LineReader>().is_none());
+
+        let downcast = any.downcast::<PointReader>().ok_or(Error::InvalidMessage)?;
+        assert_eq!(downcast.0.get_scalar::<i32>(0)?, 7);
+        assert_eq!(reader.get_scalar::<u8>(1)?, 1);
+
+        assert_eq!(reader.get_any(1).unwrap_err(), Error::InvalidFieldType);
+
+        let mut registry = TypeRegistry::new();
+        registry.register(&POINT);
+        assert_eq!(
+            registry.resolve(&any).map(|point| point.name),
+            Some("Point")
+        );
+        assert!(registry.get("geo.Line").is_none());
+
+        let owned = AnyMessage::from(any);
+        assert_eq!(owned.type_name, "geo.Point");
+        let reread = owned.reader()?;
+        assert!(reread.is::<PointReader>());
+        assert_eq!(reread.message().get_scalar::<i32>(0)?, 7);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_any_default() -> Result<()> {
+        let empty = AnyMessage::default();
+        let mut builder = MessageBuilder::new();
+        builder.set_any(0, &empty.type_name, &empty.message)?;
+        let bytes = builder.finish();
+
+        let any = MessageReader::new(&bytes)?.get_any(0)?;
+        assert_eq!(any.type_name(), "");
+        assert_eq!(any.message().field_count(), 0);
+        assert_eq!(AnyMessage::from(any), empty);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_any_merge_and_diff() -> Result<()> {
+        let holder = |type_name: &str, x: i32| -> Result<Vec<u8>> {
+            let mut point = MessageBuilder::new();
+            point.set_scalar(0, x)?;
+            let mut builder = MessageBuilder::new();
+            builder.set_any(0, type_name, &point.finish())?;
+            Ok(builder.finish())
+        };
+        let base = holder("geo.Point", 1)?;
+        let same_type = holder("geo.Point", 2)?;
+        let other_type = holder("geo.Line", 3)?;
+
+        // Messages of the same type merge field by field
+        let merged = crate::merge::merge(&base, &same_type)?;
+        let merged = MessageReader::new(&merged)?;
+        assert_eq!(merged.get_any(0)?.message().get_scalar::<i32>(0)?, 2);
+
+        let base = MessageReader::new(&base)?;
+        let same_type = MessageReader::new(&same_type)?;
+        let other_type = MessageReader::new(&other_type)?;
+        assert_eq!(
+            crate::diff::diff(&base, &same_type)?.to_string(),
+            "#0.#0: changed from 1 to 2"
+        );
+        assert_eq!(
+            crate::diff::diff(&base, &other_type)?.to_string(),
+            "#0: changed from <any geo.Point> to <any geo.Line>"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_any_bounds() {
+        // Name length runs past the payload
+        assert_eq!(
+            AnyReader::parse(&[9, 0, b'a']).unwrap_err(),
+            Error::OutOfBounds
+        );
+        // Message length runs past the payload
+        assert_eq!(
+            AnyReader::parse(&[1, 0, b'a', 8, 0, 0, 0, 0]).unwrap_err(),
+            Error::OutOfBounds
+        );
+        assert_eq!(
+            AnyReader::parse(&[1, 0, 0xff, 0, 0, 0, 0]).unwrap_err(),
+            Error::InvalidUtf8
+        );
+    }
+}
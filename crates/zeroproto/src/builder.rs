@@ -10,8 +10,11 @@ use std::{collections::BTreeMap, vec::Vec};
 use crate::{
     constants::{FIELD_ENTRY_SIZE, MAX_FIELDS},
     errors::{Error, Result},
+    layout::{self, FieldTableLayout, EXTENDED_HEADER_SIZE, SPARSE_ENTRY_SIZE},
     primitives::{Endian, PrimitiveType},
-    ZpWrite,
+    reader::MessageReader,
+    well_known::{Decimal, Duration, Timestamp, Uuid},
+    ZpRead, ZpWrite,
 };
 
 /// A message builder for serializing ZeroProto messages
@@ -20,12 +23,17 @@ pub struct MessageBuilder {
     buffer: Vec<u8>,
     field_entries: BTreeMap<u16, FieldEntry>,
     payload_offset: usize,
+    layout: FieldTableLayout,
+    canonical: bool,
+    auto_compact: bool,
 }
 
 #[derive(Debug, Clone)]
 struct FieldEntry {
     type_id: u8,
     offset: u32,
+    /// Payload length, so entries can be moved when the payload is compacted
+    len: u32,
 }
 
 impl MessageBuilder {
@@ -35,6 +43,9 @@ impl MessageBuilder {
             buffer: Vec::new(),
             field_entries: BTreeMap::new(),
             payload_offset: 0,
+            layout: FieldTableLayout::Auto,
+            canonical: false,
+            auto_compact: false,
         };
 
         // Reserve space for field count (will be filled later)
@@ -43,11 +54,85 @@ impl MessageBuilder {
         builder
     }
 
+    /// Create a new message builder that writes the given field table layout
+    pub fn with_layout(layout: FieldTableLayout) -> Self {
+        let mut builder = Self::new();
+        builder.layout = layout;
+        builder
+    }
+
+    /// Create a builder holding every field set on an existing message
+    ///
+    /// Payloads are copied as-is. Messages using the bitmap layout keep it; others
+    /// use the automatic layout.
+    pub fn from_reader(reader: &MessageReader<'_>) -> Result<Self> {
+        let mut builder = match reader.layout() {
+            FieldTableLayout::Bitmap => Self::with_layout(FieldTableLayout::Bitmap),
+            _ => Self::new(),
+        };
+
+        for field_index in 0..reader.field_count() {
+            if let Some((field_type, payload)) = reader.field_bytes(field_index)? {
+                builder.set_raw(field_index, field_type, payload)?;
+            }
+        }
+
+        Ok(builder)
+    }
+
+    /// Create a new message builder that produces canonical encodings
+    ///
+    /// Canonical messages lay out payloads in field index order with no dead bytes,
+    /// always use the automatic field table layout, and canonicalize nested messages,
+    /// so the same logical content always encodes to the same bytes.
+    pub fn canonical() -> Self {
+        let mut builder = Self::new();
+        builder.canonical = true;
+        builder
+    }
+
+    /// Check whether this builder produces canonical encodings
+    pub fn is_canonical(&self) -> bool {
+        self.canonical
+    }
+
+    /// Enable or disable canonical encoding
+    pub fn set_canonical(&mut self, canonical: bool) {
+        self.canonical = canonical;
+    }
+
+    /// Check whether `finish` drops unreferenced payload bytes
+    pub fn is_auto_compact(&self) -> bool {
+        self.auto_compact
+    }
+
+    /// Make `finish` drop unreferenced payload bytes before writing the message
+    ///
+    /// Canonical builders always do this.
+    pub fn set_auto_compact(&mut self, auto_compact: bool) {
+        self.auto_compact = auto_compact;
+    }
+
+    /// Get the field table layout used by `finish`
+    pub fn layout(&self) -> FieldTableLayout {
+        self.layout
+    }
+
+    /// Set the field table layout used by `finish`
+    pub fn set_layout(&mut self, layout: FieldTableLayout) {
+        self.layout = layout;
+    }
+
     /// Get the current number of fields
     pub fn field_count(&self) -> u16 {
         self.field_entries_count()
     }
 
+    /// Whether a field has been set and not cleared since
+    pub fn has_field(&self, field_index: u16) -> bool {
+        self.field_entries.contains_key(&field_index)
+    }
+
     /// Add a scalar field
     pub fn set_scalar<T: ZpWrite>(&mut self, field_index: u16, value: T) -> Result<()> {
         self.ensure_field_index(field_index)?;
@@ -65,7 +150,7 @@ impl MessageBuilder {
         value.write(&mut self.buffer, self.payload_offset)?;
 
         // Add/update field entry
-        self.set_field_entry(field_index, type_id, field_offset);
+        self.set_field_entry(field_index, type_id, field_offset, value.size());
 
         // Update payload offset
         self.payload_offset += value.size();
@@ -95,7 +180,7 @@ impl MessageBuilder {
         self.buffer[string_offset..string_offset + len].copy_from_slice(value.as_bytes());
 
         // Add/update field entry
-        self.set_field_entry(field_index, type_id, field_offset);
+        self.set_field_entry(field_index, type_id, field_offset, 4 + len);
 
         // Update payload offset
         self.payload_offset += 4 + len;
@@ -125,7 +210,7 @@ impl MessageBuilder {
         self.buffer[bytes_offset..bytes_offset + len].copy_from_slice(value);
 
         // Add/update field entry
-        self.set_field_entry(field_index, type_id, field_offset);
+        self.set_field_entry(field_index, type_id, field_offset, 4 + len);
 
         // Update payload offset
         self.payload_offset += 4 + len;
@@ -137,6 +222,16 @@ impl MessageBuilder {
     pub fn set_message(&mut self, field_index: u16, message: &[u8]) -> Result<()> {
         self.ensure_field_index(field_index)?;
 
+        if self.canonical {
+            let message = MessageReader::new(message)?.canonicalize()?;
+            return self.write_message(field_index, &message);
+        }
+
+        self.write_message(field_index, message)
+    }
+
+    /// Write a nested message as-is
+    pub(crate) fn write_message(&mut self, field_index: u16, message: &[u8]) -> Result<()> {
         let type_id = PrimitiveType::Message as u8;
         let field_offset = self.payload_offset as u32;
 
@@ -155,7 +250,7 @@ impl MessageBuilder {
         self.buffer[message_offset..message_offset + len].copy_from_slice(message);
 
         // Add/update field entry
-        self.set_field_entry(field_index, type_id, field_offset);
+        self.set_field_entry(field_index, type_id, field_offset, 4 + len);
 
         // Update payload offset
         self.payload_offset += 4 + len;
@@ -163,11 +258,150 @@ impl MessageBuilder {
         Ok(())
     }
 
+    /// Add a union field holding the message for the given variant discriminant
+    pub fn set_union(&mut self, field_index: u16, discriminant: u16, message: &[u8]) -> Result<()> {
+        self.ensure_field_index(field_index)?;
+
+        if self.canonical {
+            let message = MessageReader::new(message)?.canonicalize()?;
+            return self.write_union(field_index, discriminant, &message);
+        }
+
+        self.write_union(field_index, discriminant, message)
+    }
+
+    /// Write a union field as-is
+    pub(crate) fn write_union(
+        &mut self,
+        field_index: u16,
+        discriminant: u16,
+        message: &[u8],
+    ) -> Result<()> {
+        let type_id = PrimitiveType::Union as u8;
+        let field_offset = self.payload_offset as u32;
+
+        // Reserve space for discriminant + length + message bytes
+        let len = message.len();
+        let required_size = self.payload_offset + 6 + len;
+        if required_size > self.buffer.len() {
+            self.buffer.resize(required_size, 0);
+        }
+
+        // Write discriminant and length
+        Endian::Little.write_u16(discriminant, &mut self.buffer, self.payload_offset);
+        Endian::Little.write_u32(len as u32, &mut self.buffer, self.payload_offset + 2);
+
+        // Write message bytes
+        let message_offset = self.payload_offset + 6;
+        self.buffer[message_offset..message_offset + len].copy_from_slice(message);
+
+        // Add/update field entry
+        self.set_field_entry(field_index, type_id, field_offset, 6 + len);
+
+        // Update payload offset
+        self.payload_offset += 6 + len;
+
+        Ok(())
+    }
+
+    /// Add an `any` field holding a message tagged with its fully qualified type name
+    pub fn set_any(&mut self, field_index: u16, type_name: &str, message: &[u8]) -> Result<()> {
+        self.ensure_field_index(field_index)?;
+
+        if self.canonical {
+            let message = MessageReader::new(message)?.canonicalize()?;
+            return self.write_any(field_index, type_name, &message);
+        }
+
+        self.write_any(field_index, type_name, message)
+    }
+
+    /// Write an `any` field as-is
+    pub(crate) fn write_any(
+        &mut self,
+        field_index: u16,
+        type_name: &str,
+        message: &[u8],
+    ) -> Result<()> {
+        let type_id = PrimitiveType::Any as u8;
+        let field_offset = self.payload_offset as u32;
+        let name_len = u16::try_from(type_name.len()).map_err(|_| Error::OutOfBounds)? as usize;
+
+        // Reserve space for name length + name + message length + message bytes
+        let total_size = 2 + name_len + 4 + message.len();
+        let required_size = self.payload_offset + total_size;
+        if required_size > self.buffer.len() {
+            self.buffer.resize(required_size, 0);
+        }
+
+        // Write the type name, then the message with its length
+        let mut offset = self.payload_offset;
+        Endian::Little.write_u16(name_len as u16, &mut self.buffer, offset);
+        offset += 2;
+        self.buffer[offset..offset + name_len].copy_from_slice(type_name.as_bytes());
+        offset += name_len;
+        Endian::Little.write_u32(message.len() as u32, &mut self.buffer, offset);
+        offset += 4;
+        self.buffer[offset..offset + message.len()].copy_from_slice(message);
+
+        // Add/update field entry
+        self.set_field_entry(field_index, type_id, field_offset, total_size);
+
+        // Update payload offset
+        self.payload_offset += total_size;
+
+        Ok(())
+    }
+
+    /// Add a bool vector packed eight values to a byte
+    pub fn set_bit_vector(&mut self, field_index: u16, values: &[bool]) -> Result<()> {
+        self.ensure_field_index(field_index)?;
+
+        let type_id = PrimitiveType::BitVector as u8;
+        let field_offset = self.payload_offset as u32;
+
+        // Reserve space for bit count + packed bits
+        let packed_len = layout::bitmap_len(values.len());
+        let required_size = self.payload_offset + 4 + packed_len;
+        if required_size > self.buffer.len() {
+            self.buffer.resize(required_size, 0);
+        }
+
+        // Write bit count
+        Endian::Little.write_u32(values.len() as u32, &mut self.buffer, self.payload_offset);
+
+        // Write bits, least significant bit first
+        let bits_offset = self.payload_offset + 4;
+        let bits = &mut self.buffer[bits_offset..bits_offset + packed_len];
+        bits.fill(0);
+        for (i, value) in values.iter().enumerate() {
+            if *value {
+                layout::set_bit(bits, i);
+            }
+        }
+
+        // Add/update field entry
+        self.set_field_entry(field_index, type_id, field_offset, 4 + packed_len);
+
+        // Update payload offset
+        self.payload_offset += 4 + packed_len;
+
+        Ok(())
+    }
+
     /// Add a vector field
+    ///
+    /// Vectors of built-in scalars and well-known types record their element type. Other
+    /// element types are written without one, so they can only be read back with
+    /// [`MessageReader::get_vector`] and aren't supported by canonicalization, diff, or merge.
     pub fn set_vector<T: ZpWrite>(&mut self, field_index: u16, values: &[T]) -> Result<()> {
         self.ensure_field_index(field_index)?;
 
-        let type_id = PrimitiveType::Vector as u8;
+        let element_type_id = self.get_type_id::<T>().ok();
+        let (type_id, header_len) = match element_type_id {
+            Some(_) => (PrimitiveType::TypedVector as u8, 5),
+            None => (PrimitiveType::Vector as u8, 4),
+        };
         let field_offset = self.payload_offset as u32;
 
         // Calculate total size needed
@@ -176,24 +410,94 @@ impl MessageBuilder {
         } else {
             values[0].size()
         };
-        let total_size = 4 + values.len() * element_size;
+        let total_size = header_len + values.len() * element_size;
         let required_size = self.payload_offset + total_size;
         if required_size > self.buffer.len() {
             self.buffer.resize(required_size, 0);
         }
 
-        // Write count
+        // Write count and element type
         Endian::Little.write_u32(values.len() as u32, &mut self.buffer, self.payload_offset);
+        if let Some(element_type_id) = element_type_id {
+            self.buffer[self.payload_offset + 4] = element_type_id;
+        }
 
         // Write elements
-        let mut offset = self.payload_offset + 4;
+        let mut offset = self.payload_offset + header_len;
         for value in values {
             value.write(&mut self.buffer, offset)?;
             offset += value.size();
         }
 
         // Add/update field entry
-        self.set_field_entry(field_index, type_id, field_offset);
+        self.set_field_entry(field_index, type_id, field_offset, total_size);
+
+        // Update payload offset
+        self.payload_offset += total_size;
+
+        Ok(())
+    }
+
+    /// Add a struct field, stored inline after its size
+    pub fn set_struct<T: ZpWrite>(&mut self, field_index: u16, value: &T) -> Result<()> {
+        self.ensure_field_index(field_index)?;
+
+        let type_id = PrimitiveType::Struct as u8;
+        let field_offset = self.payload_offset as u32;
+        let size = u16::try_from(value.size()).map_err(|_| Error::OutOfBounds)?;
+
+        // Reserve space for size + struct bytes
+        let len = size as usize;
+        let required_size = self.payload_offset + 2 + len;
+        if required_size > self.buffer.len() {
+            self.buffer.resize(required_size, 0);
+        }
+
+        // Write size and struct bytes
+        Endian::Little.write_u16(size, &mut self.buffer, self.payload_offset);
+        value.write(&mut self.buffer, self.payload_offset + 2)?;
+
+        // Add/update field entry
+        self.set_field_entry(field_index, type_id, field_offset, 2 + len);
+
+        // Update payload offset
+        self.payload_offset += 2 + len;
+
+        Ok(())
+    }
+
+    /// Add a vector of structs, packed back to back after their stride
+    pub fn set_struct_vector<T>(&mut self, field_index: u16, values: &[T]) -> Result<()>
+    where
+        T: ZpWrite + for<'b> ZpRead<'b>,
+    {
+        self.ensure_field_index(field_index)?;
+
+        let type_id = PrimitiveType::TypedVector as u8;
+        let field_offset = self.payload_offset as u32;
+        let stride = u16::try_from(<T as ZpRead>::size()).map_err(|_| Error::OutOfBounds)?;
+
+        // Reserve space for count + element type + stride + elements
+        let total_size = 7 + values.len() * stride as usize;
+        let required_size = self.payload_offset + total_size;
+        if required_size > self.buffer.len() {
+            self.buffer.resize(required_size, 0);
+        }
+
+        // Write count, element type and stride
+        Endian::Little.write_u32(values.len() as u32, &mut self.buffer, self.payload_offset);
+        self.buffer[self.payload_offset + 4] = PrimitiveType::Struct as u8;
+        Endian::Little.write_u16(stride, &mut self.buffer, self.payload_offset + 5);
+
+        // Write elements
+        let mut offset = self.payload_offset + 7;
+        for value in values {
+            value.write(&mut self.buffer, offset)?;
+            offset += stride as usize;
+        }
+
+        // Add/update field entry
+        self.set_field_entry(field_index, type_id, field_offset, total_size);
 
         // Update payload offset
         self.payload_offset += total_size;
@@ -212,7 +516,7 @@ impl MessageBuilder {
 
     /// Ensure the field index is valid
     fn ensure_field_index(&self, field_index: u16) -> Result<()> {
-        if field_index == MAX_FIELDS {
+        if field_index >= MAX_FIELDS {
             return Err(Error::OutOfBounds);
         }
 
@@ -220,9 +524,108 @@ impl MessageBuilder {
     }
 
     /// Set a field entry
-    fn set_field_entry(&mut self, field_index: u16, type_id: u8, offset: u32) {
-        self.field_entries
-            .insert(field_index, FieldEntry { type_id, offset });
+    fn set_field_entry(&mut self, field_index: u16, type_id: u8, offset: u32, len: usize) {
+        self.field_entries.insert(
+            field_index,
+            FieldEntry {
+                type_id,
+                offset,
+                len: len as u32,
+            },
+        );
+    }
+
+    /// Add a field whose payload is already encoded
+    pub(crate) fn set_raw(
+        &mut self,
+        field_index: u16,
+        field_type: PrimitiveType,
+        payload: &[u8],
+    ) -> Result<()> {
+        self.ensure_field_index(field_index)?;
+
+        let field_offset = self.payload_offset as u32;
+        self.buffer.resize(self.payload_offset + payload.len(), 0);
+        self.buffer[self.payload_offset..].copy_from_slice(payload);
+
+        self.set_field_entry(field_index, field_type as u8, field_offset, payload.len());
+        self.payload_offset += payload.len();
+
+        Ok(())
+    }
+
+    /// Copy one field from an existing message verbatim
+    ///
+    /// Fields that aren't set in `reader` are left untouched here.
+    pub fn copy_field(&mut self, reader: &MessageReader<'_>, field_index: u16) -> Result<()> {
+        match reader.field_bytes(field_index)? {
+            Some((field_type, payload)) => self.set_raw(field_index, field_type, payload),
+            None => Ok(()),
+        }
+    }
+
+    /// Copy every field whose index isn't in `known_fields` from an existing message verbatim
+    ///
+    /// Code generated from an older schema uses this to carry fields added by newer
+    /// writers through a re-encode instead of dropping them.
+    pub fn copy_unknown_fields(
+        &mut self,
+        reader: &MessageReader<'_>,
+        known_fields: &[u16],
+    ) -> Result<()> {
+        for field_index in 0..reader.field_count() {
+            if !known_fields.contains(&field_index) {
+                self.copy_field(reader, field_index)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Get the type and encoded payload of a field set on this builder
+    pub(crate) fn field_payload(&self, field_index: u16) -> Option<(PrimitiveType, &[u8])> {
+        let entry = self.field_entries.get(&field_index)?;
+        let field_type = PrimitiveType::from_u8(entry.type_id)?;
+        let start = entry.offset as usize;
+        Some((field_type, &self.buffer[start..start + entry.len as usize]))
+    }
+
+    /// Number of payload bytes no longer referenced by any field
+    ///
+    /// Re-setting a field leaves its old payload behind, and `clear_field` only removes
+    /// the table entry, so these bytes grow until [`compact`](Self::compact) runs.
+    pub fn unreferenced_bytes(&self) -> usize {
+        let live: usize = self
+            .field_entries
+            .values()
+            .map(|entry| entry.len as usize)
+            .sum();
+        self.payload_offset - 2 - live
+    }
+
+    /// Drop payload bytes no longer referenced by any field
+    ///
+    /// Live payloads are rewritten in field index order.
+    pub fn compact(&mut self) {
+        if self.unreferenced_bytes() > 0 {
+            self.compact_payload();
+        }
+    }
+
+    /// Rewrite the payload so set fields appear in index order with no dead bytes between them
+    fn compact_payload(&mut self) {
+        let mut payload = Vec::with_capacity(self.payload_offset);
+        payload.extend_from_slice(&[0, 0]);
+
+        for entry in self.field_entries.values_mut() {
+            let start = entry.offset as usize;
+            let end = start + entry.len as usize;
+            entry.offset = payload.len() as u32;
+            payload.extend_from_slice(&self.buffer[start..end]);
+        }
+
+        self.payload_offset = payload.len();
+        self.buffer = payload;
     }
 
     /// Clear a field entry (used for optional setters)
@@ -246,6 +649,10 @@ impl MessageBuilder {
             "f32" => PrimitiveType::F32 as u8,
             "f64" => PrimitiveType::F64 as u8,
             "bool" => PrimitiveType::Bool as u8,
+            name if name == core::any::type_name::<Timestamp>() => PrimitiveType::Timestamp as u8,
+            name if name == core::any::type_name::<Duration>() => PrimitiveType::Duration as u8,
+            name if name == core::any::type_name::<Uuid>() => PrimitiveType::Uuid as u8,
+            name if name == core::any::type_name::<Decimal>() => PrimitiveType::Decimal as u8,
             _ => return Err(Error::InvalidFieldType),
         };
         Ok(type_id)
@@ -253,30 +660,44 @@ impl MessageBuilder {
 
     /// Finish building and return the serialized message
     pub fn finish(mut self) -> Vec<u8> {
-        let field_count = self.field_entries_count();
-
-        // Write field count
-        Endian::Little.write_u16(field_count, &mut self.buffer, 0);
+        let layout = if self.canonical {
+            self.compact_payload();
+            FieldTableLayout::Auto
+        } else {
+            if self.auto_compact {
+                self.compact();
+            }
+            self.layout
+        };
 
-        // Reserve space for field table
-        let field_table_size = field_count as usize * FIELD_ENTRY_SIZE;
-        let current_payload_offset = self.payload_offset;
+        let field_count = self.field_entries_count();
+        let set_fields = self.field_entries.len();
+        let layout = layout.resolve(field_count, set_fields);
+        let header_size = layout.table_size(field_count, set_fields);
 
-        // Shift payload to make room for field table
-        self.buffer
-            .resize(current_payload_offset + field_table_size, 0);
+        // Shift payload to make room for the header and field table
+        let payload_len = self.payload_offset - 2;
+        self.buffer.resize(header_size + payload_len, 0);
+        self.buffer.copy_within(2..2 + payload_len, header_size);
 
-        // Move payload data
-        for i in (0..(current_payload_offset - 2)).rev() {
-            self.buffer[2 + field_table_size + i] = self.buffer[2 + i];
+        // Update field entry offsets to account for the field table
+        for entry in self.field_entries.values_mut() {
+            entry.offset = entry.offset - 2 + header_size as u32;
         }
 
-        // Update field entry offsets to account for field table
-        for entry in self.field_entries.values_mut() {
-            entry.offset += field_table_size as u32;
+        match layout {
+            FieldTableLayout::Bitmap => self.write_bitmap_table(field_count),
+            FieldTableLayout::Sparse => self.write_sparse_table(field_count),
+            _ => self.write_dense_table(field_count),
         }
 
-        // Write field table
+        self.buffer
+    }
+
+    /// Write a field count followed by an entry for every index
+    fn write_dense_table(&mut self, field_count: u16) {
+        Endian::Little.write_u16(field_count, &mut self.buffer, 0);
+
         let mut field_table_offset = 2;
         for field_index in 0..field_count {
             if let Some(entry) = self.field_entries.get(&field_index) {
@@ -288,12 +709,40 @@ impl MessageBuilder {
             }
             field_table_offset += FIELD_ENTRY_SIZE;
         }
+    }
 
-        // Trim buffer to actual size
-        self.buffer
-            .truncate(2 + field_table_size + (current_payload_offset - 2));
+    /// Write a presence bitmap followed by entries for set fields only
+    fn write_bitmap_table(&mut self, field_count: u16) {
+        layout::write_extended_header(&mut self.buffer, FieldTableLayout::Bitmap, field_count);
 
-        self.buffer
+        let bitmap_len = layout::bitmap_len(field_count as usize);
+        let bitmap = &mut self.buffer[EXTENDED_HEADER_SIZE..EXTENDED_HEADER_SIZE + bitmap_len];
+        bitmap.fill(0);
+        for field_index in self.field_entries.keys() {
+            layout::set_bit(bitmap, *field_index as usize);
+        }
+
+        let mut field_table_offset = EXTENDED_HEADER_SIZE + bitmap_len;
+        for entry in self.field_entries.values() {
+            self.buffer[field_table_offset] = entry.type_id;
+            Endian::Little.write_u32(entry.offset, &mut self.buffer, field_table_offset + 1);
+            field_table_offset += FIELD_ENTRY_SIZE;
+        }
+    }
+
+    /// Write an entry count followed by index-sorted entries for set fields only
+    fn write_sparse_table(&mut self, field_count: u16) {
+        layout::write_extended_header(&mut self.buffer, FieldTableLayout::Sparse, field_count);
+        let entry_count = self.field_entries.len() as u16;
+        Endian::Little.write_u16(entry_count, &mut self.buffer, EXTENDED_HEADER_SIZE);
+
+        let mut field_table_offset = EXTENDED_HEADER_SIZE + 2;
+        for (field_index, entry) in &self.field_entries {
+            Endian::Little.write_u16(*field_index, &mut self.buffer, field_table_offset);
+            self.buffer[field_table_offset + 2] = entry.type_id;
+            Endian::Little.write_u32(entry.offset, &mut self.buffer, field_table_offset + 3);
+            field_table_offset += SPARSE_ENTRY_SIZE;
+        }
     }
 }
 
@@ -349,10 +798,9 @@ impl<T> Default for VectorBuilder<T> {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::primitives::Endian;
 
-    #[cfg(feature = "std")]
-    use std::println;
+    #[cfg(not(feature = "std"))]
+    use alloc::vec;
     #[cfg(feature = "std")]
     use std::vec;
 
@@ -400,6 +848,19 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_builder_has_field() -> Result<()> {
+        let mut builder = MessageBuilder::new();
+        builder.set_scalar(1, 7u8)?;
+        assert!(builder.has_field(1));
+        assert!(!builder.has_field(0));
+
+        builder.clear_field(1)?;
+        assert!(!builder.has_field(1));
+
+        Ok(())
+    }
+
     #[test]
     fn test_builder_multiple_fields() -> Result<()> {
         let mut builder = MessageBuilder::new();
@@ -407,9 +868,6 @@ mod tests {
         builder.set_scalar(1, 100u32)?;
         let data = builder.finish();
 
-        println!("Buffer length: {}", data.len());
-        println!("Buffer: {:?}", data);
-
         // Expected: 2 (field count) + 10 (field table: 2 fields * 5 bytes) + 8 (u64) + 4 (u32) = 24
         assert_eq!(data.len(), 24);
 
@@ -422,12 +880,178 @@ mod tests {
         builder.set_string(0, "hello")?;
         let data = builder.finish();
 
-        println!("String buffer length: {}", data.len());
-        println!("String buffer: {:?}", data);
-
         // Expected: 2 (field count) + 5 (field table) + 4 (string length) + 5 (string bytes) = 16
         assert_eq!(data.len(), 16);
 
         Ok(())
     }
+
+    #[test]
+    fn test_builder_bit_vector() -> Result<()> {
+        let flags = [true, false, true, true, false, false, false, false, true];
+        let mut builder = MessageBuilder::new();
+        builder.set_bit_vector(0, &flags)?;
+        let data = builder.finish();
+
+        // Expected: 2 (field count) + 5 (field table) + 4 (bit count) + 2 (packed bits) = 13
+        assert_eq!(data.len(), 13);
+        assert_eq!(&data[11..], &[0b0000_1101, 0b0000_0001]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_builder_sparse_for_high_index() -> Result<()> {
+        let mut builder = MessageBuilder::new();
+        builder.set_scalar(60000, 1u8)?;
+        let data = builder.finish();
+
+        // Expected: 5 (extended header) + 2 (entry count) + 7 (one entry) + 1 (payload) = 15
+        assert_eq!(data.len(), 15);
+
+        let reader = crate::reader::MessageReader::new(&data)?;
+        assert_eq!(reader.layout(), FieldTableLayout::Sparse);
+        assert_eq!(reader.field_count(), 60001);
+        assert_eq!(reader.get_scalar::<u8>(60000)?, 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_builder_forced_dense_layout() -> Result<()> {
+        let mut builder = MessageBuilder::with_layout(FieldTableLayout::Dense);
+        builder.set_scalar(9, 1u8)?;
+        let data = builder.finish();
+
+        // Expected: 2 (field count) + 50 (field table) + 1 (payload) = 53
+        assert_eq!(data.len(), 53);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_builder_bitmap_layout() -> Result<()> {
+        let mut builder = MessageBuilder::with_layout(FieldTableLayout::Bitmap);
+        builder.set_scalar(2, 7u8)?;
+        builder.set_scalar(20, 9u8)?;
+        let data = builder.finish();
+
+        // Expected: 5 (extended header) + 3 (bitmap) + 10 (two entries) + 2 (payload) = 20
+        assert_eq!(data.len(), 20);
+
+        let reader = crate::reader::MessageReader::new(&data)?;
+        assert_eq!(reader.field_count(), 21);
+        assert_eq!(reader.get_scalar::<u8>(2)?, 7);
+        assert_eq!(reader.get_scalar::<u8>(20)?, 9);
+        assert!(!reader.has_field(3)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_builder_rejects_reserved_index() {
+        let mut builder = MessageBuilder::new();
+        assert!(builder.set_scalar(MAX_FIELDS - 1, 1u8).is_ok());
+        assert!(matches!(
+            builder.set_scalar(MAX_FIELDS, 1u8),
+            Err(Error::OutOfBounds)
+        ));
+    }
+
+    #[test]
+    fn test_builder_union() -> Result<()> {
+        let mut variant = MessageBuilder::new();
+        variant.set_scalar(0, 7u8)?;
+        let variant_data = variant.finish();
+
+        let mut builder = MessageBuilder::new();
+        builder.set_union(0, 1, &variant_data)?;
+        builder.set_union(0, 2, &variant_data)?;
+        let data = builder.finish();
+
+        let reader = crate::reader::MessageReader::new(&data)?;
+        let (discriminant, message) = reader.get_union(0)?;
+        assert_eq!(discriminant, 2);
+        assert_eq!(message.get_scalar::<u8>(0)?, 7);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_builder_compact_drops_orphaned_payload() -> Result<()> {
+        let mut builder = MessageBuilder::new();
+        builder.set_string(0, "first draft")?;
+        builder.set_bytes(1, b"scratch")?;
+        builder.set_string(0, "final")?;
+        builder.clear_field(1)?;
+        builder.set_scalar(2, 3u32)?;
+        assert_eq!(builder.unreferenced_bytes(), 15 + 11);
+
+        builder.compact();
+        assert_eq!(builder.unreferenced_bytes(), 0);
+
+        // The builder keeps working after compaction
+        builder.set_scalar(3, 4u8)?;
+        let data = builder.finish();
+        assert_eq!(data.len(), 2 + 4 * FIELD_ENTRY_SIZE + 9 + 4 + 1);
+
+        let reader = MessageReader::new(&data)?;
+        assert_eq!(reader.get_string(0)?, "final");
+        assert!(!reader.has_field(1)?);
+        assert_eq!(reader.get_scalar::<u32>(2)?, 3);
+        assert_eq!(reader.get_scalar::<u8>(3)?, 4);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_builder_auto_compact_on_finish() -> Result<()> {
+        let build = |auto_compact: bool| -> Result<Vec<u8>> {
+            let mut builder = MessageBuilder::new();
+            builder.set_auto_compact(auto_compact);
+            for i in 0..10u32 {
+                builder.set_scalar(0, i)?;
+            }
+            Ok(builder.finish())
+        };
+
+        let bloated = build(false)?;
+        let compacted = build(true)?;
+        assert_eq!(bloated.len(), 2 + FIELD_ENTRY_SIZE + 40);
+        assert_eq!(compacted.len(), 2 + FIELD_ENTRY_SIZE + 4);
+        assert_eq!(MessageReader::new(&compacted)?.get_scalar::<u32>(0)?, 9);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_builder_copy_unknown_fields() -> Result<()> {
+        // A newer writer adds fields 2 and 5
+        let mut newer = MessageBuilder::new();
+        newer.set_scalar(0, 1u32)?;
+        newer.set_string(1, "known")?;
+        newer.set_string(2, "added later")?;
+        newer.set_vector(5, &[7u16, 8])?;
+        newer.set_scalar(6, 9u8)?;
+        let data = newer.finish();
+        let reader = MessageReader::new(&data)?;
+
+        // An older middleman only knows fields 0, 1 and 6, and rewrites field 0
+        let mut builder = MessageBuilder::new();
+        builder.set_scalar(0, 2u32)?;
+        builder.copy_field(&reader, 1)?;
+        builder.copy_field(&reader, 9)?;
+        builder.copy_unknown_fields(&reader, &[0, 1, 6])?;
+        let data = builder.finish();
+
+        let reader = MessageReader::new(&data)?;
+        assert_eq!(reader.get_scalar::<u32>(0)?, 2);
+        assert_eq!(reader.get_string(1)?, "known");
+        assert_eq!(reader.get_string(2)?, "added later");
+        assert!(!reader.has_field(3)?);
+        assert_eq!(reader.get_vector::<u16>(5)?.collect()?, [7, 8]);
+        assert!(!reader.has_field(6)?);
+
+        Ok(())
+    }
 }
@@ -0,0 +1,241 @@
+//! Canonical re-encoding and content hashing
+
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::vec::Vec;
+
+use crate::{
+    any::AnyReader,
+    builder::MessageBuilder,
+    constants::MAX_DEPTH,
+    errors::{Error, Result},
+    primitives::{Endian, PrimitiveType},
+    reader::MessageReader,
+};
+
+/// FNV-1a 64-bit offset basis
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+
+/// FNV-1a 64-bit prime
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+impl<'a> MessageReader<'a> {
+    /// Re-encode this message in canonical form
+    ///
+    /// The result is what [`MessageBuilder::canonical`] would produce for the same content:
+    /// payloads in field index order with no dead bytes, the automatic field table layout,
+    /// canonical nested messages, bools stored as `0`/`1`, and unused bit-vector bits cleared.
+    ///
+    /// Returns [`Error::TooDeep`] for messages nested more than
+    /// [`MAX_DEPTH`](crate::constants::MAX_DEPTH) levels deep.
+    pub fn canonicalize(&self) -> Result<Vec<u8>> {
+        self.canonicalize_at(0)
+    }
+
+    /// Re-encode this message, nested `depth` levels below the message being canonicalized
+    fn canonicalize_at(&self, depth: usize) -> Result<Vec<u8>> {
+        if depth > MAX_DEPTH {
+            return Err(Error::TooDeep);
+        }
+        let nested = |message: &[u8]| MessageReader::new(message)?.canonicalize_at(depth + 1);
+
+        let mut builder = MessageBuilder::canonical();
+
+        for field_index in 0..self.field_count() {
+            let (field_type, payload) = match self.field_bytes(field_index)? {
+                Some(field) => field,
+                None => continue,
+            };
+
+            match field_type {
+                PrimitiveType::Message => {
+                    builder.write_message(field_index, &nested(&payload[4..])?)?
+                }
+                PrimitiveType::Union => {
+                    let discriminant = Endian::Little.read_u16(payload, 0);
+                    builder.write_union(field_index, discriminant, &nested(&payload[6..])?)?;
+                }
+                PrimitiveType::Any => {
+                    let any = AnyReader::parse(payload)?;
+                    builder.write_any(field_index, any.type_name(), &nested(any.as_bytes())?)?;
+                }
+                PrimitiveType::Bool => builder.set_scalar(field_index, payload[0] != 0)?,
+                PrimitiveType::TypedVector if payload[4] == PrimitiveType::Bool as u8 => {
+                    let mut payload = payload.to_vec();
+                    for byte in &mut payload[5..] {
+                        *byte = (*byte != 0) as u8;
+                    }
+                    builder.set_raw(field_index, field_type, &payload)?;
+                }
+                PrimitiveType::BitVector => {
+                    let mut payload = payload.to_vec();
+                    let count = Endian::Little.read_u32(&payload, 0) as usize;
+                    if count % 8 != 0 {
+                        if let Some(last) = payload.last_mut() {
+                            *last &= (1u8 << (count % 8)) - 1;
+                        }
+                    }
+                    builder.set_raw(field_index, field_type, &payload)?;
+                }
+                PrimitiveType::Unset => return Err(Error::InvalidFieldType),
+                _ => builder.set_raw(field_index, field_type, payload)?,
+            }
+        }
+
+        Ok(builder.finish())
+    }
+
+    /// Compute a stable hash of this message's content
+    ///
+    /// This is the 64-bit FNV-1a hash of [`canonicalize`](Self::canonicalize), so messages
+    /// with the same logical content hash the same no matter how they were built.
+    pub fn content_hash(&self) -> Result<u64> {
+        Ok(fnv1a_64(&self.canonicalize()?))
+    }
+}
+
+/// Hash bytes with 64-bit FNV-1a
+fn fnv1a_64(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(FNV_OFFSET_BASIS, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(FNV_PRIME)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FieldTableLayout;
+
+    #[test]
+    fn test_fnv1a_64() {
+        assert_eq!(fnv1a_64(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv1a_64(b"a"), 0xaf63_dc4c_8601_ec8c);
+    }
+
+    #[test]
+    fn test_canonical_ignores_set_order_and_dead_bytes() -> Result<()> {
+        let mut first = MessageBuilder::new();
+        first.set_scalar(0, 7u32)?;
+        first.set_string(1, "hello")?;
+        first.set_vector(2, &[1u16, 2, 3])?;
+        let first = first.finish();
+
+        let mut second = MessageBuilder::with_layout(FieldTableLayout::Bitmap);
+        second.set_string(1, "stale")?;
+        second.set_vector(2, &[1u16, 2, 3])?;
+        second.set_string(1, "hello")?;
+        second.set_scalar(0, 7u32)?;
+        let second = second.finish();
+
+        assert_ne!(first, second);
+
+        let first = MessageReader::new(&first)?;
+        let second = MessageReader::new(&second)?;
+        assert_eq!(first.canonicalize()?, second.canonicalize()?);
+        assert_eq!(first.content_hash()?, second.content_hash()?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_canonical_builder_matches_canonicalize() -> Result<()> {
+        let mut builder = MessageBuilder::canonical();
+        builder.set_bytes(3, b"tail")?;
+        builder.set_scalar(0, true)?;
+        builder.set_bytes(3, b"end")?;
+        builder.set_bit_vector(1, &[true, false, true])?;
+        let data = builder.finish();
+
+        let reader = MessageReader::new(&data)?;
+        assert_eq!(reader.canonicalize()?, data);
+        assert_eq!(reader.get_bytes(3)?, b"end");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_canonical_nested_messages() -> Result<()> {
+        let mut inner_a = MessageBuilder::new();
+        inner_a.set_scalar(1, 2u8)?;
+        inner_a.set_scalar(0, 1u8)?;
+        let inner_a = inner_a.finish();
+
+        let mut inner_b = MessageBuilder::new();
+        inner_b.set_scalar(0, 1u8)?;
+        inner_b.set_scalar(1, 2u8)?;
+        let inner_b = inner_b.finish();
+        assert_ne!(inner_a, inner_b);
+
+        let mut outer_a = MessageBuilder::new();
+        outer_a.set_message(0, &inner_a)?;
+        outer_a.set_union(1, 4, &inner_a)?;
+        let outer_a = outer_a.finish();
+
+        let mut outer_b = MessageBuilder::new();
+        outer_b.set_message(0, &inner_b)?;
+        outer_b.set_union(1, 4, &inner_b)?;
+        let outer_b = outer_b.finish();
+
+        let reader_a = MessageReader::new(&outer_a)?;
+        let reader_b = MessageReader::new(&outer_b)?;
+        assert_eq!(reader_a.content_hash()?, reader_b.content_hash()?);
+        assert_eq!(
+            reader_a.get_message(0)?.content_hash()?,
+            reader_b.get_message(0)?.content_hash()?
+        );
+
+        // Different content hashes differently
+        let mut other = MessageBuilder::new();
+        other.set_message(0, &inner_a)?;
+        let other = other.finish();
+        assert_ne!(
+            MessageReader::new(&other)?.content_hash()?,
+            reader_a.content_hash()?
+        );
+
+        Ok(())
+    }
+
+    /// A message with `levels` messages nested inside it, one per level
+    fn nested(levels: usize) -> Result<Vec<u8>> {
+        let mut data = MessageBuilder::new().finish();
+        for level in 0..levels {
+            let mut builder = MessageBuilder::new();
+            if level % 2 == 0 {
+                builder.set_message(0, &data)?;
+            } else {
+                builder.set_union(0, 1, &data)?;
+            }
+            data = builder.finish();
+        }
+        Ok(data)
+    }
+
+    #[test]
+    fn test_canonical_depth_limit() -> Result<()> {
+        let data = nested(MAX_DEPTH)?;
+        assert_eq!(MessageReader::new(&data)?.canonicalize()?, data);
+
+        let data = nested(MAX_DEPTH + 1)?;
+        let reader = MessageReader::new(&data)?;
+        assert_eq!(reader.canonicalize(), Err(Error::TooDeep));
+        assert_eq!(reader.content_hash(), Err(Error::TooDeep));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_canonical_normalizes_bools() -> Result<()> {
+        let mut builder = MessageBuilder::new();
+        builder.set_scalar(0, true)?;
+        let mut data = builder.finish();
+        let canonical = MessageReader::new(&data)?.canonicalize()?;
+
+        // Any non-zero byte reads as true, so it must canonicalize the same way
+        *data.last_mut().unwrap() = 0x7F;
+        assert_eq!(MessageReader::new(&data)?.canonicalize()?, canonical);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,95 @@
+//! Static schema descriptors emitted by generated code
+
+/// Describes a message type from a schema
+#[derive(Debug)]
+pub struct MessageDescriptor {
+    /// Message name as written in the schema
+    pub name: &'static str,
+    /// Name qualified by the schema's package, as stored in `any` fields
+    pub type_name: &'static str,
+    /// Fields in declaration order
+    pub fields: &'static [FieldDescriptor],
+}
+
+/// Describes one field of a message
+#[derive(Debug)]
+pub struct FieldDescriptor {
+    /// Field name as written in the schema
+    pub name: &'static str,
+    /// Field index on the wire
+    pub index: u16,
+    /// Message type of this field (or of its elements, for vectors)
+    pub message: Option<&'static MessageDescriptor>,
+    /// Variants, if this field is a union
+    pub variants: &'static [VariantDescriptor],
+}
+
+/// Describes one variant of a union field
+#[derive(Debug)]
+pub struct VariantDescriptor {
+    /// Variant name as written in the schema
+    pub name: &'static str,
+    /// Discriminant written on the wire
+    pub discriminant: u16,
+    /// Message type carried by this variant
+    pub message: &'static MessageDescriptor,
+}
+
+impl MessageDescriptor {
+    /// Look up a field by its wire index
+    pub fn field(&self, index: u16) -> Option<&'static FieldDescriptor> {
+        self.fields.iter().find(|field| field.index == index)
+    }
+
+    /// Look up a field by name
+    pub fn field_by_name(&self, name: &str) -> Option<&'static FieldDescriptor> {
+        self.fields.iter().find(|field| field.name == name)
+    }
+}
+
+impl FieldDescriptor {
+    /// Look up a union variant by discriminant
+    pub fn variant(&self, discriminant: u16) -> Option<&'static VariantDescriptor> {
+        self.variants
+            .iter()
+            .find(|variant| variant.discriminant == discriminant)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static POINT: MessageDescriptor = MessageDescriptor {
+        name: "Point",
+        type_name: "geo.Point",
+        fields: &[
+            FieldDescriptor {
+                name: "x",
+                index: 0,
+                message: None,
+                variants: &[],
+            },
+            FieldDescriptor {
+                name: "next",
+                index: 1,
+                message: Some(&POINT),
+                variants: &[],
+            },
+        ],
+    };
+
+    #[test]
+    fn test_descriptor_lookup() {
+        assert_eq!(POINT.field(0).map(|field| field.name), Some("x"));
+        assert_eq!(
+            POINT.field_by_name("next").map(|field| field.index),
+            Some(1)
+        );
+        assert!(POINT.field(2).is_none());
+
+        // Self-referencing messages resolve through the static
+        let next = POINT.field(1).and_then(|field| field.message).unwrap();
+        assert_eq!(next.name, "Point");
+    }
+}
@@ -0,0 +1,759 @@
+//! Structural comparison of encoded messages
+//!
+//! [`diff`] walks two messages field by field and reports every field that was added,
+//! removed, or changed, recursing into nested messages, union variants, and vectors.
+//! [`diff_with_schema`] does the same but names fields using a [`MessageDescriptor`].
+//!
+//! ```rust
+//! use zeroproto::{diff, MessageBuilder, MessageReader};
+//!
+//! let mut old = MessageBuilder::new();
+//! old.set_scalar(0, 1u32)?;
+//! let old = old.finish();
+//!
+//! let mut new = MessageBuilder::new();
+//! new.set_scalar(0, 2u32)?;
+//! new.set_string(1, "hi")?;
+//! let new = new.finish();
+//!
+//! let changes = diff::diff(&MessageReader::new(&old)?, &MessageReader::new(&new)?)?;
+//! assert_eq!(changes.to_string(), "#0: changed from 1 to 2\n#1: added \"hi\"");
+//! # Ok::<(), zeroproto::Error>(())
+//! ```
+
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::vec::Vec;
+
+use core::fmt;
+
+use crate::{
+    any::AnyReader,
+    constants::MAX_DEPTH,
+    descriptor::{FieldDescriptor, MessageDescriptor},
+    errors::{Error, Result},
+    layout,
+    primitives::{Endian, PrimitiveType},
+    reader::{vector_layout, MessageReader},
+    well_known::{Decimal, Duration, Timestamp, Uuid},
+    ZpRead,
+};
+
+/// Compare two messages without schema information
+///
+/// Returns [`Error::TooDeep`] for messages nested more than
+/// [`MAX_DEPTH`] levels deep.
+pub fn diff<'a>(old: &MessageReader<'a>, new: &MessageReader<'a>) -> Result<Diff<'a>> {
+    let mut differ = Differ::default();
+    differ.messages(old, new, None)?;
+    Ok(Diff {
+        changes: differ.changes,
+    })
+}
+
+/// Compare two messages, naming fields from the given schema descriptor
+pub fn diff_with_schema<'a>(
+    old: &MessageReader<'a>,
+    new: &MessageReader<'a>,
+    schema: &'static MessageDescriptor,
+) -> Result<Diff<'a>> {
+    let mut differ = Differ::default();
+    differ.messages(old, new, Some(schema))?;
+    Ok(Diff {
+        changes: differ.changes,
+    })
+}
+
+/// The list of changes between two messages
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diff<'a> {
+    changes: Vec<Change<'a>>,
+}
+
+impl<'a> Diff<'a> {
+    /// Check whether the messages have the same content
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Get the number of changes
+    pub fn len(&self) -> usize {
+        self.changes.len()
+    }
+
+    /// Get the changes in field order
+    pub fn changes(&self) -> &[Change<'a>] {
+        &self.changes
+    }
+
+    /// Get an iterator over the changes
+    pub fn iter(&self) -> core::slice::Iter<'_, Change<'a>> {
+        self.changes.iter()
+    }
+}
+
+impl<'a> IntoIterator for Diff<'a> {
+    type Item = Change<'a>;
+    type IntoIter = <Vec<Change<'a>> as IntoIterator>::IntoIter;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.changes.into_iter()
+    }
+}
+
+impl fmt::Display for Diff<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.changes.is_empty() {
+            return write!(f, "no changes");
+        }
+
+        for (i, change) in self.changes.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", change)?;
+        }
+        Ok(())
+    }
+}
+
+/// A single difference between two messages
+#[derive(Debug, Clone, PartialEq)]
+pub struct Change<'a> {
+    /// Where the change is, from the outermost message inward
+    pub path: Vec<PathSegment>,
+    /// What changed
+    pub kind: ChangeKind<'a>,
+}
+
+impl fmt::Display for Change<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, segment) in self.path.iter().enumerate() {
+            if i > 0 && matches!(segment, PathSegment::Field { .. }) {
+                write!(f, ".")?;
+            }
+            write!(f, "{}", segment)?;
+        }
+
+        match &self.kind {
+            ChangeKind::Added(value) => write!(f, ": added {}", value),
+            ChangeKind::Removed(value) => write!(f, ": removed {}", value),
+            ChangeKind::ValueChanged { old, new } => {
+                write!(f, ": changed from {} to {}", old, new)
+            }
+            ChangeKind::TypeChanged { old, new } => {
+                write!(f, ": type changed from {} to {}", old, new)
+            }
+        }
+    }
+}
+
+/// One step of the path to a change
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PathSegment {
+    /// A field, with its schema name when known
+    Field {
+        /// Field index on the wire
+        index: u16,
+        /// Field name from the schema
+        name: Option<&'static str>,
+    },
+    /// An element of a vector
+    Element(usize),
+    /// The active variant of a union, with its schema name when known
+    Variant {
+        /// Variant discriminant on the wire
+        discriminant: u16,
+        /// Variant name from the schema
+        name: Option<&'static str>,
+    },
+}
+
+impl fmt::Display for PathSegment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PathSegment::Field {
+                name: Some(name), ..
+            } => write!(f, "{}", name),
+            PathSegment::Field { index, name: None } => write!(f, "#{}", index),
+            PathSegment::Element(index) => write!(f, "[{}]", index),
+            PathSegment::Variant {
+                name: Some(name), ..
+            } => write!(f, "({})", name),
+            PathSegment::Variant {
+                discriminant,
+                name: None,
+            } => write!(f, "(#{})", discriminant),
+        }
+    }
+}
+
+/// The kind of a change
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChangeKind<'a> {
+    /// Present only in the new message
+    Added(Value<'a>),
+    /// Present only in the old message
+    Removed(Value<'a>),
+    /// Present in both with the same type but a different value
+    ValueChanged {
+        /// Value in the old message
+        old: Value<'a>,
+        /// Value in the new message
+        new: Value<'a>,
+    },
+    /// Present in both with different types
+    ///
+    /// For vectors, these are the element types.
+    TypeChanged {
+        /// Type in the old message
+        old: PrimitiveType,
+        /// Type in the new message
+        new: PrimitiveType,
+    },
+}
+
+/// A decoded field value, borrowed from the message buffer
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Value<'a> {
+    /// An unsigned integer of any width
+    Unsigned(u64),
+    /// A signed integer of any width
+    Signed(i64),
+    /// A 32-bit float
+    F32(f32),
+    /// A 64-bit float
+    F64(f64),
+    /// A bool
+    Bool(bool),
+    /// A string
+    String(&'a str),
+    /// A byte slice
+    Bytes(&'a [u8]),
+    /// A nested message, summarized
+    Message,
+    /// A vector, summarized by element type and length
+    Vector {
+        /// Element type
+        element_type: PrimitiveType,
+        /// Number of elements
+        len: usize,
+    },
+    /// A bit-packed bool vector, summarized by length
+    BitVector {
+        /// Number of bools
+        len: usize,
+    },
+    /// A union, summarized by its active variant
+    Union {
+        /// Discriminant of the active variant
+        discriminant: u16,
+    },
+    /// A fixed-layout struct, as its raw bytes
+    Struct(&'a [u8]),
+    /// An `any` field, summarized by the type name of its message
+    Any {
+        /// Fully qualified type name of the stored message
+        type_name: &'a str,
+    },
+    /// A timestamp
+    Timestamp(Timestamp),
+    /// A duration
+    Duration(Duration),
+    /// A UUID
+    Uuid(Uuid),
+    /// A decimal
+    Decimal(Decimal),
+}
+
+impl<'a> Value<'a> {
+    /// Decode a field payload as returned by `MessageReader::field_bytes`
+    fn decode(field_type: PrimitiveType, payload: &'a [u8]) -> Result<Self> {
+        let le = Endian::Little;
+        let value = match field_type {
+            PrimitiveType::U8 => Value::Unsigned(le.read_u8(payload, 0) as u64),
+            PrimitiveType::U16 => Value::Unsigned(le.read_u16(payload, 0) as u64),
+            PrimitiveType::U32 => Value::Unsigned(le.read_u32(payload, 0) as u64),
+            PrimitiveType::U64 => Value::Unsigned(le.read_u64(payload, 0)),
+            PrimitiveType::I8 => Value::Signed(le.read_i8(payload, 0) as i64),
+            PrimitiveType::I16 => Value::Signed(le.read_i16(payload, 0) as i64),
+            PrimitiveType::I32 => Value::Signed(le.read_i32(payload, 0) as i64),
+            PrimitiveType::I64 => Value::Signed(le.read_i64(payload, 0)),
+            PrimitiveType::F32 => Value::F32(le.read_f32(payload, 0)),
+            PrimitiveType::F64 => Value::F64(le.read_f64(payload, 0)),
+            PrimitiveType::Bool => Value::Bool(le.read_bool(payload, 0)),
+            PrimitiveType::String => Value::String(core::str::from_utf8(&payload[4..])?),
+            PrimitiveType::Bytes => Value::Bytes(&payload[4..]),
+            PrimitiveType::Message => Value::Message,
+            PrimitiveType::TypedVector => Value::Vector {
+                element_type: element_type(payload)?,
+                len: le.read_u32(payload, 0) as usize,
+            },
+            PrimitiveType::BitVector => Value::BitVector {
+                len: le.read_u32(payload, 0) as usize,
+            },
+            PrimitiveType::Union => Value::Union {
+                discriminant: le.read_u16(payload, 0),
+            },
+            PrimitiveType::Struct => Value::Struct(&payload[2..]),
+            PrimitiveType::Any => Value::Any {
+                type_name: AnyReader::parse(payload)?.type_name(),
+            },
+            PrimitiveType::Timestamp => Value::Timestamp(Timestamp::read(payload, 0)?),
+            PrimitiveType::Duration => Value::Duration(Duration::read(payload, 0)?),
+            PrimitiveType::Uuid => Value::Uuid(Uuid::read(payload, 0)?),
+            PrimitiveType::Decimal => Value::Decimal(Decimal::read(payload, 0)?),
+            PrimitiveType::Vector | PrimitiveType::Unset => return Err(Error::InvalidFieldType),
+        };
+        Ok(value)
+    }
+}
+
+impl fmt::Display for Value<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Unsigned(value) => write!(f, "{}", value),
+            Value::Signed(value) => write!(f, "{}", value),
+            Value::F32(value) => write!(f, "{:?}", value),
+            Value::F64(value) => write!(f, "{:?}", value),
+            Value::Bool(value) => write!(f, "{}", value),
+            Value::String(value) => write!(f, "{:?}", value),
+            Value::Bytes(value) => write!(f, "{:02x?}", value),
+            Value::Message => write!(f, "<message>"),
+            Value::Vector { element_type, len } => {
+                write!(f, "<vector of {} {}>", len, element_type)
+            }
+            Value::BitVector { len } => write!(f, "<{} packed bools>", len),
+            Value::Union { discriminant } => write!(f, "<union variant #{}>", discriminant),
+            Value::Struct(bytes) => write!(f, "<struct {:02x?}>", bytes),
+            Value::Any { type_name } => write!(f, "<any {}>", type_name),
+            Value::Timestamp(value) => write!(f, "{:?}", value),
+            Value::Duration(value) => write!(f, "{:?}", value),
+            Value::Uuid(value) => write!(f, "{}", value),
+            Value::Decimal(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+/// Read the element type of a vector payload
+fn element_type(payload: &[u8]) -> Result<PrimitiveType> {
+    PrimitiveType::from_u8(payload[4]).ok_or(Error::InvalidFieldType)
+}
+
+/// A field payload with its type
+type Field<'a> = Option<(PrimitiveType, &'a [u8])>;
+
+/// Walks two messages and collects the changes between them
+#[derive(Default)]
+struct Differ<'a> {
+    changes: Vec<Change<'a>>,
+    path: Vec<PathSegment>,
+    /// Number of messages entered so far on the way to the current one
+    depth: usize,
+}
+
+impl<'a> Differ<'a> {
+    /// Record a change at the current path
+    fn push(&mut self, kind: ChangeKind<'a>) {
+        self.changes.push(Change {
+            path: self.path.clone(),
+            kind,
+        });
+    }
+
+    /// Compare every field of two messages
+    fn messages(
+        &mut self,
+        old: &MessageReader<'a>,
+        new: &MessageReader<'a>,
+        schema: Option<&'static MessageDescriptor>,
+    ) -> Result<()> {
+        if self.depth > MAX_DEPTH {
+            return Err(Error::TooDeep);
+        }
+        self.depth += 1;
+        for index in 0..old.field_count().max(new.field_count()) {
+            let field = schema.and_then(|schema| schema.field(index));
+            self.path.push(PathSegment::Field {
+                index,
+                name: field.map(|field| field.name),
+            });
+            let result = self.field(old.field_bytes(index)?, new.field_bytes(index)?, field);
+            self.path.pop();
+            result?;
+        }
+        self.depth -= 1;
+        Ok(())
+    }
+
+    /// Compare one field (or vector element) of two messages
+    fn field(
+        &mut self,
+        old: Field<'a>,
+        new: Field<'a>,
+        schema: Option<&'static FieldDescriptor>,
+    ) -> Result<()> {
+        let ((old_type, old_bytes), (new_type, new_bytes)) = match (old, new) {
+            (None, None) => return Ok(()),
+            (None, Some((field_type, payload))) => {
+                self.push(ChangeKind::Added(Value::decode(field_type, payload)?));
+                return Ok(());
+            }
+            (Some((field_type, payload)), None) => {
+                self.push(ChangeKind::Removed(Value::decode(field_type, payload)?));
+                return Ok(());
+            }
+            (Some(old), Some(new)) => (old, new),
+        };
+
+        if old_type != new_type {
+            self.push(ChangeKind::TypeChanged {
+                old: old_type,
+                new: new_type,
+            });
+            return Ok(());
+        }
+
+        if old_bytes == new_bytes {
+            return Ok(());
+        }
+
+        match old_type {
+            PrimitiveType::Message => {
+                let old = MessageReader::new(&old_bytes[4..])?;
+                let new = MessageReader::new(&new_bytes[4..])?;
+                self.messages(&old, &new, schema.and_then(|field| field.message))
+            }
+            PrimitiveType::Union => {
+                let old_value = Value::decode(old_type, old_bytes)?;
+                let new_value = Value::decode(new_type, new_bytes)?;
+                if old_value != new_value {
+                    self.push(ChangeKind::ValueChanged {
+                        old: old_value,
+                        new: new_value,
+                    });
+                    return Ok(());
+                }
+
+                let discriminant = Endian::Little.read_u16(old_bytes, 0);
+                let variant = schema.and_then(|field| field.variant(discriminant));
+                self.path.push(PathSegment::Variant {
+                    discriminant,
+                    name: variant.map(|variant| variant.name),
+                });
+                let old = MessageReader::new(&old_bytes[6..])?;
+                let new = MessageReader::new(&new_bytes[6..])?;
+                let result = self.messages(&old, &new, variant.map(|variant| variant.message));
+                self.path.pop();
+                result
+            }
+            PrimitiveType::Any => {
+                let old = AnyReader::parse(old_bytes)?;
+                let new = AnyReader::parse(new_bytes)?;
+                if old.type_name() != new.type_name() {
+                    self.push(ChangeKind::ValueChanged {
+                        old: Value::Any {
+                            type_name: old.type_name(),
+                        },
+                        new: Value::Any {
+                            type_name: new.type_name(),
+                        },
+                    });
+                    return Ok(());
+                }
+                self.messages(&old.message(), &new.message(), None)
+            }
+            PrimitiveType::TypedVector => self.vectors(old_bytes, new_bytes),
+            PrimitiveType::BitVector => self.bit_vectors(old_bytes, new_bytes),
+            _ => {
+                let old = Value::decode(old_type, old_bytes)?;
+                let new = Value::decode(new_type, new_bytes)?;
+                if old != new {
+                    self.push(ChangeKind::ValueChanged { old, new });
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Compare two vector payloads element by element
+    fn vectors(&mut self, old: &'a [u8], new: &'a [u8]) -> Result<()> {
+        let old_type = element_type(old)?;
+        let new_type = element_type(new)?;
+        if old_type != new_type {
+            self.push(ChangeKind::TypeChanged {
+                old: old_type,
+                new: new_type,
+            });
+            return Ok(());
+        }
+
+        let element = |payload: &'a [u8], index: usize| -> Result<Option<&'a [u8]>> {
+            let (_, header_len, size) = vector_layout(payload)?;
+            let count = Endian::Little.read_u32(payload, 0) as usize;
+            Ok((index < count).then(|| {
+                let start = header_len + index * size;
+                &payload[start..start + size]
+            }))
+        };
+
+        let count = Endian::Little
+            .read_u32(old, 0)
+            .max(Endian::Little.read_u32(new, 0));
+        for index in 0..count as usize {
+            let (old, new) = (element(old, index)?, element(new, index)?);
+            self.path.push(PathSegment::Element(index));
+            let result = if old_type == PrimitiveType::Struct {
+                // Struct elements have no size prefix, so compare their bytes directly
+                self.structs(old, new);
+                Ok(())
+            } else {
+                let typed = |bytes: Option<&'a [u8]>| bytes.map(|bytes| (old_type, bytes));
+                self.field(typed(old), typed(new), None)
+            };
+            self.path.pop();
+            result?;
+        }
+        Ok(())
+    }
+
+    /// Compare two struct vector elements
+    fn structs(&mut self, old: Option<&'a [u8]>, new: Option<&'a [u8]>) {
+        let kind = match (old, new) {
+            (Some(old), Some(new)) if old != new => ChangeKind::ValueChanged {
+                old: Value::Struct(old),
+                new: Value::Struct(new),
+            },
+            (None, Some(new)) => ChangeKind::Added(Value::Struct(new)),
+            (Some(old), None) => ChangeKind::Removed(Value::Struct(old)),
+            _ => return,
+        };
+        self.push(kind);
+    }
+
+    /// Compare two bit vector payloads bit by bit
+    fn bit_vectors(&mut self, old: &'a [u8], new: &'a [u8]) -> Result<()> {
+        let bit = |payload: &[u8], index: usize| -> Option<Value<'a>> {
+            let count = Endian::Little.read_u32(payload, 0) as usize;
+            (index < count).then(|| Value::Bool(layout::bit_at(&payload[4..], index)))
+        };
+
+        let count = Endian::Little
+            .read_u32(old, 0)
+            .max(Endian::Little.read_u32(new, 0));
+        for index in 0..count as usize {
+            let kind = match (bit(old, index), bit(new, index)) {
+                (Some(old), Some(new)) if old != new => ChangeKind::ValueChanged { old, new },
+                (None, Some(new)) => ChangeKind::Added(new),
+                (Some(old), None) => ChangeKind::Removed(old),
+                _ => continue,
+            };
+            self.path.push(PathSegment::Element(index));
+            self.push(kind);
+            self.path.pop();
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{builder::MessageBuilder, descriptor::VariantDescriptor};
+
+    #[cfg(not(feature = "std"))]
+    use alloc::{string::ToString, vec};
+    #[cfg(feature = "std")]
+    use std::{string::ToString, vec};
+
+    static INNER: MessageDescriptor = MessageDescriptor {
+        name: "Inner",
+        type_name: "Inner",
+        fields: &[FieldDescriptor {
+            name: "flag",
+            index: 0,
+            message: None,
+            variants: &[],
+        }],
+    };
+
+    static OUTER: MessageDescriptor = MessageDescriptor {
+        name: "Outer",
+        type_name: "Outer",
+        fields: &[
+            FieldDescriptor {
+                name: "id",
+                index: 0,
+                message: None,
+                variants: &[],
+            },
+            FieldDescriptor {
+                name: "inner",
+                index: 1,
+                message: Some(&INNER),
+                variants: &[],
+            },
+            FieldDescriptor {
+                name: "choice",
+                index: 2,
+                message: None,
+                variants: &[VariantDescriptor {
+                    name: "Pick",
+                    discriminant: 0,
+                    message: &INNER,
+                }],
+            },
+        ],
+    };
+
+    fn inner(flag: bool) -> Vec<u8> {
+        let mut builder = MessageBuilder::new();
+        builder.set_scalar(0, flag).unwrap();
+        builder.finish()
+    }
+
+    fn outer(id: u32, flag: bool) -> Vec<u8> {
+        let mut builder = MessageBuilder::new();
+        builder.set_scalar(0, id).unwrap();
+        builder.set_message(1, &inner(flag)).unwrap();
+        builder.set_union(2, 0, &inner(flag)).unwrap();
+        builder.finish()
+    }
+
+    #[test]
+    fn test_diff_identical() -> Result<()> {
+        let data = outer(1, true);
+        let reader = MessageReader::new(&data)?;
+        let changes = diff(&reader, &reader)?;
+        assert!(changes.is_empty());
+        assert_eq!(changes.to_string(), "no changes");
+        Ok(())
+    }
+
+    #[test]
+    fn test_diff_ignores_encoding_differences() -> Result<()> {
+        let mut first = MessageBuilder::new();
+        first.set_scalar(0, 5u8)?;
+        first.set_scalar(1, 6u8)?;
+        let first = first.finish();
+
+        let mut second = MessageBuilder::with_layout(crate::FieldTableLayout::Sparse);
+        second.set_scalar(1, 6u8)?;
+        second.set_scalar(0, 5u8)?;
+        let second = second.finish();
+
+        let changes = diff(&MessageReader::new(&first)?, &MessageReader::new(&second)?)?;
+        assert!(changes.is_empty(), "{}", changes);
+        Ok(())
+    }
+
+    #[test]
+    fn test_diff_nested_with_schema() -> Result<()> {
+        let old = outer(1, true);
+        let new = outer(2, false);
+        let old = MessageReader::new(&old)?;
+        let new = MessageReader::new(&new)?;
+
+        let changes = diff_with_schema(&old, &new, &OUTER)?;
+        assert_eq!(
+            changes.to_string(),
+            "id: changed from 1 to 2\n\
+             inner.flag: changed from true to false\n\
+             choice(Pick).flag: changed from true to false"
+        );
+
+        let unnamed = diff(&old, &new)?;
+        assert_eq!(
+            unnamed.changes()[2].to_string(),
+            "#2(#0).#0: changed from true to false"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_diff_added_removed_and_type_changed() -> Result<()> {
+        let mut old = MessageBuilder::new();
+        old.set_scalar(0, 1u16)?;
+        old.set_string(1, "gone")?;
+        let old = old.finish();
+
+        let mut new = MessageBuilder::new();
+        new.set_scalar(0, 1u32)?;
+        new.set_bytes(2, b"\x01\x02")?;
+        let new = new.finish();
+
+        let changes = diff(&MessageReader::new(&old)?, &MessageReader::new(&new)?)?;
+        let kinds: Vec<_> = changes.iter().map(|change| change.kind.clone()).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                ChangeKind::TypeChanged {
+                    old: PrimitiveType::U16,
+                    new: PrimitiveType::U32,
+                },
+                ChangeKind::Removed(Value::String("gone")),
+                ChangeKind::Added(Value::Bytes(&[1, 2])),
+            ]
+        );
+        assert_eq!(
+            changes.changes()[0].to_string(),
+            "#0: type changed from u16 to u32"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_diff_depth_limit() -> Result<()> {
+        let nested = |levels: usize, leaf: u8| -> Result<Vec<u8>> {
+            let mut data = MessageBuilder::new();
+            data.set_scalar(0, leaf)?;
+            let mut data = data.finish();
+            for _ in 0..levels {
+                let mut builder = MessageBuilder::new();
+                builder.set_message(0, &data)?;
+                data = builder.finish();
+            }
+            Ok(data)
+        };
+
+        let (old, new) = (nested(MAX_DEPTH, 1)?, nested(MAX_DEPTH, 2)?);
+        let changes = diff(&MessageReader::new(&old)?, &MessageReader::new(&new)?)?;
+        assert_eq!(changes.len(), 1);
+
+        let (old, new) = (nested(MAX_DEPTH + 1, 1)?, nested(MAX_DEPTH + 1, 2)?);
+        assert_eq!(
+            diff(&MessageReader::new(&old)?, &MessageReader::new(&new)?),
+            Err(Error::TooDeep)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_diff_vectors() -> Result<()> {
+        let mut old = MessageBuilder::new();
+        old.set_vector(0, &[1u32, 2, 3])?;
+        old.set_bit_vector(1, &[true, false])?;
+        old.set_struct_vector(2, &[1u16, 2])?;
+        let old = old.finish();
+
+        let mut new = MessageBuilder::new();
+        new.set_vector(0, &[1u32, 5])?;
+        new.set_bit_vector(1, &[true, true, false])?;
+        new.set_struct_vector(2, &[1u16, 3, 4])?;
+        let new = new.finish();
+
+        let changes = diff(&MessageReader::new(&old)?, &MessageReader::new(&new)?)?;
+        assert_eq!(
+            changes.to_string(),
+            "#0[1]: changed from 2 to 5\n\
+             #0[2]: removed 3\n\
+             #1[1]: changed from false to true\n\
+             #1[2]: added false\n\
+             #2[1]: changed from <struct [02, 00]> to <struct [03, 00]>\n\
+             #2[2]: added <struct [04, 00]>"
+        );
+        Ok(())
+    }
+}
@@ -1,11 +1,11 @@
 //! Error types for ZeroProto
 
-#[cfg(not(feature = "std"))]
+#[cfg(all(feature = "alloc", not(feature = "std")))]
 extern crate alloc;
-#[cfg(not(feature = "std"))]
-use alloc::string::String;
+#[cfg(all(feature = "alloc", not(feature = "std")))]
+use alloc::{string::String, vec::Vec};
 #[cfg(feature = "std")]
-use std::string::String;
+use std::{string::String, vec::Vec};
 
 /// Result type for ZeroProto operations
 pub type Result<T> = core::result::Result<T, Error>;
@@ -25,7 +25,13 @@ pub enum Error {
     InvalidMessage,
     /// Requested field is not present in the buffer
     MissingField,
+    /// Messages are nested deeper than `constants::MAX_DEPTH`
+    TooDeep,
+    /// A generated builder was finished with required fields unset
+    #[cfg(feature = "alloc")]
+    MissingFields(MissingFields),
     /// Custom error message
+    #[cfg(feature = "alloc")]
     Custom(String),
 }
 
@@ -38,6 +44,10 @@ impl core::fmt::Display for Error {
             Error::InvalidFormat => write!(f, "Invalid data format"),
             Error::InvalidMessage => write!(f, "Invalid message format"),
             Error::MissingField => write!(f, "Field not present"),
+            Error::TooDeep => write!(f, "Messages nested too deeply"),
+            #[cfg(feature = "alloc")]
+            Error::MissingFields(missing) => write!(f, "{}", missing),
+            #[cfg(feature = "alloc")]
             Error::Custom(msg) => write!(f, "{}", msg),
         }
     }
@@ -46,6 +56,40 @@ impl core::fmt::Display for Error {
 #[cfg(feature = "std")]
 impl std::error::Error for Error {}
 
+/// Required fields left unset when a generated builder was finished
+#[cfg(feature = "alloc")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MissingFields {
+    /// Schema name of the message being built
+    pub message: &'static str,
+    /// Schema names of the unset fields, in declaration order
+    pub fields: Vec<&'static str>,
+}
+
+#[cfg(feature = "alloc")]
+impl core::fmt::Display for MissingFields {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "Message '{}' is missing required fields: ", self.message)?;
+        for (i, field) in self.fields.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", field)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for MissingFields {}
+
+#[cfg(feature = "alloc")]
+impl From<MissingFields> for Error {
+    fn from(missing: MissingFields) -> Self {
+        Error::MissingFields(missing)
+    }
+}
+
 impl From<core::str::Utf8Error> for Error {
     fn from(_: core::str::Utf8Error) -> Self {
         Error::InvalidUtf8
@@ -58,3 +102,27 @@ impl From<std::string::FromUtf8Error> for Error {
         Error::InvalidUtf8
     }
 }
+
+#[cfg(all(test, feature = "alloc"))]
+mod tests {
+    use super::*;
+    #[cfg(not(feature = "std"))]
+    use alloc::{string::ToString, vec};
+    #[cfg(feature = "std")]
+    use std::{string::ToString, vec};
+
+    #[test]
+    fn test_missing_fields() {
+        let missing = MissingFields {
+            message: "User",
+            fields: vec!["username", "email"],
+        };
+        assert_eq!(
+            missing.to_string(),
+            "Message 'User' is missing required fields: username, email"
+        );
+        let error = Error::from(missing.clone());
+        assert_eq!(error.to_string(), missing.to_string());
+        assert_eq!(error, Error::MissingFields(missing));
+    }
+}
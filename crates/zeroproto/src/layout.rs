@@ -0,0 +1,180 @@
+//! Field table layouts for ZeroProto messages
+
+use crate::{constants::FIELD_ENTRY_SIZE, primitives::Endian};
+
+/// Value of the leading `u16` that marks an extended message header
+///
+/// Dense messages store their field count there, which is always below this marker.
+pub const EXTENDED_HEADER_MARKER: u16 = u16::MAX;
+
+/// Size of the extended header: marker (2) + layout tag (1) + count (2)
+pub const EXTENDED_HEADER_SIZE: usize = 5;
+
+/// Size of a sparse field table entry: index (2) + type ID (1) + offset (4)
+pub const SPARSE_ENTRY_SIZE: usize = 7;
+
+/// How a message encodes its field table
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FieldTableLayout {
+    /// Pick `Dense` or `Sparse`, whichever produces the smaller field table
+    #[default]
+    Auto,
+    /// One entry for every index up to the highest set field
+    Dense,
+    /// A presence bitmap followed by entries for set fields only
+    Bitmap,
+    /// Entries for set fields only, sorted by field index
+    Sparse,
+}
+
+impl FieldTableLayout {
+    /// Layout tag stored in the extended header
+    #[cfg_attr(not(feature = "alloc"), allow(dead_code))]
+    pub(crate) fn tag(self) -> Option<u8> {
+        match self {
+            FieldTableLayout::Auto | FieldTableLayout::Dense => None,
+            FieldTableLayout::Bitmap => Some(1),
+            FieldTableLayout::Sparse => Some(2),
+        }
+    }
+
+    /// Convert an extended header tag back into a layout
+    pub(crate) fn from_tag(tag: u8) -> Option<Self> {
+        match tag {
+            1 => Some(FieldTableLayout::Bitmap),
+            2 => Some(FieldTableLayout::Sparse),
+            _ => None,
+        }
+    }
+
+    /// Size of the header plus field table for `set_fields` entries spanning `field_count` indices
+    #[cfg_attr(not(feature = "alloc"), allow(dead_code))]
+    pub(crate) fn table_size(self, field_count: u16, set_fields: usize) -> usize {
+        match self.resolve(field_count, set_fields) {
+            FieldTableLayout::Bitmap => {
+                EXTENDED_HEADER_SIZE
+                    + bitmap_len(field_count as usize)
+                    + set_fields * FIELD_ENTRY_SIZE
+            }
+            FieldTableLayout::Sparse => EXTENDED_HEADER_SIZE + 2 + set_fields * SPARSE_ENTRY_SIZE,
+            _ => 2 + field_count as usize * FIELD_ENTRY_SIZE,
+        }
+    }
+
+    /// Replace `Auto` with the concrete layout it picks, preferring `Dense` on ties
+    #[cfg_attr(not(feature = "alloc"), allow(dead_code))]
+    pub(crate) fn resolve(self, field_count: u16, set_fields: usize) -> Self {
+        match self {
+            FieldTableLayout::Auto => {
+                let dense = FieldTableLayout::Dense.table_size(field_count, set_fields);
+                let sparse = FieldTableLayout::Sparse.table_size(field_count, set_fields);
+                if sparse < dense {
+                    FieldTableLayout::Sparse
+                } else {
+                    FieldTableLayout::Dense
+                }
+            }
+            layout => layout,
+        }
+    }
+}
+
+/// Number of bytes needed to hold `bits` bits
+#[inline]
+pub(crate) fn bitmap_len(bits: usize) -> usize {
+    bits.div_ceil(8)
+}
+
+/// Read bit `index` from an LSB-first bitmap
+#[inline]
+pub(crate) fn bit_at(bitmap: &[u8], index: usize) -> bool {
+    bitmap[index / 8] & (1 << (index % 8)) != 0
+}
+
+/// Set bit `index` in an LSB-first bitmap
+#[inline]
+#[cfg_attr(not(feature = "alloc"), allow(dead_code))]
+pub(crate) fn set_bit(bitmap: &mut [u8], index: usize) {
+    bitmap[index / 8] |= 1 << (index % 8);
+}
+
+/// Count the set bits that come before bit `index`
+pub(crate) fn rank(bitmap: &[u8], index: usize) -> usize {
+    let whole_bytes = index / 8;
+    let mut count: usize = bitmap[..whole_bytes]
+        .iter()
+        .map(|byte| byte.count_ones() as usize)
+        .sum();
+    let remainder = index % 8;
+    if remainder > 0 {
+        let mask = (1u8 << remainder) - 1;
+        count += (bitmap[whole_bytes] & mask).count_ones() as usize;
+    }
+    count
+}
+
+/// Write the extended header for `layout` at the start of `buf`
+#[cfg_attr(not(feature = "alloc"), allow(dead_code))]
+pub(crate) fn write_extended_header(buf: &mut [u8], layout: FieldTableLayout, count: u16) {
+    Endian::Little.write_u16(EXTENDED_HEADER_MARKER, buf, 0);
+    buf[2] = layout.tag().unwrap_or(0);
+    Endian::Little.write_u16(count, buf, 3);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bitmap_helpers() {
+        let mut bitmap = [0u8; 2];
+        set_bit(&mut bitmap, 0);
+        set_bit(&mut bitmap, 3);
+        set_bit(&mut bitmap, 9);
+
+        assert!(bit_at(&bitmap, 3));
+        assert!(!bit_at(&bitmap, 4));
+        assert_eq!(rank(&bitmap, 0), 0);
+        assert_eq!(rank(&bitmap, 4), 2);
+        assert_eq!(rank(&bitmap, 9), 2);
+        assert_eq!(rank(&bitmap, 16), 3);
+        assert_eq!(bitmap_len(9), 2);
+    }
+
+    #[test]
+    fn test_layout_tags() {
+        assert_eq!(FieldTableLayout::Dense.tag(), None);
+        assert_eq!(FieldTableLayout::Auto.tag(), None);
+        for layout in [FieldTableLayout::Bitmap, FieldTableLayout::Sparse] {
+            let tag = layout.tag().unwrap();
+            assert_eq!(FieldTableLayout::from_tag(tag), Some(layout));
+        }
+        assert_eq!(FieldTableLayout::from_tag(0), None);
+    }
+
+    #[test]
+    fn test_auto_layout_choice() {
+        // Contiguous fields stay dense
+        assert_eq!(
+            FieldTableLayout::Auto.resolve(1, 1),
+            FieldTableLayout::Dense
+        );
+        assert_eq!(
+            FieldTableLayout::Auto.resolve(10, 10),
+            FieldTableLayout::Dense
+        );
+
+        // A lone high index goes sparse
+        assert_eq!(
+            FieldTableLayout::Auto.resolve(60001, 1),
+            FieldTableLayout::Sparse
+        );
+        assert_eq!(FieldTableLayout::Auto.table_size(60001, 1), 14);
+
+        // Explicit layouts are left alone
+        assert_eq!(
+            FieldTableLayout::Bitmap.resolve(1, 1),
+            FieldTableLayout::Bitmap
+        );
+    }
+}
@@ -6,6 +6,15 @@
 //! - Error handling and safety utilities
 //! - Buffer abstractions
 //!
+//! # Features
+//!
+//! - `std` (default): implements `std::error::Error` and enables `alloc`
+//! - `alloc`: builders, [`Vector`], and the other APIs that allocate
+//! - `chrono`: conversions between the [well-known](well_known) time types and `chrono`
+//! - `uuid`: conversions between [`Uuid`] and `uuid::Uuid`
+//!
+//! With no features enabled, the reader path is still fully usable and never allocates.
+//!
 //! # Quick Start
 //!
 //! ```rust
@@ -30,26 +39,53 @@
 #[cfg(feature = "std")]
 extern crate std;
 
+#[cfg(all(feature = "alloc", not(feature = "std")))]
+extern crate alloc;
+
+pub mod any;
+#[cfg(feature = "alloc")]
 mod builder;
+#[cfg(feature = "alloc")]
+mod canonical;
+mod descriptor;
+#[cfg(feature = "alloc")]
+pub mod diff;
 mod errors;
+mod layout;
+#[cfg(feature = "alloc")]
+pub mod merge;
 mod primitives;
 mod reader;
+#[cfg(feature = "alloc")]
 mod vector;
+pub mod well_known;
 
+pub use any::AnyReader;
+#[cfg(feature = "alloc")]
 pub use builder::{MessageBuilder, VectorBuilder};
+pub use descriptor::{FieldDescriptor, MessageDescriptor, VariantDescriptor};
+#[cfg(feature = "alloc")]
+pub use errors::MissingFields;
 pub use errors::{Error, Result};
+pub use layout::FieldTableLayout;
 pub use primitives::{Endian, PrimitiveType};
-pub use reader::{MessageReader, VectorReader};
+pub use reader::{BitIter, BitVectorReader, MessageReader, VectorIter, VectorReader};
+#[cfg(feature = "alloc")]
 pub use vector::Vector;
+pub use well_known::{Decimal, Duration, Timestamp, Uuid};
 
 /// Re-export commonly used types
 pub mod prelude {
+    #[cfg(feature = "alloc")]
     pub use crate::{
         builder::{MessageBuilder, VectorBuilder},
+        vector::Vector,
+    };
+    pub use crate::{
         errors::{Error, Result},
+        layout::FieldTableLayout,
         primitives::{Endian, PrimitiveType},
-        reader::{MessageReader, VectorReader},
-        vector::Vector,
+        reader::{BitVectorReader, MessageReader, VectorReader},
     };
 }
 
@@ -58,7 +94,12 @@ pub mod constants {
     use crate::primitives::Endian;
 
     /// Maximum number of fields in a message
-    pub const MAX_FIELDS: u16 = u16::MAX;
+    ///
+    /// `u16::MAX` is reserved as the extended header marker.
+    pub const MAX_FIELDS: u16 = u16::MAX - 1;
+
+    /// Deepest message nesting that recursive operations, like canonicalization, will follow
+    pub const MAX_DEPTH: usize = 100;
 
     /// Field table entry size in bytes
     pub const FIELD_ENTRY_SIZE: usize = 5; // type_id (1) + offset (4)
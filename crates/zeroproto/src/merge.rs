@@ -0,0 +1,427 @@
+//! Merging encoded messages
+//!
+//! [`merge`] applies a sparse "patch" message on top of a base message. Every field set in
+//! the overlay replaces the same field in the base, except that nested messages (and union
+//! fields holding the same variant) are merged recursively. Vectors are replaced by
+//! default; [`VectorMerge::Append`] concatenates them instead.
+//!
+//! ```rust
+//! use zeroproto::{merge, MessageBuilder, MessageReader};
+//!
+//! let mut base = MessageBuilder::new();
+//! base.set_scalar(0, 1u32)?;
+//! base.set_string(1, "base")?;
+//! let base = base.finish();
+//!
+//! let mut patch = MessageBuilder::new();
+//! patch.set_string(1, "patched")?;
+//! let patch = patch.finish();
+//!
+//! let merged = merge::merge(&base, &patch)?;
+//! let reader = MessageReader::new(&merged)?;
+//! assert_eq!(reader.get_scalar::<u32>(0)?, 1);
+//! assert_eq!(reader.get_string(1)?, "patched");
+//! # Ok::<(), zeroproto::Error>(())
+//! ```
+
+#[cfg(not(feature = "std"))]
+use alloc::{string::String, vec::Vec};
+#[cfg(feature = "std")]
+use std::{string::String, vec::Vec};
+
+use crate::{
+    any::AnyReader,
+    builder::MessageBuilder,
+    constants::MAX_DEPTH,
+    errors::{Error, Result},
+    layout,
+    primitives::{Endian, PrimitiveType},
+    reader::{vector_layout, MessageReader},
+};
+
+/// How vector fields set in both messages are combined
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum VectorMerge {
+    /// The overlay's vector replaces the base's
+    #[default]
+    Replace,
+    /// The overlay's elements are appended to the base's when the element types match
+    Append,
+}
+
+/// Options controlling how messages are merged
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MergeOptions {
+    /// Policy for vector and bit-vector fields set in both messages
+    pub vectors: VectorMerge,
+}
+
+impl MergeOptions {
+    /// Options that append vectors instead of replacing them
+    pub fn append_vectors() -> Self {
+        Self {
+            vectors: VectorMerge::Append,
+        }
+    }
+}
+
+/// Merge `overlay` on top of `base` with the default options
+pub fn merge(base: &[u8], overlay: &[u8]) -> Result<Vec<u8>> {
+    merge_with(base, overlay, &MergeOptions::default())
+}
+
+/// Merge `overlay` on top of `base`
+///
+/// Returns [`Error::TooDeep`] if the merge recurses more than
+/// [`MAX_DEPTH`] levels into nested messages.
+pub fn merge_with(base: &[u8], overlay: &[u8], options: &MergeOptions) -> Result<Vec<u8>> {
+    merge_at(base, overlay, options, 0)
+}
+
+/// Merge two messages nested `depth` levels below the messages being merged
+fn merge_at(base: &[u8], overlay: &[u8], options: &MergeOptions, depth: usize) -> Result<Vec<u8>> {
+    if depth > MAX_DEPTH {
+        return Err(Error::TooDeep);
+    }
+    let base = MessageReader::new(base)?;
+    let overlay = MessageReader::new(overlay)?;
+
+    let mut builder = MessageBuilder::from_reader(&base)?;
+    builder.merge_fields(&overlay, options, depth)?;
+    // Replaced base payloads would otherwise stay behind, and pile up at every level
+    builder.compact();
+    Ok(builder.finish())
+}
+
+impl MessageBuilder {
+    /// Merge every field set in `overlay` into this builder
+    ///
+    /// Fields set in `overlay` replace fields already set here, with nested messages
+    /// and same-variant unions merged recursively and vectors handled according to
+    /// `options`.
+    pub fn merge_from(
+        &mut self,
+        overlay: &MessageReader<'_>,
+        options: &MergeOptions,
+    ) -> Result<()> {
+        self.merge_fields(overlay, options, 0)
+    }
+
+    /// Merge `overlay` into this builder, `depth` levels below the messages being merged
+    fn merge_fields(
+        &mut self,
+        overlay: &MessageReader<'_>,
+        options: &MergeOptions,
+        depth: usize,
+    ) -> Result<()> {
+        for field_index in 0..overlay.field_count() {
+            let (field_type, payload) = match overlay.field_bytes(field_index)? {
+                Some(field) => field,
+                None => continue,
+            };
+
+            let merged = match self.field_payload(field_index) {
+                Some((base_type, base)) if base_type == field_type => {
+                    merge_payloads(field_type, base, payload, options, depth)?
+                }
+                _ => None,
+            };
+
+            match merged {
+                Some(Merged::Message(message)) => self.set_message(field_index, &message)?,
+                Some(Merged::Union(discriminant, message)) => {
+                    self.set_union(field_index, discriminant, &message)?
+                }
+                Some(Merged::Any(type_name, message)) => {
+                    self.set_any(field_index, &type_name, &message)?
+                }
+                Some(Merged::Raw(payload)) => self.set_raw(field_index, field_type, &payload)?,
+                None => self.set_raw(field_index, field_type, payload)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Result of combining two payloads of the same field type
+enum Merged {
+    Message(Vec<u8>),
+    Union(u16, Vec<u8>),
+    Any(String, Vec<u8>),
+    Raw(Vec<u8>),
+}
+
+/// Combine two payloads of the same type, or return `None` if the overlay simply wins
+fn merge_payloads(
+    field_type: PrimitiveType,
+    base: &[u8],
+    overlay: &[u8],
+    options: &MergeOptions,
+    depth: usize,
+) -> Result<Option<Merged>> {
+    let nested = |base: &[u8], overlay: &[u8]| merge_at(base, overlay, options, depth + 1);
+    let merged = match field_type {
+        PrimitiveType::Message => Merged::Message(nested(&base[4..], &overlay[4..])?),
+        PrimitiveType::Union => {
+            let discriminant = Endian::Little.read_u16(overlay, 0);
+            if Endian::Little.read_u16(base, 0) != discriminant {
+                return Ok(None);
+            }
+            Merged::Union(discriminant, nested(&base[6..], &overlay[6..])?)
+        }
+        PrimitiveType::Any => {
+            let base = AnyReader::parse(base)?;
+            let overlay = AnyReader::parse(overlay)?;
+            if base.type_name() != overlay.type_name() {
+                return Ok(None);
+            }
+            Merged::Any(
+                overlay.type_name().into(),
+                nested(base.as_bytes(), overlay.as_bytes())?,
+            )
+        }
+        PrimitiveType::TypedVector if options.vectors == VectorMerge::Append => {
+            // Element type, and stride for structs, must match
+            let (_, header_len, _) = vector_layout(base)?;
+            if overlay.get(4..header_len) != Some(&base[4..header_len]) {
+                return Ok(None);
+            }
+            let count = Endian::Little
+                .read_u32(base, 0)
+                .checked_add(Endian::Little.read_u32(overlay, 0))
+                .ok_or(Error::OutOfBounds)?;
+
+            let mut payload = Vec::with_capacity(base.len() + overlay.len() - header_len);
+            payload.extend_from_slice(&count.to_le_bytes());
+            payload.extend_from_slice(&base[4..]);
+            payload.extend_from_slice(&overlay[header_len..]);
+            Merged::Raw(payload)
+        }
+        PrimitiveType::BitVector if options.vectors == VectorMerge::Append => {
+            let base_count = Endian::Little.read_u32(base, 0) as usize;
+            let overlay_count = Endian::Little.read_u32(overlay, 0) as usize;
+            let count = base_count + overlay_count;
+            if count > u32::MAX as usize {
+                return Err(Error::OutOfBounds);
+            }
+
+            let mut payload = Vec::with_capacity(4 + layout::bitmap_len(count));
+            payload.extend_from_slice(&(count as u32).to_le_bytes());
+            payload.resize(4 + layout::bitmap_len(count), 0);
+
+            let bits = &mut payload[4..];
+            let base_bits = (0..base_count).map(|i| layout::bit_at(&base[4..], i));
+            let overlay_bits = (0..overlay_count).map(|i| layout::bit_at(&overlay[4..], i));
+            for (i, bit) in base_bits.chain(overlay_bits).enumerate() {
+                if bit {
+                    layout::set_bit(bits, i);
+                }
+            }
+            Merged::Raw(payload)
+        }
+        _ => return Ok(None),
+    };
+
+    Ok(Some(merged))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FieldTableLayout;
+
+    fn nested(level: u8, name: Option<&str>) -> Result<Vec<u8>> {
+        let mut builder = MessageBuilder::new();
+        builder.set_scalar(0, level)?;
+        if let Some(name) = name {
+            builder.set_string(1, name)?;
+        }
+        Ok(builder.finish())
+    }
+
+    #[test]
+    fn test_merge_overlay_replaces_scalars() -> Result<()> {
+        let mut base = MessageBuilder::new();
+        base.set_scalar(0, 1u32)?;
+        base.set_string(1, "base")?;
+        base.set_scalar(2, true)?;
+        let base = base.finish();
+
+        let mut overlay = MessageBuilder::new();
+        overlay.set_scalar(0, 9u32)?;
+        overlay.set_scalar(3, -4i16)?;
+        let overlay = overlay.finish();
+
+        let merged = merge(&base, &overlay)?;
+        let reader = MessageReader::new(&merged)?;
+        assert_eq!(reader.get_scalar::<u32>(0)?, 9);
+        assert_eq!(reader.get_string(1)?, "base");
+        assert!(reader.get_scalar::<bool>(2)?);
+        assert_eq!(reader.get_scalar::<i16>(3)?, -4);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge_type_change_takes_overlay() -> Result<()> {
+        let mut base = MessageBuilder::new();
+        base.set_string(0, "text")?;
+        let base = base.finish();
+
+        let mut overlay = MessageBuilder::new();
+        overlay.set_scalar(0, 5u64)?;
+        let overlay = overlay.finish();
+
+        let merged = merge(&base, &overlay)?;
+        assert_eq!(MessageReader::new(&merged)?.get_scalar::<u64>(0)?, 5);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge_nested_messages_recursively() -> Result<()> {
+        let mut base = MessageBuilder::new();
+        base.set_message(0, &nested(1, Some("inner"))?)?;
+        base.set_union(1, 2, &nested(1, Some("variant"))?)?;
+        let base = base.finish();
+
+        let mut overlay = MessageBuilder::new();
+        overlay.set_message(0, &nested(7, None)?)?;
+        overlay.set_union(1, 2, &nested(8, None)?)?;
+        let overlay = overlay.finish();
+
+        let merged = merge(&base, &overlay)?;
+        let reader = MessageReader::new(&merged)?;
+
+        let inner = reader.get_message(0)?;
+        assert_eq!(inner.get_scalar::<u8>(0)?, 7);
+        assert_eq!(inner.get_string(1)?, "inner");
+
+        let (discriminant, variant) = reader.get_union(1)?;
+        assert_eq!(discriminant, 2);
+        assert_eq!(variant.get_scalar::<u8>(0)?, 8);
+        assert_eq!(variant.get_string(1)?, "variant");
+
+        Ok(())
+    }
+
+    /// A message with `levels` messages nested inside it, each with a string
+    fn chain(levels: usize) -> Result<Vec<u8>> {
+        let mut data = nested(0, Some("leaf"))?;
+        for level in 0..levels {
+            let mut builder = MessageBuilder::new();
+            builder.set_string(1, "level")?;
+            if level % 2 == 0 {
+                builder.set_message(0, &data)?;
+            } else {
+                builder.set_union(0, 1, &data)?;
+            }
+            data = builder.finish();
+        }
+        Ok(data)
+    }
+
+    #[test]
+    fn test_merge_nested_output_does_not_grow() -> Result<()> {
+        for levels in [1, 2, 10, 50] {
+            let data = chain(levels)?;
+            let merged = merge(&data, &data)?;
+            assert!(merged.len() <= data.len(), "{} levels", levels);
+            assert_eq!(merge(&merged, &data)?, merged);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge_depth_limit() -> Result<()> {
+        let data = chain(MAX_DEPTH)?;
+        merge(&data, &data)?;
+
+        let data = chain(MAX_DEPTH + 1)?;
+        assert_eq!(merge(&data, &data), Err(Error::TooDeep));
+
+        let mut builder = MessageBuilder::from_reader(&MessageReader::new(&data)?)?;
+        assert_eq!(
+            builder.merge_from(&MessageReader::new(&data)?, &MergeOptions::default()),
+            Err(Error::TooDeep)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge_union_variant_switch_replaces() -> Result<()> {
+        let mut base = MessageBuilder::new();
+        base.set_union(0, 1, &nested(1, Some("old"))?)?;
+        let base = base.finish();
+
+        let mut overlay = MessageBuilder::new();
+        overlay.set_union(0, 2, &nested(2, None)?)?;
+        let overlay = overlay.finish();
+
+        let merged = merge(&base, &overlay)?;
+        let (discriminant, variant) = MessageReader::new(&merged)?.get_union(0)?;
+        assert_eq!(discriminant, 2);
+        assert!(!variant.has_field(1)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge_vector_policies() -> Result<()> {
+        let mut base = MessageBuilder::new();
+        base.set_vector(0, &[1u32, 2])?;
+        base.set_bit_vector(1, &[true, false, true])?;
+        base.set_vector(2, &[1u8])?;
+        base.set_struct_vector(3, &[7u16])?;
+        let base = base.finish();
+
+        let mut overlay = MessageBuilder::new();
+        overlay.set_vector(0, &[3u32])?;
+        overlay.set_bit_vector(1, &[false, true])?;
+        overlay.set_vector(2, &[2u16])?;
+        overlay.set_struct_vector(3, &[8u16, 9])?;
+        let overlay = overlay.finish();
+
+        let replaced = merge(&base, &overlay)?;
+        let reader = MessageReader::new(&replaced)?;
+        assert_eq!(reader.get_vector::<u32>(0)?.collect()?, [3]);
+        assert_eq!(reader.get_bit_vector(1)?.len(), 2);
+
+        let appended = merge_with(&base, &overlay, &MergeOptions::append_vectors())?;
+        let reader = MessageReader::new(&appended)?;
+        assert_eq!(reader.get_vector::<u32>(0)?.collect()?, [1, 2, 3]);
+        let bits: Vec<bool> = reader.get_bit_vector(1)?.iter().collect();
+        assert_eq!(bits, [true, false, true, false, true]);
+        // Element types differ, so the overlay wins
+        assert_eq!(reader.get_vector::<u16>(2)?.collect()?, [2]);
+        // Struct elements append when their strides match
+        assert_eq!(reader.get_vector::<u16>(3)?.collect()?, [7, 8, 9]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_builder_merge_from_keeps_bitmap_layout() -> Result<()> {
+        let mut base = MessageBuilder::with_layout(FieldTableLayout::Bitmap);
+        base.set_scalar(0, 1u8)?;
+        let base = base.finish();
+
+        let mut overlay = MessageBuilder::new();
+        overlay.set_scalar(4, 2u8)?;
+        let overlay = overlay.finish();
+
+        let mut builder = MessageBuilder::from_reader(&MessageReader::new(&base)?)?;
+        builder.merge_from(&MessageReader::new(&overlay)?, &MergeOptions::default())?;
+        let merged = builder.finish();
+
+        let reader = MessageReader::new(&merged)?;
+        assert_eq!(reader.layout(), FieldTableLayout::Bitmap);
+        assert_eq!(reader.get_scalar::<u8>(0)?, 1);
+        assert_eq!(reader.get_scalar::<u8>(4)?, 2);
+
+        Ok(())
+    }
+}
@@ -1,7 +1,5 @@
 //! Primitive type handling and endian utilities
 
-use crate::errors::Result;
-
 /// Endianness for byte order handling
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Endian {
@@ -43,8 +41,26 @@ pub enum PrimitiveType {
     Bytes = 12,
     /// Nested message
     Message = 13,
-    /// Vector of values
+    /// Vector of fixed-size values without an element type, as written by 0.4.0
     Vector = 14,
+    /// Tagged union holding exactly one nested message variant
+    Union = 15,
+    /// Vector of booleans packed eight to a byte
+    BitVector = 16,
+    /// Fixed-layout struct stored inline
+    Struct = 17,
+    /// Seconds and nanoseconds since the Unix epoch
+    Timestamp = 18,
+    /// Signed seconds and nanoseconds
+    Duration = 19,
+    /// 128-bit UUID
+    Uuid = 20,
+    /// Scaled 128-bit decimal
+    Decimal = 21,
+    /// Nested message of any type, tagged with its type name
+    Any = 22,
+    /// Vector of fixed-size values that records its element type
+    TypedVector = 23,
     /// Sentinel for unset/absent fields
     Unset = 255,
 }
@@ -64,10 +80,18 @@ impl PrimitiveType {
             PrimitiveType::F32 => Some(4),
             PrimitiveType::F64 => Some(8),
             PrimitiveType::Bool => Some(1),
+            PrimitiveType::Timestamp | PrimitiveType::Duration => Some(12),
+            PrimitiveType::Uuid => Some(16),
+            PrimitiveType::Decimal => Some(17),
             PrimitiveType::String
             | PrimitiveType::Bytes
             | PrimitiveType::Message
             | PrimitiveType::Vector
+            | PrimitiveType::TypedVector
+            | PrimitiveType::Union
+            | PrimitiveType::BitVector
+            | PrimitiveType::Struct
+            | PrimitiveType::Any
             | PrimitiveType::Unset => None,
         }
     }
@@ -90,10 +114,56 @@ impl PrimitiveType {
             12 => Some(PrimitiveType::Bytes),
             13 => Some(PrimitiveType::Message),
             14 => Some(PrimitiveType::Vector),
+            15 => Some(PrimitiveType::Union),
+            16 => Some(PrimitiveType::BitVector),
+            17 => Some(PrimitiveType::Struct),
+            18 => Some(PrimitiveType::Timestamp),
+            19 => Some(PrimitiveType::Duration),
+            20 => Some(PrimitiveType::Uuid),
+            21 => Some(PrimitiveType::Decimal),
+            22 => Some(PrimitiveType::Any),
+            23 => Some(PrimitiveType::TypedVector),
             255 => Some(PrimitiveType::Unset),
             _ => None,
         }
     }
+
+    /// Get the schema name of this type
+    pub fn name(self) -> &'static str {
+        match self {
+            PrimitiveType::U8 => "u8",
+            PrimitiveType::U16 => "u16",
+            PrimitiveType::U32 => "u32",
+            PrimitiveType::U64 => "u64",
+            PrimitiveType::I8 => "i8",
+            PrimitiveType::I16 => "i16",
+            PrimitiveType::I32 => "i32",
+            PrimitiveType::I64 => "i64",
+            PrimitiveType::F32 => "f32",
+            PrimitiveType::F64 => "f64",
+            PrimitiveType::Bool => "bool",
+            PrimitiveType::String => "string",
+            PrimitiveType::Bytes => "bytes",
+            PrimitiveType::Message => "message",
+            PrimitiveType::Vector => "untyped vector",
+            PrimitiveType::Union => "union",
+            PrimitiveType::BitVector => "bit vector",
+            PrimitiveType::Struct => "struct",
+            PrimitiveType::Timestamp => "timestamp",
+            PrimitiveType::Duration => "duration",
+            PrimitiveType::Uuid => "uuid",
+            PrimitiveType::Decimal => "decimal",
+            PrimitiveType::Any => "any",
+            PrimitiveType::TypedVector => "vector",
+            PrimitiveType::Unset => "unset",
+        }
+    }
+}
+
+impl core::fmt::Display for PrimitiveType {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(self.name())
+    }
 }
 
 impl Endian {
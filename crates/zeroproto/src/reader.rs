@@ -1,29 +1,32 @@
 //! Message and vector readers for ZeroProto deserialization
 
-#[cfg(not(feature = "std"))]
+#[cfg(all(feature = "alloc", not(feature = "std")))]
 extern crate alloc;
-#[cfg(not(feature = "std"))]
-use alloc::boxed::Box;
-#[cfg(not(feature = "std"))]
+#[cfg(all(feature = "alloc", not(feature = "std")))]
 use alloc::vec::Vec;
 #[cfg(feature = "std")]
-use std::boxed::Box;
-#[cfg(feature = "std")]
 use std::vec::Vec;
 
 use crate::{
+    any::AnyReader,
     constants::FIELD_ENTRY_SIZE,
     errors::{Error, Result},
+    layout::{
+        self, FieldTableLayout, EXTENDED_HEADER_MARKER, EXTENDED_HEADER_SIZE, SPARSE_ENTRY_SIZE,
+    },
     primitives::{Endian, PrimitiveType},
     ZpRead,
 };
 
 /// A zero-copy message reader
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub struct MessageReader<'a> {
     buffer: &'a [u8],
     field_count: u16,
     field_table_offset: usize,
+    layout: FieldTableLayout,
+    bitmap_offset: usize,
+    entry_count: usize,
 }
 
 impl<'a> MessageReader<'a> {
@@ -34,6 +37,9 @@ impl<'a> MessageReader<'a> {
         }
 
         let field_count = Endian::Little.read_u16(buffer, 0);
+        if field_count == EXTENDED_HEADER_MARKER {
+            return Self::new_extended(buffer);
+        }
 
         let field_table_size = field_count as usize * FIELD_ENTRY_SIZE;
         let total_header_size = 2 + field_table_size;
@@ -46,21 +52,121 @@ impl<'a> MessageReader<'a> {
             buffer,
             field_count,
             field_table_offset: 2,
+            layout: FieldTableLayout::Dense,
+            bitmap_offset: 0,
+            entry_count: field_count as usize,
         })
     }
 
+    /// Parse a message that starts with an extended header
+    fn new_extended(buffer: &'a [u8]) -> Result<Self> {
+        if buffer.len() < EXTENDED_HEADER_SIZE {
+            return Err(Error::InvalidMessage);
+        }
+
+        let layout = FieldTableLayout::from_tag(buffer[2]).ok_or(Error::InvalidMessage)?;
+        let field_count = Endian::Little.read_u16(buffer, 3);
+
+        match layout {
+            FieldTableLayout::Bitmap => {
+                let bitmap_offset = EXTENDED_HEADER_SIZE;
+                let bitmap_end = bitmap_offset + layout::bitmap_len(field_count as usize);
+                if buffer.len() < bitmap_end {
+                    return Err(Error::InvalidMessage);
+                }
+
+                let set_fields =
+                    layout::rank(&buffer[bitmap_offset..bitmap_end], field_count as usize);
+                if buffer.len() < bitmap_end + set_fields * FIELD_ENTRY_SIZE {
+                    return Err(Error::InvalidMessage);
+                }
+
+                Ok(Self {
+                    buffer,
+                    field_count,
+                    field_table_offset: bitmap_end,
+                    layout,
+                    bitmap_offset,
+                    entry_count: set_fields,
+                })
+            }
+            FieldTableLayout::Sparse => {
+                if buffer.len() < EXTENDED_HEADER_SIZE + 2 {
+                    return Err(Error::InvalidMessage);
+                }
+
+                let entry_count = Endian::Little.read_u16(buffer, EXTENDED_HEADER_SIZE) as usize;
+                let field_table_offset = EXTENDED_HEADER_SIZE + 2;
+                if buffer.len() < field_table_offset + entry_count * SPARSE_ENTRY_SIZE {
+                    return Err(Error::InvalidMessage);
+                }
+
+                Ok(Self {
+                    buffer,
+                    field_count,
+                    field_table_offset,
+                    layout,
+                    bitmap_offset: 0,
+                    entry_count,
+                })
+            }
+            _ => Err(Error::InvalidMessage),
+        }
+    }
+
     /// Get the number of fields in this message
     pub fn field_count(&self) -> u16 {
         self.field_count
     }
 
+    /// Get the field table layout of this message
+    pub fn layout(&self) -> FieldTableLayout {
+        self.layout
+    }
+
+    /// Locate the field table entry for a field index, if the table stores one
+    fn entry_offset(&self, field_index: u16) -> Option<usize> {
+        match self.layout {
+            FieldTableLayout::Bitmap => {
+                let bitmap = &self.buffer[self.bitmap_offset..self.field_table_offset];
+                if !layout::bit_at(bitmap, field_index as usize) {
+                    return None;
+                }
+                let position = layout::rank(bitmap, field_index as usize);
+                Some(self.field_table_offset + position * FIELD_ENTRY_SIZE)
+            }
+            FieldTableLayout::Sparse => {
+                // Entries are sorted by index, so binary search for the one we want
+                let (mut low, mut high) = (0, self.entry_count);
+                while low < high {
+                    let mid = low + (high - low) / 2;
+                    let entry_offset = self.field_table_offset + mid * SPARSE_ENTRY_SIZE;
+                    let index = Endian::Little.read_u16(self.buffer, entry_offset);
+                    match index.cmp(&field_index) {
+                        core::cmp::Ordering::Equal => return Some(entry_offset + 2),
+                        core::cmp::Ordering::Less => low = mid + 1,
+                        core::cmp::Ordering::Greater => high = mid,
+                    }
+                }
+                None
+            }
+            _ => Some(self.field_table_offset + field_index as usize * FIELD_ENTRY_SIZE),
+        }
+    }
+
     /// Get the field table entry for a given field index
+    ///
+    /// Indices past the field count are unset, since a writer with an older schema
+    /// simply never knew about those fields.
     fn field_entry(&self, field_index: u16) -> Result<Option<(PrimitiveType, usize)>> {
         if field_index >= self.field_count {
-            return Err(Error::OutOfBounds);
+            return Ok(None);
         }
 
-        let entry_offset = self.field_table_offset + field_index as usize * FIELD_ENTRY_SIZE;
+        let entry_offset = match self.entry_offset(field_index) {
+            Some(entry_offset) => entry_offset,
+            None => return Ok(None),
+        };
         let type_id = self.buffer[entry_offset];
         let primitive_type = PrimitiveType::from_u8(type_id).ok_or(Error::InvalidFieldType)?;
 
@@ -72,15 +178,66 @@ impl<'a> MessageReader<'a> {
         Ok(Some((primitive_type, offset)))
     }
 
+    /// Get the type and complete encoded payload of a field, including any length prefix
+    #[cfg_attr(not(feature = "alloc"), allow(dead_code))]
+    pub(crate) fn field_bytes(
+        &self,
+        field_index: u16,
+    ) -> Result<Option<(PrimitiveType, &'a [u8])>> {
+        let (field_type, field_offset) = match self.field_entry(field_index)? {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+
+        let read_u32_at = |offset: usize| -> Result<usize> {
+            if offset + 4 > self.buffer.len() {
+                return Err(Error::OutOfBounds);
+            }
+            Ok(Endian::Little.read_u32(self.buffer, offset) as usize)
+        };
+
+        let len = match field_type {
+            PrimitiveType::String | PrimitiveType::Bytes | PrimitiveType::Message => {
+                4 + read_u32_at(field_offset)?
+            }
+            PrimitiveType::Union => 6 + read_u32_at(field_offset + 2)?,
+            PrimitiveType::Any => {
+                AnyReader::payload_len(self.buffer.get(field_offset..).ok_or(Error::OutOfBounds)?)?
+            }
+            PrimitiveType::BitVector => 4 + layout::bitmap_len(read_u32_at(field_offset)?),
+            // Without an element type the payload length can't be known
+            PrimitiveType::Vector => return Err(Error::InvalidFieldType),
+            PrimitiveType::TypedVector => {
+                let count = read_u32_at(field_offset)?;
+                let (_, header_len, element_size) = vector_layout(&self.buffer[field_offset..])?;
+                header_len + count * element_size
+            }
+            PrimitiveType::Struct => {
+                if field_offset + 2 > self.buffer.len() {
+                    return Err(Error::OutOfBounds);
+                }
+                2 + Endian::Little.read_u16(self.buffer, field_offset) as usize
+            }
+            PrimitiveType::Unset => return Ok(None),
+            scalar => scalar.size().ok_or(Error::InvalidFieldType)?,
+        };
+
+        self.buffer
+            .get(field_offset..field_offset + len)
+            .map(|bytes| Some((field_type, bytes)))
+            .ok_or(Error::OutOfBounds)
+    }
+
     /// Check whether a field entry exists and is set
     pub fn has_field(&self, field_index: u16) -> Result<bool> {
         if field_index >= self.field_count {
             return Ok(false);
         }
 
-        let entry_offset = self.field_table_offset + field_index as usize * FIELD_ENTRY_SIZE;
-        let type_id = self.buffer[entry_offset];
-        Ok(type_id != PrimitiveType::Unset as u8)
+        match self.entry_offset(field_index) {
+            Some(entry_offset) => Ok(self.buffer[entry_offset] != PrimitiveType::Unset as u8),
+            None => Ok(false),
+        }
     }
 
     /// Get a scalar field value
@@ -112,11 +269,32 @@ impl<'a> MessageReader<'a> {
             .ok_or(Error::MissingField)
     }
 
+    /// Get a union field as its discriminant and variant message
+    pub fn get_union(&self, field_index: u16) -> Result<(u16, MessageReader<'a>)> {
+        self.try_get_union(field_index)?.ok_or(Error::MissingField)
+    }
+
+    /// Get an `any` field
+    pub fn get_any(&self, field_index: u16) -> Result<AnyReader<'a>> {
+        self.try_get_any(field_index)?.ok_or(Error::MissingField)
+    }
+
     /// Get a vector field
     pub fn get_vector<T: ZpRead<'a>>(&self, field_index: u16) -> Result<VectorReader<'a, T>> {
         self.try_get_vector(field_index)?.ok_or(Error::MissingField)
     }
 
+    /// Get a struct field
+    pub fn get_struct<T: ZpRead<'a>>(&self, field_index: u16) -> Result<T> {
+        self.try_get_struct(field_index)?.ok_or(Error::MissingField)
+    }
+
+    /// Get a bit-packed bool vector field
+    pub fn get_bit_vector(&self, field_index: u16) -> Result<BitVectorReader<'a>> {
+        self.try_get_bit_vector(field_index)?
+            .ok_or(Error::MissingField)
+    }
+
     /// Try to get a string field
     pub fn try_get_string(&self, field_index: u16) -> Result<Option<&'a str>> {
         match self.field_entry(field_index)? {
@@ -184,6 +362,48 @@ impl<'a> MessageReader<'a> {
         }
     }
 
+    /// Try to get a union field
+    pub fn try_get_union(&self, field_index: u16) -> Result<Option<(u16, MessageReader<'a>)>> {
+        match self.field_entry(field_index)? {
+            Some((field_type, field_offset)) => {
+                if field_type != PrimitiveType::Union {
+                    return Err(Error::InvalidFieldType);
+                }
+
+                if field_offset + 6 > self.buffer.len() {
+                    return Err(Error::OutOfBounds);
+                }
+
+                let discriminant = Endian::Little.read_u16(self.buffer, field_offset);
+                let len = Endian::Little.read_u32(self.buffer, field_offset + 2) as usize;
+                let message_offset = field_offset + 6;
+
+                if message_offset + len > self.buffer.len() {
+                    return Err(Error::OutOfBounds);
+                }
+
+                let message_buffer = &self.buffer[message_offset..message_offset + len];
+                Ok(Some((discriminant, MessageReader::new(message_buffer)?)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Try to get an `any` field
+    pub fn try_get_any(&self, field_index: u16) -> Result<Option<AnyReader<'a>>> {
+        match self.field_entry(field_index)? {
+            Some((field_type, field_offset)) => {
+                if field_type != PrimitiveType::Any {
+                    return Err(Error::InvalidFieldType);
+                }
+
+                let payload = self.buffer.get(field_offset..).ok_or(Error::OutOfBounds)?;
+                AnyReader::parse(payload).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
     /// Try to get a vector field
     pub fn try_get_vector<T: ZpRead<'a>>(
         &self,
@@ -191,13 +411,26 @@ impl<'a> MessageReader<'a> {
     ) -> Result<Option<VectorReader<'a, T>>> {
         match self.field_entry(field_index)? {
             Some((field_type, field_offset)) => {
-                if field_type != PrimitiveType::Vector {
-                    return Err(Error::InvalidFieldType);
+                if field_offset + 4 > self.buffer.len() {
+                    return Err(Error::OutOfBounds);
                 }
 
                 let count = Endian::Little.read_u32(self.buffer, field_offset) as usize;
-                let vector_offset = field_offset + 4;
-
+                let header_len = match field_type {
+                    // Untyped vectors are trusted to hold `T`, as in 0.4.0
+                    PrimitiveType::Vector => 4,
+                    PrimitiveType::TypedVector => {
+                        let (_, header_len, element_size) =
+                            vector_layout(&self.buffer[field_offset..])?;
+                        if element_size != T::size() {
+                            return Err(Error::InvalidFieldType);
+                        }
+                        header_len
+                    }
+                    _ => return Err(Error::InvalidFieldType),
+                };
+
+                let vector_offset = field_offset + header_len;
                 if vector_offset + count * T::size() > self.buffer.len() {
                     return Err(Error::OutOfBounds);
                 }
@@ -212,6 +445,82 @@ impl<'a> MessageReader<'a> {
             None => Ok(None),
         }
     }
+
+    /// Try to get a struct field
+    ///
+    /// The stored struct must be exactly `T::size()` bytes.
+    pub fn try_get_struct<T: ZpRead<'a>>(&self, field_index: u16) -> Result<Option<T>> {
+        match self.field_entry(field_index)? {
+            Some((field_type, field_offset)) => {
+                if field_type != PrimitiveType::Struct {
+                    return Err(Error::InvalidFieldType);
+                }
+
+                if field_offset + 2 > self.buffer.len() {
+                    return Err(Error::OutOfBounds);
+                }
+
+                let size = Endian::Little.read_u16(self.buffer, field_offset) as usize;
+                if size != T::size() {
+                    return Err(Error::InvalidFieldType);
+                }
+
+                T::read(self.buffer, field_offset + 2).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Try to get a bit-packed bool vector field
+    pub fn try_get_bit_vector(&self, field_index: u16) -> Result<Option<BitVectorReader<'a>>> {
+        match self.field_entry(field_index)? {
+            Some((field_type, field_offset)) => {
+                if field_type != PrimitiveType::BitVector {
+                    return Err(Error::InvalidFieldType);
+                }
+
+                if field_offset + 4 > self.buffer.len() {
+                    return Err(Error::OutOfBounds);
+                }
+
+                let count = Endian::Little.read_u32(self.buffer, field_offset) as usize;
+                let bits_offset = field_offset + 4;
+                let bits_len = layout::bitmap_len(count);
+
+                if bits_offset + bits_len > self.buffer.len() {
+                    return Err(Error::OutOfBounds);
+                }
+
+                Ok(Some(BitVectorReader {
+                    bits: &self.buffer[bits_offset..bits_offset + bits_len],
+                    count,
+                }))
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+/// Read the element type, header length and element size of an encoded typed vector
+///
+/// Scalar elements are sized by their type; struct elements record their stride after
+/// the element type.
+pub(crate) fn vector_layout(payload: &[u8]) -> Result<(PrimitiveType, usize, usize)> {
+    if payload.len() < 5 {
+        return Err(Error::OutOfBounds);
+    }
+    let element_type = PrimitiveType::from_u8(payload[4]).ok_or(Error::InvalidFieldType)?;
+
+    if element_type == PrimitiveType::Struct {
+        if payload.len() < 7 {
+            return Err(Error::OutOfBounds);
+        }
+        let stride = Endian::Little.read_u16(payload, 5) as usize;
+        return Ok((element_type, 7, stride));
+    }
+
+    let size = element_type.size().ok_or(Error::InvalidFieldType)?;
+    Ok((element_type, 5, size))
 }
 
 /// A zero-copy vector reader
@@ -245,33 +554,186 @@ impl<'a, T: ZpRead<'a>> VectorReader<'a, T> {
     }
 
     /// Get an iterator over the elements
-    pub fn iter(&self) -> Box<dyn Iterator<Item = Result<T>> + '_> {
-        Box::new((0..self.count).map(move |i| self.get(i)))
+    pub fn iter(&self) -> VectorIter<'a, T> {
+        VectorIter {
+            buffer: self.buffer,
+            offset: self.offset,
+            front: 0,
+            back: self.count,
+            _phantom: core::marker::PhantomData,
+        }
     }
 
     /// Collect all elements into a Vec
+    #[cfg(feature = "alloc")]
     pub fn collect(&self) -> Result<Vec<T>> {
         self.iter().collect()
     }
 }
 
-impl<'a, T: ZpRead<'a> + 'a> IntoIterator for VectorReader<'a, T> {
+impl<'a, T: ZpRead<'a>> IntoIterator for VectorReader<'a, T> {
     type Item = Result<T>;
-    type IntoIter = Box<dyn Iterator<Item = Result<T>> + 'a>;
+    type IntoIter = VectorIter<'a, T>;
 
     fn into_iter(self) -> Self::IntoIter {
-        Box::new((0..self.count).map(move |i| self.get(i)))
+        self.iter()
+    }
+}
+
+impl<'a, T: ZpRead<'a>> IntoIterator for &VectorReader<'a, T> {
+    type Item = Result<T>;
+    type IntoIter = VectorIter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Iterator over the elements of a [`VectorReader`]
+#[derive(Debug)]
+pub struct VectorIter<'a, T> {
+    buffer: &'a [u8],
+    offset: usize,
+    front: usize,
+    back: usize,
+    _phantom: core::marker::PhantomData<T>,
+}
+
+impl<'a, T: ZpRead<'a>> VectorIter<'a, T> {
+    /// Read the element at the given index
+    fn read_at(&self, index: usize) -> Result<T> {
+        T::read(self.buffer, self.offset + index * T::size())
     }
 }
 
-#[cfg(test)]
+impl<'a, T: ZpRead<'a>> Iterator for VectorIter<'a, T> {
+    type Item = Result<T>;
+
+    fn next(&mut self) -> Option<Result<T>> {
+        if self.front >= self.back {
+            return None;
+        }
+
+        let element = self.read_at(self.front);
+        self.front += 1;
+        Some(element)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.back - self.front;
+        (remaining, Some(remaining))
+    }
+
+    fn nth(&mut self, n: usize) -> Option<Result<T>> {
+        self.front = self.front.saturating_add(n).min(self.back);
+        self.next()
+    }
+}
+
+impl<'a, T: ZpRead<'a>> DoubleEndedIterator for VectorIter<'a, T> {
+    fn next_back(&mut self) -> Option<Result<T>> {
+        if self.front >= self.back {
+            return None;
+        }
+
+        self.back -= 1;
+        Some(self.read_at(self.back))
+    }
+}
+
+impl<'a, T: ZpRead<'a>> ExactSizeIterator for VectorIter<'a, T> {}
+
+impl<'a, T: ZpRead<'a>> core::iter::FusedIterator for VectorIter<'a, T> {}
+
+/// A zero-copy reader over a bit-packed bool vector
+#[derive(Debug, Clone, Copy)]
+pub struct BitVectorReader<'a> {
+    bits: &'a [u8],
+    count: usize,
+}
+
+impl<'a> BitVectorReader<'a> {
+    /// Get the number of bools in the vector
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    /// Check if the vector is empty
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Get the bool at the given index
+    pub fn get(&self, index: usize) -> Result<bool> {
+        if index >= self.count {
+            return Err(Error::OutOfBounds);
+        }
+
+        Ok(layout::bit_at(self.bits, index))
+    }
+
+    /// Get an iterator over the bools
+    pub fn iter(&self) -> BitIter<'a> {
+        BitIter {
+            bits: self.bits,
+            index: 0,
+            count: self.count,
+        }
+    }
+
+    /// Count the bools that are `true`
+    pub fn count_ones(&self) -> usize {
+        layout::rank(self.bits, self.count)
+    }
+}
+
+impl<'a> IntoIterator for BitVectorReader<'a> {
+    type Item = bool;
+    type IntoIter = BitIter<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Iterator over the bools of a [`BitVectorReader`]
+#[derive(Debug, Clone)]
+pub struct BitIter<'a> {
+    bits: &'a [u8],
+    index: usize,
+    count: usize,
+}
+
+impl Iterator for BitIter<'_> {
+    type Item = bool;
+
+    fn next(&mut self) -> Option<bool> {
+        if self.index >= self.count {
+            return None;
+        }
+
+        let bit = layout::bit_at(self.bits, self.index);
+        self.index += 1;
+        Some(bit)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.count - self.index;
+        (remaining, Some(remaining))
+    }
+}
+
+impl ExactSizeIterator for BitIter<'_> {}
+
+impl core::iter::FusedIterator for BitIter<'_> {}
+
+#[cfg(all(test, feature = "alloc"))]
 mod tests {
     use super::*;
     use crate::builder::MessageBuilder;
-    use crate::primitives::Endian;
 
-    #[cfg(feature = "std")]
-    use std::println;
+    #[cfg(not(feature = "std"))]
+    use alloc::vec;
     #[cfg(feature = "std")]
     use std::vec;
 
@@ -297,8 +759,6 @@ mod tests {
         builder.set_scalar(0, 42u16).unwrap();
         let buffer = builder.finish();
 
-        println!("Reader buffer: {:?}", buffer);
-
         let reader = MessageReader::new(&buffer).unwrap();
         let value: u16 = reader.get_scalar(0).unwrap();
         assert_eq!(value, 42);
@@ -357,6 +817,83 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_reader_untyped_vector() -> Result<()> {
+        // A `[u16]` field as written by 0.4.0: count, then elements with no element type
+        let data = [1, 0, 14, 7, 0, 0, 0, 3, 0, 0, 0, 1, 0, 2, 0, 3, 0];
+        let reader = MessageReader::new(&data)?;
+        let values: Vec<u16> = reader.get_vector(0)?.collect()?;
+        assert_eq!(values, vec![1, 2, 3]);
+
+        // Its length depends on the element type, so it can't be copied without a schema
+        assert_eq!(reader.canonicalize(), Err(Error::InvalidFieldType));
+
+        // Element types the builder doesn't know about are still written this way
+        #[derive(Debug, PartialEq)]
+        struct Rgb(u8, u8, u8);
+        impl crate::ZpWrite for Rgb {
+            fn write(&self, buf: &mut [u8], offset: usize) -> Result<()> {
+                buf[offset..offset + 3].copy_from_slice(&[self.0, self.1, self.2]);
+                Ok(())
+            }
+            fn size(&self) -> usize {
+                3
+            }
+        }
+        impl<'b> ZpRead<'b> for Rgb {
+            fn read(buf: &'b [u8], offset: usize) -> Result<Self> {
+                Ok(Rgb(buf[offset], buf[offset + 1], buf[offset + 2]))
+            }
+            fn size() -> usize {
+                3
+            }
+        }
+
+        let mut builder = MessageBuilder::new();
+        builder.set_vector(0, &[Rgb(1, 2, 3), Rgb(4, 5, 6)])?;
+        let data = builder.finish();
+        let reader = MessageReader::new(&data)?;
+        assert_eq!(
+            reader.field_entry(0)?.map(|(field_type, _)| field_type),
+            Some(PrimitiveType::Vector)
+        );
+        let values: Vec<Rgb> = reader.get_vector(0)?.collect()?;
+        assert_eq!(values, vec![Rgb(1, 2, 3), Rgb(4, 5, 6)]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_vector_iter() -> Result<()> {
+        let mut builder = MessageBuilder::new();
+        builder.set_vector(0, &[10u16, 20, 30, 40])?;
+        let data = builder.finish();
+
+        let reader = MessageReader::new(&data)?;
+        let vector = reader.get_vector::<u16>(0)?;
+
+        let mut iter = vector.iter();
+        assert_eq!(iter.len(), 4);
+        assert_eq!(iter.next(), Some(Ok(10)));
+        assert_eq!(iter.next_back(), Some(Ok(40)));
+        assert_eq!(iter.len(), 2);
+        assert_eq!(iter.next_back(), Some(Ok(30)));
+        assert_eq!(iter.next(), Some(Ok(20)));
+        assert_eq!(iter.next(), None);
+        assert_eq!(iter.next_back(), None);
+
+        assert_eq!(vector.iter().nth(2), Some(Ok(30)));
+        assert_eq!(vector.iter().nth(9), None);
+
+        let mut sum = 0;
+        for value in &vector {
+            sum += value?;
+        }
+        assert_eq!(sum, 100);
+
+        Ok(())
+    }
+
     #[test]
     fn test_reader_nested_message() -> Result<()> {
         let mut nested_builder = MessageBuilder::new();
@@ -375,6 +912,189 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_reader_union() -> Result<()> {
+        let mut variant_builder = MessageBuilder::new();
+        variant_builder.set_string(0, "stop")?;
+        let variant_data = variant_builder.finish();
+
+        let mut builder = MessageBuilder::new();
+        builder.set_union(1, 3, &variant_data)?;
+        let data = builder.finish();
+
+        let reader = MessageReader::new(&data)?;
+        assert_eq!(reader.try_get_union(0)?.map(|(d, _)| d), None);
+
+        let (discriminant, variant) = reader.get_union(1)?;
+        assert_eq!(discriminant, 3);
+        assert_eq!(variant.get_string(0)?, "stop");
+
+        // Unions are not readable as plain nested messages
+        assert!(matches!(
+            reader.get_message(1),
+            Err(Error::InvalidFieldType)
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_reader_bit_vector() -> Result<()> {
+        let flags = [
+            true, true, false, true, false, false, true, false, false, true,
+        ];
+        let mut builder = MessageBuilder::new();
+        builder.set_bit_vector(0, &flags)?;
+        builder.set_bit_vector(1, &[])?;
+        let data = builder.finish();
+
+        let reader = MessageReader::new(&data)?;
+        let bits = reader.get_bit_vector(0)?;
+        assert_eq!(bits.len(), 10);
+        assert_eq!(bits.count_ones(), 5);
+        assert!(bits.get(9)?);
+        assert!(matches!(bits.get(10), Err(Error::OutOfBounds)));
+        assert_eq!(bits.iter().len(), 10);
+        assert!(bits.iter().eq(flags.iter().copied()));
+
+        assert!(reader.get_bit_vector(1)?.is_empty());
+        assert!(matches!(
+            reader.get_vector::<bool>(0),
+            Err(Error::InvalidFieldType)
+        ));
+
+        Ok(())
+    }
+
+    /// A fixed-layout struct, as generated code would define one
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    struct Point {
+        x: i16,
+        y: i16,
+    }
+
+    impl<'a> ZpRead<'a> for Point {
+        fn read(buf: &'a [u8], offset: usize) -> Result<Self> {
+            Ok(Point {
+                x: i16::read(buf, offset)?,
+                y: i16::read(buf, offset + 2)?,
+            })
+        }
+
+        fn size() -> usize {
+            4
+        }
+    }
+
+    impl crate::ZpWrite for Point {
+        fn write(&self, buf: &mut [u8], offset: usize) -> Result<()> {
+            self.x.write(buf, offset)?;
+            self.y.write(buf, offset + 2)
+        }
+
+        fn size(&self) -> usize {
+            4
+        }
+    }
+
+    #[test]
+    fn test_reader_struct() -> Result<()> {
+        let points = [Point { x: 1, y: -2 }, Point { x: 3, y: 4 }];
+        let mut builder = MessageBuilder::new();
+        builder.set_struct(0, &Point { x: 5, y: 6 })?;
+        builder.set_struct_vector(1, &points)?;
+        builder.set_struct_vector::<Point>(2, &[])?;
+        let data = builder.finish();
+
+        let reader = MessageReader::new(&data)?;
+        assert_eq!(reader.get_struct::<Point>(0)?, Point { x: 5, y: 6 });
+        let vector = reader.get_vector::<Point>(1)?;
+        assert_eq!(vector.len(), 2);
+        assert_eq!(vector.get(1)?, points[1]);
+        assert_eq!(vector.collect()?, points);
+        assert!(reader.get_vector::<Point>(2)?.is_empty());
+        assert_eq!(reader.field_bytes(1)?.unwrap().1.len(), 7 + 2 * 4);
+
+        // The stored size must match the type asked for
+        assert!(matches!(
+            reader.get_struct::<u64>(0),
+            Err(Error::InvalidFieldType)
+        ));
+        assert!(matches!(
+            reader.get_vector::<u64>(1),
+            Err(Error::InvalidFieldType)
+        ));
+        assert!(matches!(
+            reader.get_message(0),
+            Err(Error::InvalidFieldType)
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_reader_bitmap_layout() -> Result<()> {
+        let mut builder = MessageBuilder::with_layout(FieldTableLayout::Bitmap);
+        builder.set_string(1, "sparse")?;
+        builder.set_scalar(9, 42u32)?;
+        let data = builder.finish();
+
+        let reader = MessageReader::new(&data)?;
+        assert_eq!(reader.layout(), FieldTableLayout::Bitmap);
+        assert_eq!(reader.field_count(), 10);
+        assert!(!reader.has_field(0)?);
+        assert!(reader.has_field(1)?);
+        assert_eq!(reader.try_get_scalar::<u32>(5)?, None);
+        assert_eq!(reader.get_string(1)?, "sparse");
+        assert_eq!(reader.get_scalar::<u32>(9)?, 42);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_reader_sparse_layout() -> Result<()> {
+        let mut builder = MessageBuilder::with_layout(FieldTableLayout::Sparse);
+        builder.set_scalar(3, 30u16)?;
+        builder.set_string(1000, "far")?;
+        builder.set_scalar(40000, 7u8)?;
+        let data = builder.finish();
+
+        let reader = MessageReader::new(&data)?;
+        assert_eq!(reader.layout(), FieldTableLayout::Sparse);
+        assert_eq!(reader.field_count(), 40001);
+        assert_eq!(reader.get_scalar::<u16>(3)?, 30);
+        assert_eq!(reader.get_string(1000)?, "far");
+        assert_eq!(reader.get_scalar::<u8>(40000)?, 7);
+        assert!(!reader.has_field(0)?);
+        assert!(!reader.has_field(999)?);
+        assert_eq!(reader.try_get_scalar::<u8>(39999)?, None);
+        assert!(matches!(
+            reader.get_scalar::<u8>(40001),
+            Err(Error::MissingField)
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_reader_rejects_truncated_sparse_table() {
+        // Claims two entries but carries none
+        let buffer = [0xFF, 0xFF, 2, 10, 0, 2, 0];
+        assert!(matches!(
+            MessageReader::new(&buffer),
+            Err(Error::InvalidMessage)
+        ));
+    }
+
+    #[test]
+    fn test_reader_rejects_unknown_extended_layout() {
+        let buffer = [0xFF, 0xFF, 0xEE, 0, 0];
+        assert!(matches!(
+            MessageReader::new(&buffer),
+            Err(Error::InvalidMessage)
+        ));
+    }
+
     #[test]
     fn test_reader_field_count() -> Result<()> {
         let mut builder = MessageBuilder::new();
@@ -398,7 +1118,27 @@ mod tests {
         let reader = MessageReader::new(&data).unwrap();
         let result: Result<u64> = reader.get_scalar(1);
         assert!(result.is_err());
-        assert!(matches!(result.unwrap_err(), Error::OutOfBounds));
+        assert!(matches!(result.unwrap_err(), Error::MissingField));
+    }
+
+    #[test]
+    fn test_reader_fields_past_field_count() -> Result<()> {
+        // An older writer only knew about field 0
+        let mut builder = MessageBuilder::new();
+        builder.set_scalar(0, 42u64)?;
+        let data = builder.finish();
+
+        // A newer reader asks for fields added since, which read as unset
+        let reader = MessageReader::new(&data)?;
+        assert_eq!(reader.field_count(), 1);
+        assert!(!reader.has_field(1)?);
+        assert_eq!(reader.try_get_scalar::<u32>(1)?, None);
+        assert_eq!(reader.try_get_string(2)?, None);
+        assert!(reader.try_get_message(3)?.is_none());
+        assert!(reader.try_get_vector::<u16>(4)?.is_none());
+        assert_eq!(reader.get_scalar::<u64>(0)?, 42);
+
+        Ok(())
     }
 
     #[test]
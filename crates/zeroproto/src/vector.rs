@@ -81,7 +81,7 @@ impl<T> Vector<T> {
         self.elements.clear();
     }
 
-    /// Convert to a Vec<T>
+    /// Convert to a `Vec<T>`
     pub fn into_vec(self) -> Vec<T> {
         self.elements
     }
@@ -236,9 +236,9 @@ impl VectorUtils {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::builder::MessageBuilder;
-    use crate::reader::MessageReader;
 
+    #[cfg(not(feature = "std"))]
+    use alloc::vec;
     #[cfg(feature = "std")]
     use std::vec;
 
@@ -272,6 +272,18 @@ mod tests {
         assert_eq!(size, 4 + 3 * 4); // count + 3 u32s
     }
 
+    #[test]
+    fn test_vector_write_read_roundtrip() {
+        let elements = vec![7u16, 8u16, 9u16];
+        let mut buffer = vec![0u8; VectorUtils::calculate_serialized_size(&elements)];
+        let written = VectorUtils::write_vector(&elements, &mut buffer, 0).unwrap();
+        assert_eq!(written, buffer.len());
+
+        let (decoded, read) = VectorUtils::read_vector::<u16>(&buffer, 0).unwrap();
+        assert_eq!(decoded, elements);
+        assert_eq!(read, written);
+    }
+
     #[test]
     fn test_empty_vector_serialization() {
         let elements: Vec<u32> = vec![];
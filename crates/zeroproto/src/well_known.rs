@@ -0,0 +1,577 @@
+//! Well-known types shared by every schema
+//!
+//! Schemas write these as `timestamp`, `duration`, `uuid` and `decimal`. Each one has a
+//! fixed-size layout and its own type ID, so they work as fields, vector elements and
+//! struct fields like any other scalar.
+
+use core::fmt;
+
+use crate::{
+    errors::{Error, Result},
+    primitives::Endian,
+    ZpRead, ZpWrite,
+};
+
+/// Nanoseconds in one second
+const NANOS_PER_SECOND: u32 = 1_000_000_000;
+
+/// Largest scale a [`Decimal`] can have
+const MAX_DECIMAL_SCALE: u8 = 38;
+
+/// A point in time, as seconds and nanoseconds since the Unix epoch (UTC)
+///
+/// Encoded as an `i64` second count followed by a `u32` nanosecond count below one
+/// billion. Times before the epoch have negative seconds and count nanoseconds forward.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Timestamp {
+    seconds: i64,
+    nanos: u32,
+}
+
+impl Timestamp {
+    /// Encoded size in bytes
+    pub const SIZE: usize = 12;
+
+    /// The Unix epoch, 1970-01-01T00:00:00Z
+    pub const UNIX_EPOCH: Timestamp = Timestamp {
+        seconds: 0,
+        nanos: 0,
+    };
+
+    /// Create a timestamp, rejecting a nanosecond count of one second or more
+    pub fn new(seconds: i64, nanos: u32) -> Result<Self> {
+        if nanos >= NANOS_PER_SECOND {
+            return Err(Error::InvalidFormat);
+        }
+        Ok(Self { seconds, nanos })
+    }
+
+    /// The current time
+    #[cfg(feature = "std")]
+    pub fn now() -> Self {
+        std::time::SystemTime::now()
+            .try_into()
+            .expect("System time out of range")
+    }
+
+    /// Whole seconds since the Unix epoch
+    pub fn seconds(&self) -> i64 {
+        self.seconds
+    }
+
+    /// Nanoseconds past [`seconds`](Self::seconds)
+    pub fn subsec_nanos(&self) -> u32 {
+        self.nanos
+    }
+}
+
+impl<'a> ZpRead<'a> for Timestamp {
+    fn read(buf: &'a [u8], offset: usize) -> Result<Self> {
+        if offset + Self::SIZE > buf.len() {
+            return Err(Error::OutOfBounds);
+        }
+        Self::new(
+            Endian::Little.read_i64(buf, offset),
+            Endian::Little.read_u32(buf, offset + 8),
+        )
+    }
+
+    fn size() -> usize {
+        Self::SIZE
+    }
+}
+
+impl ZpWrite for Timestamp {
+    fn write(&self, buf: &mut [u8], offset: usize) -> Result<()> {
+        if offset + Self::SIZE > buf.len() {
+            return Err(Error::OutOfBounds);
+        }
+        Endian::Little.write_i64(self.seconds, buf, offset);
+        Endian::Little.write_u32(self.nanos, buf, offset + 8);
+        Ok(())
+    }
+
+    fn size(&self) -> usize {
+        Self::SIZE
+    }
+}
+
+#[cfg(feature = "std")]
+impl TryFrom<std::time::SystemTime> for Timestamp {
+    type Error = Error;
+
+    fn try_from(time: std::time::SystemTime) -> Result<Self> {
+        match time.duration_since(std::time::UNIX_EPOCH) {
+            Ok(since) => Ok(Self {
+                seconds: i64::try_from(since.as_secs()).map_err(|_| Error::InvalidFormat)?,
+                nanos: since.subsec_nanos(),
+            }),
+            Err(before) => {
+                let before = before.duration();
+                let seconds = i64::try_from(before.as_secs()).map_err(|_| Error::InvalidFormat)?;
+                if before.subsec_nanos() == 0 {
+                    Ok(Self {
+                        seconds: -seconds,
+                        nanos: 0,
+                    })
+                } else {
+                    Ok(Self {
+                        seconds: -seconds - 1,
+                        nanos: NANOS_PER_SECOND - before.subsec_nanos(),
+                    })
+                }
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl TryFrom<Timestamp> for std::time::SystemTime {
+    type Error = Error;
+
+    fn try_from(timestamp: Timestamp) -> Result<Self> {
+        let epoch = std::time::UNIX_EPOCH;
+        let time = if timestamp.seconds >= 0 {
+            epoch.checked_add(core::time::Duration::new(
+                timestamp.seconds as u64,
+                timestamp.nanos,
+            ))
+        } else {
+            epoch
+                .checked_sub(core::time::Duration::from_secs(
+                    timestamp.seconds.unsigned_abs(),
+                ))
+                .and_then(|time| {
+                    time.checked_add(core::time::Duration::from_nanos(timestamp.nanos as u64))
+                })
+        };
+        time.ok_or(Error::InvalidFormat)
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl From<chrono::DateTime<chrono::Utc>> for Timestamp {
+    fn from(time: chrono::DateTime<chrono::Utc>) -> Self {
+        // chrono counts a leap second as nanoseconds past one billion
+        let nanos = time.timestamp_subsec_nanos();
+        if nanos >= NANOS_PER_SECOND {
+            Self {
+                seconds: time.timestamp() + 1,
+                nanos: nanos - NANOS_PER_SECOND,
+            }
+        } else {
+            Self {
+                seconds: time.timestamp(),
+                nanos,
+            }
+        }
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl TryFrom<Timestamp> for chrono::DateTime<chrono::Utc> {
+    type Error = Error;
+
+    fn try_from(timestamp: Timestamp) -> Result<Self> {
+        chrono::DateTime::from_timestamp(timestamp.seconds, timestamp.nanos)
+            .ok_or(Error::InvalidFormat)
+    }
+}
+
+/// A signed span of time, as seconds and nanoseconds
+///
+/// Encoded as an `i64` second count followed by an `i32` nanosecond count with the same
+/// sign and a magnitude below one billion.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Duration {
+    seconds: i64,
+    nanos: i32,
+}
+
+impl Duration {
+    /// Encoded size in bytes
+    pub const SIZE: usize = 12;
+
+    /// A zero-length duration
+    pub const ZERO: Duration = Duration {
+        seconds: 0,
+        nanos: 0,
+    };
+
+    /// Create a duration, rejecting nanoseconds of one second or more or whose sign
+    /// differs from `seconds`
+    pub fn new(seconds: i64, nanos: i32) -> Result<Self> {
+        let mixed_signs = (seconds < 0 && nanos > 0) || (seconds > 0 && nanos < 0);
+        if nanos.unsigned_abs() >= NANOS_PER_SECOND || mixed_signs {
+            return Err(Error::InvalidFormat);
+        }
+        Ok(Self { seconds, nanos })
+    }
+
+    /// Whole seconds in this duration
+    pub fn seconds(&self) -> i64 {
+        self.seconds
+    }
+
+    /// Nanoseconds past [`seconds`](Self::seconds), with the same sign
+    pub fn subsec_nanos(&self) -> i32 {
+        self.nanos
+    }
+
+    /// Whether this duration is shorter than zero
+    pub fn is_negative(&self) -> bool {
+        self.seconds < 0 || self.nanos < 0
+    }
+}
+
+impl<'a> ZpRead<'a> for Duration {
+    fn read(buf: &'a [u8], offset: usize) -> Result<Self> {
+        if offset + Self::SIZE > buf.len() {
+            return Err(Error::OutOfBounds);
+        }
+        Self::new(
+            Endian::Little.read_i64(buf, offset),
+            Endian::Little.read_i32(buf, offset + 8),
+        )
+    }
+
+    fn size() -> usize {
+        Self::SIZE
+    }
+}
+
+impl ZpWrite for Duration {
+    fn write(&self, buf: &mut [u8], offset: usize) -> Result<()> {
+        if offset + Self::SIZE > buf.len() {
+            return Err(Error::OutOfBounds);
+        }
+        Endian::Little.write_i64(self.seconds, buf, offset);
+        Endian::Little.write_i32(self.nanos, buf, offset + 8);
+        Ok(())
+    }
+
+    fn size(&self) -> usize {
+        Self::SIZE
+    }
+}
+
+impl TryFrom<core::time::Duration> for Duration {
+    type Error = Error;
+
+    fn try_from(duration: core::time::Duration) -> Result<Self> {
+        Ok(Self {
+            seconds: i64::try_from(duration.as_secs()).map_err(|_| Error::InvalidFormat)?,
+            nanos: duration.subsec_nanos() as i32,
+        })
+    }
+}
+
+impl TryFrom<Duration> for core::time::Duration {
+    type Error = Error;
+
+    fn try_from(duration: Duration) -> Result<Self> {
+        if duration.is_negative() {
+            return Err(Error::InvalidFormat);
+        }
+        Ok(core::time::Duration::new(
+            duration.seconds as u64,
+            duration.nanos as u32,
+        ))
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl From<chrono::Duration> for Duration {
+    fn from(duration: chrono::Duration) -> Self {
+        Self {
+            seconds: duration.num_seconds(),
+            nanos: duration.subsec_nanos(),
+        }
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl TryFrom<Duration> for chrono::Duration {
+    type Error = Error;
+
+    fn try_from(duration: Duration) -> Result<Self> {
+        chrono::Duration::try_seconds(duration.seconds)
+            .and_then(|seconds| {
+                seconds.checked_add(&chrono::Duration::nanoseconds(duration.nanos as i64))
+            })
+            .ok_or(Error::InvalidFormat)
+    }
+}
+
+/// A 128-bit universally unique identifier
+///
+/// Encoded as its 16 bytes in the order they appear in the usual text form
+/// (RFC 9562 byte order), not little-endian.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Uuid([u8; 16]);
+
+impl Uuid {
+    /// Encoded size in bytes
+    pub const SIZE: usize = 16;
+
+    /// The all-zero UUID
+    pub const NIL: Uuid = Uuid([0; 16]);
+
+    /// Create a UUID from its bytes
+    pub const fn from_bytes(bytes: [u8; 16]) -> Self {
+        Self(bytes)
+    }
+
+    /// The UUID's bytes
+    pub const fn as_bytes(&self) -> &[u8; 16] {
+        &self.0
+    }
+}
+
+impl fmt::Display for Uuid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, byte) in self.0.iter().enumerate() {
+            if matches!(i, 4 | 6 | 8 | 10) {
+                f.write_str("-")?;
+            }
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a> ZpRead<'a> for Uuid {
+    fn read(buf: &'a [u8], offset: usize) -> Result<Self> {
+        let bytes = buf
+            .get(offset..offset + Self::SIZE)
+            .ok_or(Error::OutOfBounds)?;
+        let mut uuid = [0; 16];
+        uuid.copy_from_slice(bytes);
+        Ok(Self(uuid))
+    }
+
+    fn size() -> usize {
+        Self::SIZE
+    }
+}
+
+impl ZpWrite for Uuid {
+    fn write(&self, buf: &mut [u8], offset: usize) -> Result<()> {
+        buf.get_mut(offset..offset + Self::SIZE)
+            .ok_or(Error::OutOfBounds)?
+            .copy_from_slice(&self.0);
+        Ok(())
+    }
+
+    fn size(&self) -> usize {
+        Self::SIZE
+    }
+}
+
+#[cfg(feature = "uuid")]
+impl From<uuid::Uuid> for Uuid {
+    fn from(uuid: uuid::Uuid) -> Self {
+        Self(*uuid.as_bytes())
+    }
+}
+
+#[cfg(feature = "uuid")]
+impl From<Uuid> for uuid::Uuid {
+    fn from(uuid: Uuid) -> Self {
+        uuid::Uuid::from_bytes(uuid.0)
+    }
+}
+
+/// A decimal number, as an integer mantissa scaled by a power of ten
+///
+/// The value is `mantissa / 10^scale`. Encoded as a little-endian `i128` mantissa
+/// followed by a `u8` scale of at most 38. Equality compares the mantissa and scale, so
+/// `1.0` and `1.00` are different values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Decimal {
+    mantissa: i128,
+    scale: u8,
+}
+
+impl Decimal {
+    /// Encoded size in bytes
+    pub const SIZE: usize = 17;
+
+    /// Create a decimal, rejecting a scale above 38
+    pub fn new(mantissa: i128, scale: u8) -> Result<Self> {
+        if scale > MAX_DECIMAL_SCALE {
+            return Err(Error::InvalidFormat);
+        }
+        Ok(Self { mantissa, scale })
+    }
+
+    /// The unscaled integer value
+    pub fn mantissa(&self) -> i128 {
+        self.mantissa
+    }
+
+    /// Number of digits after the decimal point
+    pub fn scale(&self) -> u8 {
+        self.scale
+    }
+}
+
+impl fmt::Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let divisor = 10u128.pow(self.scale as u32);
+        let magnitude = self.mantissa.unsigned_abs();
+        if self.mantissa < 0 {
+            f.write_str("-")?;
+        }
+        write!(f, "{}", magnitude / divisor)?;
+        if self.scale > 0 {
+            write!(
+                f,
+                ".{:0width$}",
+                magnitude % divisor,
+                width = self.scale as usize
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a> ZpRead<'a> for Decimal {
+    fn read(buf: &'a [u8], offset: usize) -> Result<Self> {
+        let bytes = buf
+            .get(offset..offset + Self::SIZE)
+            .ok_or(Error::OutOfBounds)?;
+        let mut mantissa = [0; 16];
+        mantissa.copy_from_slice(&bytes[..16]);
+        Self::new(i128::from_le_bytes(mantissa), bytes[16])
+    }
+
+    fn size() -> usize {
+        Self::SIZE
+    }
+}
+
+impl ZpWrite for Decimal {
+    fn write(&self, buf: &mut [u8], offset: usize) -> Result<()> {
+        let bytes = buf
+            .get_mut(offset..offset + Self::SIZE)
+            .ok_or(Error::OutOfBounds)?;
+        bytes[..16].copy_from_slice(&self.mantissa.to_le_bytes());
+        bytes[16] = self.scale;
+        Ok(())
+    }
+
+    fn size(&self) -> usize {
+        Self::SIZE
+    }
+}
+
+#[cfg(all(test, feature = "alloc"))]
+mod tests {
+    use super::*;
+    use crate::{MessageBuilder, MessageReader};
+    #[cfg(not(feature = "std"))]
+    use alloc::string::ToString;
+    #[cfg(feature = "std")]
+    use std::string::ToString;
+
+    #[test]
+    fn test_well_known_roundtrip() -> Result<()> {
+        let timestamp = Timestamp::new(-2, 500)?;
+        let duration = Duration::new(-90, -5)?;
+        let uuid = Uuid::from_bytes([0x12; 16]);
+        let price = Decimal::new(-1999, 2)?;
+
+        let mut builder = MessageBuilder::new();
+        builder.set_scalar(0, timestamp)?;
+        builder.set_scalar(1, duration)?;
+        builder.set_scalar(2, uuid)?;
+        builder.set_scalar(3, price)?;
+        builder.set_vector(4, &[Timestamp::UNIX_EPOCH, timestamp])?;
+        let bytes = builder.finish();
+
+        let reader = MessageReader::new(&bytes)?;
+        assert_eq!(reader.get_scalar::<Timestamp>(0)?, timestamp);
+        assert_eq!(reader.get_scalar::<Duration>(1)?, duration);
+        assert_eq!(reader.get_scalar::<Uuid>(2)?, uuid);
+        assert_eq!(reader.get_scalar::<Decimal>(3)?, price);
+        let (field_type, payload) = reader.field_bytes(2)?.ok_or(Error::MissingField)?;
+        assert_eq!(field_type, crate::PrimitiveType::Uuid);
+        assert_eq!(payload, &[0x12; 16]);
+        let timestamps = reader.get_vector::<Timestamp>(4)?;
+        assert_eq!(timestamps.get(1)?, timestamp);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_well_known_validation() {
+        assert_eq!(Timestamp::new(0, 1_000_000_000), Err(Error::InvalidFormat));
+        assert_eq!(Duration::new(1, -1), Err(Error::InvalidFormat));
+        assert_eq!(Decimal::new(1, 39), Err(Error::InvalidFormat));
+
+        let mut bytes = [0u8; 17];
+        bytes[16] = 40;
+        assert_eq!(Decimal::read(&bytes, 0), Err(Error::InvalidFormat));
+    }
+
+    #[test]
+    fn test_well_known_display() -> Result<()> {
+        let uuid = Uuid::from_bytes([
+            0x67, 0xe5, 0x50, 0x44, 0x10, 0xb1, 0x42, 0x6f, 0x92, 0x47, 0xbb, 0x68, 0x0e, 0x5f,
+            0xe0, 0xc8,
+        ]);
+        assert_eq!(uuid.to_string(), "67e55044-10b1-426f-9247-bb680e5fe0c8");
+        assert_eq!(Decimal::new(-1205, 2)?.to_string(), "-12.05");
+        assert_eq!(Decimal::new(7, 3)?.to_string(), "0.007");
+        assert_eq!(Decimal::new(42, 0)?.to_string(), "42");
+        Ok(())
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_system_time_conversions() -> Result<()> {
+        use std::time::{SystemTime, UNIX_EPOCH};
+
+        let before = UNIX_EPOCH - core::time::Duration::from_millis(1500);
+        let timestamp = Timestamp::try_from(before)?;
+        assert_eq!(timestamp, Timestamp::new(-2, 500_000_000)?);
+        assert_eq!(SystemTime::try_from(timestamp)?, before);
+
+        let after = UNIX_EPOCH + core::time::Duration::new(10, 7);
+        assert_eq!(SystemTime::try_from(Timestamp::try_from(after)?)?, after);
+
+        let duration = Duration::try_from(core::time::Duration::from_millis(2500))?;
+        assert_eq!(duration, Duration::new(2, 500_000_000)?);
+        assert_eq!(
+            core::time::Duration::try_from(duration)?,
+            core::time::Duration::from_millis(2500)
+        );
+        assert!(core::time::Duration::try_from(Duration::new(-1, 0)?).is_err());
+        Ok(())
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn test_chrono_conversions() -> Result<()> {
+        let time = chrono::DateTime::from_timestamp(-1, 250_000_000).ok_or(Error::InvalidFormat)?;
+        let timestamp = Timestamp::from(time);
+        assert_eq!(timestamp, Timestamp::new(-1, 250_000_000)?);
+        assert_eq!(chrono::DateTime::try_from(timestamp)?, time);
+
+        let delta = chrono::Duration::milliseconds(-1500);
+        let duration = Duration::from(delta);
+        assert_eq!(duration, Duration::new(-1, -500_000_000)?);
+        assert_eq!(chrono::Duration::try_from(duration)?, delta);
+        Ok(())
+    }
+
+    #[cfg(feature = "uuid")]
+    #[test]
+    fn test_uuid_conversions() {
+        let external = uuid::Uuid::from_u128(0x67e5_5044_10b1_426f_9247_bb68_0e5f_e0c8);
+        let uuid = Uuid::from(external);
+        assert_eq!(uuid.as_bytes(), external.as_bytes());
+        assert_eq!(uuid::Uuid::from(uuid), external);
+    }
+}
@@ -96,14 +96,14 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     settings_builder.set_theme(Theme::Dark);
     settings_builder.set_notifications_enabled(true);
     settings_builder.set_max_friends(500);
-    let settings_data = settings_builder.finish();
+    let settings_data = settings_builder.finish()?;
     
     // Create user profile
     let mut profile_builder = UserProfileBuilder::new();
     profile_builder.set_bio("Rust developer and ZeroProto enthusiast");
     profile_builder.set_avatar_url("https://example.com/avatar.jpg");
     profile_builder.set_settings(&settings_data);
-    let profile_data = profile_builder.finish();
+    let profile_data = profile_builder.finish()?;
     
     // Create the main user
     let mut user_builder = UserBuilder::new();
@@ -121,7 +121,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     user_builder.set_profile(&profile_data);
     
     // Serialize the user
-    let user_data = user_builder.finish();
+    let user_data = user_builder.finish()?;
     println!("✅ Serialized user: {} bytes", user_data.len());
     
     // Read the user (zero-copy!)